@@ -0,0 +1,38 @@
+use eframe::egui::{self, Response, Ui, WidgetInfo, WidgetText, WidgetType};
+
+/// Draws a clearly visible ring around a widget while it has keyboard focus
+pub fn focus_ring(ui: &Ui, response: &Response) {
+    if response.has_focus() {
+        let stroke = egui::Stroke::new(2.0, ui.visuals().selection.stroke.color);
+        ui.painter().rect_stroke(response.rect.expand(2.0), 3.0, stroke, egui::StrokeKind::Outside);
+    }
+}
+
+/// Gives a widget the name screen readers announce (instead of e.g. its icon) and
+/// draws the focus ring
+pub fn labeled(ui: &Ui, response: Response, kind: WidgetType, label: &str) -> Response {
+    let enabled = ui.is_enabled();
+    response.widget_info(|| WidgetInfo::labeled(kind, enabled, label));
+    focus_ring(ui, &response);
+    response
+}
+
+/// A button whose accessible name can differ from what's drawn on it
+pub fn button(ui: &mut Ui, text: impl Into<WidgetText>, label: &str) -> Response {
+    let response = ui.button(text);
+    labeled(ui, response, WidgetType::Button, label)
+}
+
+/// Like `button`, but greyed out and unclickable unless `enabled`
+pub fn enabled_button(ui: &mut Ui, enabled: bool, text: impl Into<WidgetText>, label: &str) -> Response {
+    let response = ui.add_enabled(enabled, egui::Button::new(text));
+    labeled(ui, response, WidgetType::Button, label)
+}
+
+/// Names a slider for screen readers, reporting `value` as its current value
+pub fn slider(ui: &Ui, response: Response, value: f64, label: &str) -> Response {
+    let enabled = ui.is_enabled();
+    response.widget_info(|| WidgetInfo::slider(enabled, value, label));
+    focus_ring(ui, &response);
+    response
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+use crate::player::MusicPlayer;
+
+/// The playback controls a player offers, for code that wants to drive audio without
+/// depending on `MusicPlayer` itself (a test double, say). `MusicPlayer` implements it by
+/// calling its own methods, which stay as they were.
+///
+/// The GUI starts tracks, seeks to their start points and follows the position through
+/// it. Everything past the basic controls (gapless playback, crossfade, the equalizer,
+/// streams) is only on `MusicPlayer`, so the GUI still holds one of those.
+// Pause, stop and the rest are for library users; the binary calls MusicPlayer's own
+#[allow(dead_code)]
+pub trait AudioBackend {
+    /// Starts playing `path` as playlist entry `index`, replacing whatever was playing
+    fn play(&self, path: &Path, index: usize) -> Result<()>;
+    fn pause(&self);
+    fn resume(&self);
+    fn stop(&self);
+    /// Moves to `position` in the current track
+    fn seek(&self, position: Duration) -> Result<()>;
+    /// Sets the output gain, from 0.0 to 1.0
+    fn set_volume(&self, volume: f32);
+    fn position(&self) -> Duration;
+    /// Length of the current track, if it's known
+    fn duration(&self) -> Option<Duration>;
+    /// Whether the current track has played to its end
+    fn finished(&self) -> bool;
+}
+
+impl AudioBackend for MusicPlayer {
+    fn play(&self, path: &Path, index: usize) -> Result<()> {
+        self.play_playlist_item(path, index)
+    }
+
+    fn pause(&self) {
+        MusicPlayer::pause(self)
+    }
+
+    fn resume(&self) {
+        MusicPlayer::resume(self)
+    }
+
+    fn stop(&self) {
+        MusicPlayer::stop(self)
+    }
+
+    fn seek(&self, position: Duration) -> Result<()> {
+        self.seek_to(position).map(|_| ())
+    }
+
+    fn set_volume(&self, volume: f32) {
+        MusicPlayer::set_volume(self, volume)
+    }
+
+    fn position(&self) -> Duration {
+        self.get_current_position()
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.get_song_duration()
+    }
+
+    fn finished(&self) -> bool {
+        self.check_if_song_finished()
+    }
+}
@@ -0,0 +1,129 @@
+use rodio::{Source, source::SeekError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Re-read the balance every this many frames
+const SETTINGS_CHECK_FRAMES: usize = 1024;
+
+/// Gains for the (left, right) channels at `balance`, from -1.0 (left only) to 1.0 (right
+/// only). The side balanced towards stays at full level and the other is turned down, so
+/// the centre leaves the sound untouched.
+pub fn channel_gains(balance: f32) -> (f32, f32) {
+    let balance = if balance.is_nan() { 0.0 } else { balance.clamp(-1.0, 1.0) };
+    (1.0 - balance.max(0.0), 1.0 + balance.min(0.0))
+}
+
+/// Left/right balance, scaling the two channels independently. Sources that aren't stereo
+/// are passed through, as there's no side to favour.
+pub struct Balance<S> {
+    inner: S,
+    balance: Arc<Mutex<f32>>,
+    gains: (f32, f32),
+    settings_countdown: usize,
+    /// The next sample is a right channel one
+    right_next: bool,
+}
+
+impl<S> Balance<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, balance: Arc<Mutex<f32>>) -> Self {
+        Self {
+            inner,
+            balance,
+            gains: (1.0, 1.0),
+            settings_countdown: 0,
+            right_next: false,
+        }
+    }
+
+    fn read_settings(&mut self) {
+        if let Ok(balance) = self.balance.lock() {
+            self.gains = channel_gains(*balance);
+        }
+    }
+}
+
+impl<S> Iterator for Balance<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.inner.channels() != 2 {
+            return self.inner.next();
+        }
+
+        let sample = self.inner.next()?;
+        if self.right_next {
+            self.right_next = false;
+            return Some(sample * self.gains.1);
+        }
+
+        if self.settings_countdown == 0 {
+            self.read_settings();
+            self.settings_countdown = SETTINGS_CHECK_FRAMES;
+        }
+        self.settings_countdown -= 1;
+        self.right_next = true;
+        Some(sample * self.gains.0)
+    }
+}
+
+impl<S> Source for Balance<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.right_next = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn test_channel_gains() {
+        assert_eq!(channel_gains(0.0), (1.0, 1.0));
+        assert_eq!(channel_gains(-1.0), (1.0, 0.0));
+        assert_eq!(channel_gains(0.5), (0.5, 1.0));
+        assert_eq!(channel_gains(3.0), (0.0, 1.0));
+        assert_eq!(channel_gains(f32::NAN), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_balance_scales_each_channel() {
+        let stereo = SamplesBuffer::new(2, 44100, vec![0.5f32; 8]);
+        let output: Vec<f32> = Balance::new(stereo, Arc::new(Mutex::new(-0.5))).collect();
+        assert_eq!(output, vec![0.5, 0.25, 0.5, 0.25, 0.5, 0.25, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn test_mono_passes_through() {
+        let mono = SamplesBuffer::new(1, 44100, vec![0.5f32; 4]);
+        let output: Vec<f32> = Balance::new(mono, Arc::new(Mutex::new(1.0))).collect();
+        assert_eq!(output, vec![0.5; 4]);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A small least-recently-used cache.
+///
+/// Lookups, inserts and evictions are O(log n): entries are also indexed by when they
+/// were last used, so the oldest is always first in line.
+pub struct LruCache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    /// Last-used tick to key, oldest first
+    order: BTreeMap<u64, K>,
+    capacity: usize,
+    tick: u64,
+}
+
+// Not every caller needs every method
+#[allow(dead_code)]
+impl<K: Eq + Hash + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            capacity: capacity.max(1),
+            tick: 0,
+        }
+    }
+
+    /// Looks up a value and marks it as recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        if let Some(key) = self.order.remove(last_used) {
+            self.order.insert(tick, key);
+        }
+        *last_used = tick;
+        Some(&*value)
+    }
+
+    /// Looks up a value without affecting eviction order
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.order.remove(&last_used);
+        }
+        self.order.insert(self.tick, key);
+        self.evict();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, last_used) = self.entries.remove(key)?;
+        self.order.remove(&last_used);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    // Drop the least recently used entries until we're within capacity
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            match self.order.pop_first() {
+                Some((_, key)) => {
+                    self.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+
+        // Touch "a" so "b" becomes the oldest
+        assert_eq!(cache.get(&"a"), Some(&1));
+        cache.insert("c", 3);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key(&"a"));
+        assert!(!cache.contains_key(&"b"));
+        assert_eq!(cache.peek(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn test_shrinking_capacity_evicts() {
+        let mut cache = LruCache::new(3);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        cache.insert(3, "three");
+
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains_key(&3));
+    }
+
+    #[test]
+    fn test_reinserting_and_removing_keep_order() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        // Replacing "a" makes it the newest
+        cache.insert("a", 10);
+        cache.insert("c", 3);
+        assert_eq!(cache.peek(&"a"), Some(&10));
+        assert!(!cache.contains_key(&"b"));
+
+        assert_eq!(cache.remove(&"a"), Some(10));
+        cache.insert("d", 4);
+        cache.insert("e", 5);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key(&"d") && cache.contains_key(&"e"));
+    }
+}
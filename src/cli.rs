@@ -0,0 +1,252 @@
+use anyhow::Result;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::config::{load_config, Config};
+use crate::controller::PlayerController;
+#[cfg(feature = "remote-control")]
+use crate::remote::{remote_token_path, RemoteRequest, RemoteServer};
+use crate::player::{MusicPlayer, PlaybackState, PlayerCommand, RepeatMode};
+use crate::playlist::load_startup_playlist;
+
+const HELP: &str = "\
+Commands:
+  p          play / pause
+  s          stop
+  n          next track
+  b          previous track (or restart the current one)
+  <number>   play that track
+  f / r      seek forward / back (seek_step_secs)
+  > / <      seek forward / back further (seek_coarse_step_secs)
+  + / -      volume up / down
+  z          toggle shuffle
+  R          cycle repeat (off, one, all)
+  l          list the playlist
+  i          show what's playing
+  h          show this help
+  q          quit";
+
+/// Headless front-end: reads commands from stdin and drives the same controller
+/// the other front-ends use
+pub fn run(files: Vec<PathBuf>, loop_file: bool) -> Result<()> {
+    let config = load_config().unwrap_or_default();
+    let player = MusicPlayer::new()?;
+    player.set_volume(config.volume);
+    player.set_crossfeed(config.crossfeed_enabled, config.crossfeed_strength);
+    player.set_replaygain_mode(config.replaygain_mode);
+    player.set_repeat(config.repeat_mode);
+
+    let mut controller = PlayerController::new(Arc::new(Mutex::new(player)));
+    // Trims are saved with playlists, and the startup playlist is the one kept between runs,
+    // so tracks trimmed there play between their trim points here too
+    match load_startup_playlist() {
+        Ok((_, trims)) => controller.set_trims(trims),
+        Err(e) => log::error!("Couldn't load trims from the startup playlist: {}", e),
+    }
+    for file in files.into_iter().take(config.max_playlist_entries) {
+        controller.add(file);
+    }
+
+    if controller.playlist().is_empty() {
+        return Err(anyhow::anyhow!("No audio files to play"));
+    }
+    controller.set_loop_single((loop_file || config.loop_single_file) && controller.playlist().len() == 1);
+
+    // Stdin blocks, so read it on its own thread and poll the lines from the loop
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+
+    #[cfg(feature = "remote-control")]
+    let remote = if config.remote_control {
+        remote_token_path()
+            .and_then(|token_file| RemoteServer::start(config.remote_port, &token_file))
+            .inspect(|server| log::info!("Remote control listening on 127.0.0.1:{}", server.port()))
+            .map_err(|e| log::warn!("Remote control unavailable: {}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    // Ctrl-C stops playback and exits normally. A second one exits straight away.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+    }) {
+        log::warn!("Could not install the Ctrl-C handler: {}", e);
+    }
+
+    println!("{}", HELP);
+    controller.play_index(0)?;
+    print_now_playing(&controller);
+
+    loop {
+        if interrupted.load(Ordering::SeqCst) {
+            println!("Stopped");
+            break;
+        }
+
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => {
+                if !handle_line(&mut controller, &config, line.trim()) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // Stdin closed (e.g. piped input ran out), keep playing until the queue ends
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(Duration::from_millis(100)),
+        }
+
+        let previous = controller.current_index();
+        #[cfg(feature = "remote-control")]
+        for pending in remote.iter().flat_map(|remote| remote.poll()) {
+            let result = match pending.request.clone() {
+                RemoteRequest::Command(command) => controller.apply(command),
+                RemoteRequest::Status => Ok(()),
+            };
+            pending.reply(result, controller.snapshot());
+        }
+
+        match controller.tick() {
+            Ok(true) => {
+                println!("End of playlist");
+                break;
+            }
+            Ok(false) => {
+                if controller.current_index() != previous {
+                    print_now_playing(&controller);
+                }
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+
+    controller.stop();
+    Ok(())
+}
+
+// Returns false when the user asked to quit
+fn handle_line(controller: &mut PlayerController, config: &Config, line: &str) -> bool {
+    let snapshot = controller.snapshot();
+    let previous = controller.current_index();
+
+    let result = match line {
+        "" => return true,
+        "q" | "quit" => return false,
+        "h" | "help" | "?" => {
+            println!("{}", HELP);
+            Ok(())
+        }
+        "l" | "list" => {
+            for (index, path) in controller.playlist().iter().enumerate() {
+                let marker = if Some(index) == controller.current_index() { ">" } else { " " };
+                println!("{} {}. {}", marker, index + 1, path.display());
+            }
+            Ok(())
+        }
+        "i" | "info" => {
+            print_now_playing(controller);
+            Ok(())
+        }
+        "p" | " " => controller.apply(PlayerCommand::TogglePlayPause),
+        "s" => controller.apply(PlayerCommand::Stop),
+        "n" => controller.apply(PlayerCommand::Next),
+        "b" => controller.apply(PlayerCommand::Previous),
+        "f" => controller.seek_relative(config.seek_step(false)),
+        "r" => controller.seek_relative(-config.seek_step(false)),
+        ">" => controller.seek_relative(config.seek_step(true)),
+        "<" => controller.seek_relative(-config.seek_step(true)),
+        "+" | "-" => {
+            let volume = snapshot.as_ref().map(|s| s.volume).unwrap_or(0.5);
+            let step = if line == "+" { 0.05 } else { -0.05 };
+            let result = controller.apply(PlayerCommand::SetVolume(volume + step));
+            if let Some(snapshot) = controller.snapshot() {
+                println!("Volume: {}%", (snapshot.volume * 100.0).round());
+            }
+            result
+        }
+        "z" => {
+            let shuffle = !snapshot.as_ref().is_some_and(|s| s.shuffle);
+            println!("Shuffle {}", if shuffle { "on" } else { "off" });
+            controller.apply(PlayerCommand::SetShuffle(shuffle))
+        }
+        "R" => {
+            let repeat = match snapshot.as_ref().map(|s| s.repeat).unwrap_or_default() {
+                RepeatMode::None => RepeatMode::One,
+                RepeatMode::One => RepeatMode::All,
+                RepeatMode::All => RepeatMode::None,
+            };
+            println!("Repeat: {:?}", repeat);
+            controller.apply(PlayerCommand::SetRepeat(repeat))
+        }
+        other => match other.parse::<usize>() {
+            Ok(number) if number > 0 => controller.play_index(number - 1),
+            _ => {
+                println!("Unknown command '{}', type h for help", other);
+                Ok(())
+            }
+        },
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+    }
+    if controller.current_index() != previous {
+        print_now_playing(controller);
+    }
+    true
+}
+
+fn print_now_playing(controller: &PlayerController) {
+    let Some(path) = controller.current_path() else {
+        println!("Nothing playing");
+        return;
+    };
+
+    let name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown");
+    let index = controller.current_index().map(|i| i + 1).unwrap_or(0);
+    let total = controller.playlist().len();
+
+    match controller.snapshot() {
+        Some(snapshot) => {
+            let state = match snapshot.state {
+                PlaybackState::Playing => "Playing",
+                PlaybackState::Paused => "Paused",
+                PlaybackState::Stopped => "Stopped",
+            };
+            println!(
+                "{} [{}/{}]: {} ({} / {})",
+                state,
+                index,
+                total,
+                name,
+                format_duration(snapshot.position),
+                snapshot.duration.map(format_duration).unwrap_or_else(|| "--:--".to_string())
+            );
+        }
+        None => println!("[{}/{}]: {}", index, total, name),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
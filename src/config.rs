@@ -4,28 +4,261 @@ use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::time::Duration;
+use crate::replaygain::ReplayGainMode;
+use crate::sleep::SleepAction;
+use crate::player::RepeatMode;
+use crate::equalizer::{EQ_BANDS, normalize_gains};
 
 const APP_NAME: &str = "musicplayer";
 const ORG_NAME: &str = "musicplayer";
 
+/// Layout of the config file. Bump it and add a step to `MIGRATIONS` when a field is renamed
+/// or changes meaning; a new field with a default needs neither.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Port the remote control listens on (loopback only) unless the config says otherwise.
+/// Kept here so the setting exists in builds without the remote-control feature.
+pub const DEFAULT_REMOTE_PORT: u16 = 47317;
+
+/// What the Stop button does with the current track
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StopBehavior {
+    /// Keep the track selected so Play starts it again from the beginning
+    #[default]
+    KeepTrack,
+    /// Forget the current track entirely
+    ClearTrack,
+}
+
+/// Colour scheme of the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// Follow the operating system's light or dark setting
+    System,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)] // Missing fields in older config files fall back to their defaults
 pub struct Config {
+    /// Layout the file was written with. Files from before there was one read as 0.
+    #[serde(default)]
+    pub version: u32,
     pub filename: String,
     pub volume: f32,
+    /// Folder to watch for new audio files
+    pub watch_folder: Option<String>,
+    /// Automatically add new files that appear in `watch_folder`
+    pub watch_folder_enabled: bool,
+    /// Move finished tracks below a "Played" divider so upcoming tracks stay on top
+    pub move_played_to_bottom: bool,
+    pub stop_behavior: StopBehavior,
+    /// Repeat mode chosen in the player, restored on the next start
+    pub repeat_mode: RepeatMode,
+    /// Key that instantly silences and pauses playback (egui key name, e.g. "Escape")
+    pub panic_key: String,
+    /// Tracks beyond this are refused when adding to the playlist
+    pub max_playlist_entries: usize,
+    /// Maximum entries kept in each per-track cache (fingerprints, metadata, artwork...)
+    pub cache_capacity: usize,
+    /// Directory the file dialogs open in, updated after every successful pick
+    pub last_dialog_dir: Option<String>,
+    /// Blend a little of each stereo channel into the other for easier headphone listening
+    pub crossfeed_enabled: bool,
+    /// Crossfeed amount from 0.0 to 1.0
+    pub crossfeed_strength: f32,
+    /// Left/right balance from -1.0 (left only) to 1.0 (right only), 0.0 in the centre
+    pub balance: f32,
+    /// Loudness normalisation from ReplayGain tags: "off", "track" or "album"
+    pub replaygain_mode: ReplayGainMode,
+    /// Loop forever when exactly one file is opened (same as `--loop`)
+    pub loop_single_file: bool,
+    /// Optional playlist columns filled in from the file's tags
+    pub show_genre_column: bool,
+    pub show_year_column: bool,
+    /// Badge showing each file's format or bitrate ("FLAC", "320"...)
+    pub show_quality_column: bool,
+    /// Pop up a system notification when a new track starts
+    pub desktop_notifications: bool,
+    /// How far the seek keys jump, in seconds
+    pub seek_step_secs: f64,
+    /// How far the seek keys jump with Shift held, in seconds
+    pub seek_coarse_step_secs: f64,
+    /// Show the lyrics panel when the current track has lyrics
+    pub show_lyrics: bool,
+    /// Accept commands from `musicplayer --remote` (and scripts) on the loopback interface.
+    /// Clients must send the token the player writes to `remote_token` each run.
+    pub remote_control: bool,
+    pub remote_port: u16,
+    /// Pause when the output device in use goes away (e.g. headphones unplugged) instead
+    /// of carrying on through whatever device takes over
+    pub pause_on_output_change: bool,
+    /// What the sleep timer does when it goes off: "pause", "stop" or "quit"
+    pub sleep_action: SleepAction,
+    /// Playback speed from 0.5 to 2.0, restored on the next start
+    pub playback_speed: f32,
+    /// Keep the pitch when playing faster or slower, at the cost of more CPU
+    pub preserve_pitch: bool,
+    /// Add Folder also picks up audio files in subfolders
+    pub recursive_folder_scan: bool,
+    /// Seconds each track overlaps the next, fading one out as the other fades in (0 = off)
+    pub crossfade_secs: f32,
+    /// Colour scheme: "dark", "light" or "system"
+    pub theme: Theme,
+    /// Shuffle was on, restored on the next start
+    pub shuffle: bool,
+    /// Scroll the playlist to each new track as it starts
+    pub follow_current_track: bool,
+    /// Equalizer gain in dB for each band, lowest first
+    pub eq_gains: Vec<f32>,
+    /// Name of the last equalizer preset picked, unset once the sliders have been moved
+    pub eq_preset: Option<String>,
+    /// The equalizer window was open
+    pub show_equalizer: bool,
+    /// Move on to the next track when one stops partway through because the rest of it
+    /// can't be decoded, rather than stopping playback there
+    pub skip_on_playback_error: bool,
+    /// Draw the current track's waveform above the progress bar
+    pub show_waveform: bool,
+    /// Queue the next track behind the current one so it starts without a gap. Not used with
+    /// crossfade, trims or cue sheet tracks.
+    pub gapless: bool,
+    /// Shrink the window to a narrow bar with just the current track and the transport controls
+    pub mini_mode: bool,
+    /// Load the playlist set with Set as Startup Playlist when started without any files
+    pub load_startup_playlist: bool,
+    /// Start each track where it was left off, unless it had barely begun or was nearly over
+    pub remember_position: bool,
+    /// Track playing when the player was last closed, selected again on the next start
+    pub last_track: Option<PathBuf>,
+    /// Where `last_track` was left off, or zero to start it from the beginning
+    pub last_position: Duration,
+    /// Start playing `last_track` on startup instead of just selecting it
+    pub resume_last_track: bool,
     // Add more config options here in the future
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             filename: "config.toml".to_string(),
             volume: 0.5,
+            watch_folder: None,
+            watch_folder_enabled: false,
+            move_played_to_bottom: false,
+            stop_behavior: StopBehavior::default(),
+            repeat_mode: RepeatMode::default(),
+            panic_key: "Escape".to_string(),
+            max_playlist_entries: 50_000,
+            cache_capacity: crate::utils::DEFAULT_CACHE_CAPACITY,
+            last_dialog_dir: None,
+            crossfeed_enabled: false,
+            crossfeed_strength: 0.5,
+            balance: 0.0,
+            replaygain_mode: ReplayGainMode::default(),
+            loop_single_file: false,
+            show_genre_column: false,
+            show_year_column: false,
+            show_quality_column: false,
+            desktop_notifications: false,
+            seek_step_secs: 5.0,
+            seek_coarse_step_secs: 30.0,
+            show_lyrics: false,
+            remote_control: false,
+            remote_port: DEFAULT_REMOTE_PORT,
+            pause_on_output_change: true,
+            sleep_action: SleepAction::default(),
+            playback_speed: 1.0,
+            preserve_pitch: false,
+            recursive_folder_scan: true,
+            crossfade_secs: 0.0,
+            theme: Theme::default(),
+            shuffle: false,
+            follow_current_track: true,
+            eq_gains: vec![0.0; EQ_BANDS.len()],
+            eq_preset: Some("Flat".to_string()),
+            show_equalizer: false,
+            skip_on_playback_error: true,
+            show_waveform: true,
+            gapless: true,
+            mini_mode: false,
+            load_startup_playlist: true,
+            remember_position: false,
+            last_track: None,
+            last_position: Duration::ZERO,
+            resume_last_track: false,
         }
     }
 }
 
+impl Config {
+    /// Seek distance in seconds, the larger one when `coarse` is set
+    pub fn seek_step(&self, coarse: bool) -> f64 {
+        let step = if coarse { self.seek_coarse_step_secs } else { self.seek_step_secs };
+        // A zero or negative step would make the keys do nothing or seek the wrong way
+        if step > 0.0 { step } else { 5.0 }
+    }
+    
+    /// Brings hand-edited values back into range, e.g. a volume above full
+    pub fn sanitize(&mut self) {
+        self.volume = if self.volume.is_nan() {
+            Config::default().volume
+        } else {
+            self.volume.clamp(0.0, 1.0)
+        };
+        self.balance = if self.balance.is_nan() { 0.0 } else { self.balance.clamp(-1.0, 1.0) };
+        // `max` also turns NaN into 0
+        self.crossfade_secs = self.crossfade_secs.max(0.0);
+        self.eq_gains = normalize_gains(&self.eq_gains);
+    }
+}
+
+// Steps bringing each old layout up to the next, in order: entry n turns version n into n + 1
+const MIGRATIONS: [fn(&mut Config); CONFIG_VERSION as usize] = [migrate_from_v0, migrate_from_v1];
+
+// Version 0 is any file written before the version field. Its missing fields were filled in
+// from the defaults while reading, and none of the ones it has changed meaning.
+fn migrate_from_v0(_config: &mut Config) {}
+
+// Version 1 files saved the remote control's old default of on. It's off by default now,
+// as any local program could drive the player through it, so it has to be turned on again.
+fn migrate_from_v1(config: &mut Config) {
+    config.remote_control = false;
+}
+
+// Runs the steps from the file's version up to the current one. Returns whether there were
+// any, so the file gets written back in the new layout.
+fn migrate(config: &mut Config) -> bool {
+    let Some(steps) = MIGRATIONS.get(config.version as usize..) else {
+        // Written by a newer version of the player, so leave it be
+        return false;
+    };
+    if steps.is_empty() {
+        return false;
+    }
+    for step in steps {
+        step(config);
+    }
+    config.version = CONFIG_VERSION;
+    true
+}
+
+/// Reads a config file's contents, bringing an older layout up to date. Also returns
+/// whether it was migrated, in which case the file should be saved again.
+pub fn parse_config(contents: &str) -> Result<(Config, bool)> {
+    let mut config: Config = toml::from_str(contents)?;
+    let migrated = migrate(&mut config);
+    config.sanitize();
+    Ok((config, migrated))
+}
+
 /// Gets the config directory, creating it if it doesn't exist
-fn get_config_dir() -> Result<PathBuf> {
+pub(crate) fn get_config_dir() -> Result<PathBuf> {
     let proj_dirs = ProjectDirs::from("", ORG_NAME, APP_NAME)
         .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?;
     
@@ -37,6 +270,14 @@ fn get_config_dir() -> Result<PathBuf> {
     Ok(config_dir.to_path_buf())
 }
 
+/// Opens the folder holding the config file and the player's other saved data in the
+/// system's file manager, creating it first if it doesn't exist yet
+pub fn open_config_dir() -> Result<()> {
+    let config_dir = get_config_dir()?;
+    crate::utils::open_folder(&config_dir)
+        .map_err(|e| anyhow::anyhow!("Could not open {}: {}", config_dir.display(), e))
+}
+
 /// Gets the config file path
 fn get_config_file_path() -> Result<PathBuf> {
     let config_dir = get_config_dir()?;
@@ -57,7 +298,13 @@ pub fn load_config() -> Result<Config> {
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
     
-    let config: Config = toml::from_str(&contents)?;
+    let (config, migrated) = parse_config(&contents)?;
+    if migrated {
+        // Still usable as it is if this fails, it's just migrated again next time
+        if let Err(e) = save_config(&config) {
+            log::warn!("Couldn't save the updated config file: {}", e);
+        }
+    }
     Ok(config)
 }
 
@@ -91,6 +338,60 @@ mod tests {
         let default_config = Config::default();
         assert_eq!(default_config.volume, 0.5);
         assert_eq!(default_config.filename, "config.toml");
+        assert_eq!(default_config.seek_step(false), 5.0);
+        assert_eq!(default_config.seek_step(true), 30.0);
+    }
+
+    #[test]
+    fn test_sanitize_keeps_volume_in_range() {
+        for (volume, expected) in [(1.5, 1.0), (-0.2, 0.0), (0.3, 0.3), (f32::NAN, 0.5)] {
+            let mut config = Config { volume, ..Config::default() };
+            config.sanitize();
+            assert_eq!(config.volume, expected);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_fills_in_eq_bands() {
+        let mut config = Config { eq_gains: vec![3.0, 40.0], ..Config::default() };
+        config.sanitize();
+        assert_eq!(config.eq_gains.len(), EQ_BANDS.len());
+        assert_eq!(&config.eq_gains[..3], &[3.0, crate::equalizer::MAX_GAIN_DB, 0.0]);
+    }
+
+    #[test]
+    fn test_v0_config_fills_in_defaults() {
+        // A file from before the version field and most of the settings
+        let (config, migrated) = parse_config("filename = \"old.toml\"\nvolume = 0.8\nshuffle = true\n")
+            .expect("Failed to read a v0 config");
+        assert!(migrated);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.volume, 0.8);
+        assert!(config.shuffle);
+        assert_eq!(config.seek_step(true), 30.0);
+        assert!(config.gapless);
+        assert_eq!(config.eq_gains.len(), EQ_BANDS.len());
+    }
+
+    #[test]
+    fn test_v1_config_turns_remote_control_off() {
+        let (config, migrated) = parse_config("version = 1\nremote_control = true\n").expect("Failed to read a v1 config");
+        assert!(migrated);
+        assert!(!config.remote_control);
+    }
+
+    #[test]
+    fn test_current_config_is_not_migrated() {
+        let contents = toml::to_string_pretty(&Config::default()).unwrap();
+        let (config, migrated) = parse_config(&contents).unwrap();
+        assert!(!migrated);
+        assert_eq!(config.version, CONFIG_VERSION);
+        
+        // Nor is one from a newer player
+        let newer = format!("version = {}\n", CONFIG_VERSION + 1);
+        let (config, migrated) = parse_config(&newer).unwrap();
+        assert!(!migrated);
+        assert_eq!(config.version, CONFIG_VERSION + 1);
     }
 
     #[test]
@@ -99,6 +400,7 @@ mod tests {
         let test_config = Config {
             filename: "test.toml".to_string(),
             volume: 0.75,
+            ..Config::default()
         };
 
         // Save the config to disk
@@ -0,0 +1,822 @@
+use anyhow::Result;
+use rand::{rng, Rng};
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::play_counts::ListenTime;
+use crate::player::{MusicPlayer, PlayerCommand, PlayerSnapshot, RepeatMode};
+use crate::trims::TrackTrims;
+
+/// Going back within this much of a track's start goes to the previous track,
+/// later than that it restarts the current one
+pub const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+/// A track that ends having played less audio than this almost certainly failed to play
+pub const INSTANT_FINISH: Duration = Duration::from_millis(100);
+/// Stop auto-advancing after this many instant finishes in a row
+pub const MAX_INSTANT_SKIPS: usize = 5;
+
+/// How a track that just ended should be treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishKind {
+    /// Played normally
+    Played,
+    /// Ended right away (empty, truncated or undecodable file), skip it without counting it as played
+    InstantSkip,
+    /// Too many instant finishes in a row, stop instead of spinning through the playlist
+    TooManySkips,
+}
+
+/// Spots tracks that end as soon as they start, so a playlist full of broken files
+/// doesn't get auto-advanced through in a busy loop. A track is judged on whether it could
+/// be opened and how much of it was heard, not on the clock, which only moves as often as
+/// the player looks.
+#[derive(Debug, Default)]
+pub struct InstantSkipGuard {
+    failed: bool,
+    consecutive: usize,
+}
+
+impl InstantSkipGuard {
+    /// Takes the result of opening the track
+    pub fn track_started<T>(&mut self, result: &Result<T>) {
+        self.failed = result.is_err();
+    }
+
+    /// Classifies a track that finished after `played` of it was heard
+    pub fn track_finished(&mut self, played: Duration) -> FinishKind {
+        if !std::mem::take(&mut self.failed) && played >= INSTANT_FINISH {
+            self.consecutive = 0;
+            return FinishKind::Played;
+        }
+
+        self.consecutive += 1;
+        if self.consecutive >= MAX_INSTANT_SKIPS {
+            // Start counting afresh in case the user tries again
+            self.consecutive = 0;
+            FinishKind::TooManySkips
+        } else {
+            FinishKind::InstantSkip
+        }
+    }
+}
+
+/// Picks the track after `current` in playlist order. With `wrap` the playlist starts
+/// over at the end, otherwise `None` means playback is done.
+pub fn next_index(len: usize, current: Option<usize>, wrap: bool) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+
+    match current {
+        Some(current) if current + 1 < len => Some(current + 1),
+        Some(_) if wrap => Some(0),
+        Some(_) => None,
+        None => Some(0),
+    }
+}
+
+/// Picks the track before `current`, wrapping to the end if `wrap` is set
+pub fn previous_index(len: usize, current: Option<usize>, wrap: bool) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+
+    match current {
+        Some(0) if wrap => Some(len - 1),
+        Some(0) => Some(0),
+        Some(current) => Some(current.min(len) - 1),
+        None => Some(0),
+    }
+}
+
+/// Track to play once `current` finishes on its own, honouring the repeat mode. `next`
+/// picks the track that follows, starting the playlist over when passed true.
+pub fn index_after_finished(
+    len: usize,
+    current: Option<usize>,
+    repeat: RepeatMode,
+    next: impl FnOnce(bool) -> Option<usize>,
+) -> Option<usize> {
+    match repeat {
+        RepeatMode::One if current.is_some_and(|c| c < len) => current,
+        RepeatMode::All => next(true),
+        _ => next(false),
+    }
+}
+
+/// The order shuffle plays a playlist in: every track once, in random order, before any
+/// of them plays again. Holds entries rather than indices, so reordering or removing
+/// tracks doesn't throw it off.
+#[derive(Debug, Clone)]
+pub struct ShuffleQueue<T> {
+    /// Still to play this cycle, the next one last
+    upcoming: Vec<T>,
+    /// A cycle is under way
+    in_cycle: bool,
+}
+
+impl<T> Default for ShuffleQueue<T> {
+    fn default() -> Self {
+        Self { upcoming: Vec::new(), in_cycle: false }
+    }
+}
+
+impl<T: Clone + PartialEq> ShuffleQueue<T> {
+    /// Index in `playlist` of the next track to shuffle to. Once every track has played,
+    /// a new order is drawn if `wrap` is set, otherwise `None` means playback is done.
+    /// `current` is left out of a cycle starting while it plays, and never comes straight
+    /// after itself when a cycle starts over.
+    pub fn next(&mut self, playlist: &[T], current: Option<&T>, wrap: bool) -> Option<usize> {
+        if playlist.is_empty() {
+            return None;
+        }
+        if let Some(index) = self.pop_listed(playlist) {
+            return Some(index);
+        }
+        if self.in_cycle && !wrap {
+            self.in_cycle = false;
+            return None;
+        }
+
+        let fresh = !self.in_cycle;
+        self.in_cycle = true;
+        self.reshuffle(playlist, current, fresh);
+        if let Some(index) = self.pop_listed(playlist) {
+            return Some(index);
+        }
+        // Only the playing track was left out, so this cycle is already over
+        if !wrap {
+            self.in_cycle = false;
+            return None;
+        }
+        self.reshuffle(playlist, current, false);
+        self.pop_listed(playlist)
+    }
+
+    /// Whether `next` would find a track, without using one up
+    pub fn has_next(&self, playlist: &[T], current: Option<&T>, wrap: bool) -> bool {
+        if playlist.is_empty() {
+            return false;
+        }
+        if wrap || self.upcoming.iter().any(|entry| playlist.contains(entry)) {
+            return true;
+        }
+        !self.in_cycle && playlist.iter().any(|entry| Some(entry) != current)
+    }
+
+    /// Index of the track `next` will return, if it's already decided. Nothing is drawn
+    /// here, so at the end of a cycle this is `None` even if `next` would start another.
+    pub fn peek(&self, playlist: &[T]) -> Option<usize> {
+        self.upcoming.iter().rev().find_map(|entry| playlist.iter().position(|p| p == entry))
+    }
+
+    /// Fits a newly added track into the rest of the cycle
+    pub fn add(&mut self, entry: T) {
+        if self.in_cycle {
+            let at = rng().random_range(0..=self.upcoming.len());
+            self.upcoming.insert(at, entry);
+        }
+    }
+
+    /// Makes `entry` the next track shuffled to, whether or not a cycle is under way
+    pub fn play_next(&mut self, entry: T) {
+        self.upcoming.retain(|upcoming| *upcoming != entry);
+        self.upcoming.push(entry);
+    }
+
+    /// Moves `entry` behind every other track still to play this cycle. Outside a cycle
+    /// there's no order to move it in, so nothing changes.
+    pub fn play_last(&mut self, entry: T) {
+        if self.in_cycle {
+            self.upcoming.retain(|upcoming| *upcoming != entry);
+            self.upcoming.insert(0, entry);
+        }
+    }
+
+    /// Starts a new cycle on the next track
+    pub fn reset(&mut self) {
+        self.upcoming.clear();
+        self.in_cycle = false;
+    }
+
+    // Entries that have left the playlist since the order was drawn are passed over
+    fn pop_listed(&mut self, playlist: &[T]) -> Option<usize> {
+        while let Some(entry) = self.upcoming.pop() {
+            if let Some(index) = playlist.iter().position(|p| *p == entry) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    fn reshuffle(&mut self, playlist: &[T], current: Option<&T>, skip_current: bool) {
+        let mut rng = rng();
+        self.upcoming = playlist.iter()
+            .filter(|entry| !(skip_current && Some(*entry) == current))
+            .cloned()
+            .collect();
+        self.upcoming.shuffle(&mut rng);
+
+        let last = self.upcoming.len().saturating_sub(1);
+        if last > 0 && current.is_some_and(|current| self.upcoming[last] == *current) {
+            let other = rng.random_range(0..last);
+            self.upcoming.swap(other, last);
+        }
+    }
+}
+
+/// First playlist entry that isn't in `played`, for picking a half-finished queue back up
+pub fn first_unplayed<T: Eq + Hash>(playlist: &[T], played: &HashSet<T>) -> Option<usize> {
+    playlist.iter().position(|entry| !played.contains(entry))
+}
+
+/// Where a relative seek lands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekTarget {
+    /// A position in the track, never before its start
+    To(Duration),
+    /// Past the end, so the track should finish
+    End,
+}
+
+/// Works out a jump of `delta_secs` (negative for back) from `position` in a track lasting `duration`
+pub fn relative_seek_target(position: Duration, delta_secs: f64, duration: Option<Duration>) -> SeekTarget {
+    let target = position.as_secs_f64() + delta_secs;
+    match duration {
+        Some(duration) if target >= duration.as_secs_f64() => SeekTarget::End,
+        _ => SeekTarget::To(Duration::from_secs_f64(target.max(0.0))),
+    }
+}
+
+/// Where the entry at `index` ends up after the entry at `from` is moved to `to`
+pub fn index_after_move(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// The prefix to look for after type-ahead `typed`, and whether to look past the selected
+/// row. The same letter typed over and over steps through the tracks starting with it;
+/// otherwise the row found so far is kept while it still matches.
+pub fn type_ahead_prefix(typed: &str) -> (&str, bool) {
+    let mut chars = typed.chars();
+    let Some(first) = chars.next() else {
+        return (typed, false);
+    };
+    if chars.all(|c| c == first) {
+        (&typed[..first.len_utf8()], true)
+    } else {
+        (typed, false)
+    }
+}
+
+/// Where type-ahead lands among `rows`, the playlist indices shown in order: the first that
+/// `matches`, from the selected row (or the one after it with `past_selected`), wrapping round
+pub fn type_ahead_index(rows: &[usize], selected: Option<usize>, past_selected: bool, matches: impl Fn(usize) -> bool) -> Option<usize> {
+    let start = match selected.and_then(|selected| rows.iter().position(|&row| row == selected)) {
+        Some(position) if past_selected => position + 1,
+        Some(position) => position,
+        None => 0,
+    };
+    (0..rows.len())
+        .map(|offset| rows[(start + offset) % rows.len()])
+        .find(|&index| matches(index))
+}
+
+/// Where playback goes from the current track: the shuffle order, and the trail of tracks
+/// Next moved away from so Previous can retrace a shuffle. Every front-end moves through
+/// its playlist with one of these, so they all behave the same. `current` is the index
+/// playlist order carries on from and `playing` the track the shuffle steers clear of;
+/// they only differ once a front-end has moved the playing track out of the way.
+#[derive(Debug, Default)]
+pub struct Navigator {
+    /// Tracks Next moved away from, oldest first
+    pub history: Vec<usize>,
+    /// Tracks still to play in this shuffle cycle
+    pub shuffle_order: ShuffleQueue<PathBuf>,
+}
+
+impl Navigator {
+    /// The track after `current`, from the shuffled order when shuffle is on. Draws from
+    /// that order, so only call it when actually moving on.
+    pub fn upcoming(&mut self, playlist: &[PathBuf], current: Option<usize>, playing: Option<&PathBuf>, shuffle: bool, wrap: bool) -> Option<usize> {
+        if shuffle {
+            self.shuffle_order.next(playlist, playing, wrap)
+        } else {
+            next_index(playlist.len(), current, wrap)
+        }
+    }
+
+    pub fn has_upcoming(&self, playlist: &[PathBuf], current: Option<usize>, playing: Option<&PathBuf>, shuffle: bool, wrap: bool) -> bool {
+        if shuffle {
+            self.shuffle_order.has_next(playlist, playing, wrap)
+        } else {
+            next_index(playlist.len(), current, wrap).is_some()
+        }
+    }
+
+    /// Moves on from `current` for Next, remembering it so Previous can come back
+    pub fn next(&mut self, playlist: &[PathBuf], current: Option<usize>, playing: Option<&PathBuf>, shuffle: bool, wrap: bool) -> Option<usize> {
+        let next = self.upcoming(playlist, current, playing, shuffle, wrap);
+        if let (Some(current), Some(_)) = (current, next) {
+            self.history.push(current);
+        }
+        next
+    }
+
+    /// The track Previous goes back to. A shuffle is retraced, passing over entries that
+    /// have been removed since; otherwise it's the one before `current`.
+    pub fn previous(&mut self, len: usize, current: Option<usize>, shuffle: bool, wrap: bool) -> Option<usize> {
+        if shuffle {
+            while let Some(index) = self.history.pop() {
+                if index < len {
+                    return Some(index);
+                }
+            }
+            None
+        } else {
+            self.history.pop();
+            previous_index(len, current, wrap)
+        }
+    }
+
+    /// The track to play once `current` finishes on its own, honouring the repeat mode
+    pub fn after_finished(&mut self, playlist: &[PathBuf], current: Option<usize>, playing: Option<&PathBuf>, shuffle: bool, repeat: RepeatMode) -> Option<usize> {
+        index_after_finished(playlist.len(), current, repeat, |wrap| self.next(playlist, current, playing, shuffle, wrap))
+    }
+
+    /// Forgets the trail and the shuffled order, for a new playlist
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.shuffle_order.reset();
+    }
+}
+
+/// Owns a playlist and drives a `MusicPlayer` through it. Front-ends (terminal, remote
+/// control) only translate input into commands; queueing, shuffle and repeat live here.
+pub struct PlayerController {
+    player: Arc<Mutex<MusicPlayer>>,
+    playlist: Vec<PathBuf>,
+    current: Option<usize>,
+    navigator: Navigator,
+    /// Set by Stop, so the stopped track isn't mistaken for one that finished
+    stopped: bool,
+    /// Replay the track forever while it's the only one in the playlist
+    loop_single: bool,
+    skip_guard: InstantSkipGuard,
+    /// How much of the current track has been heard, for the skip guard
+    listen_time: ListenTime,
+    /// Start and end points set for tracks in the GUI
+    trims: TrackTrims,
+}
+
+impl PlayerController {
+    pub fn new(player: Arc<Mutex<MusicPlayer>>) -> Self {
+        Self {
+            player,
+            playlist: Vec::new(),
+            current: None,
+            navigator: Navigator::default(),
+            stopped: false,
+            loop_single: false,
+            skip_guard: InstantSkipGuard::default(),
+            listen_time: ListenTime::default(),
+            trims: TrackTrims::default(),
+        }
+    }
+
+    pub fn add(&mut self, path: PathBuf) {
+        self.navigator.shuffle_order.add(path.clone());
+        self.playlist.push(path);
+    }
+
+    /// Loops the track forever while the playlist holds exactly one entry. Each pass restarts
+    /// the file, so the position goes back to zero at the loop point.
+    pub fn set_loop_single(&mut self, loop_single: bool) {
+        self.loop_single = loop_single;
+    }
+
+    /// Plays tracks between their trim points, the same as the GUI does
+    pub fn set_trims(&mut self, trims: TrackTrims) {
+        self.trims = trims;
+    }
+
+    pub fn playlist(&self) -> &[PathBuf] {
+        &self.playlist
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.and_then(|i| self.playlist.get(i)).map(|p| p.as_path())
+    }
+
+    pub fn snapshot(&self) -> Option<PlayerSnapshot> {
+        self.player.lock().ok().map(|player| player.snapshot())
+    }
+
+    /// Starts playing the playlist entry at `index`
+    pub fn play_index(&mut self, index: usize) -> Result<()> {
+        let Some(path) = self.playlist.get(index).cloned() else {
+            return Err(anyhow::anyhow!("No track at position {}", index + 1));
+        };
+
+        self.current = Some(index);
+        self.stopped = false;
+        self.listen_time = ListenTime::default();
+
+        let trim = self.trims.get(&path);
+        let result = match self.player.lock() {
+            Ok(player) => player.play_playlist_range(&path, index, trim.start().unwrap_or_default(), trim.end()),
+            Err(_) => Err(anyhow::anyhow!("Failed to lock player")),
+        };
+        self.skip_guard.track_started(&result);
+        result
+    }
+
+    /// Skips to the next track. Returns false when the end of the playlist was reached.
+    pub fn next(&mut self) -> Result<bool> {
+        let (shuffle, repeat) = self.modes();
+        let playing = self.current.and_then(|i| self.playlist.get(i));
+        match self.navigator.next(&self.playlist, self.current, playing, shuffle, repeat == RepeatMode::All) {
+            Some(index) => self.play_index(index).map(|_| true),
+            None => {
+                self.stop();
+                Ok(false)
+            }
+        }
+    }
+
+    /// Restarts the current track, or goes back one if we're near its start
+    pub fn previous(&mut self) -> Result<()> {
+        // A trimmed track restarts at its start point, and counts from there
+        let start = self.current
+            .and_then(|index| self.playlist.get(index))
+            .and_then(|path| self.trims.get(path).start())
+            .unwrap_or_default();
+        let position = self.snapshot().map(|s| s.position).unwrap_or_default();
+        if position.saturating_sub(start) > RESTART_THRESHOLD {
+            return self.apply(PlayerCommand::Seek(start));
+        }
+
+        let (shuffle, repeat) = self.modes();
+        match self.navigator.previous(self.playlist.len(), self.current, shuffle, repeat == RepeatMode::All) {
+            Some(index) => self.play_index(index),
+            None => Ok(()),
+        }
+    }
+
+    /// Seeks relative to the current position, see `MusicPlayer::seek_relative`
+    pub fn seek_relative(&mut self, delta_secs: f64) -> Result<()> {
+        match self.player.lock() {
+            Ok(player) => player.seek_relative(delta_secs),
+            Err(_) => Err(anyhow::anyhow!("Failed to lock player")),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped = true;
+        if let Ok(player) = self.player.lock() {
+            player.stop();
+        }
+    }
+
+    /// Runs any control action. Playing a path that isn't queued adds it first.
+    pub fn apply(&mut self, command: PlayerCommand) -> Result<()> {
+        match command {
+            PlayerCommand::Next => self.next().map(|_| ()),
+            PlayerCommand::Previous => self.previous(),
+            PlayerCommand::Stop => {
+                self.stop();
+                Ok(())
+            },
+            PlayerCommand::Play(path) => {
+                let index = match self.playlist.iter().position(|p| *p == path) {
+                    Some(index) => index,
+                    None => {
+                        self.add(path);
+                        self.playlist.len() - 1
+                    }
+                };
+                self.play_index(index)
+            },
+            PlayerCommand::Resume | PlayerCommand::TogglePlayPause if self.is_stopped() => {
+                // Nothing loaded (fresh start or after Stop), so start the current track over
+                match self.current {
+                    Some(index) => self.play_index(index),
+                    None => self.next().map(|_| ()),
+                }
+            },
+            command => match self.player.lock() {
+                Ok(player) => player.apply_command(command),
+                Err(_) => Err(anyhow::anyhow!("Failed to lock player")),
+            },
+        }
+    }
+
+    /// Call regularly. Moves on to the next track when the current one finishes,
+    /// returning true if playback has run out.
+    pub fn tick(&mut self) -> Result<bool> {
+        if self.stopped {
+            return Ok(false);
+        }
+
+        let finished = if let Ok(player) = self.player.lock() {
+            // A station that couldn't be reached is reported, then skipped like a broken file
+            if let Some(Err(e)) = player.poll_stream() {
+                return Err(e);
+            }
+            if player.is_playing() {
+                self.listen_time.update(player.get_current_position(), Instant::now());
+            } else {
+                self.listen_time.pause();
+            }
+            player.check_if_song_finished()
+        } else {
+            false
+        };
+
+        if !finished {
+            return Ok(false);
+        }
+
+        match self.skip_guard.track_finished(self.listen_time.listened()) {
+            FinishKind::Played => {}
+            FinishKind::InstantSkip => {
+                if let Some(path) = self.current_path() {
+                    log::warn!("{} ended immediately, skipping it", path.display());
+                }
+                // Skip even when looping or repeating one track, otherwise we'd spin on it
+                return self.next().map(|more| !more);
+            }
+            FinishKind::TooManySkips => {
+                self.stop();
+                return Err(anyhow::anyhow!("Stopped after {} tracks in a row failed to play", MAX_INSTANT_SKIPS));
+            }
+        }
+
+        let ended_early = self.player.lock().is_ok_and(|player| player.ended_early());
+        if ended_early {
+            if let Some(path) = self.current_path() {
+                log::warn!("{} stopped partway through, the rest of it may be damaged", path.display());
+            }
+            // Looping or repeating would only hit the same error again
+            return self.next().map(|more| !more);
+        }
+
+        if self.loop_single && self.playlist.len() == 1 {
+            self.play_index(0)?;
+            return Ok(false);
+        }
+
+        let (shuffle, repeat) = self.modes();
+        let playing = self.current.and_then(|i| self.playlist.get(i));
+        match self.navigator.after_finished(&self.playlist, self.current, playing, shuffle, repeat) {
+            Some(index) => {
+                self.play_index(index)?;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        if let Ok(player) = self.player.lock() {
+            player.is_stopped()
+        } else {
+            false
+        }
+    }
+
+    fn modes(&self) -> (bool, RepeatMode) {
+        if let Ok(player) = self.player.lock() {
+            (player.get_shuffle(), player.get_repeat())
+        } else {
+            (false, RepeatMode::None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequential_navigation() {
+        assert_eq!(next_index(3, None, false), Some(0));
+        assert_eq!(next_index(3, Some(1), false), Some(2));
+        assert_eq!(next_index(3, Some(2), false), None);
+        assert_eq!(next_index(3, Some(2), true), Some(0));
+        assert_eq!(next_index(0, None, true), None);
+
+        assert_eq!(previous_index(3, Some(2), false), Some(1));
+        assert_eq!(previous_index(3, Some(0), false), Some(0));
+        assert_eq!(previous_index(3, Some(0), true), Some(2));
+    }
+
+    #[test]
+    fn test_repeat_modes() {
+        let after = |current, repeat| index_after_finished(3, Some(current), repeat, |wrap| next_index(3, Some(current), wrap));
+        assert_eq!(after(1, RepeatMode::One), Some(1));
+        assert_eq!(after(2, RepeatMode::All), Some(0));
+        assert_eq!(after(2, RepeatMode::None), None);
+    }
+
+    #[test]
+    fn test_shuffle_plays_every_track_once_per_cycle() {
+        let playlist: Vec<usize> = (0..10).collect();
+        let mut queue = ShuffleQueue::default();
+        let mut current = None;
+        for _cycle in 0..5 {
+            let mut played = Vec::new();
+            for _ in 0..playlist.len() {
+                let next = queue.next(&playlist, current.as_ref(), true).unwrap();
+                // Never the same track twice in a row, even across cycles
+                assert_ne!(Some(next), current);
+                played.push(next);
+                current = Some(next);
+            }
+            played.sort();
+            assert_eq!(played, playlist);
+        }
+    }
+
+    #[test]
+    fn test_shuffle_ends_without_wrap() {
+        let playlist = ["a", "b", "c"];
+        let mut queue = ShuffleQueue::default();
+        // "b" is already playing, so it counts towards this cycle
+        let mut current = Some(&playlist[1]);
+        let mut played = vec!["b"];
+        while let Some(next) = queue.next(&playlist, current, false) {
+            played.push(playlist[next]);
+            current = Some(&playlist[next]);
+        }
+        played.sort();
+        assert_eq!(played, playlist);
+        assert!(queue.has_next(&playlist, current, false));
+        assert_eq!(ShuffleQueue::default().next(&playlist[..1], Some(&"a"), false), None);
+        assert_eq!(ShuffleQueue::default().next(&playlist[..1], Some(&"a"), true), Some(0));
+    }
+
+    #[test]
+    fn test_shuffle_peek_matches_next() {
+        let playlist = ["a", "b", "c", "d"];
+        let mut queue = ShuffleQueue::default();
+        // Nothing is drawn until the first track is asked for
+        assert_eq!(queue.peek(&playlist), None);
+
+        let mut current = queue.next(&playlist, None, false).map(|i| &playlist[i]);
+        loop {
+            let peeked = queue.peek(&playlist);
+            let next = queue.next(&playlist, current, false);
+            assert_eq!(peeked, next);
+            let Some(next) = next else { break };
+            current = Some(&playlist[next]);
+        }
+    }
+
+    #[test]
+    fn test_shuffle_play_next_and_last() {
+        let playlist = ["a", "b", "c", "d"];
+        let mut queue = ShuffleQueue::default();
+        // Works before any order has been drawn
+        queue.play_next("c");
+        assert_eq!(queue.next(&playlist, None, false), Some(2));
+
+        let current = Some(&playlist[2]);
+        let first = queue.next(&playlist, current, false).unwrap();
+        let rest: Vec<&str> = playlist.iter().copied().filter(|&p| p != "c" && p != playlist[first]).collect();
+        queue.play_last(rest[0]);
+        queue.play_next(rest[1]);
+        assert_eq!(queue.next(&playlist, current, false), Some(playlist.iter().position(|&p| p == rest[1]).unwrap()));
+        assert_eq!(queue.next(&playlist, current, false), Some(playlist.iter().position(|&p| p == rest[0]).unwrap()));
+        assert_eq!(queue.next(&playlist, current, false), None);
+    }
+
+    #[test]
+    fn test_navigator_previous_retraces_next() {
+        let playlist: Vec<PathBuf> = ["a", "b", "c", "d"].iter().map(PathBuf::from).collect();
+        let mut navigator = Navigator::default();
+        let mut current = None;
+        let mut visited = Vec::new();
+        while let Some(next) = navigator.next(&playlist, current, current.map(|i| &playlist[i]), true, false) {
+            visited.push(next);
+            current = Some(next);
+        }
+
+        // Going back walks the shuffle in reverse, not playlist order
+        for &expected in visited.iter().rev().skip(1) {
+            let previous = navigator.previous(playlist.len(), current, true, false);
+            assert_eq!(previous, Some(expected));
+            current = previous;
+        }
+        assert_eq!(navigator.previous(playlist.len(), current, true, false), None);
+
+        // Entries that were removed since are passed over
+        navigator.history = vec![1, 7];
+        assert_eq!(navigator.previous(playlist.len(), Some(0), true, false), Some(1));
+        assert_eq!(navigator.previous(playlist.len(), Some(2), false, false), Some(1));
+    }
+
+    #[test]
+    fn test_first_unplayed() {
+        let playlist = ["a", "b", "c"];
+        let mut played = HashSet::new();
+        assert_eq!(first_unplayed(&playlist, &played), Some(0));
+
+        played.insert("a");
+        played.insert("c");
+        assert_eq!(first_unplayed(&playlist, &played), Some(1));
+
+        played.insert("b");
+        assert_eq!(first_unplayed(&playlist, &played), None);
+    }
+
+    #[test]
+    fn test_relative_seek_target() {
+        let position = Duration::from_secs(10);
+        let duration = Some(Duration::from_secs(60));
+
+        assert_eq!(relative_seek_target(position, 5.0, duration), SeekTarget::To(Duration::from_secs(15)));
+        assert_eq!(relative_seek_target(position, -5.0, duration), SeekTarget::To(Duration::from_secs(5)));
+        // Before the start rewinds, past the end finishes the track
+        assert_eq!(relative_seek_target(position, -30.0, duration), SeekTarget::To(Duration::ZERO));
+        assert_eq!(relative_seek_target(position, 50.0, duration), SeekTarget::End);
+        // Without a known length there's no end to run into
+        assert_eq!(relative_seek_target(position, 500.0, None), SeekTarget::To(Duration::from_secs(510)));
+    }
+
+    #[test]
+    fn test_index_after_move() {
+        // Moving entry 1 down to 3: [a, b, c, d, e] -> [a, c, d, b, e]
+        let moved: Vec<usize> = (0..5).map(|i| index_after_move(i, 1, 3)).collect();
+        assert_eq!(moved, vec![0, 3, 1, 2, 4]);
+
+        // Moving entry 3 up to 0: [a, b, c, d] -> [d, a, b, c]
+        let moved: Vec<usize> = (0..4).map(|i| index_after_move(i, 3, 0)).collect();
+        assert_eq!(moved, vec![1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_type_ahead_selection() {
+        let names = ["Intro", "Alpha", "Beta", "Another", "Almost"];
+        let matches = |prefix: &'static str| move |index: usize| names[index].to_lowercase().starts_with(prefix);
+        let rows: Vec<usize> = (0..names.len()).collect();
+
+        // A first letter looks past the selected row
+        assert_eq!(type_ahead_prefix("a"), ("a", true));
+        assert_eq!(type_ahead_index(&rows, None, true, matches("a")), Some(1));
+        assert_eq!(type_ahead_index(&rows, Some(1), true, matches("a")), Some(3));
+
+        // More letters keep the row while it still matches
+        assert_eq!(type_ahead_prefix("al"), ("al", false));
+        assert_eq!(type_ahead_index(&rows, Some(1), false, matches("al")), Some(1));
+        assert_eq!(type_ahead_index(&rows, Some(1), false, matches("alm")), Some(4));
+
+        // The same letter again steps on, wrapping round
+        assert_eq!(type_ahead_prefix("aaa"), ("a", true));
+        assert_eq!(type_ahead_index(&rows, Some(4), true, matches("a")), Some(1));
+
+        // Only the rows shown are considered
+        assert_eq!(type_ahead_index(&[0, 2], Some(0), true, matches("a")), None);
+        assert_eq!(type_ahead_index(&[], None, true, matches("a")), None);
+    }
+
+    #[test]
+    fn test_instant_skips_are_capped() {
+        let mut guard = InstantSkipGuard::default();
+        for _ in 1..MAX_INSTANT_SKIPS {
+            assert_eq!(guard.track_finished(Duration::from_millis(5)), FinishKind::InstantSkip);
+        }
+        assert_eq!(guard.track_finished(Duration::from_millis(5)), FinishKind::TooManySkips);
+
+        // A real play resets the count
+        guard.track_finished(Duration::from_millis(5));
+        assert_eq!(guard.track_finished(Duration::from_secs(200)), FinishKind::Played);
+        assert_eq!(guard.track_finished(Duration::from_millis(5)), FinishKind::InstantSkip);
+    }
+
+    #[test]
+    fn test_track_that_failed_to_open_is_skipped() {
+        let mut guard = InstantSkipGuard::default();
+        guard.track_started(&Err::<(), _>(anyhow::anyhow!("Unsupported format")));
+        // However late the finish is noticed
+        assert_eq!(guard.track_finished(Duration::from_secs(1)), FinishKind::InstantSkip);
+
+        guard.track_started(&Ok(()));
+        assert_eq!(guard.track_finished(Duration::from_secs(1)), FinishKind::Played);
+    }
+}
@@ -0,0 +1,73 @@
+use eframe::egui::{self, ColorImage, TextureHandle, TextureOptions};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use crate::metadata::read_embedded_art;
+use crate::playlist::cue_track_for;
+use crate::utils::find_folder_cover;
+
+/// Covers are shrunk to fit this many pixels on a side, plenty for the small display
+/// and it keeps big embedded scans from taking up texture memory
+const DECODE_SIZE: u32 = 256;
+
+/// The cover for a track: the art embedded in its tags, or failing that a cover image in
+/// its folder (cover.jpg, folder.png...). `None` when there's neither, or it won't decode.
+pub fn load_cover_image(track: &Path) -> Option<ColorImage> {
+    // A cue sheet track's cover is the one of the file it's part of
+    let cue_file = cue_track_for(track).map(|cue| cue.file);
+    let track = cue_file.as_deref().unwrap_or(track);
+    
+    let bytes = read_embedded_art(track)
+        .or_else(|| find_folder_cover(track).and_then(|cover| std::fs::read(cover).ok()))?;
+
+    let image = match image::load_from_memory(&bytes) {
+        Ok(image) => image.thumbnail(DECODE_SIZE, DECODE_SIZE).to_rgba8(),
+        Err(e) => {
+            log::debug!("Could not decode the cover for {}: {}", track.display(), e);
+            return None;
+        }
+    };
+    let size = [image.width() as usize, image.height() as usize];
+    Some(ColorImage::from_rgba_unmultiplied(size, image.as_raw()))
+}
+
+/// Cover art for the current track, loaded on a background thread and kept as a texture
+/// until the track changes
+#[derive(Default)]
+pub struct CoverArt {
+    track: Option<PathBuf>,
+    texture: Option<TextureHandle>,
+    loading: Option<Receiver<Option<ColorImage>>>,
+}
+
+impl CoverArt {
+    /// Switches to `track`'s cover, dropping the old texture, and picks up a cover that has
+    /// finished loading. Call this every frame.
+    pub fn update(&mut self, ctx: &egui::Context, track: Option<&Path>) {
+        if self.track.as_deref() != track {
+            self.track = track.map(Path::to_path_buf);
+            self.texture = None;
+            self.loading = track.map(|track| {
+                let (tx, rx) = channel();
+                let track = track.to_path_buf();
+                let ctx = ctx.clone();
+                thread::spawn(move || {
+                    let _ = tx.send(load_cover_image(&track));
+                    ctx.request_repaint();
+                });
+                rx
+            });
+        }
+
+        let loaded = self.loading.as_ref().and_then(|rx| rx.try_recv().ok());
+        if let Some(image) = loaded {
+            self.loading = None;
+            self.texture = image.map(|image| ctx.load_texture("cover-art", image, TextureOptions::LINEAR));
+        }
+    }
+
+    /// The current track's cover, `None` while it loads or when there isn't one
+    pub fn texture(&self) -> Option<&TextureHandle> {
+        self.texture.as_ref()
+    }
+}
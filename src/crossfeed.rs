@@ -0,0 +1,180 @@
+use rodio::{Source, source::SeekError};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Crossfed signal is low-passed at this frequency, roughly where head shadowing starts
+const CUTOFF_HZ: f32 = 700.0;
+/// Interaural delay applied to the crossfed signal
+const DELAY_US: u32 = 300;
+/// Re-read the settings every this many frames
+const SETTINGS_CHECK_FRAMES: usize = 1024;
+/// Crossfeed gain at full strength (about -4.5dB)
+const MAX_GAIN: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrossfeedSettings {
+    pub enabled: bool,
+    /// 0.0 (none) to 1.0 (strongest)
+    pub strength: f32,
+}
+
+impl Default for CrossfeedSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strength: 0.5,
+        }
+    }
+}
+
+/// Headphone crossfeed: each channel gets a low-passed, slightly delayed copy of the other,
+/// roughly what happens acoustically when listening to speakers.
+///
+/// Only the crossfed part is delayed (about 0.3ms), the direct signal goes straight through,
+/// so the adapter adds no latency to what you hear. Non-stereo sources are passed through.
+pub struct Crossfeed<S> {
+    inner: S,
+    settings: Arc<Mutex<CrossfeedSettings>>,
+    current: CrossfeedSettings,
+    settings_countdown: usize,
+    coefficient: f32,
+    low_passed: [f32; 2],
+    delayed: [VecDeque<f32>; 2],
+    pending_right: Option<f32>,
+}
+
+impl<S> Crossfeed<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, settings: Arc<Mutex<CrossfeedSettings>>) -> Self {
+        let sample_rate = inner.sample_rate().max(1);
+        let coefficient = 1.0 - (-2.0 * std::f32::consts::PI * CUTOFF_HZ / sample_rate as f32).exp();
+        let delay_frames = (sample_rate as u64 * DELAY_US as u64 / 1_000_000).max(1) as usize;
+
+        Self {
+            inner,
+            settings,
+            current: CrossfeedSettings::default(),
+            settings_countdown: 0,
+            coefficient,
+            low_passed: [0.0; 2],
+            delayed: [VecDeque::from(vec![0.0; delay_frames]), VecDeque::from(vec![0.0; delay_frames])],
+            pending_right: None,
+        }
+    }
+
+    fn read_settings(&mut self) {
+        if let Ok(settings) = self.settings.lock() {
+            self.current = *settings;
+        }
+    }
+
+    // Mix one stereo frame, returning the new (left, right)
+    fn process_frame(&mut self, left: f32, right: f32) -> (f32, f32) {
+        // Keep the filters and delay lines running even when disabled so toggling doesn't click
+        let mut bled = [0.0; 2];
+        for (channel, sample) in [left, right].into_iter().enumerate() {
+            self.low_passed[channel] += self.coefficient * (sample - self.low_passed[channel]);
+            self.delayed[channel].push_back(self.low_passed[channel]);
+            bled[channel] = self.delayed[channel].pop_front().unwrap_or(0.0);
+        }
+
+        if !self.current.enabled {
+            return (left, right);
+        }
+
+        // Normalise so a centred (mono) signal keeps roughly the same level
+        let gain = self.current.strength.clamp(0.0, 1.0) * MAX_GAIN;
+        let scale = 1.0 / (1.0 + gain);
+        ((left + gain * bled[1]) * scale, (right + gain * bled[0]) * scale)
+    }
+}
+
+impl<S> Iterator for Crossfeed<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.inner.channels() != 2 {
+            return self.inner.next();
+        }
+
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+
+        let left = self.inner.next()?;
+        let right = self.inner.next().unwrap_or(0.0);
+
+        if self.settings_countdown == 0 {
+            self.read_settings();
+            self.settings_countdown = SETTINGS_CHECK_FRAMES;
+        }
+        self.settings_countdown -= 1;
+
+        let (left, right) = self.process_frame(left, right);
+        self.pending_right = Some(right);
+        Some(left)
+    }
+}
+
+impl<S> Source for Crossfeed<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.pending_right = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn left_only(frames: usize) -> SamplesBuffer<f32> {
+        let data: Vec<f32> = (0..frames).flat_map(|_| [0.5, 0.0]).collect();
+        SamplesBuffer::new(2, 44100, data)
+    }
+
+    #[test]
+    fn test_disabled_passes_samples_through() {
+        let expected: Vec<f32> = left_only(2000).collect();
+        let settings = Arc::new(Mutex::new(CrossfeedSettings::default()));
+        let output: Vec<f32> = Crossfeed::new(left_only(2000), settings).collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_hard_panned_signal_bleeds_into_other_channel() {
+        let settings = Arc::new(Mutex::new(CrossfeedSettings { enabled: true, strength: 1.0 }));
+        let output: Vec<f32> = Crossfeed::new(left_only(2000), settings).collect();
+
+        assert_eq!(output.len(), 4000);
+        let (left, right) = (output[3998], output[3999]);
+        assert!(right > 0.1, "right channel should pick up the left, got {}", right);
+        assert!(left > right, "direct signal should stay louder than the crossfeed");
+    }
+}
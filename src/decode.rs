@@ -0,0 +1,101 @@
+use anyhow::Result;
+use rodio::decoder::DecoderError;
+use rodio::Decoder;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Why a file couldn't be decoded, worded for the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeFailure {
+    /// Not audio at all, or a format this build has no decoder for
+    Unsupported(String),
+    /// A format we can play, but the data is damaged or cut short
+    Corrupt(String),
+}
+
+impl fmt::Display for DecodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeFailure::Unsupported(reason) => write!(f, "Unsupported format: {}", reason),
+            DecodeFailure::Corrupt(reason) => write!(f, "File appears corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DecodeFailure {}
+
+/// Opens a file for playback. Decoder errors come back as a `DecodeFailure`
+/// (reachable with `downcast_ref`) rather than rodio's terse messages.
+pub fn open_decoder(path: &Path) -> Result<Decoder<BufReader<File>>> {
+    let file = File::open(path)?;
+    Decoder::new(BufReader::new(file)).map_err(|e| diagnose(path, &e).into())
+}
+
+// Containers and codecs we can recognise from their first bytes
+pub(crate) enum Signature {
+    /// One of the formats rodio decodes
+    Playable(&'static str),
+    /// Audio, but not something this build can decode
+    Unplayable(&'static str),
+}
+
+/// Works out whether a decode failure means the file is damaged or just not something we can
+/// play, by looking at the file's header. The decoder can't tell us: it reports a cut-short
+/// WAV and a text file the same way.
+pub fn diagnose(path: &Path, error: &DecoderError) -> DecodeFailure {
+    let extension = path.extension()
+        .map(|e| format!(".{}", e.to_string_lossy().to_lowercase()))
+        .unwrap_or_else(|| "this file".to_string());
+
+    match sniff(path) {
+        Some(Signature::Playable(format)) => DecodeFailure::Corrupt(format!("{} data could not be read ({})", format, error)),
+        Some(Signature::Unplayable(hint)) => DecodeFailure::Unsupported(format!("{} {}", extension, hint)),
+        None if matches!(error, DecoderError::UnrecognizedFormat) => {
+            DecodeFailure::Unsupported(format!(
+                "{} is not a recognised audio format, or uses a codec this build was compiled without",
+                extension,
+            ))
+        }
+        None => DecodeFailure::Corrupt(error.to_string()),
+    }
+}
+
+pub(crate) fn sniff(path: &Path) -> Option<Signature> {
+    let mut header = [0u8; 64];
+    let mut file = File::open(path).ok()?;
+    let len = file.read(&mut header).ok()?;
+    let header = &header[..len];
+
+    if header.len() < 4 {
+        return None;
+    }
+
+    let signature = if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE") {
+        Signature::Playable("WAV")
+    } else if header.starts_with(b"fLaC") {
+        Signature::Playable("FLAC")
+    } else if header.starts_with(b"OggS") {
+        // Ogg is only a container, the codec is named in the first page
+        if header.windows(8).any(|w| w == b"OpusHead") {
+            Signature::Unplayable("is Opus audio, which rodio has no decoder for")
+        } else {
+            Signature::Playable("Ogg Vorbis")
+        }
+    } else if header.starts_with(b"ID3") {
+        Signature::Playable("MP3")
+    } else if header[0] == 0xFF && header[1] & 0xF6 == 0xF0 {
+        // ADTS sync word, checked before MP3 since both start with 0xFFF
+        Signature::Playable("AAC")
+    } else if header[0] == 0xFF && header[1] & 0xE0 == 0xE0 {
+        Signature::Playable("MP3")
+    } else if header.get(4..8) == Some(b"ftyp") {
+        Signature::Playable("M4A")
+    } else if header.starts_with(&[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+        Signature::Unplayable("is Windows Media Audio, which none of the available decoders support")
+    } else {
+        return None;
+    };
+    Some(signature)
+}
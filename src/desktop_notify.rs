@@ -0,0 +1,31 @@
+use notify_rust::Notification;
+use std::path::{Path, PathBuf};
+use std::thread;
+use crate::metadata::{TrackMetadata, display_name, read_metadata};
+use crate::utils::find_folder_cover;
+
+/// Shows a native "Now playing" notification with the track's cover, if there is one.
+/// Runs on its own thread since some notification servers are slow to answer.
+/// Does nothing on systems without a notification service.
+pub fn notify_now_playing(path: &Path, metadata: Option<TrackMetadata>) {
+    let path: PathBuf = path.to_path_buf();
+
+    thread::spawn(move || {
+        // Not in the cache yet, read the tags here rather than waiting for it
+        let metadata = metadata.unwrap_or_else(|| read_metadata(&path));
+
+        let mut notification = Notification::new();
+        notification
+            .summary("Now playing")
+            .body(&display_name(&path, Some(&metadata)))
+            .appname("Music Player");
+
+        if let Some(cover) = find_folder_cover(&path) {
+            notification.icon(&cover.to_string_lossy());
+        }
+
+        if let Err(e) = notification.show() {
+            log::debug!("Desktop notification not shown: {}", e);
+        }
+    });
+}
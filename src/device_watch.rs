@@ -0,0 +1,83 @@
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// How often the output devices are checked. cpal has no change notifications,
+/// so we poll, which is cheap at this rate.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The system's default output device changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputChange {
+    /// Name of the device we were on, if there was one
+    pub previous: Option<String>,
+    /// Name of the new default device, `None` if there's no output left at all
+    pub current: Option<String>,
+    /// The previous device is gone (headphones unplugged), rather than just no longer the default
+    pub previous_removed: bool,
+}
+
+/// Name of the system's default output device
+pub fn default_output_name() -> Option<String> {
+    rodio::cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+fn output_names() -> Vec<String> {
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            log::debug!("Could not list output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Watches for the default output device changing or disappearing on a background thread
+pub struct OutputDeviceWatcher {
+    changes: Receiver<OutputChange>,
+}
+
+impl OutputDeviceWatcher {
+    pub fn start() -> Self {
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let mut previous = default_output_name();
+            loop {
+                thread::sleep(POLL_INTERVAL);
+
+                let current = default_output_name();
+                if current == previous {
+                    continue;
+                }
+
+                let previous_removed = previous.as_ref().is_some_and(|name| !output_names().contains(name));
+                let change = OutputChange {
+                    previous: previous.clone(),
+                    current: current.clone(),
+                    previous_removed,
+                };
+                previous = current;
+
+                log::info!("Default output device changed: {:?}", change);
+                if tx.send(change).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { changes: rx }
+    }
+
+    /// Everything that changed since the last call, merged into one change. Call this regularly.
+    pub fn poll(&self) -> Option<OutputChange> {
+        self.changes.try_iter().reduce(|first, latest| OutputChange {
+            previous: first.previous,
+            current: latest.current,
+            previous_removed: first.previous_removed || latest.previous_removed,
+        })
+    }
+}
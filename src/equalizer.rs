@@ -0,0 +1,251 @@
+use rodio::{Source, source::SeekError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Centre frequency of each band in Hz, lowest first
+pub const EQ_BANDS: [f32; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+/// Furthest a band can be boosted or cut, in dB
+pub const MAX_GAIN_DB: f32 = 12.0;
+/// Width of each band. About one octave, so neighbouring bands blend smoothly.
+const BAND_Q: f32 = 1.41;
+/// Re-read the gains every this many frames
+const SETTINGS_CHECK_FRAMES: usize = 1024;
+
+/// Presets offered next to the sliders, as gains in dB for each band
+pub const BUILTIN_PRESETS: [(&str, [f32; 10]); 3] = [
+    ("Flat", [0.0; 10]),
+    ("Bass Boost", [6.0, 5.0, 4.0, 2.5, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("Vocal", [-2.0, -2.0, -1.0, 0.5, 2.0, 3.5, 3.5, 2.0, 0.0, -1.0]),
+];
+
+/// The gains for every band, padded or cut to `EQ_BANDS` and limited to `MAX_GAIN_DB`
+pub fn normalize_gains(gains: &[f32]) -> Vec<f32> {
+    (0..EQ_BANDS.len())
+        .map(|band| gains.get(band).copied().filter(|g| g.is_finite()).unwrap_or(0.0))
+        .map(|gain| gain.clamp(-MAX_GAIN_DB, MAX_GAIN_DB))
+        .collect()
+}
+
+// A peaking filter from the Audio EQ Cookbook, normalised so a0 is 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn peaking(frequency: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * std::f32::consts::PI * frequency / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * BAND_Q);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha / a;
+
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * cos_w0 / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+}
+
+/// Multi-band graphic equalizer: a chain of peaking filters, one per band in `EQ_BANDS`.
+///
+/// Boosting a band would push loud tracks into clipping, so the whole signal is turned down
+/// by the largest boost first. Flat settings pass samples straight through.
+pub struct Equalizer<S> {
+    inner: S,
+    gains: Arc<Mutex<Vec<f32>>>,
+    current: Vec<f32>,
+    settings_countdown: usize,
+    filters: Vec<Coefficients>,
+    preamp: f32,
+    // Two samples of filter state per band, per channel
+    state: Vec<[[f32; 2]; EQ_BANDS.len()]>,
+    channel: usize,
+}
+
+impl<S> Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, gains: Arc<Mutex<Vec<f32>>>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        Self {
+            inner,
+            gains,
+            current: vec![0.0; EQ_BANDS.len()],
+            settings_countdown: 0,
+            filters: Vec::new(),
+            preamp: 1.0,
+            state: vec![[[0.0; 2]; EQ_BANDS.len()]; channels],
+            channel: 0,
+        }
+    }
+
+    fn read_settings(&mut self) {
+        let gains = match self.gains.lock() {
+            Ok(gains) => normalize_gains(&gains),
+            Err(_) => return,
+        };
+        if gains == self.current && !self.filters.is_empty() {
+            return;
+        }
+
+        // Bands at or near Nyquist can't be filtered at this sample rate
+        let sample_rate = self.inner.sample_rate().max(1);
+        let nyquist = sample_rate as f32 / 2.0;
+        self.filters = EQ_BANDS.iter().zip(&gains)
+            .map(|(&frequency, &gain)| {
+                let gain = if frequency < nyquist * 0.9 { gain } else { 0.0 };
+                Coefficients::peaking(frequency, gain, sample_rate)
+            })
+            .collect();
+        let boost = gains.iter().copied().fold(0.0, f32::max);
+        self.preamp = 10f32.powf(-boost / 20.0);
+        self.current = gains;
+    }
+
+    fn is_flat(&self) -> bool {
+        self.current.iter().all(|gain| *gain == 0.0)
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let channels = self.inner.channels().max(1) as usize;
+        if self.state.len() != channels {
+            self.state = vec![[[0.0; 2]; EQ_BANDS.len()]; channels];
+            self.channel = 0;
+        }
+        let state = &mut self.state[self.channel % channels];
+        self.channel = (self.channel + 1) % channels;
+
+        // Transposed direct form II, band after band
+        let mut value = sample * self.preamp;
+        for (filter, z) in self.filters.iter().zip(state.iter_mut()) {
+            let output = filter.b0 * value + z[0];
+            z[0] = filter.b1 * value - filter.a1 * output + z[1];
+            z[1] = filter.b2 * value - filter.a2 * output;
+            value = output;
+        }
+        value
+    }
+}
+
+impl<S> Iterator for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+
+        // Only check between frames, so the channel count stays lined up
+        if self.channel == 0 {
+            if self.settings_countdown == 0 {
+                let was_flat = self.is_flat();
+                self.read_settings();
+                // Start from silence rather than whatever was left from before
+                if was_flat && !self.is_flat() {
+                    self.state.iter_mut().for_each(|channel| *channel = [[0.0; 2]; EQ_BANDS.len()]);
+                }
+                self.settings_countdown = SETTINGS_CHECK_FRAMES;
+            }
+            self.settings_countdown -= 1;
+        }
+
+        if self.is_flat() {
+            self.channel = (self.channel + 1) % self.inner.channels().max(1) as usize;
+            return Some(sample);
+        }
+        Some(self.process(sample))
+    }
+}
+
+impl<S> Source for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.state.iter_mut().for_each(|channel| *channel = [[0.0; 2]; EQ_BANDS.len()]);
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(frequency: f32) -> SamplesBuffer<f32> {
+        let data: Vec<f32> = (0..SAMPLE_RATE / 2)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        SamplesBuffer::new(1, SAMPLE_RATE, data)
+    }
+
+    // Level of the second half, once the filters have settled
+    fn rms(samples: &[f32]) -> f32 {
+        let tail = &samples[samples.len() / 2..];
+        (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+
+    fn equalized(frequency: f32, gains: &[f32]) -> f32 {
+        let gains = Arc::new(Mutex::new(gains.to_vec()));
+        let output: Vec<f32> = Equalizer::new(sine(frequency), gains).collect();
+        rms(&output) / rms(&sine(frequency).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_flat_passes_samples_through() {
+        let expected: Vec<f32> = sine(440.0).collect();
+        let gains = Arc::new(Mutex::new(vec![0.0; EQ_BANDS.len()]));
+        let output: Vec<f32> = Equalizer::new(sine(440.0), gains).collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_boosted_band_stands_out() {
+        let mut gains = vec![0.0; EQ_BANDS.len()];
+        gains[5] = 6.0; // 1kHz
+
+        // The boost and the headroom made for it cancel out in the boosted band...
+        let boosted = equalized(1000.0, &gains);
+        assert!((boosted - 1.0).abs() < 0.1, "1kHz level was {}", boosted);
+        // ...so the rest of the spectrum ends up about 6dB down
+        let elsewhere = equalized(8000.0, &gains);
+        assert!(elsewhere < 0.6, "8kHz level was {}", elsewhere);
+    }
+
+    #[test]
+    fn test_gains_are_normalized() {
+        let gains = normalize_gains(&[30.0, -30.0, f32::NAN]);
+        assert_eq!(gains.len(), EQ_BANDS.len());
+        assert_eq!(&gains[..4], &[MAX_GAIN_DB, -MAX_GAIN_DB, 0.0, 0.0]);
+    }
+}
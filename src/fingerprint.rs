@@ -0,0 +1,277 @@
+use anyhow::Result;
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+use crate::cache::LruCache;
+
+/// Only the start of each track is analysed, which is plenty to tell songs apart
+const ANALYSIS_SECS: u32 = 60;
+/// Each fingerprint frame covers this fraction of a second
+const FRAMES_PER_SEC: u32 = 10;
+/// Bits produced per frame (3 band-energy deltas + 2 band-difference deltas)
+const BITS_PER_FRAME: u32 = 5;
+/// How many frames either side we try when lining two fingerprints up
+const MAX_OFFSET: isize = 5;
+/// Fraction of matching bits above which two tracks are considered the same recording
+pub const DUPLICATE_THRESHOLD: f32 = 0.85;
+
+/// A compact description of how a track's loudness moves over time in three frequency bands.
+///
+/// This is much cruder than chromaprint, but it survives re-encoding to other formats and
+/// bitrates, which is what we need to spot the same song stored twice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    frames: Vec<u8>,
+}
+
+impl Fingerprint {
+    /// Builds a fingerprint from interleaved samples
+    pub fn from_samples<I>(samples: I, channels: u16, sample_rate: u32) -> Self
+    where
+        I: Iterator<Item = f32>,
+    {
+        let channels = channels.max(1) as usize;
+        let sample_rate = sample_rate.max(1);
+        let frame_len = (sample_rate / FRAMES_PER_SEC).max(1) as usize;
+        let max_frames = (ANALYSIS_SECS * FRAMES_PER_SEC) as usize;
+
+        // One-pole low-passes split the signal into low (<300Hz), mid and high (>3kHz) bands
+        let coefficient = |cutoff: f32| 1.0 - (-2.0 * std::f32::consts::PI * cutoff / sample_rate as f32).exp();
+        let (low_coef, high_coef) = (coefficient(300.0), coefficient(3000.0));
+        let (mut low, mut mid_low) = (0.0f32, 0.0f32);
+
+        let mut energies: Vec<[f32; 3]> = Vec::new();
+        let mut current = [0.0f32; 3];
+        let mut frame_pos = 0;
+        let mut mono_sum = 0.0;
+        let mut channel = 0;
+
+        for sample in samples {
+            mono_sum += sample;
+            channel += 1;
+            if channel < channels {
+                continue;
+            }
+            let mono = mono_sum / channels as f32;
+            mono_sum = 0.0;
+            channel = 0;
+
+            low += low_coef * (mono - low);
+            mid_low += high_coef * (mono - mid_low);
+            let bands = [low, mid_low - low, mono - mid_low];
+            for (energy, band) in current.iter_mut().zip(bands) {
+                *energy += band * band;
+            }
+
+            frame_pos += 1;
+            if frame_pos == frame_len {
+                energies.push(current);
+                current = [0.0; 3];
+                frame_pos = 0;
+                if energies.len() > max_frames {
+                    break;
+                }
+            }
+        }
+
+        // Each bit records whether something got louder since the previous frame,
+        // which doesn't depend on the absolute level or the codec
+        let frames = energies.windows(2)
+            .map(|pair| {
+                let (prev, cur) = (pair[0], pair[1]);
+                let mut bits = 0u8;
+                for (band, (cur_energy, prev_energy)) in cur.iter().zip(prev.iter()).enumerate() {
+                    if cur_energy > prev_energy {
+                        bits |= 1 << band;
+                    }
+                }
+                for band in 0..2 {
+                    if cur[band] - cur[band + 1] > prev[band] - prev[band + 1] {
+                        bits |= 1 << (3 + band);
+                    }
+                }
+                bits
+            })
+            .collect();
+
+        Self { frames }
+    }
+
+    /// Fraction of matching bits (0.0 to 1.0) at the best alignment of the two fingerprints
+    pub fn similarity(&self, other: &Fingerprint) -> f32 {
+        let mut best = 0.0f32;
+
+        for offset in -MAX_OFFSET..=MAX_OFFSET {
+            let (a, b) = if offset >= 0 {
+                (&self.frames[(offset as usize).min(self.frames.len())..], &other.frames[..])
+            } else {
+                (&self.frames[..], &other.frames[((-offset) as usize).min(other.frames.len())..])
+            };
+
+            let overlap = a.len().min(b.len());
+            // Too short to say anything meaningful
+            if overlap < FRAMES_PER_SEC as usize {
+                continue;
+            }
+
+            let differing: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+            let total = overlap as u32 * BITS_PER_FRAME;
+            best = best.max(1.0 - differing as f32 / total as f32);
+        }
+
+        best
+    }
+}
+
+/// Decodes the start of a file and fingerprints it
+pub fn compute_fingerprint(path: &Path) -> Result<Fingerprint> {
+    let file = File::open(path)?;
+    let source = Decoder::new(BufReader::new(file))?;
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+    Ok(Fingerprint::from_samples(source.convert_samples::<f32>(), channels, sample_rate))
+}
+
+/// Groups paths whose fingerprints are similar enough to be the same recording.
+/// Only groups with more than one entry are returned.
+pub fn group_duplicates(fingerprints: &[(PathBuf, Fingerprint)], threshold: f32) -> Vec<Vec<PathBuf>> {
+    let mut grouped = vec![false; fingerprints.len()];
+    let mut groups = Vec::new();
+
+    for i in 0..fingerprints.len() {
+        if grouped[i] {
+            continue;
+        }
+
+        let mut group = vec![fingerprints[i].0.clone()];
+        for j in (i + 1)..fingerprints.len() {
+            if !grouped[j] && fingerprints[i].1.similarity(&fingerprints[j].1) >= threshold {
+                grouped[j] = true;
+                group.push(fingerprints[j].0.clone());
+            }
+        }
+
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+
+    groups
+}
+
+/// Fingerprints keyed by path, invalidated when the file's modification time changes
+pub struct FingerprintCache {
+    entries: LruCache<PathBuf, (SystemTime, Fingerprint)>,
+}
+
+impl FingerprintCache {
+    /// Creates a cache holding at most `capacity` fingerprints
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.entries.set_capacity(capacity);
+    }
+
+    pub fn get_or_compute(&mut self, path: &Path) -> Result<Fingerprint> {
+        let modified = std::fs::metadata(path)?.modified()?;
+
+        if let Some((cached_modified, fingerprint)) = self.entries.get(&path.to_path_buf()) {
+            if *cached_modified == modified {
+                return Ok(fingerprint.clone());
+            }
+        }
+
+        let fingerprint = compute_fingerprint(path)?;
+        self.entries.insert(path.to_path_buf(), (modified, fingerprint.clone()));
+        Ok(fingerprint)
+    }
+}
+
+/// A duplicate scan running on a background thread
+pub struct DuplicateScan {
+    result: Receiver<Vec<Vec<PathBuf>>>,
+}
+
+impl DuplicateScan {
+    /// Starts fingerprinting `paths` off the UI thread. Files that can't be decoded are skipped.
+    pub fn start(paths: Vec<PathBuf>, cache: Arc<Mutex<FingerprintCache>>) -> Self {
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let mut unique = paths;
+            unique.sort();
+            unique.dedup();
+
+            let mut fingerprints = Vec::new();
+            for path in unique {
+                let fingerprint = if let Ok(mut cache) = cache.lock() {
+                    cache.get_or_compute(&path)
+                } else {
+                    compute_fingerprint(&path)
+                };
+
+                match fingerprint {
+                    Ok(fingerprint) => fingerprints.push((path, fingerprint)),
+                    Err(e) => log::warn!("Could not fingerprint {}: {}", path.display(), e),
+                }
+            }
+
+            let _ = tx.send(group_duplicates(&fingerprints, DUPLICATE_THRESHOLD));
+        });
+
+        Self { result: rx }
+    }
+
+    /// Returns the duplicate groups once the scan has finished
+    pub fn poll(&self) -> Option<Vec<Vec<PathBuf>>> {
+        self.result.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A few seconds of a tone whose loudness wobbles in a song-like, non-periodic way
+    fn test_signal(seed: u32, gain: f32) -> Vec<f32> {
+        let sample_rate = 8000;
+        (0..sample_rate * 5)
+            .map(|n| {
+                let t = n as f32 / sample_rate as f32;
+                let envelope = ((t * (1.3 + seed as f32)).sin() * (t * 0.7 * seed as f32).cos()).abs();
+                gain * envelope * (t * 220.0 * seed as f32 * std::f32::consts::TAU).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_audio_at_different_levels_matches() {
+        let a = Fingerprint::from_samples(test_signal(1, 1.0).into_iter(), 1, 8000);
+        let b = Fingerprint::from_samples(test_signal(1, 0.4).into_iter(), 1, 8000);
+        assert!(a.similarity(&b) > DUPLICATE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let a = Fingerprint::from_samples(test_signal(1, 1.0).into_iter(), 1, 8000);
+        let b = Fingerprint::from_samples(test_signal(1, 0.5).into_iter(), 1, 8000);
+        let c = Fingerprint::from_samples(test_signal(3, 1.0).into_iter(), 1, 8000);
+
+        let groups = group_duplicates(&[
+            (PathBuf::from("a.flac"), a),
+            (PathBuf::from("c.mp3"), c),
+            (PathBuf::from("a.mp3"), b),
+        ], 0.99);
+
+        assert_eq!(groups, vec![vec![PathBuf::from("a.flac"), PathBuf::from("a.mp3")]]);
+    }
+}
@@ -1,12 +1,107 @@
 use anyhow::Result;
 use eframe::{ egui, egui::ViewportBuilder, NativeOptions };
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use crate::player::MusicPlayer;
-use crate::utils::{ is_audio_file, get_supported_extensions};
-use rand::{ rng, Rng };
-use crate::config::{Config, load_config, save_config};
+use crate::player::{MAX_SPEED, MIN_SPEED, MusicPlayer, PlayerCommand, RepeatMode, SeekAccuracy, SeekJob};
+use crate::backend::AudioBackend;
+use crate::utils::{ is_audio_file, is_probably_audio, partition_existing, gain_to_perceptual, get_supported_extensions, perceptual_to_gain, scan_dir_for_audio, set_folder_cover_cache_capacity, show_in_file_manager};
+use crate::controller::{FinishKind, InstantSkipGuard, MAX_INSTANT_SKIPS, Navigator, RESTART_THRESHOLD, SeekTarget, first_unplayed, index_after_move, next_index, relative_seek_target, type_ahead_index, type_ahead_prefix};
+use crate::replaygain::ReplayGainMode;
+use crate::status::{ConditionKind, Severity, StatusConditions};
+use crate::config::{Config, StopBehavior, Theme, load_config, save_config};
+use crate::watcher::FolderWatcher;
+use crate::fingerprint::{DuplicateScan, FingerprintCache};
+use crate::metadata::{MetadataCache, display_name, matches_search, matches_type_ahead, playlist_summary};
+use crate::playlist::{SortValue, cue_track_for, load_cue, load_m3u_with_trims, load_pls_with_trims, load_startup_playlist, save_m3u, save_pls, save_startup_playlist, sorted_order};
+use crate::cover_art::CoverArt;
+use crate::waveform::Waveform;
+use crate::equalizer::{BUILTIN_PRESETS, EQ_BANDS, MAX_GAIN_DB};
+use crate::presets::{EqPresets, load_presets, save_presets};
+use crate::history::{PlayHistory, load_history, played_ago, save_history};
+use crate::play_counts::{ListenTime, PlayCounts, counts_as_played, load_play_counts, save_play_counts};
+use crate::resume::{RESUME_SAVE_INTERVAL, ResumePositions, find_last_track, load_resume_positions, position_to_remember, save_resume_positions};
+#[cfg(feature = "media-controls")]
+use crate::media_controls::SystemMediaControls;
+#[cfg(feature = "tray")]
+use crate::tray::{Tray, TrayAction};
+use crate::a11y;
+use crate::desktop_notify::notify_now_playing;
+use crate::lyrics::{current_line, load_lyrics, Lyrics};
+#[cfg(feature = "remote-control")]
+use crate::remote::{remote_token_path, RemoteRequest, RemoteServer};
+use crate::decode::DecodeFailure;
+use crate::trims::TrackTrims;
+use crate::device_watch::OutputDeviceWatcher;
+use crate::sleep::{SleepAction, SleepTimer};
+use crate::stream::is_stream;
+
+/// How much of a track the Preview button plays
+const PREVIEW_LENGTH: Duration = Duration::from_secs(10);
+
+/// How much the Up and Down keys move the volume slider
+const VOLUME_STEP: f32 = 0.05;
+
+/// Following the current track holds off this long after the playlist is scrolled by hand
+const FOLLOW_PAUSE: Duration = Duration::from_secs(10);
+
+/// How long to wait for files from an "Open with" launch before giving up on them
+const OPENED_FILES_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest crossfade offered in Settings
+const MAX_CROSSFADE_SECS: f32 = 12.0;
+
+/// Height of the waveform above the progress bar
+const WAVEFORM_HEIGHT: f32 = 36.0;
+
+/// How long before the end of a track the next one is queued for gapless playback
+const GAPLESS_PRELOAD: Duration = Duration::from_secs(5);
+
+/// Type-ahead starts a new name when no key has been typed for this long
+const TYPE_AHEAD_RESET: Duration = Duration::from_secs(1);
+
+/// Window size on first start, and when leaving mini mode without a size to go back to
+const FULL_WINDOW_SIZE: egui::Vec2 = egui::vec2(500.0, 600.0);
+/// Window size in mini mode: room for the track name, the controls and the progress bar
+const MINI_WINDOW_SIZE: egui::Vec2 = egui::vec2(420.0, 96.0);
+
+/// Playlist columns that can be sorted on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    Name,
+    Title,
+    Artist,
+    Duration,
+    Genre,
+    Year,
+    PlayCount,
+}
+
+/// Where Play Next and Add to Queue move a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueuePlacement {
+    /// Straight after the current track
+    Next,
+    /// After the rest of the upcoming tracks
+    Last,
+}
+
+/// A track shown in the playlist: its index, the row's widget if it's on screen, and where
+/// the row is or would be drawn
+type PlaylistRow = (usize, Option<egui::Id>, egui::Rect);
+
+/// Actions in a playlist row's context menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowAction {
+    Play,
+    Requeue(QueuePlacement),
+    Remove,
+    MoveUp,
+    MoveDown,
+    ShowInFileManager,
+    CopyPath,
+}
 
 struct MusicPlayerApp {
     player: Arc<Mutex<MusicPlayer>>,
@@ -17,18 +112,82 @@ struct MusicPlayerApp {
     selected_song_index: Option<usize>,
     is_playing: bool,
     volume: f32,
+    pre_mute_volume: Option<f32>, // Volume to go back to on unmute, set while muted
+    full_window_size: Option<egui::Vec2>, // Window size to go back to when leaving mini mode
     song_position: Duration,
     song_duration: Option<Duration>,
+    cue_range: Option<(Duration, Option<Duration>)>, // Where the current cue sheet track starts and ends in its file
+    buffered_position: Option<Duration>, // How much of a stream has been downloaded
     seeking: bool,
     seek_position: f32, // 0.0 to 1.0 for slider
     shuffle_mode: bool,
+    repeat_mode: RepeatMode,
     pending_drops: Vec<PathBuf>, // Store files that were dropped
+    awaiting_opened_files: Option<std::time::Instant>, // Launched to open files that haven't arrived yet
     config: Config,
     notification: Option<(String, std::time::Instant)>, // (message, time shown)
+    folder_watcher: Option<FolderWatcher>,
+    played_count: usize, // Entries at the bottom of the playlist that have finished playing
+    fingerprint_cache: Arc<Mutex<FingerprintCache>>,
+    duplicate_scan: Option<DuplicateScan>,
+    duplicate_groups: Option<Vec<Vec<PathBuf>>>, // Results shown in the duplicates window
+    show_settings: bool,
+    show_history: bool,
+    status: StatusConditions, // Ongoing problems shown in the status bar
+    decode_failures: usize,
+    loop_single: bool, // Replay the only opened file forever
+    metadata: MetadataCache,
+    sort: Option<(SortColumn, bool)>, // Column the playlist was last sorted on, and whether ascending
+    skip_guard: InstantSkipGuard,
+    broken_tracks: HashSet<PathBuf>, // Tracks that ended as soon as they started, marked with ⚠
+    lyrics: Option<(PathBuf, Option<Lyrics>)>, // Lyrics looked up for this track, if any were found
+    lyrics_line: Option<usize>, // Highlighted line, so we only scroll when it changes
+    played: HashSet<PathBuf>, // Tracks played to the end this session, for Continue
+    #[cfg(feature = "remote-control")]
+    remote: Option<RemoteServer>, // Commands from `musicplayer --remote` and scripts
+    #[cfg(feature = "media-controls")]
+    media_controls: Option<SystemMediaControls>, // Media keys and the system's media widget
+    #[cfg(feature = "tray")]
+    tray: Option<Tray>, // Icon in the system tray
+    trims: TrackTrims, // Start and end points of playlist entries, saved with the playlist
+    device_watcher: OutputDeviceWatcher,
+    sleep_timer: Option<SleepTimer>,
+    sleep_minutes: u64, // Length typed into the sleep menu
+    lossless_only: bool, // Hide tracks known to be lossy
+    search_query: String, // Only show playlist entries matching this
+    stream_url_input: String, // URL typed in next to Add Stream
+    playlist_summary: Option<String>, // Track count and total length, cleared when tracks are added or removed (order doesn't change it)
+    scroll_to_current: bool, // Bring the current track into view on the next frame
+    playlist_focused: bool, // A playlist row had keyboard focus, so letters go to type-ahead
+    focus_selected: bool, // Give the selected row focus once it has scrolled into view
+    type_ahead_buffer: String, // Start of a name typed into the playlist, lowercase
+    type_ahead_at: Option<std::time::Instant>, // When the last letter was typed
+    followed_index: Option<usize>, // Current track when we last checked whether to follow it
+    manual_scroll_at: Option<std::time::Instant>, // Last time the playlist was scrolled by hand
+    confirm_clear: bool, // Asking whether to empty the playlist
+    menu_open: bool, // A menu was open last frame, so Escape is left to close it
+    cover_art: CoverArt, // Album art for the current track
+    waveform: Waveform,
+    history: PlayHistory, // Recently played tracks, kept between runs
+    navigator: Navigator, // Shuffle order and the trail Previous retraces, shared with the other front-ends
+    play_counts: PlayCounts,
+    eq_presets: EqPresets, // Saved EQ presets and the tracks that play with them
+    track_eq: Option<String>, // The current track's own EQ preset, edited by the window while it plays
+    eq_preset_name: String, // Name typed in to save the EQ as a preset
+    resume_positions: ResumePositions, // Where tracks were left off, when remember_position is on
+    resume_track: Option<PathBuf>, // The playing track whose place is being kept
+    resume_saved_at: std::time::Instant, // Last time the playing track's place was kept
+    last_session: Option<(PathBuf, Duration)>, // Track and place the last run ended on, until a track is played
+    pending_play_count: Option<(Duration, Option<Duration>)>, // Where the current track started and its length, until it counts as played
+    listen_time: ListenTime, // How much of the current track has been heard, towards its play count and for the skip guard
+    queued_next: Option<PathBuf>, // Track queued (or tried) behind the current one for gapless playback
+    gapless_handover: Option<PathBuf>, // Queued track the player just rolled over to, for play_current_song to take on
+    history_seeded: bool, // The navigator's trail has been filled in from the saved history
+    quit_requested: bool, // Close the window on the next frame (sleep timer set to quit)
 }
 
 impl MusicPlayerApp {
-    fn new(_cc: &eframe::CreationContext<'_>, paths: Vec<PathBuf>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, paths: Vec<PathBuf>, opened_with_files: bool, loop_file: bool, start_paused: bool) -> Self {
         let mut file: Option<PathBuf> = None;
         let mut started_playing: bool = false;
         let mut playlist = Vec::new();
@@ -38,18 +197,89 @@ impl MusicPlayerApp {
         
         // Add all provided files to the playlist (they should already be filtered)
         for path in paths {
+            if playlist.len() >= config.max_playlist_entries {
+                log::warn!("Playlist limit of {} reached, ignoring remaining files", config.max_playlist_entries);
+                break;
+            }
             if path.is_file() {
                 // Use the first valid file as the initial file to play
                 if file.is_none() {
                     file = Some(path.clone());
-                    started_playing = true;
+                    started_playing = !start_paused;
                 }
                 playlist.push(path);
             }
         }
+        
+        // Nothing on the command line and nothing opened from the desktop
+        let started_without_files = !opened_with_files && playlist.is_empty();
+        
+        // Started without any files: the playlist set for startup, if there is one
+        let mut trims = TrackTrims::default();
+        if started_without_files && config.load_startup_playlist {
+            match load_startup_playlist() {
+                Ok((tracks, startup_trims)) => {
+                    let (mut found, missing) = partition_existing(&tracks);
+                    if !missing.is_empty() {
+                        log::warn!("{} track(s) in the startup playlist could not be found", missing.len());
+                    }
+                    found.truncate(config.max_playlist_entries);
+                    playlist = found;
+                    trims = startup_trims;
+                }
+                Err(e) => log::error!("Couldn't load the startup playlist: {}", e),
+            }
+        }
+        
+        // Started without any files: select the track the last run ended on
+        let mut last_session = None;
+        let mut last_index = None;
+        if let (true, Some(last)) = (started_without_files, config.last_track.clone()) {
+            match find_last_track(&last, &playlist) {
+                Some(path) => {
+                    let index = match playlist.iter().position(|entry| *entry == path) {
+                        Some(index) => Some(index),
+                        None if playlist.len() < config.max_playlist_entries => {
+                            playlist.push(path.clone());
+                            Some(playlist.len() - 1)
+                        }
+                        None => None,
+                    };
+                    if index.is_some() {
+                        file = Some(path.clone());
+                        started_playing = config.resume_last_track && !start_paused;
+                        last_index = index;
+                        last_session = Some((path, config.last_position));
+                    }
+                }
+                None => log::warn!("The last track played, {}, was moved or deleted", last.display()),
+            }
+        }
+        
+        let cache_capacity = config.cache_capacity;
+        set_folder_cover_cache_capacity(cache_capacity);
+        
+        // Without a device the window still opens, saying what went wrong, and Retry in
+        // the status bar tries again once one is plugged in
+        let (player, output_error) = match MusicPlayer::new() {
+            Ok(player) => (player, None),
+            Err(e) => {
+                log::error!("Failed to open audio output: {}", e);
+                (MusicPlayer::without_output(), Some(e))
+            }
+        };
+        player.set_crossfeed(config.crossfeed_enabled, config.crossfeed_strength);
+        player.set_balance(config.balance);
+        player.set_eq_gains(&config.eq_gains);
+        player.set_replaygain_mode(config.replaygain_mode);
+        player.set_repeat(config.repeat_mode);
+        player.set_shuffle(config.shuffle);
+        player.set_crossfade(Duration::from_secs_f32(config.crossfade_secs.clamp(0.0, MAX_CROSSFADE_SECS)));
+        Self::apply_speed(&player, &config);
+        Self::apply_theme(&cc.egui_ctx, config.theme);
 
-        Self {
-            player: Arc::new(Mutex::new(MusicPlayer::new().unwrap())),
+        let mut app = Self {
+            player: Arc::new(Mutex::new(player)),
             current_file: file,
             started_playing,
             playlist,
@@ -57,152 +287,1407 @@ impl MusicPlayerApp {
             selected_song_index: None,
             is_playing: false,
             volume: config.volume,  // Use volume from config
+            pre_mute_volume: None,
+            full_window_size: None,
             song_position: Duration::from_secs(0),
             song_duration: None,
+            cue_range: None,
+            buffered_position: None,
             seeking: false,
             seek_position: 0.0,
-            shuffle_mode: false,
+            shuffle_mode: config.shuffle,
+            repeat_mode: config.repeat_mode,
             pending_drops: Vec::new(),
+            awaiting_opened_files: None,
             config,
             notification: None,
+            folder_watcher: None,
+            played_count: 0,
+            fingerprint_cache: Arc::new(Mutex::new(FingerprintCache::new(cache_capacity))),
+            duplicate_scan: None,
+            duplicate_groups: None,
+            show_settings: false,
+            show_history: false,
+            status: StatusConditions::default(),
+            decode_failures: 0,
+            loop_single: false,
+            metadata: MetadataCache::new(cache_capacity),
+            sort: None,
+            skip_guard: InstantSkipGuard::default(),
+            broken_tracks: HashSet::new(),
+            lyrics: None,
+            lyrics_line: None,
+            played: HashSet::new(),
+            #[cfg(feature = "remote-control")]
+            remote: None,
+            #[cfg(feature = "media-controls")]
+            media_controls: None,
+            #[cfg(feature = "tray")]
+            tray: None,
+            trims,
+            device_watcher: OutputDeviceWatcher::start(),
+            sleep_timer: None,
+            sleep_minutes: 20,
+            lossless_only: false,
+            search_query: String::new(),
+            stream_url_input: String::new(),
+            playlist_summary: None,
+            scroll_to_current: false,
+            playlist_focused: false,
+            focus_selected: false,
+            type_ahead_buffer: String::new(),
+            type_ahead_at: None,
+            followed_index: None,
+            manual_scroll_at: None,
+            confirm_clear: false,
+            menu_open: false,
+            cover_art: CoverArt::default(),
+            waveform: Waveform::default(),
+            history: load_history().unwrap_or_else(|e| {
+                log::error!("Failed to load history: {}", e);
+                PlayHistory::default()
+            }),
+            history_seeded: false,
+            navigator: Navigator::default(),
+            play_counts: load_play_counts().unwrap_or_else(|e| {
+                log::error!("Failed to load play counts: {}", e);
+                PlayCounts::default()
+            }),
+            eq_presets: load_presets().unwrap_or_else(|e| {
+                log::error!("Failed to load EQ presets: {}", e);
+                EqPresets::default()
+            }),
+            track_eq: None,
+            eq_preset_name: String::new(),
+            resume_positions: load_resume_positions().unwrap_or_else(|e| {
+                log::error!("Failed to load resume positions: {}", e);
+                ResumePositions::default()
+            }),
+            resume_track: None,
+            resume_saved_at: std::time::Instant::now(),
+            last_session,
+            pending_play_count: None,
+            listen_time: ListenTime::default(),
+            queued_next: None,
+            gapless_handover: None,
+            quit_requested: false,
+        };
+        
+        #[cfg(feature = "remote-control")]
+        if app.config.remote_control {
+            match remote_token_path().and_then(|token_file| RemoteServer::start(app.config.remote_port, &token_file)) {
+                Ok(server) => {
+                    log::info!("Remote control listening on 127.0.0.1:{}", server.port());
+                    app.remote = Some(server);
+                }
+                // Most likely another instance already has the port
+                Err(e) => log::warn!("Remote control unavailable: {}", e),
+            }
+        }
+        
+        #[cfg(feature = "media-controls")]
+        match SystemMediaControls::start(window_handle(cc)) {
+            Ok(controls) => app.media_controls = Some(controls),
+            // No session bus over SSH, for example
+            Err(e) => log::warn!("Media keys unavailable: {}", e),
+        }
+        
+        #[cfg(feature = "tray")]
+        match Tray::start(cc.egui_ctx.clone()) {
+            Ok(tray) => app.tray = Some(tray),
+            // Some desktops have no tray
+            Err(e) => log::warn!("Tray icon unavailable: {}", e),
+        }
+        
+        // A single opened file (ambient track, sound loop...) can be set to loop forever
+        app.loop_single = (loop_file || app.config.loop_single_file) && app.playlist.len() == 1;
+        
+        // Started with --paused: the first track is loaded and selected, so Play starts it
+        if start_paused && app.current_file.is_some() {
+            app.current_playlist_index = Some(0);
+            app.selected_song_index = Some(0);
+        }
+        
+        if last_index.is_some() {
+            app.current_playlist_index = last_index;
+            app.selected_song_index = last_index;
+            app.scroll_to_current = true;
+        }
+        
+        // Files opened from the desktop come in as events once the window is up
+        crate::open_events::set_repaint_context(cc.egui_ctx.clone());
+        if opened_with_files && app.playlist.is_empty() {
+            app.awaiting_opened_files = Some(std::time::Instant::now());
+        }
+        
+        if let Some(e) = output_error {
+            let message = format!("Audio output is not working: {}", e);
+            app.status.raise(ConditionKind::AudioOutput, Severity::Error, message);
+        }
+        
+        app.start_folder_watcher();
+        app
+    }
+    
+    // Start watching the drop zone folder if enabled
+    fn start_folder_watcher(&mut self) {
+        self.folder_watcher = None;
+        self.status.clear(ConditionKind::WatchFolder);
+        
+        let (Some(folder), true) = (&self.config.watch_folder, self.config.watch_folder_enabled) else {
+            return;
+        };
+        
+        match FolderWatcher::new(&PathBuf::from(folder)) {
+            Ok(watcher) => self.folder_watcher = Some(watcher),
+            Err(e) => {
+                log::error!("Failed to watch folder {}: {}", folder, e);
+                let message = format!("Not watching {}: {}", folder, e);
+                self.status.raise(ConditionKind::WatchFolder, Severity::Warning, message);
+            }
+        }
+    }
+    
+    // Save the config, keeping a status bar error up until a save succeeds
+    fn persist_config(&mut self) {
+        match save_config(&self.config) {
+            Ok(()) => self.status.clear(ConditionKind::ConfigSave),
+            Err(e) => {
+                log::error!("Failed to save config: {}", e);
+                let message = format!("Settings could not be saved: {}", e);
+                self.status.raise(ConditionKind::ConfigSave, Severity::Error, message);
+            }
         }
     }
     
     fn play_current_song(&mut self) {
         if let Some(index) = self.current_playlist_index {
             if index < self.playlist.len() {
-                let path = &self.playlist[index];
+                // The track being left, before the position moves on to the new one
+                self.remember_position();
+                let path = self.playlist[index].clone();
                 self.current_file = Some(path.clone());
-                if let Ok(player) = self.player.lock() {
-                    let _ = player.play_playlist_item(path, index);
-                    self.is_playing = true;
+                self.queued_next = None;
+                let cue_track = cue_track_for(&path);
+                self.cue_range = cue_track.as_ref().map(|track| (track.start, track.end));
+                self.apply_track_eq(&path);
+                // A track queued for gapless playback is already playing, so it isn't opened again
+                let handed_over = self.gapless_handover.take_if(|queued| *queued == path).is_some();
+                let result = if handed_over {
+                    Ok(())
+                } else if let Ok(player) = self.player.lock() {
+                    match &cue_track {
+                        Some(track) => player.play_playlist_range(&track.file, index, track.start, track.end),
+                        None => player.play(&path, index)
+                            .and_then(|_| self.seek_to_start_point(&*player, &path)),
+                    }
+                } else {
+                    return;
+                };
+                // Still counts as playing, so a failed track is skipped when the next check finds it finished
+                self.is_playing = true;
+                self.skip_guard.track_started(&result);
+                self.listen_time = ListenTime::default();
+                
+                self.resume_track = None;
+                match result {
+                    Ok(()) => {
+                        self.last_session = None;
+                        if Self::can_resume(&self.playlist[index]) {
+                            self.resume_track = Some(self.playlist[index].clone());
+                        }
+                        self.announce_track(index);
+                        self.record_history(index);
+                        self.pending_play_count = Some(match &cue_track {
+                            Some(track) => (Duration::ZERO, track.end.map(|end| end.saturating_sub(track.start))),
+                            None => (self.trims.get(&self.playlist[index]).start().unwrap_or_default(), None),
+                        });
+                    },
+                    Err(e) => self.report_play_failure(&self.playlist[index].clone(), e),
                 }
             }
         }
     }
     
-    fn play_next_song(&mut self) {
-        let next_index = if self.shuffle_mode && !self.playlist.is_empty() {
-            // In shuffle mode, randomly select a song that's not the current one
-            if self.playlist.len() > 1 {
-                let mut rng = rng();
-                let mut random_index = self.current_playlist_index.unwrap_or(0);
-                
-                // Keep generating a random index until we get one that's different from current
-                while random_index == self.current_playlist_index.unwrap_or(usize::MAX) {
-                    random_index = rng.random_range(0..self.playlist.len());
-                }
-                
-                Some(random_index)
-            } else {
-                // Only one song in playlist, just play it
-                Some(0)
+    fn report_play_failure(&mut self, path: &Path, e: anyhow::Error) {
+        log::error!("{:#}", e);
+        self.decode_failures += 1;
+        let message = format!("{} file(s) failed to play", self.decode_failures);
+        self.status.raise(ConditionKind::DecodeFailures, Severity::Warning, message);
+        
+        // Say whether the file is damaged or just a format we can't play
+        if let Some(failure) = e.downcast_ref::<DecodeFailure>() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            self.show_notification(&format!("{}: {}", name, failure));
+        } else if is_stream(path) {
+            // Couldn't connect. It's skipped like a broken file.
+            self.show_notification(&format!("{:#}", e));
+        }
+    }
+    
+    // Start a stream once it has connected (done on a worker, so a slow station doesn't freeze
+    // the window), or report why it couldn't be reached
+    fn check_stream_connection(&mut self) {
+        let result = match self.player.lock() {
+            Ok(player) => player.poll_stream(),
+            Err(_) => return,
+        };
+        if let (Some(Err(e)), Some(path)) = (result, self.current_file.clone()) {
+            self.report_play_failure(&path, e);
+        }
+    }
+    
+    // Start a track where it was left off (last run, or remember_position), or a trimmed one at its start point
+    fn seek_to_start_point(&self, player: &impl AudioBackend, path: &Path) -> Result<()> {
+        let last_session = self.last_session.as_ref()
+            .filter(|(last, position)| last == path && !position.is_zero())
+            .map(|(_, position)| *position);
+        let resume = if self.config.remember_position { self.resume_positions.get(path) } else { None };
+        match last_session.or(resume).or(self.trims.get(path).start()) {
+            Some(start) => player.seek(start),
+            None => Ok(()),
+        }
+    }
+    
+    // Places are kept for whole files; cue sheet tracks and streams start over each time
+    fn can_resume(path: &Path) -> bool {
+        !is_stream(path) && cue_track_for(path).is_none()
+    }
+    
+    // Keep the playing track's place for next time, or forget it if it's better started over
+    fn remember_position(&mut self) {
+        self.resume_saved_at = std::time::Instant::now();
+        let Some(path) = self.resume_track.clone() else {
+            return;
+        };
+        if self.config.remember_position && self.resume_positions.update(&path, self.song_position, self.song_duration) {
+            self.persist_resume_positions();
+        }
+    }
+    
+    // Note the current track and its place in the config, to select again on the next start
+    fn remember_last_track(&mut self) {
+        let last = self.current_file.clone().filter(|path| Self::can_resume(path));
+        self.config.last_position = match &last {
+            Some(_) => position_to_remember(self.song_position, self.song_duration).unwrap_or_default(),
+            None => Duration::ZERO,
+        };
+        self.config.last_track = last;
+        self.persist_config();
+    }
+    
+    // A track that played to its end starts from the beginning next time
+    fn forget_position(&mut self) {
+        if let Some(path) = self.resume_track.take() {
+            if self.resume_positions.forget(&path) {
+                self.persist_resume_positions();
             }
-        } else if let Some(current) = self.current_playlist_index {
-            // Normal sequential mode
-            if current + 1 < self.playlist.len() {
-                Some(current + 1)
-            } else {
-                None // End of playlist
+        }
+    }
+    
+    // Every so often while playing, so a crash doesn't lose the place
+    fn check_resume_save(&mut self) {
+        if self.is_playing && !self.seeking && self.resume_saved_at.elapsed() >= RESUME_SAVE_INTERVAL {
+            self.remember_position();
+        }
+    }
+    
+    fn persist_resume_positions(&mut self) {
+        if let Err(e) = save_resume_positions(&self.resume_positions) {
+            log::error!("Failed to save resume positions: {}", e);
+        }
+    }
+    
+    // Near the end of a track, queue the one after it so the player goes straight on
+    fn check_gapless(&mut self) {
+        let crossfade = self.config.crossfade_secs > 0.0;
+        if !self.config.gapless || crossfade || !self.is_playing || self.seeking || self.queued_next.is_some() {
+            return;
+        }
+        // The sleep timer stops after this track, and a stream may not be able to reach its end
+        if self.sleep_timer == Some(SleepTimer::EndOfTrack) || self.buffered_position.is_some() {
+            return;
+        }
+        let (Some(path), Some(duration)) = (&self.current_file, self.song_duration) else {
+            return;
+        };
+        if duration.saturating_sub(self.song_position) > GAPLESS_PRELOAD {
+            return;
+        }
+        // Tracks that start or stop partway through a file need a seek or an early stop
+        if cue_track_for(path).is_some() || self.trims.get(path).end().is_some() {
+            return;
+        }
+        
+        let Some(index) = self.gapless_candidate() else {
+            return;
+        };
+        let next = self.playlist[index].clone();
+        self.queued_next = Some(next.clone());
+        if is_stream(&next) || cue_track_for(&next).is_some() || self.trims.get(&next).start().is_some() {
+            return;
+        }
+        if let Ok(player) = self.player.lock() {
+            // Not fatal: the track is opened again in the usual way once this one ends
+            if let Err(e) = player.queue_playlist_item(&next, index) {
+                log::warn!("Couldn't queue {} for gapless playback: {:#}", next.display(), e);
+            }
+        }
+    }
+    
+    // The track check_song_finished will move on to, if that's already known. Unlike
+    // Navigator::upcoming this doesn't use up a shuffled track.
+    fn gapless_candidate(&self) -> Option<usize> {
+        let current = self.current_playlist_index.filter(|&index| index < self.playlist.len())?;
+        if (self.loop_single && self.playlist.len() == 1) || self.repeat_mode == RepeatMode::One {
+            return Some(current);
+        }
+        if self.shuffle_mode {
+            return self.navigator.shuffle_order.peek(&self.playlist);
+        }
+        let wrap = self.repeat_mode == RepeatMode::All;
+        if self.config.move_played_to_bottom {
+            // Worked out as if the current track had already moved down to the played ones,
+            // as finish_song does, then taken back to where that track is now
+            let len = self.playlist.len();
+            let moved = if current < self.played_section_start() { current.checked_sub(1) } else { Some(len - 1) };
+            return next_index(len, moved, wrap).map(|next| match next {
+                next if next == len - 1 => current,
+                next if next >= current => next + 1,
+                next => next,
+            });
+        }
+        next_index(self.playlist.len(), Some(current), wrap)
+    }
+    
+    // Once the player has rolled over to the queued track, finish the last one as usual.
+    // play_current_song then picks up the queued track instead of opening it again.
+    fn check_gapless_rollover(&mut self) {
+        let rolled_over = self.player.lock().ok().and_then(|player| player.reconcile_queue());
+        let Some((_, path)) = rolled_over else {
+            return;
+        };
+        self.gapless_handover = Some(path);
+        self.finish_song();
+        
+        let Ok(player) = self.player.lock() else {
+            return;
+        };
+        // Playback stopped or went elsewhere, so the queued track shouldn't keep playing
+        if self.gapless_handover.take().is_some() && !self.is_playing {
+            player.stop();
+        }
+        (self.song_position, self.song_duration) = self.track_progress(&*player);
+    }
+    
+    // With crossfade on, hand the end of the track over to a fade-out and move on early
+    fn check_crossfade(&mut self) {
+        let crossfade = Duration::from_secs_f32(self.config.crossfade_secs.clamp(0.0, MAX_CROSSFADE_SECS));
+        if crossfade.is_zero() || !self.is_playing || self.seeking {
+            return;
+        }
+        let Some(path) = &self.current_file else {
+            return;
+        };
+        let Some(end) = self.trims.get(path).end().or(self.song_duration) else {
+            return;
+        };
+        // Tracks this short would be all fade
+        if end < crossfade * 2 || end.saturating_sub(self.song_position) > crossfade {
+            return;
+        }
+        
+        // The last track, or one the sleep timer stops after, plays out in full
+        let moves_on = (self.loop_single && self.playlist.len() == 1)
+            || self.repeat_mode == RepeatMode::One
+            || self.navigator.has_upcoming(&self.playlist, self.current_playlist_index, self.current_file.as_ref(), self.shuffle_mode, self.repeat_mode == RepeatMode::All);
+        if !moves_on || self.sleep_timer == Some(SleepTimer::EndOfTrack) {
+            return;
+        }
+        
+        if let Ok(player) = self.player.lock() {
+            if player.is_stopped() {
+                return;
+            }
+            if let Err(e) = player.begin_crossfade(self.file_position(end)) {
+                // The track still plays to the end and the next one starts as usual
+                log::warn!("Could not crossfade from {}: {}", path.display(), e);
+            }
+        }
+    }
+    
+    // A trimmed track ends at its end point, and then moves on like any other finished track
+    fn check_trim_end(&mut self) {
+        let Some(path) = &self.current_file else {
+            return;
+        };
+        let past_end = self.trims.get(path).end().is_some_and(|end| self.song_position >= end);
+        if self.is_playing && past_end {
+            if let Ok(player) = self.player.lock() {
+                player.end_track();
             }
-        } else if !self.playlist.is_empty() {
-            Some(0) // Start of playlist
+        }
+    }
+    
+    fn set_trim_start(&mut self) {
+        let Some(path) = self.current_file.clone() else {
+            return;
+        };
+        if let Err(e) = self.trims.set_start(&path, self.song_position) {
+            self.show_notification(&e.to_string());
+        }
+    }
+    
+    fn set_trim_end(&mut self) {
+        let Some(path) = self.current_file.clone() else {
+            return;
+        };
+        if let Err(e) = self.trims.set_end(&path, self.song_position) {
+            self.show_notification(&e.to_string());
+        }
+    }
+    
+    fn clear_trim(&mut self) {
+        if let Some(path) = self.current_file.clone() {
+            self.trims.clear(&path);
+        }
+    }
+    
+    fn record_history(&mut self, index: usize) {
+        let Some(path) = self.playlist.get(index) else {
+            return;
+        };
+        self.history.record(path);
+        if let Err(e) = save_history(&self.history) {
+            log::error!("Failed to save history: {}", e);
+        }
+    }
+    
+    // Count a play once enough of the track has been heard, not when it's merely started
+    fn check_play_count(&mut self) {
+        if !self.is_playing || self.seeking {
+            self.listen_time.pause();
+            return;
+        }
+        // Resuming, picking up the last run and seeking ahead all skip part of the track.
+        // Kept up after the play is counted, for the skip guard.
+        self.listen_time.update(self.song_position, std::time::Instant::now());
+        
+        let Some((start, length)) = self.pending_play_count else {
+            return;
+        };
+        let Some(path) = self.current_file.clone() else {
+            return;
+        };
+        let length = length.or(self.song_duration.map(|duration| duration.saturating_sub(start)));
+        if counts_as_played(self.listen_time.listened(), length) {
+            self.pending_play_count = None;
+            self.play_counts.record(&path);
+            self.persist_play_counts();
+        }
+    }
+    
+    fn persist_play_counts(&mut self) {
+        if let Err(e) = save_play_counts(&self.play_counts) {
+            log::error!("Failed to save play counts: {}", e);
+        }
+    }
+    
+    // Set the EQ before a track starts: its own preset if it has one, otherwise the one in the window
+    fn apply_track_eq(&mut self, path: &Path) {
+        self.track_eq = self.eq_presets.preset_for_track(path).map(|(name, _)| name.to_string());
+        let gains = self.eq_gains().to_vec();
+        if let Ok(player) = self.player.lock() {
+            player.set_eq_gains(&gains);
+        }
+    }
+    
+    // The bands in use, which the equalizer window shows
+    fn eq_gains(&self) -> &[f32] {
+        self.track_eq.as_ref()
+            .and_then(|name| self.eq_presets.presets.get(name))
+            .unwrap_or(&self.config.eq_gains)
+    }
+    
+    fn persist_eq_presets(&mut self) {
+        if let Err(e) = save_presets(&self.eq_presets) {
+            log::error!("Failed to save EQ presets: {}", e);
+            self.show_notification("EQ presets could not be saved");
+        }
+    }
+    
+    // Audition the selected track without losing our place in the current one
+    fn preview_selected(&mut self) {
+        let Some(path) = self.selected_song_index.and_then(|i| self.playlist.get(i)).cloned() else {
+            return;
+        };
+        
+        let result = if let Ok(player) = self.player.lock() {
+            player.preview(&path, PREVIEW_LENGTH)
         } else {
-            None // Empty playlist
+            return;
         };
         
-        self.current_playlist_index = next_index;
-        if next_index.is_some() {
-            self.play_current_song();
+        if let Err(e) = result {
+            log::error!("Failed to preview {}: {}", path.display(), e);
+            self.show_notification(&format!("Could not preview {}", path.display()));
+        }
+    }
+    
+    fn stop_preview(&mut self) {
+        if let Ok(player) = self.player.lock() {
+            player.stop_preview();
+        }
+    }
+    
+    fn is_previewing(&self) -> bool {
+        if let Ok(player) = self.player.lock() {
+            player.is_previewing()
         } else {
-            self.is_playing = false;
+            false
         }
     }
     
-    fn add_to_playlist(&mut self) {
-        let extensions = get_supported_extensions();
-        if let Some(paths) = rfd::FileDialog::new()
-            .add_filter("Audio Files", &extensions)
-            .pick_files()
-        {
-            let mut added = 0;
-            
-            for path in paths {
-                if is_audio_file(&path) {
-                    self.playlist.push(path);
-                    added += 1;
+    // Tell the desktop about a track that just started, if the user wants that
+    fn announce_track(&mut self, index: usize) {
+        if !self.config.desktop_notifications {
+            return;
+        }
+        if let Some(path) = self.playlist.get(index) {
+            let metadata = self.metadata.get(path).cloned();
+            notify_now_playing(path, metadata);
+        }
+    }
+    
+    fn show_sleep_menu(&mut self, ui: &mut egui::Ui) {
+        let text = match self.sleep_timer {
+            Some(SleepTimer::At(_)) => {
+                let remaining = self.sleep_timer.and_then(|t| t.remaining()).unwrap_or_default();
+                format!("💤 {}", Self::format_duration(remaining))
+            },
+            Some(SleepTimer::EndOfTrack) => "💤 End of track".to_string(),
+            None => "💤 Sleep".to_string(),
+        };
+        
+        let menu = ui.menu_button(text, |ui| {
+            for minutes in [15, 30, 45, 60, 90] {
+                if ui.button(format!("{} minutes", minutes)).clicked() {
+                    self.set_sleep_timer(SleepTimer::after(Duration::from_secs(minutes * 60)));
+                    ui.close_menu();
                 }
             }
-            
-            if added > 0 {
-                // If no song is playing, start with the first added song
-                if self.current_playlist_index.is_none() && !self.playlist.is_empty() {
-                    self.current_playlist_index = Some(0);
-                    self.play_current_song();
+            ui.horizontal(|ui| {
+                let minutes = ui.add(egui::DragValue::new(&mut self.sleep_minutes).range(1..=600).suffix(" min"));
+                a11y::labeled(ui, minutes, egui::WidgetType::DragValue, "Sleep after this many minutes");
+                if a11y::button(ui, "Start", "Start the sleep timer").clicked() {
+                    self.set_sleep_timer(SleepTimer::after(Duration::from_secs(self.sleep_minutes * 60)));
+                    ui.close_menu();
                 }
+            });
+            if ui.button("End of current track").clicked() {
+                self.set_sleep_timer(SleepTimer::EndOfTrack);
+                ui.close_menu();
             }
-        }
+            if self.sleep_timer.is_some() {
+                ui.separator();
+                if ui.button("Cancel sleep timer").clicked() {
+                    self.cancel_sleep_timer();
+                    ui.close_menu();
+                }
+            }
+        });
+        self.menu_open |= menu.inner.is_some();
+        a11y::labeled(ui, menu.response, egui::WidgetType::Button, "Sleep timer");
     }
     
-    fn remove_from_playlist(&mut self) {
-        if let Some(index) = self.selected_song_index {
-            if index < self.playlist.len() {
-                // If the currently playing song is removed, stop playback
-                if Some(index) == self.current_playlist_index {
-                    if let Ok(player) = self.player.lock() {
-                        player.stop();
-                    }
-                    self.is_playing = false;
-                }
-                
-                // Update current playlist index if needed
-                if let Some(current) = self.current_playlist_index {
-                    self.current_playlist_index = match current {
-                        // If removing the current item
-                        c if c == index => {
-                            if c > 0 {
-                                // If not the first item, move to previous
-                                Some(c - 1)
-                            } else if self.playlist.len() > 1 {
-                                // If first item and playlist has more items, stay at 0
-                                // (which will point to the next song after removal)
-                                Some(0)
-                            } else {
-                                // If removing the only item
-                                None
-                            }
-                        },
-                        // If removing an item before current, decrement current index
-                        c if c > index => Some(c - 1),
-                        // Otherwise keep the same index
-                        c => Some(c),
-                    };
-                }
-                
-                // Remove the track
-                self.playlist.remove(index);
-                
-                // Select the next track for better UX
-                if !self.playlist.is_empty() {
-                    if index < self.playlist.len() {
-                        // If there's a next track at same position, select it
-                        self.selected_song_index = Some(index);
-                    } else {
-                        // If we removed the last track, select the new last one
-                        self.selected_song_index = Some(self.playlist.len() - 1);
-                    }
-                } else {
-                    // No tracks left
-                    self.selected_song_index = None;
+    fn show_speed_menu(&mut self, ui: &mut egui::Ui) {
+        let speed = self.config.playback_speed;
+        let menu = ui.menu_button(format!("⏩ {}×", speed), |ui| {
+            for option in [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0] {
+                if ui.selectable_label(speed == option, format!("{}×", option)).clicked() {
+                    self.set_speed(option, self.config.preserve_pitch);
+                    ui.close_menu();
                 }
             }
+            ui.separator();
+            let mut preserve_pitch = self.config.preserve_pitch;
+            if ui.checkbox(&mut preserve_pitch, "Keep pitch")
+                .on_hover_text("Keeps music in tune at other speeds, but uses more CPU")
+                .changed()
+            {
+                self.set_speed(speed, preserve_pitch);
+            }
+        });
+        self.menu_open |= menu.inner.is_some();
+        a11y::labeled(ui, menu.response, egui::WidgetType::Button, &format!("Playback speed, {} times", speed));
+    }
+    
+    fn set_speed(&mut self, speed: f32, preserve_pitch: bool) {
+        self.config.playback_speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.config.preserve_pitch = preserve_pitch;
+        if let Ok(player) = self.player.lock() {
+            Self::apply_speed(&player, &self.config);
         }
+        self.persist_config();
     }
     
-    fn move_up_in_playlist(&mut self) {
-        if let Some(index) = self.selected_song_index {
-            if index > 0 && index < self.playlist.len() {
-                self.playlist.swap(index, index - 1);
+    // System follows the OS as it changes, where eframe can tell (it falls back to dark)
+    fn apply_theme(ctx: &egui::Context, theme: Theme) {
+        ctx.set_theme(match theme {
+            Theme::Dark => egui::ThemePreference::Dark,
+            Theme::Light => egui::ThemePreference::Light,
+            Theme::System => egui::ThemePreference::System,
+        });
+    }
+    
+    // Plays at the configured speed, through the time-stretch adapter if the pitch is kept
+    fn apply_speed(player: &MusicPlayer, config: &Config) {
+        if config.preserve_pitch {
+            player.set_time_stretch(config.playback_speed.clamp(MIN_SPEED, MAX_SPEED), true);
+        } else {
+            player.set_speed(config.playback_speed);
+        }
+    }
+    
+    // Look up lyrics when the track changes
+    fn refresh_lyrics(&mut self) {
+        let current = self.current_playlist_index.and_then(|i| self.playlist.get(i));
+        let cached = self.lyrics.as_ref().map(|(path, _)| path);
+        if current == cached {
+            return;
+        }
+        
+        self.lyrics = current.map(|path| (path.clone(), load_lyrics(path)));
+        self.lyrics_line = None;
+    }
+    
+    fn current_lyrics(&self) -> Option<&Lyrics> {
+        self.lyrics.as_ref().and_then(|(_, lyrics)| lyrics.as_ref())
+    }
+    
+    fn show_lyrics_panel(&mut self, ctx: &egui::Context) {
+        if !self.config.show_lyrics {
+            return;
+        }
+        let Some(lyrics) = self.lyrics.as_ref().and_then(|(_, lyrics)| lyrics.as_ref()) else {
+            return;
+        };
+        
+        let position = self.song_position;
+        let mut highlighted = self.lyrics_line;
+        egui::SidePanel::right("lyrics_panel")
+            .resizable(true)
+            .default_width(250.0)
+            .show(ctx, |ui| {
+                ui.heading("Lyrics");
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    match lyrics {
+                        Lyrics::Synced(lines) => {
+                            let current = current_line(lines, position);
+                            for (index, (_, text)) in lines.iter().enumerate() {
+                                if Some(index) == current {
+                                    let color = ui.visuals().strong_text_color();
+                                    let response = ui.label(egui::RichText::new(text).strong().color(color));
+                                    // Follow the song, but leave the user free to scroll in between lines
+                                    if highlighted != current {
+                                        response.scroll_to_me(Some(egui::Align::Center));
+                                    }
+                                } else {
+                                    ui.label(egui::RichText::new(text).weak());
+                                }
+                            }
+                            highlighted = current;
+                        }
+                        Lyrics::Unsynced(text) => {
+                            ui.label(text);
+                        }
+                    }
+                });
+            });
+        self.lyrics_line = highlighted;
+    }
+    
+    fn play_next_song(&mut self) {
+        // Repeat All starts the playlist over instead of stopping at the end
+        let wrap = self.repeat_mode == RepeatMode::All;
+        let next_index = self.navigator.next(&self.playlist, self.current_playlist_index, self.current_file.as_ref(), self.shuffle_mode, wrap);
+        self.current_playlist_index = next_index;
+        if next_index.is_some() {
+            self.play_current_song();
+        } else {
+            self.is_playing = false;
+        }
+    }
+    
+    // After a restart this session's trail is empty, so pick up the saved history instead.
+    // Only done once, so going back doesn't bounce between the last two tracks.
+    fn seed_play_history(&mut self, current: usize) {
+        if self.history_seeded {
+            return;
+        }
+        self.history_seeded = true;
+        let Some(current_path) = self.playlist.get(current) else {
+            return;
+        };
+        let mut seeded: Vec<usize> = self.history.before(current_path)
+            .filter_map(|path| self.playlist.iter().position(|p| p == path))
+            .collect();
+        // Oldest first, like the navigator's own trail
+        seeded.reverse();
+        seeded.dedup();
+        self.navigator.history = seeded;
+    }
+    
+    // Go back a track, or restart the current one if it's been playing a while
+    fn play_previous_song(&mut self) {
+        let Some(current) = self.current_playlist_index else {
+            return;
+        };
+        if self.playlist.is_empty() {
+            return;
+        }
+        
+        if self.song_position > RESTART_THRESHOLD {
+            self.seek_to_position(0.0);
+            return;
+        }
+        
+        if self.shuffle_mode && self.navigator.history.is_empty() {
+            self.seed_play_history(current);
+        }
+        let wrap = self.repeat_mode == RepeatMode::All;
+        let previous = self.navigator.previous(self.playlist.len(), Some(current), self.shuffle_mode, wrap);
+        
+        match previous {
+            Some(index) if index != current => {
+                self.current_playlist_index = Some(index);
+                self.play_current_song();
+            },
+            // Already at the start, so just start this track over
+            _ => self.seek_to_position(0.0),
+        }
+    }
+    
+    // A file dialog that opens wherever the user last picked something
+    fn file_dialog(&self) -> rfd::FileDialog {
+        let dialog = rfd::FileDialog::new();
+        match &self.config.last_dialog_dir {
+            Some(dir) if Path::new(dir).is_dir() => dialog.set_directory(dir),
+            _ => dialog,
+        }
+    }
+    
+    // Remember the directory of a successful pick for the next dialog
+    fn remember_dialog_dir(&mut self, picked: &Path) {
+        let dir = if picked.is_dir() {
+            picked
+        } else {
+            match picked.parent() {
+                Some(parent) => parent,
+                None => return,
+            }
+        };
+        
+        let dir = dir.to_string_lossy().to_string();
+        if self.config.last_dialog_dir.as_ref() == Some(&dir) {
+            return;
+        }
+        self.config.last_dialog_dir = Some(dir);
+        self.persist_config();
+    }
+    
+    fn add_to_playlist(&mut self) {
+        let extensions = get_supported_extensions();
+        if let Some(paths) = self.file_dialog()
+            .add_filter("Audio Files", &extensions)
+            .pick_files()
+        {
+            if let Some(first) = paths.first() {
+                self.remember_dialog_dir(first);
+            }
+            
+            let mut added = 0;
+            
+            let audio_files: Vec<PathBuf> = paths.into_iter().filter(|p| is_audio_file(p)).collect();
+            added += self.enqueue_all(audio_files);
+            
+            if added > 0 {
+                // If no song is playing, start with the first added song
+                if self.current_playlist_index.is_none() && !self.playlist.is_empty() {
+                    self.current_playlist_index = Some(0);
+                    self.play_current_song();
+                }
+            }
+        }
+    }
+    
+    // Add the URL typed into the stream box, starting it if nothing is playing
+    fn add_stream(&mut self) {
+        let url = PathBuf::from(self.stream_url_input.trim());
+        if !is_stream(&url) {
+            return;
+        }
+        let Some(index) = self.enqueue(url) else {
+            self.show_notification("The playlist is full");
+            return;
+        };
+        self.stream_url_input.clear();
+        
+        if self.current_playlist_index.is_none() {
+            self.current_playlist_index = Some(index);
+            self.play_current_song();
+        }
+    }
+    
+    // Add every audio file in a folder, and in its subfolders if that's turned on
+    fn add_folder(&mut self) {
+        let Some(dir) = self.file_dialog().pick_folder() else {
+            return;
+        };
+        self.remember_dialog_dir(&dir);
+        
+        let audio_files = scan_dir_for_audio(&dir, self.config.recursive_folder_scan);
+        if audio_files.is_empty() {
+            self.show_notification(&format!("No audio files found in {}", dir.display()));
+            return;
+        }
+        
+        if self.enqueue_all(audio_files) > 0
+            && self.current_playlist_index.is_none()
+            && !self.playlist.is_empty()
+        {
+            self.current_playlist_index = Some(0);
+            self.play_current_song();
+        }
+    }
+    
+    // Add the tracks from a saved M3U or PLS playlist, skipping any that have gone missing
+    fn open_playlist(&mut self) {
+        let Some(file) = self.file_dialog()
+            .add_filter("Playlist or Cue Sheet", &["m3u", "m3u8", "pls", "cue"])
+            .pick_file()
+        else {
+            return;
+        };
+        self.remember_dialog_dir(&file);
+        
+        // Each entry alongside the audio file it plays, which differ for cue sheet tracks.
+        // M3U and PLS playlists may carry trim points for their entries as well.
+        let extension_is = |extension: &str| file.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension));
+        let mut trims = TrackTrims::default();
+        let loaded = if extension_is("cue") {
+            load_cue(&file).map(|tracks| tracks.into_iter().map(|track| (track.path, track.file)).collect())
+        } else {
+            let entries = if extension_is("pls") { load_pls_with_trims(&file) } else { load_m3u_with_trims(&file) };
+            entries.map(|(entries, entry_trims)| {
+                trims = entry_trims;
+                entries.into_iter().map(|entry| {
+                    let audio = cue_track_for(&entry).map_or_else(|| entry.clone(), |track| track.file);
+                    (entry, audio)
+                }).collect::<Vec<_>>()
+            })
+        };
+        let tracks = match loaded {
+            Ok(tracks) => tracks,
+            Err(e) => {
+                log::error!("Error opening playlist: {}", e);
+                self.show_notification(&format!("Could not open playlist: {}", e));
+                return;
+            }
+        };
+        
+        let total = tracks.len();
+        let audio_files: Vec<PathBuf> = tracks.into_iter()
+            .filter(|(_, audio)| is_stream(audio) || (audio.is_file() && is_audio_file(audio)))
+            .map(|(entry, _)| entry)
+            .collect();
+        let missing = total - audio_files.len();
+        if missing > 0 {
+            log::warn!("{} track(s) in {} could not be found", missing, file.display());
+            self.show_notification(&format!("{} track(s) in the playlist could not be found", missing));
+        }
+        
+        // Trims go with the entries they were saved for, replacing any the track had
+        for entry in &audio_files {
+            self.trims.set(entry, trims.get(entry));
+        }
+        
+        if self.enqueue_all(audio_files) > 0
+            && self.current_playlist_index.is_none()
+            && !self.playlist.is_empty()
+        {
+            self.current_playlist_index = Some(0);
+            self.play_current_song();
+        }
+    }
+    
+    // Write the whole playlist, played tracks included, to an M3U or PLS file
+    fn save_playlist(&mut self) {
+        let Some(mut file) = self.file_dialog()
+            .add_filter("M3U Playlist", &["m3u", "m3u8"])
+            .add_filter("PLS Playlist", &["pls"])
+            .set_file_name("playlist.m3u")
+            .save_file()
+        else {
+            return;
+        };
+        if file.extension().is_none() {
+            file.set_extension("m3u");
+        }
+        self.remember_dialog_dir(&file);
+        
+        // Only tags already read are written, saving doesn't open every file again
+        let metadata = |path: &Path| self.metadata.peek(path).cloned();
+        let saved = if file.extension().is_some_and(|e| e.eq_ignore_ascii_case("pls")) {
+            save_pls(&self.playlist, &file, metadata, &self.trims)
+        } else {
+            save_m3u(&self.playlist, &file, metadata, &self.trims)
+        };
+        match saved {
+            Ok(()) => self.show_notification(&format!("Saved {} track(s) to {}", self.playlist.len(), file.display())),
+            Err(e) => {
+                log::error!("Error saving playlist: {}", e);
+                self.show_notification(&format!("Could not save playlist: {}", e));
+            }
+        }
+    }
+    
+    // Keep the playlist as it is now to load whenever the player starts without files
+    fn set_startup_playlist(&mut self) {
+        match save_startup_playlist(&self.playlist, &self.trims) {
+            Ok(()) => {
+                let message = if self.config.load_startup_playlist {
+                    format!("These {} track(s) will load when the player starts", self.playlist.len())
+                } else {
+                    "Startup playlist saved. Turn on loading it in Settings.".to_string()
+                };
+                self.show_notification(&message);
+            }
+            Err(e) => {
+                log::error!("Error saving startup playlist: {}", e);
+                self.show_notification(&format!("Could not save the startup playlist: {}", e));
+            }
+        }
+    }
+    
+    // Pick a partly listened queue back up at the first track that hasn't been played yet
+    fn continue_from_unplayed(&mut self) {
+        match first_unplayed(&self.playlist, &self.played) {
+            Some(index) => {
+                self.current_playlist_index = Some(index);
+                self.play_current_song();
+            }
+            None => self.show_notification("Every track has been played. Use Start Over to play them again."),
+        }
+    }
+    
+    // Forget what has been played and go back to the top of the playlist
+    fn start_over(&mut self) {
+        self.played.clear();
+        self.played_count = 0;
+        self.navigator.shuffle_order.reset();
+        if !self.playlist.is_empty() {
+            self.current_playlist_index = Some(0);
+            self.play_current_song();
+        }
+    }
+    
+    // Index of the first entry in the "played" section at the bottom of the playlist
+    fn played_section_start(&self) -> usize {
+        self.playlist.len().saturating_sub(self.played_count)
+    }
+    
+    // Add tracks to the playlist, refusing any beyond the configured maximum.
+    // Returns how many were added.
+    fn enqueue_all(&mut self, paths: Vec<PathBuf>) -> usize {
+        // Files named like audio but holding something else would only fail when they came up
+        let (paths, not_audio): (Vec<_>, Vec<_>) = paths.into_iter().partition(|path| {
+            match cue_track_for(path) {
+                Some(track) => is_probably_audio(&track.file),
+                None => is_probably_audio(path),
+            }
+        });
+        if let Some(first) = not_audio.first() {
+            log::warn!("Skipped {} file(s) that don't look like audio, such as {}", not_audio.len(), first.display());
+            let name = first.file_name().unwrap_or_default().to_string_lossy().to_string();
+            self.show_notification(&match not_audio.len() {
+                1 => format!("{} isn't an audio file, skipped it", name),
+                count => format!("Skipped {} files that aren't audio, including {}", count, name),
+            });
+        }
+        
+        let total = paths.len();
+        let mut added = 0;
+        for path in paths {
+            if self.enqueue(path).is_none() {
+                break;
+            }
+            added += 1;
+        }
+        
+        if added < total {
+            log::warn!("Playlist limit of {} reached, skipped {} file(s)", self.config.max_playlist_entries, total - added);
+            self.show_notification(&format!(
+                "Playlist is full ({} tracks), skipped {} file(s)",
+                self.config.max_playlist_entries,
+                total - added
+            ));
+        }
+        added
+    }
+    
+    // Add a track to the end of the upcoming tracks (above the played section).
+    // Returns where it went, or None if the playlist is already at its maximum size.
+    fn enqueue(&mut self, path: PathBuf) -> Option<usize> {
+        if self.playlist.len() >= self.config.max_playlist_entries {
+            return None;
+        }
+        
+        let insert_at = self.played_section_start();
+        self.navigator.shuffle_order.add(path.clone());
+        self.playlist.insert(insert_at, path);
+        self.playlist_summary = None;
+        
+        if let Some(current) = self.current_playlist_index {
+            if current >= insert_at {
+                self.current_playlist_index = Some(current + 1);
+            }
+        }
+        if let Some(selected) = self.selected_song_index {
+            if selected >= insert_at {
+                self.selected_song_index = Some(selected + 1);
+            }
+        }
+        Some(insert_at)
+    }
+    
+    // Move the track that just finished below the played divider
+    fn move_current_to_played(&mut self) {
+        let Some(index) = self.current_playlist_index else {
+            return;
+        };
+        if index >= self.playlist.len() {
+            return;
+        }
+        
+        let was_played = index >= self.played_section_start();
+        let entry = self.playlist.remove(index);
+        self.playlist.push(entry);
+        if !was_played {
+            self.played_count += 1;
+        }
+        
+        // Keep the selection on the same track
+        if let Some(selected) = self.selected_song_index {
+            self.selected_song_index = match selected {
+                s if s == index => Some(self.playlist.len() - 1),
+                s if s > index => Some(s - 1),
+                s => Some(s),
+            };
+        }
+        
+        // Point just before the next upcoming track so play_next_song picks it up,
+        // or at the end of the list when nothing upcoming is left
+        self.current_playlist_index = if index < self.played_section_start() {
+            index.checked_sub(1)
+        } else {
+            Some(self.playlist.len() - 1)
+        };
+    }
+    
+    // Remove a single entry, keeping the current and selected indices pointing at the same tracks
+    fn remove_index(&mut self, index: usize) {
+        if index >= self.playlist.len() {
+            return;
+        }
+        
+        // Removing a played entry shrinks the played section
+        if index >= self.played_section_start() {
+            self.played_count -= 1;
+        }
+        
+        // If the currently playing song is removed, stop playback
+        if Some(index) == self.current_playlist_index {
+            if let Ok(player) = self.player.lock() {
+                player.stop();
+            }
+            self.is_playing = false;
+        }
+        
+        // Update current playlist index if needed
+        if let Some(current) = self.current_playlist_index {
+            self.current_playlist_index = match current {
+                // If removing the current item
+                c if c == index => {
+                    if c > 0 {
+                        // If not the first item, move to previous
+                        Some(c - 1)
+                    } else if self.playlist.len() > 1 {
+                        // If first item and playlist has more items, stay at 0
+                        // (which will point to the next song after removal)
+                        Some(0)
+                    } else {
+                        // If removing the only item
+                        None
+                    }
+                },
+                // If removing an item before current, decrement current index
+                c if c > index => Some(c - 1),
+                // Otherwise keep the same index
+                c => Some(c),
+            };
+        }
+        
+        // Remove the track, and the trim that went with it
+        let removed = self.playlist.remove(index);
+        self.trims.clear(&removed);
+        self.playlist_summary = None;
+        
+        if let Some(selected) = self.selected_song_index {
+            self.selected_song_index = match selected {
+                s if s == index => None,
+                s if s > index => Some(s - 1),
+                s => Some(s),
+            };
+        }
+    }
+    
+    fn remove_from_playlist(&mut self) {
+        if let Some(index) = self.selected_song_index {
+            if index < self.playlist.len() {
+                self.remove_index(index);
+                
+                // Select the next track for better UX
+                if !self.playlist.is_empty() {
+                    if index < self.playlist.len() {
+                        // If there's a next track at same position, select it
+                        self.selected_song_index = Some(index);
+                    } else {
+                        // If we removed the last track, select the new last one
+                        self.selected_song_index = Some(self.playlist.len() - 1);
+                    }
+                } else {
+                    // No tracks left
+                    self.selected_song_index = None;
+                }
+            }
+        }
+    }
+    
+    // Keyboard control of the playlist while one of its rows has focus, so the search box and
+    // sliders keep their keys. Up/Down move through the rows shown, Delete removes the
+    // selected track and letters jump to a track by name; Enter is handled on the row itself.
+    fn handle_playlist_keys(&mut self, ui: &mut egui::Ui, focused: usize, rows: &[PlaylistRow]) {
+        // Stop egui moving focus with the arrows as well
+        if let Some(id) = ui.memory(|m| m.focused()) {
+            ui.memory_mut(|m| m.set_focus_lock_filter(id, egui::EventFilter { vertical_arrows: true, ..Default::default() }));
+        }
+        let (up, down, delete) = ui.input_mut(|i| (
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            i.consume_key(egui::Modifiers::NONE, egui::Key::Delete),
+        ));
+        
+        // The highlight follows focus, so it's always on the row the keys act on
+        self.selected_song_index = Some(focused);
+        let position = rows.iter().position(|(index, ..)| *index == focused);
+        let target = match position {
+            Some(position) if up => position.checked_sub(1),
+            Some(position) if down => Some(position + 1),
+            _ => None,
+        };
+        if let Some(&row) = target.and_then(|position| rows.get(position)) {
+            self.select_row(ui, row);
+        }
+        
+        if delete {
+            self.remove_from_playlist();
+        }
+        
+        let typed: String = ui.input(|i| i.events.iter().filter_map(|event| match event {
+            egui::Event::Text(text) => Some(text.as_str()),
+            _ => None,
+        }).collect());
+        if !typed.is_empty() {
+            self.type_ahead(ui, &typed, rows);
+        }
+    }
+    
+    // Jump to the next row whose name starts with what's been typed, see `type_ahead_prefix`
+    fn type_ahead(&mut self, ui: &mut egui::Ui, typed: &str, rows: &[PlaylistRow]) {
+        let now = std::time::Instant::now();
+        if self.type_ahead_at.is_none_or(|at| now.duration_since(at) > TYPE_AHEAD_RESET) {
+            self.type_ahead_buffer.clear();
+        }
+        self.type_ahead_at = Some(now);
+        // No name starts with a space
+        let typed = if self.type_ahead_buffer.is_empty() { typed.trim_start() } else { typed };
+        self.type_ahead_buffer.push_str(&typed.to_lowercase());
+        if self.type_ahead_buffer.is_empty() {
+            return;
+        }
+        
+        let (prefix, past_selected) = type_ahead_prefix(&self.type_ahead_buffer);
+        let shown: Vec<usize> = rows.iter().map(|(index, ..)| *index).collect();
+        let target = type_ahead_index(&shown, self.selected_song_index, past_selected, |index| {
+            let path = &self.playlist[index];
+            // Only the rows on screen have been asked for, the rest match on what's been read
+            matches_type_ahead(path, self.metadata.peek(path), prefix)
+        });
+        if let Some(&row) = target.and_then(|target| rows.iter().find(|(index, ..)| *index == target)) {
+            self.select_row(ui, row);
+        }
+    }
+    
+    // Select a playlist row, moving keyboard focus to it and scrolling it into view. A row
+    // that isn't drawn yet takes focus once it has scrolled on screen.
+    fn select_row(&mut self, ui: &mut egui::Ui, (index, id, rect): PlaylistRow) {
+        self.selected_song_index = Some(index);
+        match id {
+            Some(id) => ui.memory_mut(|m| m.request_focus(id)),
+            None => self.focus_selected = true,
+        }
+        ui.scroll_to_rect(rect, None);
+    }
+    
+    // Drop entries whose files have been moved or deleted since they were added
+    fn remove_missing_files(&mut self) {
+        let (_, missing) = partition_existing(&self.playlist);
+        if missing.is_empty() {
+            self.show_notification("No missing files in the playlist");
+            return;
+        }
+        
+        let missing: HashSet<PathBuf> = missing.into_iter().collect();
+        // From the end, so the indices still to check don't shift
+        let mut removed = 0;
+        for index in (0..self.playlist.len()).rev() {
+            if missing.contains(&self.playlist[index]) {
+                self.remove_index(index);
+                removed += 1;
+            }
+        }
+        
+        let message = if removed == 1 {
+            "Removed 1 missing file".to_string()
+        } else {
+            format!("Removed {} missing files", removed)
+        };
+        self.show_notification(&message);
+    }
+    
+    // Stop playing and empty the playlist, forgetting everything about the old tracks
+    fn clear_playlist(&mut self) {
+        self.remember_position();
+        self.resume_track = None;
+        if let Ok(player) = self.player.lock() {
+            player.stop_preview();
+            player.stop();
+        }
+        self.is_playing = false;
+        self.started_playing = false;
+        self.song_position = Duration::from_secs(0);
+        self.song_duration = None;
+        
+        self.playlist.clear();
+        self.trims = TrackTrims::default();
+        self.playlist_summary = None;
+        self.current_playlist_index = None;
+        self.selected_song_index = None;
+        self.current_file = None;
+        self.played.clear();
+        self.played_count = 0;
+        self.navigator.reset();
+    }
+    
+    fn show_clear_confirmation(&mut self, ctx: &egui::Context) {
+        if !self.confirm_clear {
+            return;
+        }
+        
+        let mut open = true;
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Clear Playlist")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("Remove all {} tracks from the playlist?", self.playlist.len()));
+                ui.horizontal(|ui| {
+                    confirmed = a11y::button(ui, "Clear", "Clear the playlist").clicked();
+                    cancelled = a11y::button(ui, "Cancel", "Keep the playlist").clicked();
+                });
+            });
+        cancelled |= ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        
+        if confirmed {
+            self.clear_playlist();
+        }
+        self.confirm_clear = open && !confirmed && !cancelled;
+    }
+    
+    // Sort key for one playlist entry, None when the tag is missing (or not read yet)
+    fn sort_key(&mut self, index: usize, column: SortColumn) -> Option<SortValue> {
+        let path = &self.playlist[index];
+        let text = |value: &Option<String>| value.clone().map(SortValue::Text);
+        match column {
+            SortColumn::Name => path.file_name().map(|n| SortValue::Text(n.to_string_lossy().to_string())),
+            SortColumn::Title => self.metadata.get(path).and_then(|m| text(&m.title)),
+            SortColumn::Artist => self.metadata.get(path).and_then(|m| text(&m.artist)),
+            SortColumn::Duration => self.metadata.get(path).and_then(|m| m.duration).map(|d| SortValue::Number(d.as_millis() as u64)),
+            SortColumn::Genre => self.metadata.get(path).and_then(|m| text(&m.genre)),
+            SortColumn::Year => self.metadata.get(path).and_then(|m| m.year).map(|y| SortValue::Number(y as u64)),
+            // Unplayed tracks have no key and go last
+            SortColumn::PlayCount => match self.play_counts.get(path) {
+                0 => None,
+                count => Some(SortValue::Number(count as u64)),
+            },
+        }
+    }
+    
+    // Sort on a column, reversing the order if it was already sorted on that column.
+    // Upcoming and played tracks are sorted separately so the divider stays put.
+    fn sort_playlist(&mut self, column: SortColumn) {
+        let ascending = match self.sort {
+            Some((sorted, ascending)) if sorted == column => !ascending,
+            // Most played first
+            _ => column != SortColumn::PlayCount,
+        };
+        self.sort = Some((column, ascending));
+        
+        let keys: Vec<Option<SortValue>> = (0..self.playlist.len())
+            .map(|index| self.sort_key(index, column))
+            .collect();
+        let played_start = self.played_section_start();
+        let upcoming = sorted_order(&keys[..played_start], ascending);
+        let played = sorted_order(&keys[played_start..], ascending);
+        let order: Vec<usize> = upcoming.into_iter()
+            .chain(played.into_iter().map(|i| i + played_start))
+            .collect();
+        
+        // Keep the playing and selected entries pointing at the same tracks
+        let new_position = |old: usize| order.iter().position(|&i| i == old);
+        self.current_playlist_index = self.current_playlist_index.and_then(new_position);
+        self.selected_song_index = self.selected_song_index.and_then(new_position);
+        self.playlist = order.iter().map(|&i| self.playlist[i].clone()).collect();
+    }
+    
+    // Column header text, with an arrow on the column the playlist is sorted by
+    fn header_text(label: &str, column: SortColumn, sort: Option<(SortColumn, bool)>) -> String {
+        match sort {
+            Some((sorted, true)) if sorted == column => format!("{} ⏶", label),
+            Some((sorted, false)) if sorted == column => format!("{} ⏷", label),
+            _ => label.to_string(),
+        }
+    }
+    
+    fn move_up_in_playlist(&mut self) {
+        if let Some(index) = self.selected_song_index {
+            if index > 0 && index < self.playlist.len() {
+                self.playlist.swap(index, index - 1);
                 // Update current index if it was one of the swapped items
                 if let Some(current) = self.current_playlist_index {
                     self.current_playlist_index = match current {
@@ -211,123 +1696,1641 @@ impl MusicPlayerApp {
                         c => Some(c),
                     };
                 }
-                self.selected_song_index = Some(index - 1);
+                self.selected_song_index = Some(index - 1);
+            }
+        }
+    }
+    
+    fn move_down_in_playlist(&mut self) {
+        if let Some(index) = self.selected_song_index {
+            if index < self.playlist.len() - 1 {
+                self.playlist.swap(index, index + 1);
+                // Update current index if it was one of the swapped items
+                if let Some(current) = self.current_playlist_index {
+                    self.current_playlist_index = match current {
+                        c if c == index => Some(c + 1),
+                        c if c == index + 1 => Some(c - 1),
+                        c => Some(c),
+                    };
+                }
+                self.selected_song_index = Some(index + 1);
+            }
+        }
+    }
+    
+    // Move a track to `to`, counted as an insert position before the move, so
+    // `playlist.len()` puts it at the end. Indices pointing into the playlist follow it.
+    fn move_playlist_entry(&mut self, from: usize, to: usize) {
+        if from >= self.playlist.len() {
+            return;
+        }
+        let to = if to > from { to - 1 } else { to }.min(self.playlist.len() - 1);
+        if to == from {
+            return;
+        }
+        
+        let entry = self.playlist.remove(from);
+        self.playlist.insert(to, entry);
+        
+        let follow = |index| index_after_move(index, from, to);
+        self.current_playlist_index = self.current_playlist_index.map(follow);
+        self.selected_song_index = self.selected_song_index.map(follow);
+        for index in &mut self.navigator.history {
+            *index = follow(*index);
+        }
+    }
+    
+    // Carry out an action picked from a playlist row's context menu
+    fn apply_row_action(&mut self, ui: &mut egui::Ui, index: usize, action: RowAction) {
+        let Some(path) = self.playlist.get(index).cloned() else {
+            return;
+        };
+        // The file behind a cue sheet track is the one to show or copy
+        let file = cue_track_for(&path).map_or_else(|| path.clone(), |track| track.file);
+        match action {
+            RowAction::Play => {
+                self.current_playlist_index = Some(index);
+                self.play_current_song();
+            }
+            RowAction::Requeue(placement) => self.requeue(index, placement),
+            // The same as the buttons under the playlist, on this row rather than the selected one
+            RowAction::Remove => {
+                self.selected_song_index = Some(index);
+                self.remove_from_playlist();
+            }
+            RowAction::MoveUp => {
+                self.selected_song_index = Some(index);
+                self.move_up_in_playlist();
+            }
+            RowAction::MoveDown => {
+                self.selected_song_index = Some(index);
+                self.move_down_in_playlist();
+            }
+            RowAction::ShowInFileManager => {
+                if let Err(e) = show_in_file_manager(&file) {
+                    log::error!("Couldn't open the file manager for {}: {}", file.display(), e);
+                    self.show_notification("Couldn't open the file manager");
+                }
+            }
+            RowAction::CopyPath => {
+                let text = file.display().to_string();
+                ui.output_mut(|o| o.copied_text = text);
+                self.show_notification("Path copied to clipboard");
+            }
+        }
+    }
+    
+    // Play Next and Add to Queue. The track is moved rather than copied, keeping the current
+    // and selected tracks where they are. With shuffle on, the shuffle order is changed to
+    // match, since the playlist order isn't followed.
+    fn requeue(&mut self, index: usize, placement: QueuePlacement) {
+        if index >= self.playlist.len() || Some(index) == self.current_playlist_index {
+            return;
+        }
+        let played_start = self.played_section_start();
+        let to = match placement {
+            QueuePlacement::Next => self.current_playlist_index.map_or(0, |current| current + 1),
+            QueuePlacement::Last => played_start,
+        };
+        let path = self.playlist[index].clone();
+        self.move_playlist_entry(index, to);
+        // A track from the played section comes back up to the upcoming ones
+        if index >= played_start && to <= played_start {
+            self.played_count -= 1;
+        }
+        
+        match placement {
+            QueuePlacement::Next => self.navigator.shuffle_order.play_next(path),
+            QueuePlacement::Last => self.navigator.shuffle_order.play_last(path),
+        }
+    }
+    
+    fn check_song_finished(&mut self) {
+        if self.is_playing {
+            let song_finished = if let Ok(player) = self.player.lock() {
+                player.finished()
+            } else {
+                false
+            };
+            
+            if song_finished {
+                self.finish_song();
+            }
+        }
+    }
+    
+    // Move on from a track that played to its end, or stop, depending on the play mode
+    fn finish_song(&mut self) {
+        self.forget_position();
+        match self.skip_guard.track_finished(self.listen_time.listened()) {
+            FinishKind::Played => {
+                if let Some(path) = &self.current_file {
+                    self.broken_tracks.remove(path);
+                    self.played.insert(path.clone());
+                }
+            },
+            FinishKind::InstantSkip => {
+                // Likely a broken file: flag it and move on without counting it as played
+                if let Some(path) = self.current_file.clone() {
+                    log::warn!("{} ended immediately, skipping it", path.display());
+                    self.broken_tracks.insert(path);
+                }
+                self.play_next_song();
+                return;
+            },
+            FinishKind::TooManySkips => {
+                self.cancel_sleep_timer();
+                if let Some(path) = self.current_file.clone() {
+                    self.broken_tracks.insert(path);
+                }
+                self.is_playing = false;
+                let message = format!("Stopped after {} tracks in a row failed to play", MAX_INSTANT_SKIPS);
+                self.status.raise(ConditionKind::DecodeFailures, Severity::Warning, message);
+                return;
+            },
+        }
+        
+        // A sleep timer set for the end of this track goes off instead of moving on
+        if self.sleep_timer == Some(SleepTimer::EndOfTrack) {
+            self.sleep_timer = None;
+            self.go_to_sleep(true);
+            return;
+        }
+        
+        // Checked before looping or repeating, which would only hit the same error again.
+        // After a gapless rollover the player is already on the next track.
+        let ended_early = self.gapless_handover.is_none()
+            && self.player.lock().is_ok_and(|player| player.ended_early());
+        if ended_early {
+            self.handle_track_cut_short();
+            return;
+        }
+        
+        // Looping only applies while the opened file is the only one in the playlist
+        if self.loop_single && self.playlist.len() == 1 {
+            self.play_current_song();
+            return;
+        }
+        
+        // Broken files never get here (the skip guard moves past them, however slowly the
+        // end is noticed), so this can't spin
+        if self.repeat_mode == RepeatMode::One {
+            self.play_current_song();
+            return;
+        }
+        
+        if self.config.move_played_to_bottom {
+            self.move_current_to_played();
+        }
+        self.play_next_song();
+    }
+    
+    // The track stopped well short of its length, so the rest of the file most likely
+    // couldn't be decoded. It isn't counted as played.
+    fn handle_track_cut_short(&mut self) {
+        if let Some(path) = self.current_file.clone() {
+            self.played.remove(&path);
+            log::warn!(
+                "{} stopped at {} of {}, the rest of it may be damaged",
+                path.display(),
+                Self::format_duration(self.song_position),
+                self.song_duration.map(Self::format_duration).unwrap_or_else(|| "--:--".to_string())
+            );
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            self.show_notification(&format!("{} stopped partway through and may be damaged", name));
+        }
+        
+        if self.config.skip_on_playback_error {
+            self.play_next_song();
+        } else {
+            self.is_playing = false;
+        }
+    }
+    
+    // Switch between the full window and the mini player, resizing the window to suit
+    fn toggle_mini_mode(&mut self, ctx: &egui::Context) {
+        let size = if self.config.mini_mode {
+            self.full_window_size.take().unwrap_or(FULL_WINDOW_SIZE)
+        } else {
+            self.full_window_size = ctx.input(|i| i.viewport().inner_rect).map(|rect| rect.size());
+            MINI_WINDOW_SIZE
+        };
+        self.config.mini_mode = !self.config.mini_mode;
+        ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        self.persist_config();
+    }
+    
+    fn set_volume(&mut self, volume: f32) {
+        // Changing the volume while muted unmutes
+        self.pre_mute_volume = None;
+        let volume = volume.clamp(0.0, 1.0);
+        self.volume = volume;
+        self.config.volume = volume;  // Update config with new volume
+        
+        if let Ok(player) = self.player.lock() {
+            player.set_volume(volume);
+        }
+        
+        // Save config when volume changes
+        self.persist_config();
+    }
+    
+    // Silence the output without touching the volume setting, or bring it back
+    fn toggle_mute(&mut self) {
+        match self.pre_mute_volume.take() {
+            Some(volume) => self.volume = volume,
+            None => self.pre_mute_volume = Some(self.volume),
+        }
+        let volume = self.output_volume();
+        if let Ok(player) = self.player.lock() {
+            player.set_volume(volume);
+        }
+    }
+    
+    // What the player should be set to: the volume, less any sleep fade, or nothing when muted
+    fn output_volume(&self) -> f32 {
+        if self.pre_mute_volume.is_some() {
+            return 0.0;
+        }
+        self.volume * self.sleep_timer.map(|timer| timer.fade()).unwrap_or(1.0)
+    }
+    
+    fn update_song_position(&mut self) {
+        if self.is_playing && !self.seeking {
+            if let Ok(player) = self.player.lock() {
+                let (position, duration) = self.track_progress(&*player);
+                self.song_position = position;
+                self.buffered_position = player.get_buffered_position();
+                
+                // Update song duration if not set yet
+                if self.song_duration.is_none() {
+                    self.song_duration = duration;
+                }
+            }
+        }
+    }
+    
+    // Position and length within the current track. A cue sheet track is only part of its
+    // file, so both are measured from where it starts, and it ends where the next one starts.
+    fn track_progress(&self, player: &impl AudioBackend) -> (Duration, Option<Duration>) {
+        let Some((start, end)) = self.cue_range else {
+            return (player.position(), player.duration());
+        };
+        let length = end.or(player.duration()).map(|end| end.saturating_sub(start));
+        (player.position().saturating_sub(start), length)
+    }
+    
+    // Where in the file a position in the current track is
+    fn file_position(&self, position: Duration) -> Duration {
+        self.cue_range.map_or(position, |(start, _)| start + position)
+    }
+    
+    // Scroll to a new current track, unless the user has just scrolled somewhere else
+    fn follow_current_track(&mut self) {
+        if self.current_playlist_index == self.followed_index {
+            return;
+        }
+        self.followed_index = self.current_playlist_index;
+        
+        let scrolled_by_hand = self.manual_scroll_at.is_some_and(|at| at.elapsed() < FOLLOW_PAUSE);
+        if self.config.follow_current_track && !scrolled_by_hand {
+            self.scroll_to_current = true;
+        }
+    }
+    
+    // Worked out again when the playlist grows or shrinks, or more lengths are read.
+    // Reordering doesn't change it.
+    fn playlist_summary(&mut self) -> String {
+        if let Some(summary) = &self.playlist_summary {
+            return summary.clone();
+        }
+        let len = self.playlist.len();
+        
+        // Reading tags for every track would only churn the cache once it can't hold them all
+        let read_all = len <= self.config.cache_capacity;
+        let mut durations = Vec::with_capacity(len);
+        for path in &self.playlist {
+            let metadata = if read_all { self.metadata.get(path) } else { self.metadata.peek(path) };
+            durations.push(metadata.and_then(|m| m.duration));
+        }
+        
+        let summary = playlist_summary(durations);
+        self.playlist_summary = Some(summary.clone());
+        summary
+    }
+    
+    fn format_duration(duration: Duration) -> String {
+        let total_seconds = duration.as_secs();
+        let minutes = total_seconds / 60;
+        let seconds = total_seconds % 60;
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+    
+    // Where `x` falls along a horizontal slider, from 0.0 to 1.0. The ends of the rail are
+    // inset by the handle's radius, as egui draws it.
+    fn slider_ratio_at(rect: egui::Rect, x: f32) -> f32 {
+        let handle_radius = rect.height() / 2.5;
+        let rail = rect.x_range().shrink(handle_radius);
+        if rail.span() <= 0.0 {
+            return 0.0;
+        }
+        ((x - rail.min) / rail.span()).clamp(0.0, 1.0)
+    }
+    
+    fn seek_to_position(&mut self, position_ratio: f32) {
+        if let Some(duration) = self.song_duration {
+            let position = Duration::from_secs_f32(position_ratio * duration.as_secs_f32());
+            self.song_position = position;
+            let target = self.file_position(position);
+            
+            let job = if let Ok(player) = self.player.lock() {
+                player.begin_seek(target)
+            } else {
+                return;
+            };
+            match job {
+                Ok(job) => self.run_seek_in_background(job),
+                Err(e) => log::error!("Error seeking: {}", e),
+            }
+        }
+    }
+    
+    // Seeking can mean waiting on the audio thread or decoding the file up to the new
+    // position, so that happens on a worker while the UI keeps drawing. The player is
+    // only locked again briefly at the end.
+    fn run_seek_in_background(&self, job: SeekJob) {
+        let player = self.player.clone();
+        std::thread::spawn(move || {
+            let outcome = job.run();
+            if let Ok(player) = player.lock() {
+                match player.finish_seek(outcome) {
+                    Ok(SeekAccuracy::Exact) => {}
+                    Ok(SeekAccuracy::Approximate) => log::debug!("Seek position is approximate for this file"),
+                    Err(e) => log::error!("Error seeking: {}", e),
+                }
+            }
+        });
+    }
+    
+    // Method to handle files dropped by the user or from the OS
+    // Files opened from the desktop (Finder's "Open with" on macOS) are handled like drops
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let mut dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect()
+        });
+        dropped.extend(crate::open_events::take_opened_files());
+        
+        if !dropped.is_empty() {
+            let mut new_files = Vec::new();
+            let mut folders = Vec::new();
+            
+            // Extract valid audio files from the dropped files
+            for path in dropped {
+                if path.is_dir() {
+                    folders.push(path.clone());
+                    // Keeps its place among the other drops, filled in below
+                    new_files.push(path);
+                } else if is_audio_file(&path) {
+                    new_files.push(path.clone());
+                    // Store these files to process later
+                    self.pending_drops.push(path);
+                }
+            }
+            
+            // A folder adds everything in it and its subfolders, in the order Add Folder uses.
+            // Tracks already in the playlist, or found twice, are only added once.
+            let mut seen: HashSet<PathBuf> = self.playlist.iter().cloned().collect();
+            new_files = new_files.into_iter()
+                .flat_map(|path| {
+                    if folders.contains(&path) {
+                        scan_dir_for_audio(&path, true).into_iter()
+                            .filter(|track| seen.insert(track.clone()))
+                            .collect()
+                    } else {
+                        vec![path]
+                    }
+                })
+                .collect();
+            
+            // Process the dropped files if any found
+            if !new_files.is_empty() {
+                let was_empty = self.playlist.is_empty();
+                
+                // Add files to the playlist
+                let added = self.enqueue_all(new_files);
+                
+                if let Some(folder) = folders.first() {
+                    let source = match folders.len() {
+                        1 => folder.file_name().unwrap_or(folder.as_os_str()).to_string_lossy().to_string(),
+                        count => format!("{} folders", count),
+                    };
+                    let plural = if added == 1 { "" } else { "s" };
+                    self.show_notification(&format!("Added {} track{} from {}", added, plural, source));
+                }
+                
+                // If playlist was empty before, start playing the first added file
+                if let Some(index) = crate::open_events::first_to_play(was_empty, self.playlist.len()) {
+                    self.current_playlist_index = Some(index);
+                    self.play_current_song();
+                }
+            } else if !folders.is_empty() {
+                let message = match folders.as_slice() {
+                    [folder] => format!("No new audio files found in {}", folder.display()),
+                    _ => "No new audio files found in the dropped folders".to_string(),
+                };
+                self.show_notification(&message);
+            }
+        }
+    }
+
+    // An "Open with" launch without files on the command line expects them to arrive as
+    // open events. Say so if they never do, as nothing else will.
+    fn check_opened_files(&mut self) {
+        let Some(since) = self.awaiting_opened_files else {
+            return;
+        };
+        if !self.playlist.is_empty() {
+            self.awaiting_opened_files = None;
+        } else if since.elapsed() > OPENED_FILES_TIMEOUT {
+            log::warn!("Launched to open files, but none arrived within {} seconds", OPENED_FILES_TIMEOUT.as_secs());
+            self.show_notification("The files to open never arrived, add them from the File menu or drop them here");
+            self.awaiting_opened_files = None;
+        }
+    }
+    
+    // While files are dragged over the window, show whether dropping them will add anything
+    fn show_drop_overlay(&self, ctx: &egui::Context) {
+        let (audio, total) = ctx.input(|i| {
+            let audio = i.raw.hovered_files.iter().filter(|file| Self::is_audio_payload(file)).count();
+            (audio, i.raw.hovered_files.len())
+        });
+        if total == 0 {
+            return;
+        }
+        
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let (text, color) = if audio == 0 {
+            ("No audio files, nothing will be added".to_string(), egui::Color32::from_rgba_unmultiplied(180, 50, 50, 110))
+        } else if audio < total {
+            let text = format!("Drop to add {} audio file{} ({} other{} ignored)", audio, plural(audio), total - audio, plural(total - audio));
+            (text, egui::Color32::from_rgba_unmultiplied(40, 160, 60, 110))
+        } else {
+            (format!("Drop to add {} audio file{}", audio, plural(audio)), egui::Color32::from_rgba_unmultiplied(40, 160, 60, 110))
+        };
+        
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay")));
+        let rect = ctx.screen_rect();
+        painter.rect_filled(rect, 0.0, color);
+        painter.text(rect.center(), egui::Align2::CENTER_CENTER, text, egui::FontId::proportional(20.0), egui::Color32::WHITE);
+    }
+    
+    // Some platforms only give a MIME type while hovering, not the path
+    fn is_audio_payload(file: &egui::HoveredFile) -> bool {
+        match &file.path {
+            // A folder is scanned for audio files when it's dropped
+            Some(path) => path.is_dir() || is_audio_file(path),
+            None => file.mime.starts_with("audio/"),
+        }
+    }
+    
+    // Append audio files that showed up in the watched folder
+    fn check_watched_folder(&mut self) {
+        let new_files = match &mut self.folder_watcher {
+            Some(watcher) => watcher.poll(),
+            None => return,
+        };
+        
+        let new_files: Vec<PathBuf> = new_files.into_iter()
+            .filter(|path| !self.playlist.contains(path))
+            .collect();
+        
+        if new_files.is_empty() {
+            return;
+        }
+        
+        let was_empty = self.playlist.is_empty();
+        let added = self.enqueue_all(new_files);
+        if added == 0 {
+            return;
+        }
+        
+        if was_empty {
+            self.current_playlist_index = Some(0);
+            self.play_current_song();
+        }
+        
+        self.show_notification(&format!("Added {} new file(s) from watched folder", added));
+    }
+
+    // Stop playback and rewind. Depending on the config the track stays armed for the
+    // next Play or is cleared entirely.
+    fn stop_playback(&mut self) {
+        // Kept before the rewind, and nothing is playing afterwards to keep
+        self.remember_position();
+        self.resume_track = None;
+        if let Ok(player) = self.player.lock() {
+            player.stop();
+        }
+        self.is_playing = false;
+        self.song_position = Duration::from_secs(0);
+        
+        if self.config.stop_behavior == StopBehavior::ClearTrack {
+            self.current_playlist_index = None;
+            self.current_file = None;
+            self.song_duration = None;
+        }
+    }
+    
+    fn set_sleep_timer(&mut self, timer: SleepTimer) {
+        self.cancel_sleep_timer();
+        self.sleep_timer = Some(timer);
+    }
+    
+    fn cancel_sleep_timer(&mut self) {
+        if self.sleep_timer.take().is_some() {
+            // Undo any fade that was under way
+            let volume = self.output_volume();
+            if let Ok(player) = self.player.lock() {
+                player.set_volume(volume);
+            }
+        }
+    }
+    
+    // Fade out as a timed sleep runs down, then carry out the sleep action
+    fn check_sleep_timer(&mut self, ctx: &egui::Context) {
+        if self.quit_requested {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            return;
+        }
+        
+        let Some(timer) = self.sleep_timer else {
+            return;
+        };
+        
+        if timer.is_due() {
+            self.cancel_sleep_timer();
+            self.go_to_sleep(false);
+        } else if timer.remaining().is_some() {
+            // The fade leaves a panic stop alone, Play brings back the volume it had
+            let volume = self.output_volume();
+            if let Ok(player) = self.player.lock() {
+                if !player.is_killed() {
+                    player.set_volume(volume);
+                }
+            }
+        }
+    }
+    
+    // `track_ended` is set when the timer waited for the current track to finish
+    fn go_to_sleep(&mut self, track_ended: bool) {
+        match self.config.sleep_action {
+            SleepAction::Pause if track_ended => {
+                // Line up the next track without starting it, so Play carries on from there
+                if let Some(next) = self.navigator.upcoming(&self.playlist, self.current_playlist_index, self.current_file.as_ref(), self.shuffle_mode, false) {
+                    self.current_playlist_index = Some(next);
+                    self.current_file = self.playlist.get(next).cloned();
+                    self.song_position = Duration::ZERO;
+                    self.song_duration = None;
+                }
+                self.is_playing = false;
+            },
+            SleepAction::Pause => self.apply_command(PlayerCommand::Pause),
+            SleepAction::Stop => self.stop_playback(),
+            SleepAction::Quit => {
+                self.stop_playback();
+                self.quit_requested = true;
+            },
+        }
+        log::info!("Sleep timer went off ({:?})", self.config.sleep_action);
+    }
+    
+    // Panic stop: silence everything right now, but keep the position so Play carries on
+    fn kill_audio(&mut self) {
+        if let Ok(player) = self.player.lock() {
+            player.kill_audio();
+        }
+        self.is_playing = false;
+        self.show_notification("Audio killed. Press Play to resume.");
+    }
+    
+    // Global shortcuts that work no matter which widget has focus
+    fn handle_global_shortcuts(&mut self, ctx: &egui::Context) {
+        // The panic key only stops sound that's actually playing. It's checked before anything
+        // that looks at focus, so it works while typing in the search box too.
+        let panic_key = egui::Key::from_name(&self.config.panic_key).unwrap_or(egui::Key::Escape);
+        let dialog_open = std::mem::take(&mut self.menu_open) || self.confirm_clear;
+        let audible = self.is_playing || self.is_previewing();
+        if audible && panic_key_pressed(ctx, panic_key, dialog_open) {
+            self.kill_audio();
+        }
+        
+        // Space and the arrows only work when nothing has focus, since buttons, sliders,
+        // rows and text fields use them themselves. Left/Right seek, further with Shift.
+        if ctx.memory(|m| m.focused().is_none()) {
+            let (back, forward, coarse, toggle, louder, quieter) = ctx.input(|i| (
+                i.key_pressed(egui::Key::ArrowLeft),
+                i.key_pressed(egui::Key::ArrowRight),
+                i.modifiers.shift,
+                i.key_pressed(egui::Key::Space),
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+            ));
+            let step = self.config.seek_step(coarse);
+            if forward {
+                self.seek_relative(step);
+            } else if back {
+                self.seek_relative(-step);
+            }
+            if toggle {
+                self.apply_command(PlayerCommand::TogglePlayPause);
+            }
+            if louder || quieter {
+                let step = if louder { VOLUME_STEP } else { -VOLUME_STEP };
+                self.set_volume(perceptual_to_gain(gain_to_perceptual(self.volume) + step));
+            }
+        }
+        
+        // Letter shortcuts, except while typing (in the search box, for instance) or while
+        // a playlist row has focus, where letters jump to a track instead
+        if !ctx.wants_keyboard_input() && !self.playlist_focused {
+            let (next, previous, shuffle) = ctx.input(|i| {
+                let plain = |key| i.modifiers.is_none() && i.key_pressed(key);
+                (plain(egui::Key::N), plain(egui::Key::P), plain(egui::Key::S))
+            });
+            if next {
+                self.apply_command(PlayerCommand::Next);
+            } else if previous {
+                self.apply_command(PlayerCommand::Previous);
+            }
+            if shuffle {
+                self.apply_command(PlayerCommand::SetShuffle(!self.shuffle_mode));
+            }
+        }
+        
+        // Hidden diagnostic: Ctrl+Shift+T plays a test tone on the output device
+        if ctx.input(|i| i.modifiers.command && i.modifiers.shift && i.key_pressed(egui::Key::T)) {
+            self.play_test_tone();
+        }
+    }
+    
+    fn seek_relative(&mut self, delta_secs: f64) {
+        if self.current_playlist_index.is_none() || self.current_file.as_deref().is_some_and(is_stream) {
+            return;
+        }
+        // Kept within a cue sheet track, rather than running on into its neighbours
+        let target = self.cue_range.map(|_| relative_seek_target(self.song_position, delta_secs, self.song_duration));
+        let job = if let Ok(player) = self.player.lock() {
+            match target {
+                Some(SeekTarget::To(position)) => player.begin_seek(self.file_position(position)).map(Some),
+                Some(SeekTarget::End) => {
+                    player.end_track();
+                    Ok(None)
+                },
+                None => player.begin_seek_relative(delta_secs),
+            }
+        } else {
+            return;
+        };
+        match job {
+            Ok(Some(job)) => self.run_seek_in_background(job),
+            Ok(None) => {}
+            Err(e) => log::error!("Error seeking: {}", e),
+        }
+    }
+    
+    fn play_test_tone(&mut self) {
+        let result = if let Ok(player) = self.player.lock() {
+            player.play_test_tone(440.0, 3.0)
+        } else {
+            return;
+        };
+        
+        match result {
+            Ok(()) => self.show_notification("Playing 440Hz test tone"),
+            Err(e) => {
+                log::error!("Failed to play test tone: {}", e);
+                let message = format!("Audio output is not working: {}", e);
+                self.status.raise(ConditionKind::AudioOutput, Severity::Error, message);
+            }
+        }
+    }
+    
+    fn is_player_stopped(&self) -> bool {
+        if let Ok(player) = self.player.lock() {
+            player.is_stopped()
+        } else {
+            false
+        }
+    }
+    
+    /// Applies a control action from any front-end (buttons, remote control, media keys).
+    /// Playlist navigation is handled here, everything else goes to the player.
+    fn apply_command(&mut self, command: PlayerCommand) {
+        if let Err(e) = self.try_apply_command(command) {
+            log::error!("Failed to apply command: {}", e);
+        }
+    }
+    
+    // apply_command, saying what went wrong for front-ends that can pass it on (the remote control)
+    fn try_apply_command(&mut self, command: PlayerCommand) -> Result<()> {
+        match command {
+            PlayerCommand::Next => self.play_next_song(),
+            PlayerCommand::Previous => self.play_previous_song(),
+            PlayerCommand::Play(path) => {
+                if !path.exists() && !is_stream(&path) && cue_track_for(&path).is_none() {
+                    return Err(anyhow::anyhow!("{} does not exist", path.display()));
+                }
+                // Play the existing entry if the track is already queued
+                if !self.playlist.contains(&path) {
+                    self.enqueue_all(vec![path.clone()]);
+                }
+                let Some(index) = self.playlist.iter().position(|p| *p == path) else {
+                    return Err(anyhow::anyhow!("The playlist is full"));
+                };
+                self.current_playlist_index = Some(index);
+                self.play_current_song_checked()?;
+            },
+            PlayerCommand::SetVolume(volume) => self.set_volume(volume),
+            PlayerCommand::SetShuffle(shuffle) => {
+                if shuffle && !self.shuffle_mode {
+                    self.navigator.shuffle_order.reset();
+                }
+                self.shuffle_mode = shuffle;
+                if let Ok(player) = self.player.lock() {
+                    player.set_shuffle(shuffle);
+                }
+                self.config.shuffle = shuffle;
+                self.persist_config();
+            },
+            PlayerCommand::SetRepeat(mode) => {
+                self.repeat_mode = mode;
+                if let Ok(player) = self.player.lock() {
+                    player.set_repeat(mode);
+                }
+                self.config.repeat_mode = mode;
+                self.persist_config();
+            },
+            PlayerCommand::Stop => self.stop_playback(),
+            PlayerCommand::Resume | PlayerCommand::TogglePlayPause if self.current_playlist_index.is_none() => {
+                return Err(anyhow::anyhow!("Nothing to play"));
+            },
+            PlayerCommand::Resume | PlayerCommand::TogglePlayPause if self.is_player_stopped() => {
+                // Stop empties the sink, so there's nothing to resume. Start the track again instead.
+                self.play_current_song_checked()?;
+            },
+            command => {
+                let result = match self.player.lock() {
+                    Ok(player) => {
+                        let result = player.apply_command(command);
+                        self.is_playing = player.is_playing();
+                        result
+                    }
+                    Err(_) => Err(anyhow::anyhow!("The player is unavailable")),
+                };
+                if !self.is_playing {
+                    self.remember_position();
+                }
+                return result;
+            },
+        }
+        Ok(())
+    }
+    
+    // play_current_song, failing if the track couldn't be played
+    fn play_current_song_checked(&mut self) -> Result<()> {
+        let failures = self.decode_failures;
+        self.play_current_song();
+        if self.decode_failures > failures {
+            let name = self.current_file.as_deref().map(|path| display_name(path, None)).unwrap_or_default();
+            return Err(anyhow::anyhow!("{} could not be played", name));
+        }
+        Ok(())
+    }
+
+    // Follow the system's output device when it changes. If the one we were using was
+    // unplugged, pause instead of carrying on through the speakers (unless turned off).
+    fn check_output_device(&mut self) {
+        let Some(change) = self.device_watcher.poll() else {
+            return;
+        };
+        
+        let pause = change.previous_removed && self.config.pause_on_output_change;
+        let resume = self.is_playing && !pause;
+        self.reconnect_output(resume, change.current);
+    }
+    
+    fn reconnect_output(&mut self, resume: bool, device: Option<String>) {
+        let was_playing = self.is_playing;
+        let result = if let Ok(mut player) = self.player.lock() {
+            player.reconnect_output(resume)
+        } else {
+            return;
+        };
+        
+        let device = device.unwrap_or_else(|| "the default output".to_string());
+        match result {
+            Ok(()) => {
+                self.status.clear(ConditionKind::AudioOutput);
+                self.is_playing = resume;
+                if was_playing && !resume {
+                    self.show_notification(&format!("Output changed to {}. Paused, press Play to resume.", device));
+                } else {
+                    self.show_notification(&format!("Output switched to {}", device));
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to open audio output: {}", e);
+                self.is_playing = false;
+                let message = format!("Audio output is not working: {}", e);
+                self.status.raise(ConditionKind::AudioOutput, Severity::Error, message);
+            }
+        }
+    }
+    
+    // Act on commands sent with `musicplayer --remote`
+    #[cfg(feature = "remote-control")]
+    fn handle_remote_requests(&mut self) {
+        let requests = match &self.remote {
+            Some(remote) => remote.poll(),
+            None => return,
+        };
+        
+        for pending in requests {
+            let result = match pending.request.clone() {
+                RemoteRequest::Command(command) => self.try_apply_command(command),
+                RemoteRequest::Status => Ok(()),
+            };
+            let snapshot = self.player.lock().ok().map(|player| player.snapshot());
+            pending.reply(result, snapshot);
+        }
+    }
+    
+    // Act on media keys, and keep the system's media widget and seek bar up to date
+    #[cfg(feature = "media-controls")]
+    fn handle_media_controls(&mut self) {
+        let Some(snapshot) = self.player.lock().ok().map(|player| player.snapshot()) else {
+            return;
+        };
+        let commands = match &self.media_controls {
+            Some(controls) => controls.poll(&snapshot),
+            None => return,
+        };
+        let snapshot = if commands.is_empty() {
+            snapshot
+        } else {
+            for command in commands {
+                self.apply_command(command);
+            }
+            match self.player.lock() {
+                Ok(player) => player.snapshot(),
+                Err(_) => return,
+            }
+        };
+        let metadata = snapshot.current_path.as_ref().and_then(|path| self.metadata.get(path)).cloned();
+        if let Some(controls) = &mut self.media_controls {
+            controls.update(&snapshot, metadata.as_ref());
+        }
+    }
+    
+    // Act on the tray menu the same way as the buttons, and show the current track on the icon
+    #[cfg(feature = "tray")]
+    fn handle_tray(&mut self, ctx: &egui::Context) {
+        let actions = match &self.tray {
+            Some(tray) => tray.poll(),
+            None => return,
+        };
+        for action in actions {
+            match action {
+                TrayAction::TogglePlayPause => self.apply_command(PlayerCommand::TogglePlayPause),
+                TrayAction::Next => self.apply_command(PlayerCommand::Next),
+                TrayAction::ShowWindow => {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                },
+                TrayAction::Quit => self.quit_requested = true,
+            }
+        }
+        
+        let tooltip = match self.current_file.clone() {
+            Some(path) => format!("Music Player: {}", display_name(&path, self.metadata.get(&path))),
+            None => "Music Player".to_string(),
+        };
+        if let Some(tray) = &mut self.tray {
+            tray.set_tooltip(&tooltip);
+        }
+    }
+    
+    // Fingerprint the playlist in the background to find the same song stored more than once
+    fn start_duplicate_scan(&mut self) {
+        if self.duplicate_scan.is_none() {
+            self.duplicate_scan = Some(DuplicateScan::start(self.playlist.clone(), self.fingerprint_cache.clone()));
+            self.show_notification("Scanning playlist for duplicates...");
+        }
+    }
+    
+    fn check_duplicate_scan(&mut self) {
+        let groups = match &self.duplicate_scan {
+            Some(scan) => scan.poll(),
+            None => return,
+        };
+        
+        if let Some(groups) = groups {
+            self.duplicate_scan = None;
+            if groups.is_empty() {
+                self.show_notification("No duplicates found");
+            } else {
+                self.duplicate_groups = Some(groups);
+            }
+        }
+    }
+    
+    // Keep one copy of a duplicated song and drop the others from the playlist
+    fn keep_duplicate(&mut self, keep: &Path, group: &[PathBuf]) {
+        for path in group.iter().filter(|p| p.as_path() != keep) {
+            while let Some(index) = self.playlist.iter().position(|p| p == path) {
+                self.remove_index(index);
+            }
+        }
+    }
+    
+    fn show_duplicates_window(&mut self, ctx: &egui::Context) {
+        let Some(groups) = self.duplicate_groups.clone() else {
+            return;
+        };
+        
+        let mut open = true;
+        let mut kept_group = None;
+        egui::Window::new("Possible Duplicates")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("These tracks sound the same. Pick the copy to keep.");
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (group_index, group) in groups.iter().enumerate() {
+                        ui.separator();
+                        for path in group {
+                            ui.horizontal(|ui| {
+                                if a11y::button(ui, "Keep", &format!("Keep {}", path.display())).clicked() {
+                                    kept_group = Some((group_index, path.clone()));
+                                }
+                                ui.label(path.display().to_string());
+                            });
+                        }
+                    }
+                });
+            });
+        
+        if let Some((group_index, keep)) = kept_group {
+            self.keep_duplicate(&keep, &groups[group_index]);
+            if let Some(remaining) = &mut self.duplicate_groups {
+                remaining.remove(group_index);
+            }
+        }
+        
+        if !open || self.duplicate_groups.as_ref().is_some_and(|g| g.is_empty()) {
+            self.duplicate_groups = None;
+        }
+    }
+
+    // Recently played tracks, newest first. Double-click one to play it again.
+    fn show_history_window(&mut self, ctx: &egui::Context) {
+        if !self.show_history {
+            return;
+        }
+        
+        let mut open = true;
+        let mut play = None;
+        let mut clear = false;
+        let mut reset_counts = false;
+        egui::Window::new("History")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                if self.history.is_empty() {
+                    ui.label(egui::RichText::new("Nothing played yet").weak());
+                    return;
+                }
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for entry in self.history.recent() {
+                        let name = display_name(&entry.path, self.metadata.peek(&entry.path));
+                        ui.horizontal(|ui| {
+                            let row = ui.add(egui::SelectableLabel::new(false, &name))
+                                .on_hover_text(entry.path.display().to_string());
+                            let row = a11y::labeled(ui, row, egui::WidgetType::SelectableLabel, &name);
+                            let enter_pressed = row.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                            if row.double_clicked() || enter_pressed {
+                                play = Some(entry.path.clone());
+                            }
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                ui.label(egui::RichText::new(played_ago(entry.played_at)).weak());
+                            });
+                        });
+                    }
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    clear = a11y::button(ui, "Clear History", "Clear the play history").clicked();
+                    reset_counts = a11y::button(ui, "Reset Play Counts", "Reset every track's play count").clicked();
+                    ui.label(egui::RichText::new(format!("{} plays in total", self.play_counts.total())).weak());
+                });
+            });
+        
+        if let Some(path) = play {
+            // Added back to the playlist if it's been removed since
+            self.apply_command(PlayerCommand::Play(path));
+        }
+        if clear {
+            self.history.clear();
+            self.navigator.history.clear();
+            if let Err(e) = save_history(&self.history) {
+                log::error!("Failed to save history: {}", e);
+            }
+        }
+        if reset_counts {
+            self.play_counts.reset_all();
+            self.persist_play_counts();
+        }
+        self.show_history = open && self.show_history;
+    }
+    
+    fn show_equalizer_window(&mut self, ctx: &egui::Context) {
+        if !self.config.show_equalizer {
+            return;
+        }
+        
+        let mut open = true;
+        let mut changed = false;
+        // Edited here, then kept for the current track or as the usual EQ
+        let mut gains = self.eq_gains().to_vec();
+        let mut picked = None;
+        let mut removed = None;
+        let mut bound = None;
+        let mut preset_menu_open = false;
+        let mut rebound = false;
+        let track = self.current_file.clone().filter(|path| !is_stream(path));
+        egui::Window::new("Equalizer")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for (name, preset) in BUILTIN_PRESETS {
+                        let selected = self.track_eq.is_none() && self.config.eq_preset.as_deref() == Some(name);
+                        let preset_label = ui.add(egui::SelectableLabel::new(selected, name));
+                        if a11y::labeled(ui, preset_label, egui::WidgetType::SelectableLabel, name).clicked() {
+                            gains = preset.to_vec();
+                            picked = Some(name.to_string());
+                        }
+                    }
+                    // Saved presets, which can also be given to the current track
+                    for (name, preset) in &self.eq_presets.presets {
+                        let selected = match &self.track_eq {
+                            Some(track_eq) => track_eq == name,
+                            None => self.config.eq_preset.as_ref() == Some(name),
+                        };
+                        let preset_label = ui.add(egui::SelectableLabel::new(selected, name.as_str()))
+                            .on_hover_text("Right-click to use it for this track or delete it");
+                        let preset_label = a11y::labeled(ui, preset_label, egui::WidgetType::SelectableLabel, name);
+                        if preset_label.clicked() {
+                            gains = preset.clone();
+                            picked = Some(name.clone());
+                        }
+                        let preset_menu = preset_label.context_menu(|ui| {
+                            if ui.add_enabled(track.is_some(), egui::Button::new("Use for This Track")).clicked() {
+                                bound = Some(name.clone());
+                                ui.close_menu();
+                            }
+                            if ui.button("Delete Preset").clicked() {
+                                removed = Some(name.clone());
+                                ui.close_menu();
+                            }
+                        });
+                        preset_menu_open |= preset_menu.is_some();
+                    }
+                });
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    for (band, frequency) in EQ_BANDS.iter().enumerate() {
+                        let label = if *frequency >= 1000.0 {
+                            format!("{}k", frequency / 1000.0)
+                        } else {
+                            format!("{}", frequency)
+                        };
+                        ui.vertical(|ui| {
+                            let gain = &mut gains[band];
+                            let slider = ui.add(egui::Slider::new(&mut *gain, -MAX_GAIN_DB..=MAX_GAIN_DB)
+                                .vertical()
+                                .show_value(false))
+                                .on_hover_text(format!("{}Hz: {:+.1} dB", label, gain));
+                            let name = format!("{}Hz", label);
+                            if a11y::slider(ui, slider, *gain as f64, &name).changed() {
+                                changed = true;
+                            }
+                            ui.small(label);
+                        });
+                    }
+                });
+                ui.separator();
+                
+                ui.horizontal(|ui| {
+                    let name = ui.add(egui::TextEdit::singleline(&mut self.eq_preset_name).hint_text("Preset name").desired_width(120.0));
+                    a11y::labeled(ui, name, egui::WidgetType::TextEdit, "Preset name");
+                    let name = self.eq_preset_name.trim().to_string();
+                    let save = ui.add_enabled(!name.is_empty(), egui::Button::new("Save Preset"));
+                    if a11y::labeled(ui, save, egui::WidgetType::Button, "Save preset").clicked() {
+                        self.eq_presets.save_preset(&name, gains.clone());
+                        self.eq_preset_name.clear();
+                        if self.track_eq.is_none() {
+                            self.config.eq_preset = Some(name);
+                            self.persist_config();
+                        }
+                        self.persist_eq_presets();
+                    }
+                });
+                
+                // Tracks that always need the same tweak, such as old recordings
+                if let Some(path) = &track {
+                    ui.horizontal(|ui| match &self.track_eq {
+                        Some(name) => {
+                            ui.label(format!("This track plays with {}", name));
+                            let forget = ui.button("Use Usual EQ")
+                                .on_hover_text("Play this track with the same EQ as the others again");
+                            if a11y::labeled(ui, forget, egui::WidgetType::Button, "Use the usual EQ for this track").clicked() {
+                                self.eq_presets.unbind_track(path);
+                                rebound = true;
+                            }
+                        }
+                        None => {
+                            let save = ui.button("Save EQ as Default for This Track")
+                                .on_hover_text("Play this track with these bands from now on, whatever the others use");
+                            if a11y::labeled(ui, save, egui::WidgetType::Button, "Save EQ as default for this track").clicked() {
+                                self.eq_presets.save_for_track(path, gains.clone());
+                                rebound = true;
+                            }
+                        }
+                    });
+                }
+            });
+        
+        let removed_any = removed.is_some();
+        self.menu_open |= preset_menu_open;
+        if let Some(name) = removed {
+            self.eq_presets.remove_preset(&name);
+            if self.config.eq_preset.as_ref() == Some(&name) {
+                self.config.eq_preset = None;
+                self.persist_config();
+            }
+            self.persist_eq_presets();
+        }
+        if let (Some(name), Some(path)) = (bound, &track) {
+            match self.eq_presets.bind_track(path, &name) {
+                Ok(()) => rebound = true,
+                Err(e) => log::error!("{}", e),
+            }
+        }
+        if rebound {
+            self.persist_eq_presets();
+        }
+        let refresh = changed || picked.is_some() || removed_any || rebound;
+        if changed || picked.is_some() {
+            match self.track_eq.clone() {
+                Some(name) => {
+                    self.eq_presets.save_preset(&name, gains);
+                    self.persist_eq_presets();
+                }
+                None => {
+                    self.config.eq_gains = gains;
+                    self.config.eq_preset = picked;
+                    self.persist_config();
+                }
+            }
+        }
+        // Deleting, binding or editing may all change the bands the current track plays with
+        if refresh {
+            match &track {
+                Some(path) => self.apply_track_eq(path),
+                None => {
+                    if let Ok(player) = self.player.lock() {
+                        player.set_eq_gains(self.eq_gains());
+                    }
+                }
+            }
+        }
+        if !open {
+            self.config.show_equalizer = false;
+            self.persist_config();
+        }
+    }
+    
+    fn show_settings_window(&mut self, ctx: &egui::Context) {
+        if !self.show_settings {
+            return;
+        }
+        
+        let mut open = true;
+        let mut changed = false;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.heading("Playback");
+                // The slider moves along a loudness curve, the player gets the gain
+                let mut volume = gain_to_perceptual(self.volume);
+                let slider = ui.add(egui::Slider::new(&mut volume, 0.0..=1.0).text("Volume"))
+                    .on_hover_text("Also used the next time the player starts");
+                if a11y::slider(ui, slider, volume as f64, "Volume").changed() {
+                    self.set_volume(perceptual_to_gain(volume));
+                }
+                let mut shuffle = self.shuffle_mode;
+                let checkbox = ui.checkbox(&mut shuffle, "Shuffle");
+                a11y::focus_ring(ui, &checkbox);
+                if checkbox.changed() {
+                    self.apply_command(PlayerCommand::SetShuffle(shuffle));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Repeat:");
+                    let mut repeat = self.repeat_mode;
+                    for (mode, label) in [
+                        (RepeatMode::None, "Off"),
+                        (RepeatMode::All, "All"),
+                        (RepeatMode::One, "One"),
+                    ] {
+                        let radio = ui.radio_value(&mut repeat, mode, label);
+                        a11y::focus_ring(ui, &radio);
+                        if radio.changed() {
+                            self.apply_command(PlayerCommand::SetRepeat(repeat));
+                        }
+                    }
+                });
+                let remember = ui.checkbox(&mut self.config.remember_position, "Resume tracks where they were left off")
+                    .on_hover_text("For podcasts and audiobooks. Tracks stopped near the end start over.");
+                a11y::focus_ring(ui, &remember);
+                changed |= remember.changed();
+                
+                ui.separator();
+                ui.heading("Appearance");
+                ui.horizontal(|ui| {
+                    for (theme, label) in [(Theme::Dark, "Dark"), (Theme::Light, "Light"), (Theme::System, "Same as system")] {
+                        let radio = ui.radio_value(&mut self.config.theme, theme, label);
+                        a11y::focus_ring(ui, &radio);
+                        if radio.changed() {
+                            Self::apply_theme(ui.ctx(), theme);
+                            changed = true;
+                        }
+                    }
+                });
+                
+                let waveform = ui.checkbox(&mut self.config.show_waveform, "Show the waveform")
+                    .on_hover_text("Each track is read through once in the background to draw it");
+                a11y::focus_ring(ui, &waveform);
+                changed |= waveform.changed();
+                
+                ui.separator();
+                ui.heading("Headphones");
+                let crossfeed = ui.checkbox(&mut self.config.crossfeed_enabled, "Crossfeed")
+                    .on_hover_text("Blend a little of each channel into the other, like listening to speakers");
+                a11y::focus_ring(ui, &crossfeed);
+                changed |= crossfeed.changed();
+                ui.add_enabled_ui(self.config.crossfeed_enabled, |ui| {
+                    let strength = ui.add(egui::Slider::new(&mut self.config.crossfeed_strength, 0.0..=1.0).text("Strength"));
+                    let value = self.config.crossfeed_strength as f64;
+                    changed |= a11y::slider(ui, strength, value, "Crossfeed strength").changed();
+                });
+                
+                ui.separator();
+                ui.heading("Balance");
+                ui.horizontal(|ui| {
+                    let balance = ui.add(egui::Slider::new(&mut self.config.balance, -1.0..=1.0)
+                        .custom_formatter(|value, _| match value {
+                            v if v < -0.005 => format!("L {:.0}%", -v * 100.0),
+                            v if v > 0.005 => format!("R {:.0}%", v * 100.0),
+                            _ => "Centre".to_string(),
+                        }))
+                        .on_hover_text("Turn one side down to favour the other. Mono tracks aren't affected.");
+                    let value = self.config.balance as f64;
+                    changed |= a11y::slider(ui, balance, value, "Left/right balance").changed();
+                    if a11y::enabled_button(ui, self.config.balance != 0.0, "Centre", "Centre the balance").clicked() {
+                        self.config.balance = 0.0;
+                        changed = true;
+                    }
+                });
+                
+                ui.separator();
+                ui.heading("ReplayGain");
+                ui.horizontal(|ui| {
+                    for (mode, label) in [
+                        (ReplayGainMode::Off, "Off"),
+                        (ReplayGainMode::Track, "Track"),
+                        (ReplayGainMode::Album, "Album"),
+                    ] {
+                        let radio = ui.radio_value(&mut self.config.replaygain_mode, mode, label);
+                        a11y::focus_ring(ui, &radio);
+                        changed |= radio.changed();
+                    }
+                });
+                
+                ui.separator();
+                ui.heading("Crossfade");
+                let crossfade = ui.add(egui::Slider::new(&mut self.config.crossfade_secs, 0.0..=MAX_CROSSFADE_SECS).suffix(" s"))
+                    .on_hover_text("How long each track overlaps the next, 0 to switch straight over");
+                let value = self.config.crossfade_secs as f64;
+                changed |= a11y::slider(ui, crossfade, value, "Crossfade in seconds").changed();
+                let gapless = ui.checkbox(&mut self.config.gapless, "Gapless playback")
+                    .on_hover_text("Start the next track with no gap. Not used while crossfading.");
+                a11y::focus_ring(ui, &gapless);
+                changed |= gapless.changed();
+                
+                ui.separator();
+                ui.heading("Seeking");
+                egui::Grid::new("seek_steps").num_columns(2).show(ui, |ui| {
+                    for (value, label, name) in [
+                        (&mut self.config.seek_step_secs, "Arrow keys", "Seek step in seconds"),
+                        (&mut self.config.seek_coarse_step_secs, "Shift + arrow keys", "Coarse seek step in seconds"),
+                    ] {
+                        ui.label(label);
+                        let drag = ui.add(egui::DragValue::new(value).range(1.0..=600.0).suffix(" s"));
+                        changed |= a11y::labeled(ui, drag, egui::WidgetType::DragValue, name).changed();
+                        ui.end_row();
+                    }
+                });
+                
+                ui.separator();
+                ui.heading("Output");
+                let pause = ui.checkbox(&mut self.config.pause_on_output_change, "Pause when the output device is unplugged")
+                    .on_hover_text("Otherwise playback carries on through whichever device takes over");
+                a11y::focus_ring(ui, &pause);
+                changed |= pause.changed();
+                
+                ui.separator();
+                ui.heading("Sleep Timer");
+                ui.horizontal(|ui| {
+                    ui.label("When it goes off:");
+                    for (action, label) in [
+                        (SleepAction::Pause, "Pause"),
+                        (SleepAction::Stop, "Stop"),
+                        (SleepAction::Quit, "Quit"),
+                    ] {
+                        let radio = ui.radio_value(&mut self.config.sleep_action, action, label);
+                        a11y::focus_ring(ui, &radio);
+                        changed |= radio.changed();
+                    }
+                });
+                
+                ui.separator();
+                ui.heading("Notifications");
+                let desktop = ui.checkbox(&mut self.config.desktop_notifications, "Show a desktop notification when a track starts");
+                a11y::focus_ring(ui, &desktop);
+                changed |= desktop.changed();
+                
+                ui.separator();
+                ui.heading("Adding Folders");
+                let recursive = ui.checkbox(&mut self.config.recursive_folder_scan, "Include subfolders");
+                a11y::focus_ring(ui, &recursive);
+                changed |= recursive.changed();
+                
+                ui.separator();
+                ui.heading("Startup Playlist");
+                let startup = ui.checkbox(&mut self.config.load_startup_playlist, "Load the startup playlist when started without files")
+                    .on_hover_text("Set one with Set as Startup Playlist");
+                a11y::focus_ring(ui, &startup);
+                changed |= startup.changed();
+                let resume_last = ui.checkbox(&mut self.config.resume_last_track, "Carry on with the last track when started without files")
+                    .on_hover_text("Otherwise it's just selected, ready for Play");
+                a11y::focus_ring(ui, &resume_last);
+                changed |= resume_last.changed();
+                
+                ui.separator();
+                ui.heading("Damaged Files");
+                let skip = ui.checkbox(&mut self.config.skip_on_playback_error, "Move on when a track stops partway through")
+                    .on_hover_text("Otherwise playback stops at the damaged track");
+                a11y::focus_ring(ui, &skip);
+                changed |= skip.changed();
+                
+                ui.separator();
+                ui.heading("Scrolling");
+                let follow = ui.checkbox(&mut self.config.follow_current_track, "Scroll to each track as it starts")
+                    .on_hover_text("Waits a few seconds after you scroll the playlist yourself");
+                a11y::focus_ring(ui, &follow);
+                changed |= follow.changed();
+                
+                ui.separator();
+                ui.heading("Playlist Columns");
+                for (value, label) in [
+                    (&mut self.config.show_genre_column, "Genre"),
+                    (&mut self.config.show_year_column, "Year"),
+                    (&mut self.config.show_quality_column, "Quality"),
+                ] {
+                    let checkbox = ui.checkbox(value, label);
+                    a11y::focus_ring(ui, &checkbox);
+                    changed |= checkbox.changed();
+                }
+                
+                ui.separator();
+                ui.heading("Config File");
+                if a11y::button(ui, "Copy config path", "Copy config file location").clicked() {
+                    let location = crate::config::get_config_location_description();
+                    ui.output_mut(|o| o.copied_text = location.clone());
+                    self.show_notification("Config location copied to clipboard!");
+                    log::info!("{}", location);
+                }
+                if a11y::button(ui, "Open config folder", "Open the folder with the config file and saved data").clicked() {
+                    match crate::config::open_config_dir() {
+                        Ok(()) => self.show_notification("Opened the config folder"),
+                        Err(e) => {
+                            log::error!("{}", e);
+                            self.show_notification(&format!("{}", e));
+                        }
+                    }
+                }
+            });
+        
+        if changed {
+            if let Ok(player) = self.player.lock() {
+                player.set_crossfeed(self.config.crossfeed_enabled, self.config.crossfeed_strength);
+                player.set_balance(self.config.balance);
+                player.set_replaygain_mode(self.config.replaygain_mode);
+                player.set_crossfade(Duration::from_secs_f32(self.config.crossfade_secs));
             }
+            self.persist_config();
         }
+        
+        self.show_settings = open;
     }
     
-    fn move_down_in_playlist(&mut self) {
-        if let Some(index) = self.selected_song_index {
-            if index < self.playlist.len() - 1 {
-                self.playlist.swap(index, index + 1);
-                // Update current index if it was one of the swapped items
-                if let Some(current) = self.current_playlist_index {
-                    self.current_playlist_index = match current {
-                        c if c == index => Some(c + 1),
-                        c if c == index + 1 => Some(c - 1),
-                        c => Some(c),
-                    };
+    // The narrow bar shown in mini mode: play/pause, next, the track name and the progress bar
+    fn show_mini_player(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing {
+                if a11y::button(ui, "⏸", "Pause").clicked() {
+                    self.apply_command(PlayerCommand::Pause);
                 }
-                self.selected_song_index = Some(index + 1);
+            } else if a11y::enabled_button(ui, self.current_playlist_index.is_some(), "▶", "Play").clicked() {
+                self.apply_command(PlayerCommand::Resume);
             }
-        }
+            if a11y::enabled_button(ui, !self.playlist.is_empty(), "⏭", "Next track").clicked() {
+                self.apply_command(PlayerCommand::Next);
+            }
+            
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let full_btn = a11y::button(ui, "🗖", "Full player")
+                    .on_hover_text("Back to the full player");
+                if full_btn.clicked() {
+                    self.toggle_mini_mode(ctx);
+                }
+                
+                // Whatever room the buttons leave, cut short with "…" if the name is longer
+                let name = match &self.current_file {
+                    Some(path) => display_name(path, self.metadata.get(path)),
+                    None => "Nothing playing".to_string(),
+                };
+                ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
+                    ui.add(egui::Label::new(egui::RichText::new(name).strong()).truncate());
+                });
+            });
+        });
+        
+        ui.horizontal(|ui| {
+            // Stretch the slider across the window, leaving room for the two times
+            ui.spacing_mut().slider_width = (ui.available_width() - 100.0).max(40.0);
+            self.show_progress_bar(ui);
+        });
     }
     
-    fn check_song_finished(&mut self) {
-        if self.is_playing {
-            let song_finished = if let Ok(player) = self.player.lock() {
-                player.check_if_song_finished()
+    // Position, seek slider and length of the current track, laid out in a row
+    fn show_progress_bar(&mut self, ui: &mut egui::Ui) {
+        // Current position display
+        ui.label(Self::format_duration(self.song_position));
+        
+        // Progress slider
+        let progress_ratio = if let Some(duration) = self.song_duration {
+            if duration.as_secs() > 0 {
+                self.song_position.as_secs_f32() / duration.as_secs_f32()
             } else {
-                false
-            };
-            
-            if song_finished {
-                self.play_next_song();
+                0.0
             }
-        }
-    }
-    
-    fn set_volume(&mut self, volume: f32) {
-        self.volume = volume;
-        self.config.volume = volume;  // Update config with new volume
+        } else {
+            0.0
+        };
         
-        if let Ok(player) = self.player.lock() {
-            player.set_volume(volume);
-        }
+        let mut seek_pos = if self.seeking {
+            self.seek_position
+        } else {
+            progress_ratio
+        };
         
-        // Save config when volume changes
-        if let Err(e) = save_config(&self.config) {
-            log::error!("Failed to save config: {}", e);
+        // Reserve a spot behind the slider for the buffered fill
+        let buffered_fill = ui.painter().add(egui::Shape::Noop);
+        
+        // Stays in place with nothing loaded, just greyed out. Streams have no end to seek to.
+        let slider_response = ui.add_enabled(
+            self.current_file.as_deref().is_some_and(|path| !is_stream(path)),
+            egui::Slider::new(&mut seek_pos, 0.0..=1.0)
+                .show_value(false)
+                .trailing_fill(true)
+        );
+        let slider_response = a11y::slider(ui, slider_response, seek_pos as f64, "Song position");
+        
+        // Show how far ahead a stream has buffered. Local files are fully
+        // buffered, so nothing extra is drawn for them.
+        if let (Some(buffered), Some(duration)) = (self.buffered_position, self.song_duration) {
+            if duration.as_secs_f32() > 0.0 && buffered < duration {
+                let buffered_ratio = (buffered.as_secs_f32() / duration.as_secs_f32()).clamp(0.0, 1.0);
+                let rect = slider_response.rect;
+                let rail = egui::Rect::from_min_size(
+                    egui::pos2(rect.left(), rect.center().y - ui.spacing().slider_rail_height / 2.0),
+                    egui::vec2(rect.width() * buffered_ratio, ui.spacing().slider_rail_height),
+                );
+                let color = ui.visuals().widgets.inactive.fg_stroke.color.gamma_multiply(0.4);
+                ui.painter().set(buffered_fill, egui::Shape::rect_filled(rail, 2.0, color));
+            }
         }
-    }
-    
-    fn update_song_position(&mut self) {
-        if self.is_playing && !self.seeking {
-            if let Ok(player) = self.player.lock() {
-                self.song_position = player.get_current_position();
-                
-                // Update song duration if not set yet
-                if self.song_duration.is_none() {
-                    self.song_duration = player.get_song_duration();
+        
+        // Shade the parts of the track that are trimmed off
+        if let (Some(path), Some(duration)) = (&self.current_file, self.song_duration) {
+            let total = duration.as_secs_f32();
+            let trim = self.trims.get(path);
+            if total > 0.0 && !trim.is_empty() {
+                let rect = slider_response.rect;
+                let x_at = |time: Duration| rect.left() + rect.width() * (time.as_secs_f32() / total).clamp(0.0, 1.0);
+                let shade = egui::Color32::from_black_alpha(120);
+                if let Some(start) = trim.start() {
+                    let cut = egui::Rect::from_x_y_ranges(rect.left()..=x_at(start), rect.y_range());
+                    ui.painter().rect_filled(cut, 2.0, shade);
+                }
+                if let Some(end) = trim.end() {
+                    let cut = egui::Rect::from_x_y_ranges(x_at(end)..=rect.right(), rect.y_range());
+                    ui.painter().rect_filled(cut, 2.0, shade);
                 }
             }
         }
-    }
-    
-    fn format_duration(duration: Duration) -> String {
-        let total_seconds = duration.as_secs();
-        let minutes = total_seconds / 60;
-        let seconds = total_seconds % 60;
-        format!("{:02}:{:02}", minutes, seconds)
-    }
-    
-    fn seek_to_position(&mut self, position_ratio: f32) {
-        if let Some(duration) = self.song_duration {
-            let position = Duration::from_secs_f32(position_ratio * duration.as_secs_f32());
-            self.song_position = position;
-            
-            if let Ok(player) = self.player.lock() {
-                if let Err(e) = player.seek_to(position) {
-                    log::error!("Error seeking: {}", e);
-                }
+        
+        // Handle seeking
+        if slider_response.drag_started() {
+            self.seeking = true;
+            self.seek_position = seek_pos;
+        } else if slider_response.drag_stopped() {
+            self.seeking = false;
+            self.seek_to_position(seek_pos);
+        } else if slider_response.dragged() {
+            self.seek_position = seek_pos;
+        } else if slider_response.clicked() {
+            // A click without a drag never starts one, so seek straight there
+            if let Some(pointer) = slider_response.interact_pointer_pos() {
+                self.seek_to_position(Self::slider_ratio_at(slider_response.rect, pointer.x));
             }
         }
+        
+        // Total duration display
+        if let Some(duration) = self.song_duration {
+            ui.label(Self::format_duration(duration));
+        } else if self.current_file.as_deref().is_some_and(is_stream) {
+            ui.label("Live");
+        } else {
+            ui.label("--:--");
+        }
     }
     
-    // Method to handle files dropped by the user or from the OS
-    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
-        // First check for dropped files
-        if !ctx.input(|i| i.raw.dropped_files.is_empty()) {
-            let mut new_files = Vec::new();
-            
-            // Extract valid audio files from the dropped files
-            ctx.input(|i| {
-                for file in &i.raw.dropped_files {
-                    if let Some(path) = &file.path {
-                        if is_audio_file(path) {
-                            new_files.push(path.clone());
-                            // Store these files to process later
-                            self.pending_drops.push(path.clone());
-                        }
+    // Persistent bar for ongoing problems, showing the worst one
+    fn show_status_bar(&mut self, ctx: &egui::Context) {
+        let Some(condition) = self.status.worst().cloned() else {
+            return;
+        };
+        
+        let mut retry = false;
+        let mut dismiss = false;
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let color = match condition.severity {
+                    Severity::Error => ui.visuals().error_fg_color,
+                    Severity::Warning => ui.visuals().warn_fg_color,
+                    Severity::Info => ui.visuals().text_color(),
+                };
+                ui.colored_label(color, &condition.message);
+                
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    dismiss = a11y::button(ui, "✕", "Dismiss").on_hover_text("Dismiss").clicked();
+                    if condition.kind.retryable() {
+                        retry = a11y::button(ui, "Retry", "Retry").clicked();
                     }
-                }
+                });
             });
-            
-            // Process the dropped files if any found
-            if !new_files.is_empty() {
-                let was_empty = self.playlist.is_empty();
-                
-                // Add files to the playlist
-                for path in new_files {
-                    self.playlist.push(path);
-                }
-                
-                // If playlist was empty before, start playing the first added file
-                if was_empty && !self.playlist.is_empty() {
-                    self.current_playlist_index = Some(0);
-                    self.play_current_song();
-                }
+        });
+        
+        if retry {
+            match condition.kind {
+                ConditionKind::ConfigSave => self.persist_config(),
+                ConditionKind::WatchFolder => self.start_folder_watcher(),
+                ConditionKind::AudioOutput => self.reconnect_output(false, None),
+                _ => {}
             }
+        } else if dismiss {
+            if condition.kind == ConditionKind::DecodeFailures {
+                self.decode_failures = 0;
+            }
+            self.status.clear(condition.kind);
         }
     }
 
@@ -339,32 +3342,80 @@ impl MusicPlayerApp {
 
 impl eframe::App for MusicPlayerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // The panic stop is checked first so nothing else can swallow it
+        self.handle_global_shortcuts(ctx);
+        // Set again if the playlist is shown with a row focused
+        self.playlist_focused = false;
+        
+        if ctx.input(|i| i.viewport().close_requested()) {
+            self.remember_position();
+            self.remember_last_track();
+        }
+        
         // Handle files dropped onto the application
         self.handle_dropped_files(ctx);
+        self.check_opened_files();
+        self.show_drop_overlay(ctx);
         
+        // Pick up new files from the watched folder
+        self.check_watched_folder();
+        
+        #[cfg(feature = "remote-control")]
+        self.handle_remote_requests();
+        #[cfg(feature = "media-controls")]
+        self.handle_media_controls();
+        #[cfg(feature = "tray")]
+        self.handle_tray(ctx);
+        self.check_output_device();
+        self.check_sleep_timer(ctx);
+        
+        // Pick up tags read in the background
+        if self.metadata.poll() {
+            // Lengths that just came in add to the total
+            self.playlist_summary = None;
+            ctx.request_repaint();
+        }
+        
+        // Collect the results of a background duplicate scan
+        self.check_duplicate_scan();
+        self.show_duplicates_window(ctx);
+        self.show_clear_confirmation(ctx);
+        self.show_settings_window(ctx);
+        self.show_equalizer_window(ctx);
+        self.show_history_window(ctx);
+        
+        // Played like any other track, so cue sheet tracks, resuming and play counts all work
         if self.started_playing {
             self.started_playing = false;
-            if let Some(path) = &self.current_file {
-                if let Ok(player) = self.player.lock() {
-                    if self.current_playlist_index.is_none() {
-                        self.current_playlist_index = Some(0);
-                    }
-                    let _ = player.play_playlist_item(path, self.current_playlist_index.unwrap());
-                    self.is_playing = true;
-                    
-                    // Reset position tracking
-                    self.song_position = Duration::from_secs(0);
-                    self.song_duration = player.get_song_duration();
-                }
+            if self.current_playlist_index.is_none() {
+                self.current_playlist_index = Some(0);
             }
+            self.play_current_song();
+            
+            // Reset position tracking
+            self.song_position = Duration::from_secs(0);
+            self.song_duration = self.player.lock().ok().and_then(|player| self.track_progress(&*player).1);
         }
         
         // Update song position
         self.update_song_position();
         
+        self.check_stream_connection();
+        
         // Check if current song has finished and we need to play the next one
+        self.check_play_count();
+        self.check_resume_save();
+        self.check_crossfade();
+        self.check_trim_end();
+        self.check_gapless();
+        self.check_gapless_rollover();
         self.check_song_finished();
         
+        // Resume the main track once a preview clip ends
+        if let Ok(player) = self.player.lock() {
+            player.update_preview();
+        }
+        
         // Request continuous repaint for checking song status
         ctx.request_repaint_after(std::time::Duration::from_millis(100));
         
@@ -387,6 +3438,18 @@ impl eframe::App for MusicPlayerApp {
             }
         }
         
+        // Must come before the central panel so it gets its space at the bottom
+        self.show_status_bar(ctx);
+        
+        // Just the current track and the transport controls, no playlist or side panels
+        if self.config.mini_mode {
+            egui::CentralPanel::default().show(ctx, |ui| self.show_mini_player(ctx, ui));
+            return;
+        }
+        
+        self.refresh_lyrics();
+        self.show_lyrics_panel(ctx);
+        
         egui::CentralPanel::default().show(ctx, |ui| {
             // Use vertical layout to allow proper resizing
             ui.vertical(|ui| {
@@ -396,35 +3459,166 @@ impl eframe::App for MusicPlayerApp {
                     
                     // Push config button to the right
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        // Config button with just the gear icon
-                        let config_btn = ui.button("⚙").on_hover_text("Show config file location");
-                        if config_btn.clicked() {
-                            let location = crate::config::get_config_location_description();
-                            ui.output_mut(|o| o.copied_text = location.clone());
-                            self.show_notification("Config location copied to clipboard!");
-                            log::info!("{}", location);
+                        // Settings button with just the gear icon
+                        let settings_btn = a11y::button(ui, "⚙", "Settings")
+                            .on_hover_text("Settings");
+                        if settings_btn.clicked() {
+                            self.show_settings = !self.show_settings;
+                        }
+                        
+                        let mini_btn = a11y::button(ui, "🗕", "Mini player")
+                            .on_hover_text("Shrink to the mini player");
+                        if mini_btn.clicked() {
+                            self.toggle_mini_mode(ctx);
+                        }
+                        
+                        let has_lyrics = self.current_lyrics().is_some();
+                        let lyrics_btn = ui.add_enabled(has_lyrics, egui::SelectableLabel::new(self.config.show_lyrics, "Lyrics"))
+                            .on_disabled_hover_text("No lyrics found for this track");
+                        if a11y::labeled(ui, lyrics_btn, egui::WidgetType::SelectableLabel, "Show lyrics").clicked() {
+                            self.config.show_lyrics = !self.config.show_lyrics;
+                            self.persist_config();
+                        }
+                        
+                        let history_btn = ui.add(egui::SelectableLabel::new(self.show_history, "History"))
+                            .on_hover_text("Recently played tracks");
+                        if a11y::labeled(ui, history_btn, egui::WidgetType::SelectableLabel, "Show play history").clicked() {
+                            self.show_history = !self.show_history;
+                        }
+                        
+                        let eq_btn = ui.add(egui::SelectableLabel::new(self.config.show_equalizer, "EQ"))
+                            .on_hover_text("Equalizer");
+                        if a11y::labeled(ui, eq_btn, egui::WidgetType::SelectableLabel, "Show equalizer").clicked() {
+                            self.config.show_equalizer = !self.config.show_equalizer;
+                            self.persist_config();
                         }
                     });
                 });
                 
                 // Playlist management buttons - fixed height
                 ui.horizontal(|ui| {
-                    if ui.button("Add Songs").clicked() {
+                    if a11y::button(ui, "Add Songs", "Add songs").clicked() {
                         self.add_to_playlist();
                     }
                     
+                    let folder_label = if self.config.recursive_folder_scan {
+                        "Add all songs in a folder and its subfolders"
+                    } else {
+                        "Add all songs in a folder"
+                    };
+                    if a11y::button(ui, "Add Folder", folder_label).clicked() {
+                        self.add_folder();
+                    }
+                    
+                    if a11y::button(ui, "Open Playlist", "Add songs from an M3U or PLS playlist").clicked() {
+                        self.open_playlist();
+                    }
+                    
+                    if a11y::enabled_button(ui, !self.playlist.is_empty(), "Save Playlist", "Save the playlist as M3U or PLS").clicked() {
+                        self.save_playlist();
+                    }
+                    
+                    let set_startup = ui.add_enabled(!self.playlist.is_empty(), egui::Button::new("Set as Startup Playlist"))
+                        .on_hover_text("Load these tracks whenever the player starts without any files");
+                    if a11y::labeled(ui, set_startup, egui::WidgetType::Button, "Set the playlist as the startup playlist").clicked() {
+                        self.set_startup_playlist();
+                    }
+                    
+                    if a11y::enabled_button(ui, !self.playlist.is_empty(), "Clear", "Clear the playlist").clicked() {
+                        self.confirm_clear = true;
+                    }
+                    
+                    let can_scan = self.playlist.len() > 1 && self.duplicate_scan.is_none();
+                    let find_duplicates = ui.add_enabled(can_scan, egui::Button::new("Find Duplicates"));
+                    if a11y::labeled(ui, find_duplicates, egui::WidgetType::Button, "Find duplicate songs").clicked() {
+                        self.start_duplicate_scan();
+                    }
+                    
+                    let remove_missing = ui.add_enabled(!self.playlist.is_empty(), egui::Button::new("Remove Missing"))
+                        .on_hover_text("Remove tracks whose files can no longer be found");
+                    if a11y::labeled(ui, remove_missing, egui::WidgetType::Button, "Remove missing files from the playlist").clicked() {
+                        self.remove_missing_files();
+                    }
+                    
+                    let most_played = ui.add_enabled(self.play_counts.total() > 0, egui::Button::new("Most Played"))
+                        .on_hover_text("Sort the playlist by how often each track has been played");
+                    if a11y::labeled(ui, most_played, egui::WidgetType::Button, "Sort by most played").clicked() {
+                        // Always most played first, rather than flipping like the column headers
+                        self.sort = None;
+                        self.sort_playlist(SortColumn::PlayCount);
+                    }
+                    
+                    let mut sort_by = None;
+                    let sort_menu = ui.add_enabled_ui(self.playlist.len() > 1, |ui| {
+                        ui.menu_button("Sort", |ui| {
+                            for (label, column) in [
+                                ("File name", SortColumn::Name),
+                                ("Title", SortColumn::Title),
+                                ("Artist", SortColumn::Artist),
+                                ("Length", SortColumn::Duration),
+                            ] {
+                                let text = Self::header_text(label, column, self.sort);
+                                if a11y::button(ui, text, &format!("Sort by {}", label.to_lowercase())).clicked() {
+                                    sort_by = Some(column);
+                                    ui.close_menu();
+                                }
+                            }
+                        }).inner.is_some()
+                    });
+                    self.menu_open |= sort_menu.inner;
+                    if let Some(column) = sort_by {
+                        self.sort_playlist(column);
+                    }
+                    
+                    let has_played = !self.played.is_empty();
+                    let continue_btn = ui.add_enabled(has_played, egui::Button::new("Continue"))
+                        .on_hover_text("Play the first track that hasn't been played yet");
+                    if a11y::labeled(ui, continue_btn, egui::WidgetType::Button, "Continue from the first unplayed track").clicked() {
+                        self.continue_from_unplayed();
+                    }
+                    
+                    let start_over = ui.add_enabled(has_played, egui::Button::new("Start Over"))
+                        .on_hover_text("Mark everything unplayed and play from the top");
+                    if a11y::labeled(ui, start_over, egui::WidgetType::Button, "Start over from the first track").clicked() {
+                        self.start_over();
+                    }
+                    
                     if let Some(_index) = self.selected_song_index {
-                        if ui.button("Remove").clicked() {
+                        if a11y::button(ui, "Remove", "Remove selected song").clicked() {
                             self.remove_from_playlist();
                         }
                         
-                        if ui.button("Move Up").clicked() {
+                        if a11y::button(ui, "Move Up", "Move selected song up").clicked() {
                             self.move_up_in_playlist();
                         }
                         
-                        if ui.button("Move Down").clicked() {
+                        if a11y::button(ui, "Move Down", "Move selected song down").clicked() {
                             self.move_down_in_playlist();
                         }
+                        
+                        if self.is_previewing() {
+                            if a11y::button(ui, "⏹ Preview", "Stop preview").clicked() {
+                                self.stop_preview();
+                            }
+                        } else if self.selected_song_index != self.current_playlist_index
+                            && a11y::button(ui, "Preview", "Preview selected song").on_hover_text("Play a short clip from the middle of this track").clicked()
+                        {
+                            self.preview_selected();
+                        }
+                    }
+                });
+                
+                // Internet radio and other streams, added by URL
+                ui.horizontal(|ui| {
+                    let input = ui.add(egui::TextEdit::singleline(&mut self.stream_url_input).hint_text("http://… stream URL"));
+                    let input = a11y::labeled(ui, input, egui::WidgetType::TextEdit, "Stream URL");
+                    let entered = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let valid = is_stream(Path::new(self.stream_url_input.trim()));
+                    let add = ui.add_enabled(valid, egui::Button::new("Add Stream"))
+                        .on_hover_text("Add an internet radio station or other audio stream")
+                        .on_disabled_hover_text("Type an http:// or https:// address");
+                    if a11y::labeled(ui, add, egui::WidgetType::Button, "Add stream").clicked() || (entered && valid) {
+                        self.add_stream();
                     }
                 });
                 
@@ -433,146 +3627,484 @@ impl eframe::App for MusicPlayerApp {
                 // Calculate available space for playlist
                 // This is the key part - allocate remaining space between fixed elements
                 let available_height = ui.available_height();
-                // Reserve space for playback controls and now playing label at bottom
-                let bottom_section_height = 70.0;
+                // Reserve space for playback controls and now playing label at bottom, and the
+                // waveform above them when it's drawn
+                let mut bottom_section_height = 95.0;
+                if self.config.show_waveform && self.current_file.as_deref().is_some_and(|path| !is_stream(path)) {
+                    bottom_section_height += WAVEFORM_HEIGHT + ui.spacing().item_spacing.y;
+                }
                 let playlist_height = available_height - bottom_section_height;
                 
                 // Playlist section - takes up remaining space with scroll
                 ui.allocate_ui(egui::vec2(ui.available_width(), playlist_height), |ui| {
-                    ui.heading("Playlist");
+                    ui.horizontal(|ui| {
+                        ui.heading("Playlist");
+                        if self.config.show_quality_column {
+                            let filter = ui.checkbox(&mut self.lossless_only, "Lossless only")
+                                .on_hover_text("Hide tracks in lossy formats such as MP3");
+                            a11y::focus_ring(ui, &filter);
+                        }
+                    });
+                    
+                    // Filters the rows shown, the playlist itself is left alone
+                    ui.horizontal(|ui| {
+                        let search = ui.add(egui::TextEdit::singleline(&mut self.search_query).hint_text("Search playlist"));
+                        a11y::labeled(ui, search, egui::WidgetType::TextEdit, "Search playlist");
+                        if !self.search_query.is_empty() && a11y::button(ui, "✖", "Clear search").clicked() {
+                            self.search_query.clear();
+                        }
+                        if !self.playlist.is_empty() {
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                let jump = ui.add_enabled(self.current_playlist_index.is_some(), egui::Button::new("⌖"))
+                                    .on_hover_text("Scroll to the current track");
+                                if a11y::labeled(ui, jump, egui::WidgetType::Button, "Scroll to the current track").clicked() {
+                                    self.scroll_to_current = true;
+                                }
+                                ui.label(egui::RichText::new(self.playlist_summary()).weak());
+                            });
+                        }
+                    });
+                    
+                    self.follow_current_track();
+                    
+                    if self.playlist.is_empty() {
+                        ui.add_space(20.0);
+                        ui.vertical_centered(|ui| {
+                            ui.label(egui::RichText::new("Your playlist is empty").strong());
+                            ui.label("Drop audio files here or click Add Songs to get started.");
+                        });
+                    }
+                    
+                    // Work out which rows are shown up front, so only the ones on screen are drawn
+                    let played_start = self.played_section_start();
+                    let show_played_divider = self.config.move_played_to_bottom && self.played_count > 0;
+                    let show_genre = self.config.show_genre_column;
+                    let show_year = self.config.show_year_column;
+                    let show_quality = self.config.show_quality_column;
+                    let lossless_only = show_quality && self.lossless_only;
+                    let query = self.search_query.trim().to_lowercase();
+                    // As for the summary, only read every track's tags when the cache can hold them all
+                    let read_all = self.playlist.len() <= self.config.cache_capacity;
+                    // Playlist index of each row below the header, None for the Played divider
+                    let mut entries = Vec::with_capacity(self.playlist.len());
+                    // Each track shown, with its row number counting the header as row 0
+                    let mut shown = Vec::new();
+                    for (index, path) in self.playlist.iter().enumerate() {
+                        // Divider between upcoming and already played tracks
+                        if show_played_divider && index == played_start {
+                            entries.push(None);
+                        }
+                        
+                        if lossless_only || !query.is_empty() {
+                            let metadata = if read_all { self.metadata.get(path) } else { self.metadata.peek(path) };
+                            // Tracks still being probed stay visible until we know they're lossy
+                            let lossy = metadata.and_then(|m| m.quality.as_ref()).is_some_and(|q| !q.lossless);
+                            if lossless_only && lossy {
+                                continue;
+                            }
+                            if !query.is_empty() && !matches_search(path, metadata, &query) {
+                                continue;
+                            }
+                        }
+                        entries.push(Some(index));
+                        shown.push((index, entries.len()));
+                    }
                     
-                    egui::ScrollArea::vertical()
+                    if shown.is_empty() && !query.is_empty() && !self.playlist.is_empty() {
+                        ui.label(egui::RichText::new("No tracks match your search").weak());
+                    }
+                    
+                    let row_height = (ui.text_style_height(&egui::TextStyle::Body) + 2.0 * ui.spacing().button_padding.y)
+                        .max(ui.spacing().interact_size.y);
+                    // The header, the rows, and a gap at the end to drop tracks on
+                    let total_rows = entries.len() + 2;
+                    let playlist_scroll = egui::ScrollArea::vertical()
                         .auto_shrink([false, false])
                         .max_height(playlist_height - 30.0) // Account for playlist header
-                        .show(ui, |ui| {
-                            for (index, path) in self.playlist.iter().enumerate() {
-                                let is_selected = Some(index) == self.selected_song_index;
-                                let is_playing = Some(index) == self.current_playlist_index && self.is_playing;
-                                
-                                let text = format!("{}. {}", index + 1, path.file_name()
-                                    .and_then(|n| n.to_str())
-                                    .unwrap_or("Unknown"));
-                                
-                                let response = ui.selectable_label(is_selected, if is_playing {
-                                    format!("▶ {}", text)
-                                } else {
-                                    text
-                                });
-                                
-                                if response.clicked() {
-                                    self.selected_song_index = Some(index);
+                        .show_rows(ui, row_height, total_rows, |ui, range| {
+                            let sort = self.sort;
+                            let mut sort_clicked = None;
+                            let mut dropped = None;
+                            let mut row_action = None;
+                            let mut row_menu_open = false;
+                            let mut play_row = None;
+                            let mut focused_row = None;
+                            
+                            // Where any row is, drawn or not, so the keyboard can move off screen
+                            let row_spacing = row_height + ui.spacing().item_spacing.y;
+                            let drawn = ui.max_rect();
+                            let top = drawn.top() - range.start as f32 * row_spacing;
+                            let row_rect = |row: usize| egui::Rect::from_min_size(
+                                egui::pos2(drawn.left(), top + row as f32 * row_spacing),
+                                egui::vec2(drawn.width(), row_height),
+                            );
+                            let mut rows: Vec<PlaylistRow> = shown.iter().map(|&(index, row)| (index, None, row_rect(row))).collect();
+                            
+                            if self.scroll_to_current {
+                                let current = self.current_playlist_index
+                                    .and_then(|current| rows.binary_search_by_key(&current, |(index, ..)| *index).ok());
+                                if let Some(position) = current {
+                                    ui.scroll_to_rect(rows[position].2, Some(egui::Align::Center));
                                 }
-                                
-                                if response.double_clicked() {
-                                    self.current_playlist_index = Some(index);
-                                    self.started_playing = true;
-                                    self.current_file = Some(path.clone());
+                            }
+                            
+                            egui::Grid::new("playlist_grid")
+                                .striped(true)
+                                .min_row_height(row_height)
+                                .start_row(range.start)
+                                .show(ui, |ui| {
+                                    for row in range.clone() {
+                                        if row == 0 {
+                                            // Header row, click a column to sort on it
+                                            ui.label("#");
+                                            let mut columns = vec![("Title", SortColumn::Name)];
+                                            if show_genre {
+                                                columns.push(("Genre", SortColumn::Genre));
+                                            }
+                                            if show_year {
+                                                columns.push(("Year", SortColumn::Year));
+                                            }
+                                            for (label, column) in columns {
+                                                // Frameless buttons so the headers can be reached with the keyboard
+                                                let text = egui::RichText::new(Self::header_text(label, column, sort)).strong();
+                                                let header = ui.add(egui::Button::new(text).frame(false));
+                                                let description = format!("Sort by {}", label.to_lowercase());
+                                                if a11y::labeled(ui, header, egui::WidgetType::Button, &description).clicked() {
+                                                    sort_clicked = Some(column);
+                                                }
+                                            }
+                                            if show_quality {
+                                                ui.label(egui::RichText::new("Quality").strong());
+                                            }
+                                            ui.end_row();
+                                            continue;
+                                        }
+                                        
+                                        let index = match entries.get(row - 1) {
+                                            Some(Some(index)) => *index,
+                                            Some(None) => {
+                                                ui.label("");
+                                                ui.label(egui::RichText::new("Played").weak());
+                                                ui.end_row();
+                                                continue;
+                                            }
+                                            // The gap at the end
+                                            None => break,
+                                        };
+                                        let path = &self.playlist[index];
+                                        
+                                        let is_selected = Some(index) == self.selected_song_index;
+                                        let is_playing = Some(index) == self.current_playlist_index && self.is_playing;
+                                        
+                                        ui.label(if is_playing {
+                                            format!("▶ {}", index + 1)
+                                        } else {
+                                            format!("{}", index + 1)
+                                        });
+                                        
+                                        // Tag columns fill in once the background reader gets to them
+                                        let metadata = self.metadata.get(path).cloned();
+                                        let quality = if show_quality {
+                                            metadata.as_ref().and_then(|m| m.quality.clone())
+                                        } else {
+                                            None
+                                        };
+                                        let name = display_name(path, metadata.as_ref());
+                                        let response = if self.broken_tracks.contains(path) {
+                                            ui.selectable_label(is_selected, format!("⚠ {}", name))
+                                                .on_hover_text("This file ended as soon as it started and was skipped")
+                                        } else {
+                                            ui.selectable_label(is_selected, name.as_str())
+                                        };
+                                        // Rows can be dragged to a new place in the playlist
+                                        let mut response = response.interact(egui::Sense::drag());
+                                        response.dnd_set_drag_payload(index);
+                                        
+                                        // Read the whole row out, not just the file name
+                                        let mut description = format!("Track {}: {}", index + 1, name);
+                                        if is_playing {
+                                            description.push_str(", playing");
+                                        }
+                                        if self.broken_tracks.contains(path) {
+                                            description.push_str(", failed to play");
+                                        }
+                                        if let Some(quality) = &quality {
+                                            if quality.label.parse::<u32>().is_ok() {
+                                                description.push_str(&format!(", {} kbps", quality.label));
+                                            } else {
+                                                description.push_str(&format!(", {}", quality.label));
+                                            }
+                                        }
+                                        let plays = self.play_counts.get(path);
+                                        if plays > 0 {
+                                            let times = if plays == 1 { "once".to_string() } else { format!("{} times", plays) };
+                                            description.push_str(&format!(", played {}", times));
+                                            response = response.on_hover_text(format!("Played {}", times));
+                                        }
+                                        response = a11y::labeled(ui, response, egui::WidgetType::SelectableLabel, &description);
+                                        
+                                        if show_genre || show_year {
+                                            let metadata = metadata.unwrap_or_default();
+                                            if show_genre {
+                                                let genre = metadata.genre.unwrap_or_default();
+                                                response = response.union(ui.selectable_label(is_selected, genre));
+                                            }
+                                            if show_year {
+                                                let year = metadata.year.map(|y| y.to_string()).unwrap_or_default();
+                                                response = response.union(ui.selectable_label(is_selected, year));
+                                            }
+                                        }
+                                        if show_quality {
+                                            match &quality {
+                                                Some(quality) => {
+                                                    let color = if quality.lossless {
+                                                        egui::Color32::from_rgb(90, 170, 90)
+                                                    } else {
+                                                        ui.visuals().weak_text_color()
+                                                    };
+                                                    let badge = egui::RichText::new(&quality.label).small().color(color);
+                                                    response = response.union(ui.selectable_label(is_selected, badge));
+                                                }
+                                                None => {
+                                                    ui.label("");
+                                                }
+                                            }
+                                        }
+                                        ui.end_row();
+                                        
+                                        // A dragged row lands above or below this one, depending on which half it's over
+                                        if response.dnd_hover_payload::<usize>().is_some() {
+                                            let below = ui.ctx().pointer_interact_pos().is_some_and(|pos| pos.y > response.rect.center().y);
+                                            let y = if below { response.rect.bottom() } else { response.rect.top() };
+                                            ui.painter().hline(response.rect.x_range(), y, ui.visuals().selection.stroke);
+                                            if let Some(dragged) = response.dnd_release_payload::<usize>() {
+                                                dropped = Some((*dragged, if below { index + 1 } else { index }));
+                                            }
+                                        }
+                                        
+                                        // Keyboard focus moved here from a row that was off screen
+                                        if self.focus_selected && is_selected {
+                                            self.focus_selected = false;
+                                            response.request_focus();
+                                        }
+                                        if response.clicked() {
+                                            self.selected_song_index = Some(index);
+                                            // So the arrow keys carry on from here
+                                            response.request_focus();
+                                        }
+                                        
+                                        let is_current = Some(index) == self.current_playlist_index;
+                                        let last = self.playlist.len() - 1;
+                                        let row_menu = response.context_menu(|ui| {
+                                            for (enabled, label, action) in [
+                                                (true, "Play", RowAction::Play),
+                                                (!is_current, "Play Next", RowAction::Requeue(QueuePlacement::Next)),
+                                                (!is_current, "Add to Queue", RowAction::Requeue(QueuePlacement::Last)),
+                                                (true, "Remove", RowAction::Remove),
+                                                (index > 0, "Move Up", RowAction::MoveUp),
+                                                (index < last, "Move Down", RowAction::MoveDown),
+                                                (!is_stream(path), "Show in File Manager", RowAction::ShowInFileManager),
+                                                (true, "Copy Path", RowAction::CopyPath),
+                                            ] {
+                                                if ui.add_enabled(enabled, egui::Button::new(label)).clicked() {
+                                                    row_action = Some((index, action));
+                                                    ui.close_menu();
+                                                }
+                                            }
+                                        });
+                                        row_menu_open |= row_menu.is_some();
+                                        if let Ok(position) = rows.binary_search_by_key(&index, |(index, ..)| *index) {
+                                            rows[position] = (index, Some(response.id), response.rect);
+                                        }
+                                        if response.has_focus() {
+                                            focused_row = Some(index);
+                                        }
+                                        
+                                        // Double-click, or Enter on the focused row, plays it
+                                        let enter_pressed = response.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                        if response.double_clicked() || enter_pressed {
+                                            play_row = Some(index);
+                                        }
+                                    }
+                                });
+                            
+                            self.playlist_focused = focused_row.is_some();
+                            if let Some(focused) = focused_row {
+                                self.handle_playlist_keys(ui, focused, &rows);
+                            }
+                            
+                            // Dropping below the last row moves the track to the end. The gap
+                            // reaches down to the bottom of a short playlist.
+                            if range.end == total_rows {
+                                let gap = row_rect(total_rows - 1);
+                                let zone = gap.with_max_y(gap.bottom().max(ui.clip_rect().bottom()));
+                                let end_zone = ui.interact(zone, ui.id().with("playlist_end"), egui::Sense::hover());
+                                if end_zone.dnd_hover_payload::<usize>().is_some() {
+                                    ui.painter().hline(end_zone.rect.x_range(), end_zone.rect.top(), ui.visuals().selection.stroke);
+                                    if let Some(dragged) = end_zone.dnd_release_payload::<usize>() {
+                                        dropped = Some((*dragged, self.playlist.len()));
+                                    }
                                 }
                             }
+                            
+                            if let Some(column) = sort_clicked {
+                                self.sort_playlist(column);
+                            }
+                            if let Some((from, to)) = dropped {
+                                self.move_playlist_entry(from, to);
+                            }
+                            self.menu_open |= row_menu_open;
+                            if let Some((index, action)) = row_action {
+                                self.apply_row_action(ui, index, action);
+                            }
+                            if let Some(index) = play_row {
+                                self.current_playlist_index = Some(index);
+                                self.play_current_song();
+                            }
                         });
+                    // Done, or the current track is hidden by a filter and there's nowhere to go
+                    self.scroll_to_current = false;
+                    
+                    let scrolled = ui.input(|i| i.smooth_scroll_delta.y != 0.0);
+                    if scrolled && ui.rect_contains_pointer(playlist_scroll.inner_rect) {
+                        self.manual_scroll_at = Some(std::time::Instant::now());
+                    }
                 });
                 
                 ui.separator();
                 
                 // Bottom controls section - fixed height, always visible
                 ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                    // Now playing display
-                    if let Some(path) = &self.current_file {
-                        ui.label(format!("Now playing: {}", path.file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("Unknown")));
+                    // Now playing display, with the current track's art and trim controls
+                    self.cover_art.update(ctx, self.current_file.as_deref());
+                    if let Some(path) = self.current_file.clone() {
+                        ui.horizontal(|ui| {
+                            let art_size = egui::vec2(40.0, 40.0);
+                            match self.cover_art.texture() {
+                                Some(texture) => {
+                                    ui.add(egui::Image::new(texture).fit_to_exact_size(art_size))
+                                        .on_hover_text("Album art");
+                                }
+                                None => {
+                                    let (rect, _) = ui.allocate_exact_size(art_size, egui::Sense::hover());
+                                    ui.painter().rect_filled(rect, 4.0, ui.visuals().faint_bg_color);
+                                    ui.painter().text(rect.center(), egui::Align2::CENTER_CENTER, "♪",
+                                        egui::FontId::proportional(20.0), ui.visuals().weak_text_color());
+                                }
+                            }
+                            ui.label(format!("Now playing: {}", display_name(&path, self.metadata.get(&path))));
+                            // Streams have no file to read the details from
+                            if !is_stream(&path) {
+                                let details = self.metadata.get(&path).map(|m| m.audio).unwrap_or_default();
+                                ui.weak("ℹ").on_hover_text(details.describe());
+                            }
+                            
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                if !self.trims.get(&path).is_empty()
+                                    && a11y::button(ui, "Clear Trim", "Play the whole track again").clicked()
+                                {
+                                    self.clear_trim();
+                                }
+                                if a11y::button(ui, "Set end here", "Stop this track at the current position").clicked() {
+                                    self.set_trim_end();
+                                }
+                                if a11y::button(ui, "Set start here", "Start this track at the current position").clicked() {
+                                    self.set_trim_start();
+                                }
+                            });
+                        });
                     }
                     
-                    // Progress bar and time display
-                    ui.horizontal(|ui| {
-                        // Current position display
-                        ui.label(Self::format_duration(self.song_position));
-                        
-                        // Progress slider
-                        let progress_ratio = if let Some(duration) = self.song_duration {
-                            if duration.as_secs() > 0 {
-                                self.song_position.as_secs_f32() / duration.as_secs_f32()
-                            } else {
-                                0.0
-                            }
-                        } else {
-                            0.0
-                        };
-                        
-                        let mut seek_pos = if self.seeking {
-                            self.seek_position
-                        } else {
-                            progress_ratio
+                    // Waveform of the current track, drawn once the background analysis is done
+                    if self.config.show_waveform && self.current_file.as_deref().is_some_and(|path| !is_stream(path)) {
+                        self.waveform.update(ctx, self.current_file.as_deref());
+                        let size = egui::vec2(ui.available_width(), WAVEFORM_HEIGHT);
+                        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                        let played = match self.song_duration {
+                            _ if self.seeking => self.seek_position,
+                            Some(duration) if !duration.is_zero() => self.song_position.as_secs_f32() / duration.as_secs_f32(),
+                            _ => 0.0,
                         };
-                        
-                        let slider_response = ui.add(
-                            egui::Slider::new(&mut seek_pos, 0.0..=1.0)
-                                .show_value(false)
-                                .trailing_fill(true)
-                        );
-                        
-                        // Handle seeking
-                        if slider_response.drag_started() {
-                            self.seeking = true;
-                            self.seek_position = seek_pos;
-                        } else if slider_response.drag_stopped() {
-                            self.seeking = false;
-                            self.seek_to_position(seek_pos);
-                        } else if slider_response.dragged() {
-                            self.seek_position = seek_pos;
-                        }
-                        
-                        // Total duration display
-                        if let Some(duration) = self.song_duration {
-                            ui.label(Self::format_duration(duration));
-                        } else {
-                            ui.label("--:--");
+                        match self.waveform.peaks() {
+                            Some(peaks) => Waveform::paint(ui, rect, peaks, played),
+                            None if self.waveform.is_loading() => {
+                                ui.put(rect, egui::Spinner::new());
+                            },
+                            None => {},
                         }
-                    });
+                    } else {
+                        // Stops any analysis still running
+                        self.waveform.update(ctx, None);
+                    }
+                    
+                    // Progress bar and time display
+                    ui.horizontal(|ui| self.show_progress_bar(ui));
                     
-                    // Playback controls
+                    // Playback controls. They're always shown, and greyed out when there's
+                    // nothing to act on, so the row doesn't jump around as tracks are added.
                     ui.horizontal(|ui| {
+                        let has_tracks = !self.playlist.is_empty();
+                        let has_current = self.current_playlist_index.is_some();
+                        if a11y::enabled_button(ui, has_tracks && has_current, "⏮ Previous", "Previous track").clicked() {
+                            self.apply_command(PlayerCommand::Previous);
+                        }
+                        
                         if self.is_playing {
-                            if ui.button("⏸ Pause").clicked() {
-                                if let Ok(player) = self.player.lock() {
-                                    player.pause();
-                                    self.is_playing = false;
-                                }
-                            }
-                        } else if self.current_playlist_index.is_some() && ui.button("▶ Play").clicked() {
-                            if let Ok(player) = self.player.lock() {
-                                player.resume();
-                                self.is_playing = true;
+                            if a11y::button(ui, "⏸ Pause", "Pause").clicked() {
+                                self.apply_command(PlayerCommand::Pause);
                             }
+                        } else if a11y::enabled_button(ui, self.current_playlist_index.is_some(), "▶ Play", "Play").clicked() {
+                            self.apply_command(PlayerCommand::Resume);
                         }
                         
-                        if ui.button("⏹ Stop").clicked() {
-                            if let Ok(player) = self.player.lock() {
-                                player.stop();
-                                self.is_playing = false;
-                            }
+                        if a11y::enabled_button(ui, has_tracks, "⏹ Stop", "Stop").clicked() {
+                            self.apply_command(PlayerCommand::Stop);
                         }
                         
-                        if ui.button("⏭ Next").clicked() {
-                            self.play_next_song();
+                        if a11y::enabled_button(ui, has_tracks, "⏭ Next", "Next track").clicked() {
+                            self.apply_command(PlayerCommand::Next);
                         }
                         
                         // Add shuffle toggle button
                         let shuffle_text = if self.shuffle_mode { "🔀 Shuffle: On" } else { "🔀 Shuffle: Off" };
-                        if ui.button(shuffle_text).clicked() {
-                            self.shuffle_mode = !self.shuffle_mode;
+                        let shuffle_label = if self.shuffle_mode { "Shuffle, on" } else { "Shuffle, off" };
+                        if a11y::button(ui, shuffle_text, shuffle_label).clicked() {
+                            self.apply_command(PlayerCommand::SetShuffle(!self.shuffle_mode));
                         }
                         
+                        let (repeat_text, repeat_label, next_mode) = match self.repeat_mode {
+                            RepeatMode::None => ("🔁 Repeat: Off", "Repeat, off", RepeatMode::All),
+                            RepeatMode::All => ("🔁 Repeat: All", "Repeat, all tracks", RepeatMode::One),
+                            RepeatMode::One => ("🔂 Repeat: One", "Repeat, current track", RepeatMode::None),
+                        };
+                        if a11y::button(ui, repeat_text, repeat_label).clicked() {
+                            self.apply_command(PlayerCommand::SetRepeat(next_mode));
+                        }
+                        
+                        self.show_sleep_menu(ui);
+                        self.show_speed_menu(ui);
+                        
                         // Add volume slider
                         ui.add_space(20.0);
                         ui.label("Volume:");
-                        let mut volume = self.volume;
-                        if ui.add(egui::Slider::new(&mut volume, 0.0..=1.0).show_value(false)).changed() {
-                            self.set_volume(volume);
+                        let mut volume = gain_to_perceptual(self.volume);
+                        let volume_slider = ui.add(egui::Slider::new(&mut volume, 0.0..=1.0).show_value(false));
+                        if a11y::slider(ui, volume_slider, volume as f64, "Volume").changed() {
+                            self.set_volume(perceptual_to_gain(volume));
                         }
                         
-                        // Show volume percentage
-                        ui.label(format!("{}%", (volume * 100.0).round() as i32));
+                        let muted = self.pre_mute_volume.is_some();
+                        let (mute_text, mute_label) = if muted { ("🔇", "Unmute") } else { ("🔊", "Mute") };
+                        if a11y::button(ui, mute_text, mute_label).on_hover_text(mute_label).clicked() {
+                            self.toggle_mute();
+                        }
+                        
+                        // Show volume percentage, of the way up the slider rather than of the gain
+                        if self.pre_mute_volume.is_some() {
+                            ui.label("Muted");
+                        } else {
+                            ui.label(format!("{}%", (gain_to_perceptual(self.volume) * 100.0).round() as i32));
+                        }
                     });
                 });
             });
@@ -580,21 +4112,49 @@ impl eframe::App for MusicPlayerApp {
     }
 }
 
-pub fn run(paths: Vec<PathBuf>, _opened_with_files: bool) -> Result<()> {
+// Windows delivers media key events through the main window, other systems don't need it
+#[cfg(feature = "media-controls")]
+fn window_handle(cc: &eframe::CreationContext<'_>) -> Option<*mut std::ffi::c_void> {
+    #[cfg(windows)]
+    {
+        use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+        match cc.window_handle().map(|handle| handle.as_raw()) {
+            Ok(RawWindowHandle::Win32(handle)) => Some(handle.hwnd.get() as *mut std::ffi::c_void),
+            _ => None,
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = cc;
+        None
+    }
+}
+
+/// Whether the panic key was pressed this frame. It works whatever has focus, text fields
+/// included, but is left alone while a menu, popup or dialog is open (Escape closes them).
+pub fn panic_key_pressed(ctx: &egui::Context, key: egui::Key, dialog_open: bool) -> bool {
+    !dialog_open && !ctx.memory(|m| m.any_popup_open()) && ctx.input(|i| i.key_pressed(key))
+}
+
+pub fn run(paths: Vec<PathBuf>, opened_with_files: bool, loop_file: bool, start_paused: bool) -> Result<()> {
+    // Open at the size of the mode the player was left in
+    let mini_mode = load_config().map(|config| config.mini_mode).unwrap_or(false);
     let options = NativeOptions {
         viewport: ViewportBuilder::default()
-            .with_inner_size(egui::vec2(500.0, 600.0))
+            .with_inner_size(if mini_mode { MINI_WINDOW_SIZE } else { FULL_WINDOW_SIZE })
             .with_drag_and_drop(true), // Enable drag-drop file support
         ..Default::default()
     };
     
+    // Finder's open events have to be handled from before the window opens
+    #[cfg(target_os = "macos")]
+    crate::open_events::install();
+    
     if eframe::run_native(
         "Music Player",
         options,
         Box::new(|cc| {
-            // Enable handling dropped files
-            cc.egui_ctx.set_visuals(egui::Visuals::dark());
-            Ok(Box::new(MusicPlayerApp::new(cc, paths)))
+            Ok(Box::new(MusicPlayerApp::new(cc, paths, opened_with_files, loop_file, start_paused)))
         }),
     ).is_err() {
         return Err(anyhow::anyhow!("Failed to run eframe"));
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::config::get_config_dir;
+
+/// Most tracks kept in the history. The oldest are dropped first.
+pub const HISTORY_LIMIT: usize = 200;
+
+/// A track that started playing, and when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    /// Seconds since the Unix epoch
+    pub played_at: u64,
+}
+
+/// Recently played tracks, oldest first. Stored next to the config file in `history.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayHistory {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl PlayHistory {
+    /// Notes that `path` started playing now
+    pub fn record(&mut self, path: &Path) {
+        self.record_at(path, now());
+    }
+
+    fn record_at(&mut self, path: &Path, played_at: u64) {
+        // Restarting or seeking back into the same track isn't a new entry
+        if let Some(last) = self.entries.last_mut().filter(|last| last.path == path) {
+            last.played_at = played_at;
+            return;
+        }
+        self.entries.push(HistoryEntry { path: path.to_path_buf(), played_at });
+        if self.entries.len() > HISTORY_LIMIT {
+            let excess = self.entries.len() - HISTORY_LIMIT;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Most recent first
+    pub fn recent(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter().rev()
+    }
+
+    /// Tracks played before `current`, most recent first, with `current` itself skipped
+    pub fn before<'a>(&'a self, current: &'a Path) -> impl Iterator<Item = &'a Path> {
+        self.recent().map(|entry| entry.path.as_path()).filter(move |path| *path != current)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// How long ago `played_at` was, e.g. "5 min ago" or "3 days ago"
+pub fn played_ago(played_at: u64) -> String {
+    format_ago(now().saturating_sub(played_at))
+}
+
+fn format_ago(seconds: u64) -> String {
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        86400..172800 => "yesterday".to_string(),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+fn get_history_file_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("history.toml"))
+}
+
+/// Loads the saved history, or an empty one if nothing has been played yet
+pub fn load_history() -> Result<PlayHistory> {
+    let path = get_history_file_path()?;
+    if !path.exists() {
+        return Ok(PlayHistory::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+pub fn save_history(history: &PlayHistory) -> Result<()> {
+    let serialized = toml::to_string_pretty(history)?;
+    fs::write(get_history_file_path()?, serialized)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_bounded_and_skips_repeats() {
+        let mut history = PlayHistory::default();
+        history.record_at(Path::new("/music/a.mp3"), 10);
+        history.record_at(Path::new("/music/a.mp3"), 20);
+        history.record_at(Path::new("/music/b.mp3"), 30);
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.entries[0].played_at, 20);
+
+        for i in 0..HISTORY_LIMIT {
+            history.record_at(&PathBuf::from(format!("/music/{}.mp3", i)), 40 + i as u64);
+        }
+        assert_eq!(history.entries.len(), HISTORY_LIMIT);
+        assert_eq!(history.entries[0].path, PathBuf::from("/music/0.mp3"));
+    }
+
+    #[test]
+    fn test_before_walks_back_from_current() {
+        let mut history = PlayHistory::default();
+        for (i, name) in ["a", "b", "a", "c"].iter().enumerate() {
+            history.record_at(&PathBuf::from(format!("/music/{}.mp3", name)), i as u64);
+        }
+        let current = Path::new("/music/c.mp3");
+        let before: Vec<&Path> = history.before(current).collect();
+        assert_eq!(before, vec![Path::new("/music/a.mp3"), Path::new("/music/b.mp3"), Path::new("/music/a.mp3")]);
+    }
+
+    #[test]
+    fn test_history_round_trips_through_toml() {
+        let mut history = PlayHistory::default();
+        history.record_at(Path::new("/music/song.flac"), 1_700_000_000);
+
+        let serialized = toml::to_string_pretty(&history).unwrap();
+        let loaded: PlayHistory = toml::from_str(&serialized).unwrap();
+        assert_eq!(loaded, history);
+    }
+
+    #[test]
+    fn test_format_ago() {
+        assert_eq!(format_ago(5), "just now");
+        assert_eq!(format_ago(300), "5 min ago");
+        assert_eq!(format_ago(7200), "2 h ago");
+        assert_eq!(format_ago(90000), "yesterday");
+        assert_eq!(format_ago(3 * 86400), "3 days ago");
+    }
+}
@@ -2,4 +2,46 @@
 pub mod player;
 pub mod gui;
 pub mod utils;
-pub mod config; 
\ No newline at end of file
+pub mod config;
+pub mod watcher;
+pub mod timestretch;
+pub mod fingerprint;
+pub mod cache;
+pub mod crossfeed;
+pub mod controller;
+pub mod cli;
+pub mod replaygain;
+pub mod status;
+pub mod metadata;
+pub mod presets;
+pub mod a11y;
+pub mod desktop_notify;
+pub mod lyrics;
+#[cfg(feature = "remote-control")]
+pub mod remote;
+pub mod decode;
+pub mod trims;
+pub mod device_watch;
+pub mod sleep;
+pub mod playlist;
+pub mod cover_art;
+pub mod equalizer;
+pub mod history;
+pub mod play_counts;
+pub mod waveform;
+pub mod stream;
+pub mod backend;
+pub mod resume;
+pub mod balance;
+pub mod open_events;
+#[cfg(feature = "media-controls")]
+pub mod media_controls;
+#[cfg(feature = "tray")]
+pub mod tray; 
+
+// The pieces for using the player as a library
+pub use backend::AudioBackend;
+pub use player::MusicPlayer;
+pub use metadata::{display_name, read_metadata, AudioDetails, TrackMetadata};
+pub use playlist::{load_cue, load_m3u, load_m3u_with_trims, load_pls, load_pls_with_trims, save_m3u, save_pls, CueTrack};
+pub use trims::{Trim, TrackTrims};
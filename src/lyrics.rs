@@ -0,0 +1,151 @@
+use lofty::file::TaggedFileExt;
+use lofty::tag::ItemKey;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Lyrics for a track, either timed line by line or as plain text
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lyrics {
+    /// Lines with the time they start at, sorted by time
+    Synced(Vec<(Duration, String)>),
+    Unsynced(String),
+}
+
+/// Finds lyrics for a track. A sidecar `.lrc` file next to the audio wins over
+/// lyrics embedded in the tags, since it's usually the timed version.
+pub fn load_lyrics(path: &Path) -> Option<Lyrics> {
+    let lrc_path = path.with_extension("lrc");
+    if lrc_path.is_file() {
+        match fs::read_to_string(&lrc_path) {
+            Ok(text) => {
+                if let Some(lyrics) = lyrics_from_text(&text) {
+                    return Some(lyrics);
+                }
+            }
+            Err(e) => log::warn!("Could not read {}: {}", lrc_path.display(), e),
+        }
+    }
+
+    let tagged_file = match lofty::read_from_path(path) {
+        Ok(file) => file,
+        Err(e) => {
+            log::debug!("No lyrics for {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    tagged_file
+        .tags()
+        .iter()
+        .find_map(|tag| tag.get_string(&ItemKey::Lyrics).map(|s| s.to_string()))
+        .and_then(|text| lyrics_from_text(&text))
+}
+
+// Some taggers embed LRC text, so anything with timestamps is treated as synced
+fn lyrics_from_text(text: &str) -> Option<Lyrics> {
+    let lines = parse_lrc(text);
+    if !lines.is_empty() {
+        return Some(Lyrics::Synced(lines));
+    }
+
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(Lyrics::Unsynced(text.to_string()))
+    }
+}
+
+/// Parses LRC text into timed lines. A line may carry several timestamps
+/// (`[00:12.00][01:30.00]Chorus`) and an `[offset:ms]` tag shifts every line.
+/// Other ID tags (`[ar:...]`, `[ti:...]`) and untimed lines are ignored.
+pub fn parse_lrc(text: &str) -> Vec<(Duration, String)> {
+    let mut offset_ms: i64 = 0;
+    let mut timed: Vec<(i64, String)> = Vec::new();
+
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut stamps = Vec::new();
+
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(end) = tag.find(']') else {
+                break;
+            };
+            let content = &tag[..end];
+            rest = &tag[end + 1..];
+
+            if let Some(ms) = parse_timestamp(content) {
+                stamps.push(ms);
+            } else if let Some(value) = content.strip_prefix("offset:") {
+                offset_ms = value.trim().parse().unwrap_or(0);
+            }
+        }
+
+        for ms in stamps {
+            timed.push((ms, rest.trim().to_string()));
+        }
+    }
+
+    // A positive offset makes the lyrics come up sooner
+    let mut lines: Vec<(Duration, String)> = timed
+        .into_iter()
+        .map(|(ms, text)| (Duration::from_millis((ms - offset_ms).max(0) as u64), text))
+        .collect();
+    lines.sort_by_key(|(time, _)| *time);
+    lines
+}
+
+// "mm:ss", "mm:ss.xx" or "mm:ss.xxx" to milliseconds
+fn parse_timestamp(stamp: &str) -> Option<i64> {
+    let (minutes, seconds) = stamp.split_once(':')?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+    let (whole, fraction) = seconds.split_once(['.', ':']).unwrap_or((seconds, ""));
+    let whole: i64 = whole.trim().parse().ok()?;
+
+    let fraction_ms = if fraction.is_empty() {
+        0
+    } else {
+        let digits = &fraction[..fraction.len().min(3)];
+        let value: i64 = digits.parse().ok()?;
+        value * 10_i64.pow(3 - digits.len() as u32)
+    };
+
+    Some(minutes * 60_000 + whole * 1000 + fraction_ms)
+}
+
+/// Index of the line being sung at `position`, if the first line has started
+pub fn current_line(lines: &[(Duration, String)], position: Duration) -> Option<usize> {
+    lines.partition_point(|(time, _)| *time <= position).checked_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lrc() {
+        let text = "[ar:Someone]\n[00:01.50]First\n[00:10.00][01:00.123]Chorus\nno timestamp\n[00:05]Second";
+        let lines = parse_lrc(text);
+        assert_eq!(lines, vec![
+            (Duration::from_millis(1500), "First".to_string()),
+            (Duration::from_secs(5), "Second".to_string()),
+            (Duration::from_secs(10), "Chorus".to_string()),
+            (Duration::from_millis(60_123), "Chorus".to_string()),
+        ]);
+
+        let shifted = parse_lrc("[offset:500]\n[00:02.00]Line");
+        assert_eq!(shifted, vec![(Duration::from_millis(1500), "Line".to_string())]);
+
+        assert_eq!(lyrics_from_text("Just words"), Some(Lyrics::Unsynced("Just words".to_string())));
+    }
+
+    #[test]
+    fn test_current_line() {
+        let lines = parse_lrc("[00:01.00]a\n[00:03.00]b\n[00:05.00]c");
+        assert_eq!(current_line(&lines, Duration::ZERO), None);
+        assert_eq!(current_line(&lines, Duration::from_secs(1)), Some(0));
+        assert_eq!(current_line(&lines, Duration::from_secs(4)), Some(1));
+        assert_eq!(current_line(&lines, Duration::from_secs(60)), Some(2));
+    }
+}
@@ -2,12 +2,45 @@ mod gui;
 mod player;
 mod utils;
 mod config;
+mod watcher;
+mod timestretch;
+mod fingerprint;
+mod cache;
+mod crossfeed;
+mod controller;
+mod cli;
+mod replaygain;
+mod status;
+mod metadata;
+mod presets;
+mod a11y;
+mod desktop_notify;
+mod lyrics;
+#[cfg(feature = "remote-control")]
+mod remote;
+mod decode;
+mod trims;
+mod device_watch;
+mod sleep;
+mod playlist;
+mod cover_art;
+mod equalizer;
+mod history;
+mod play_counts;
+mod waveform;
+mod stream;
+mod backend;
+mod resume;
+mod balance;
+mod open_events;
+#[cfg(feature = "media-controls")]
+mod media_controls;
+#[cfg(feature = "tray")]
+mod tray;
 
 use anyhow::Result;
 use clap::Parser;
-use glob::glob;
-use std::path::PathBuf;
-use utils::is_audio_file;
+use utils::expand_paths;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -19,76 +52,92 @@ struct Args {
     /// When true, the app was launched via "Open with" from the OS
     #[arg(long, hide = true)]
     opened_with: bool,
+
+    /// Loop the file forever when exactly one file is opened
+    #[arg(long = "loop")]
+    loop_file: bool,
+
+    /// Load the files and select the first one, but wait for Play instead of starting it
+    #[arg(long)]
+    paused: bool,
+
+    /// Treat every argument as a literal path, even if it contains glob characters like [ or ?
+    #[arg(long)]
+    no_glob: bool,
+
+    /// Run in the terminal instead of opening a window
+    #[arg(long)]
+    no_gui: bool,
+
+    /// Play a 440Hz test tone for a few seconds and exit
+    #[arg(long)]
+    test_tone: bool,
+
+    /// Send a command (e.g. "next", "volume 0.3", "seek 120") to the running player and exit
+    #[arg(long, value_name = "COMMAND")]
+    remote: Option<String>,
 }
 
-fn expand_glob_patterns(patterns: Vec<String>) -> Vec<PathBuf> {
-    let mut files = Vec::new();
+// Forward a command to the running player and print its status line
+#[cfg(feature = "remote-control")]
+fn send_remote(command: &str) -> Result<()> {
+    let port = config::load_config().map(|c| c.remote_port).unwrap_or(config::DEFAULT_REMOTE_PORT);
+    let reply = remote::send_command(port, &remote::remote_token_path()?, command)?;
+    println!("{}", reply);
     
-    for pattern in patterns {
-        // Check if it's a direct file path
-        let path = PathBuf::from(&pattern);
-        if path.is_file() {
-            if is_audio_file(&path) {
-                files.push(path);
-            } else {
-                eprintln!("Skipping non-audio file: {}", pattern);
-            }
-            continue;
-        }
-        
-        // Try as a glob pattern
-        match glob(&pattern) {
-            Ok(entries) => {
-                let mut matched = false;
-                let mut audio_matched = false;
-                
-                for path in entries.flatten() {
-                    if path.is_file() {
-                        matched = true;
-                        if is_audio_file(&path) {
-                            audio_matched = true;
-                            files.push(path);
-                        } // Silently skip non-audio files from globs
-                    }
-                }
-                
-                if !matched {
-                    eprintln!("No files matched pattern: {}", pattern);
-                } else if !audio_matched {
-                    eprintln!("Pattern '{}' matched files, but none were audio files", pattern);
-                }
-            },
-            Err(_) => {
-                eprintln!("Invalid pattern: {}", pattern);
-            }
-        }
+    if remote::is_error_reply(&reply) {
+        eprintln!("\nCommands:\n{}", remote::GRAMMAR);
+        std::process::exit(1);
     }
+    Ok(())
+}
+
+#[cfg(not(feature = "remote-control"))]
+fn send_remote(_command: &str) -> Result<()> {
+    anyhow::bail!("This build has no remote control (the remote-control feature is turned off)")
+}
+
+// Play a tone on the default output device so users can check their audio setup
+fn play_test_tone() -> Result<()> {
+    const SECS: f32 = 3.0;
     
-    if files.is_empty() {
-        eprintln!("No audio files found in the provided patterns");
-    } else {
-        println!("Found {} audio files", files.len());
-    }
+    let player = player::MusicPlayer::new()?;
+    player.play_test_tone(440.0, SECS)?;
+    println!("Playing a 440Hz test tone for {} seconds...", SECS);
     
-    files
+    // The output stream closes when the player is dropped, so wait for the tone to finish
+    std::thread::sleep(std::time::Duration::from_secs_f32(SECS));
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    
     // Detect if app was launched via OS file association
     // On macOS, if the app is launched via "Open with", the first argument will be -psn_*
     // This is macOS-specific process serial number
     let is_macos_file_open = std::env::args().any(|arg| arg.starts_with("-psn_"));
     
+    // clap would reject the process serial number as an unknown option
+    let args = Args::parse_from(std::env::args().filter(|arg| !arg.starts_with("-psn_")));
+    
+    if args.test_tone {
+        return play_test_tone();
+    }
+    
+    if let Some(command) = &args.remote {
+        return send_remote(command);
+    }
+    
     // Get files from command-line args
-    let file_paths = expand_glob_patterns(args.files);
+    let file_paths = expand_paths(args.files, !args.no_glob);
+    
+    if args.no_gui {
+        return cli::run(file_paths, args.loop_file);
+    }
     
-    // On Windows/Linux, the files are passed directly as arguments
-    // On macOS, we need to check for AppleEvents (via eframe's integration)
-    // If no files found yet and we're launched via file association,
-    // eframe will handle it via context.dropped_files in the app
+    // On Windows/Linux, the files are passed directly as arguments. On macOS, Finder sends
+    // them as Apple Events, which open_events picks up once the window is up, so the first
+    // one starts playing then instead.
     
     // Launch the GUI with the files
-    gui::run(file_paths, is_macos_file_open || args.opened_with)
+    gui::run(file_paths, is_macos_file_open || args.opened_with, args.loop_file, args.paused)
 }
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig, SeekDirection};
+use std::ffi::c_void;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use crate::metadata::{TrackMetadata, display_name};
+use crate::player::{PlaybackState, PlayerCommand, PlayerSnapshot};
+
+/// How far the desktop's plain seek buttons jump
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// Position drift tolerated before the system is told about it. Seek bars move along on
+/// their own while we're playing, so only jumps need sending.
+const POSITION_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// What the system was last told about the track
+#[derive(Debug, Clone, PartialEq)]
+struct PublishedTrack {
+    path: Option<PathBuf>,
+    metadata: Option<TrackMetadata>,
+    duration: Option<Duration>,
+}
+
+/// Hooks the player up to the system's media controls: MPRIS on Linux, the System Media
+/// Transport Controls on Windows and Now Playing on macOS. Media keys and the system's media
+/// widget can then control it, and it shows what's playing with a working seek bar.
+pub struct SystemMediaControls {
+    controls: MediaControls,
+    events: Receiver<MediaControlEvent>,
+    published_track: Option<PublishedTrack>,
+    published_state: Option<(PlaybackState, Duration, Instant)>,
+}
+
+impl SystemMediaControls {
+    /// `hwnd` is the main window's handle, which Windows needs and other systems ignore
+    pub fn start(hwnd: Option<*mut c_void>) -> Result<Self> {
+        let config = PlatformConfig {
+            dbus_name: "musicplayer",
+            display_name: "Music Player",
+            hwnd,
+        };
+        let mut controls = MediaControls::new(config)
+            .map_err(|e| anyhow::anyhow!("Could not create media controls: {:?}", e))?;
+
+        let (tx, rx) = channel();
+        controls.attach(move |event| {
+            let _ = tx.send(event);
+        }).map_err(|e| anyhow::anyhow!("Could not listen for media control events: {:?}", e))?;
+
+        Ok(Self {
+            controls,
+            events: rx,
+            published_track: None,
+            published_state: None,
+        })
+    }
+
+    /// Commands from media keys and the system's media widget since the last call. Relative seeks are
+    /// worked out from `snapshot`. Call this regularly.
+    pub fn poll(&self, snapshot: &PlayerSnapshot) -> Vec<PlayerCommand> {
+        self.events.try_iter().filter_map(|event| to_command(event, snapshot)).collect()
+    }
+
+    /// Tells the system about a new track, tags that have just been read, or a change of
+    /// playback state or position. Does nothing when none of those changed, so it's cheap
+    /// to call every frame.
+    pub fn update(&mut self, snapshot: &PlayerSnapshot, metadata: Option<&TrackMetadata>) {
+        let track = PublishedTrack {
+            path: snapshot.current_path.clone(),
+            metadata: metadata.cloned(),
+            duration: snapshot.duration,
+        };
+        if self.published_track.as_ref() != Some(&track) {
+            self.publish_track(&track);
+            self.published_track = Some(track);
+        }
+
+        let jumped = match self.published_state {
+            Some((state, position, at)) if state == snapshot.state => {
+                let expected = match state {
+                    PlaybackState::Playing => position + at.elapsed(),
+                    _ => position,
+                };
+                snapshot.position.abs_diff(expected) > POSITION_TOLERANCE
+            }
+            _ => true,
+        };
+        if jumped {
+            let progress = Some(MediaPosition(snapshot.position));
+            let playback = match snapshot.state {
+                PlaybackState::Playing => MediaPlayback::Playing { progress },
+                PlaybackState::Paused => MediaPlayback::Paused { progress },
+                PlaybackState::Stopped => MediaPlayback::Stopped,
+            };
+            if let Err(e) = self.controls.set_playback(playback) {
+                log::debug!("Could not publish playback state: {:?}", e);
+            }
+            self.published_state = Some((snapshot.state, snapshot.position, Instant::now()));
+        }
+    }
+
+    fn publish_track(&mut self, track: &PublishedTrack) {
+        let Some(path) = &track.path else {
+            if let Err(e) = self.controls.set_metadata(MediaMetadata::default()) {
+                log::debug!("Could not clear track metadata: {:?}", e);
+            }
+            return;
+        };
+
+        // Untagged files still get a title, from their file name
+        let title = track.metadata.as_ref()
+            .and_then(|m| m.title.clone())
+            .unwrap_or_else(|| display_name(path, None));
+        let artist = track.metadata.as_ref().and_then(|m| m.artist.as_deref());
+        let album = track.metadata.as_ref().and_then(|m| m.album.as_deref());
+
+        let metadata = MediaMetadata {
+            title: Some(&title),
+            artist,
+            album,
+            duration: track.duration,
+            ..MediaMetadata::default()
+        };
+        if let Err(e) = self.controls.set_metadata(metadata) {
+            log::debug!("Could not publish track metadata: {:?}", e);
+        }
+    }
+}
+
+fn to_command(event: MediaControlEvent, snapshot: &PlayerSnapshot) -> Option<PlayerCommand> {
+    let seek_by = |direction: SeekDirection, offset: Duration| {
+        let target = match direction {
+            SeekDirection::Forward => snapshot.position + offset,
+            SeekDirection::Backward => snapshot.position.saturating_sub(offset),
+        };
+        PlayerCommand::Seek(snapshot.duration.map_or(target, |duration| target.min(duration)))
+    };
+
+    let command = match event {
+        MediaControlEvent::Play => PlayerCommand::Resume,
+        MediaControlEvent::Pause => PlayerCommand::Pause,
+        MediaControlEvent::Toggle => PlayerCommand::TogglePlayPause,
+        MediaControlEvent::Next => PlayerCommand::Next,
+        MediaControlEvent::Previous => PlayerCommand::Previous,
+        MediaControlEvent::Stop => PlayerCommand::Stop,
+        MediaControlEvent::Seek(direction) => seek_by(direction, SEEK_STEP),
+        MediaControlEvent::SeekBy(direction, offset) => seek_by(direction, offset),
+        MediaControlEvent::SetPosition(MediaPosition(position)) => PlayerCommand::Seek(position),
+        MediaControlEvent::SetVolume(volume) => PlayerCommand::SetVolume((volume as f32).clamp(0.0, 1.0)),
+        other => {
+            log::debug!("Ignoring media control event {:?}", other);
+            return None;
+        }
+    };
+    Some(command)
+}
@@ -0,0 +1,369 @@
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::picture::PictureType;
+use lofty::tag::Accessor;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use crate::cache::LruCache;
+use crate::playlist::cue_track_for;
+use crate::stream::stream_url;
+
+/// Tag values shown in the playlist. Anything missing from the file is `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub duration: Option<Duration>,
+    pub quality: Option<Quality>,
+    /// Technical details of the audio stream, for the track info tooltip
+    pub audio: AudioDetails,
+}
+
+/// Sample rate, channel count, bit depth and bitrate of a file's audio. Formats that don't
+/// record one of these (bit depth for lossy files, say) leave it `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AudioDetails {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+    pub bit_depth: Option<u8>,
+    pub bitrate_kbps: Option<u32>,
+}
+
+impl AudioDetails {
+    /// One "Name: value" line per detail, with "unknown" for anything the file doesn't say
+    pub fn describe(&self) -> String {
+        let unknown = || "unknown".to_string();
+        let sample_rate = self.sample_rate
+            .map(|hz| format!("{} kHz", hz as f32 / 1000.0))
+            .unwrap_or_else(unknown);
+        let channels = self.channels
+            .map(|channels| match channels {
+                1 => "1 (mono)".to_string(),
+                2 => "2 (stereo)".to_string(),
+                n => n.to_string(),
+            })
+            .unwrap_or_else(unknown);
+        let bit_depth = self.bit_depth.map(|bits| format!("{}-bit", bits)).unwrap_or_else(unknown);
+        let bitrate = self.bitrate_kbps.map(|kbps| format!("{} kbps", kbps)).unwrap_or_else(unknown);
+        format!("Sample rate: {}\nChannels: {}\nBit depth: {}\nBitrate: {}", sample_rate, channels, bit_depth, bitrate)
+    }
+}
+
+/// Short description of a file's audio quality, shown as a badge in the playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quality {
+    /// "FLAC", "WAV"... for lossless files, the bitrate in kbps ("320", "128") for lossy ones
+    pub label: String,
+    pub lossless: bool,
+}
+
+/// Works out the quality badge from the container type and average bitrate
+pub fn quality_for(file_type: &FileType, bitrate_kbps: Option<u32>) -> Quality {
+    let lossless_name = match file_type {
+        FileType::Flac => Some("FLAC"),
+        FileType::Wav => Some("WAV"),
+        FileType::Aiff => Some("AIFF"),
+        FileType::Ape => Some("APE"),
+        FileType::WavPack => Some("WV"),
+        _ => None,
+    };
+
+    match (lossless_name, bitrate_kbps) {
+        (Some(name), _) => Quality { label: name.to_string(), lossless: true },
+        (None, Some(kbps)) => Quality { label: kbps.to_string(), lossless: false },
+        (None, None) => Quality { label: format!("{:?}", file_type).to_uppercase(), lossless: false },
+    }
+}
+
+/// "Artist - Title" for a track, or just the title when there's no artist.
+/// Falls back to the file name when the tags are missing or haven't been read yet.
+pub fn display_name(path: &Path, metadata: Option<&TrackMetadata>) -> String {
+    // A stream's address says more than the last part of it
+    let file_name = || match stream_url(path) {
+        Some(url) => url.split_once("://").map_or(url, |(_, rest)| rest).to_string(),
+        None => path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Unknown".to_string()),
+    };
+
+    match metadata.map(|m| (&m.artist, &m.title)) {
+        Some((Some(artist), Some(title))) => format!("{} - {}", artist, title),
+        Some((None, Some(title))) => title.clone(),
+        _ => file_name(),
+    }
+}
+
+/// Whether a playlist entry matches a search, ignoring case. Checks the file name, and the
+/// title and artist once the tags have been read. `query` must already be lowercase.
+pub fn matches_search(path: &Path, metadata: Option<&TrackMetadata>, query: &str) -> bool {
+    let in_file_name = path.file_name()
+        .is_some_and(|n| n.to_string_lossy().to_lowercase().contains(query));
+
+    in_file_name || metadata.is_some_and(|m| {
+        [&m.title, &m.artist].into_iter().flatten().any(|tag| tag.to_lowercase().contains(query))
+    })
+}
+
+/// Whether a playlist entry's name as shown, title or file name starts with `prefix`,
+/// ignoring case. Used to jump to a track by typing. `prefix` must already be lowercase.
+pub fn matches_type_ahead(path: &Path, metadata: Option<&TrackMetadata>, prefix: &str) -> bool {
+    let title = metadata.and_then(|m| m.title.clone());
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string());
+    [Some(display_name(path, metadata)), title, file_name]
+        .into_iter()
+        .flatten()
+        .any(|name| name.to_lowercase().starts_with(prefix))
+}
+
+/// The decoder's idea of how long a track is, or failing that the length in the file's
+/// headers. Decoders can't tell for many MP3 and Ogg streams without reading them through.
+pub fn track_duration(path: &Path, reported: Option<Duration>) -> Option<Duration> {
+    reported.or_else(|| {
+        let tagged_file = lofty::read_from_path(path).ok()?;
+        Some(tagged_file.properties().duration()).filter(|d| !d.is_zero())
+    })
+}
+
+/// "12 tracks • 0:48:10 total" for a playlist, given each track's length. A `+` after the
+/// total means some lengths aren't known yet, so the real total is longer.
+pub fn playlist_summary(durations: impl IntoIterator<Item = Option<Duration>>) -> String {
+    let mut count = 0;
+    let mut total = Duration::ZERO;
+    let mut unknown = 0;
+    for duration in durations {
+        count += 1;
+        match duration {
+            Some(duration) => total += duration,
+            None => unknown += 1,
+        }
+    }
+
+    let tracks = if count == 1 { "1 track".to_string() } else { format!("{} tracks", count) };
+    if count == 0 || unknown == count {
+        return tracks;
+    }
+    let seconds = total.as_secs();
+    let more = if unknown > 0 { "+" } else { "" };
+    format!("{} • {}:{:02}:{:02}{} total", tracks, seconds / 3600, seconds / 60 % 60, seconds % 60, more)
+}
+
+/// The picture embedded in a file's tags, as the encoded image bytes. The front cover is
+/// preferred when there are several.
+pub fn read_embedded_art(path: &Path) -> Option<Vec<u8>> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let pictures = tagged_file.tags().iter().flat_map(|tag| tag.pictures()).collect::<Vec<_>>();
+    let picture = pictures.iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())?;
+    Some(picture.data().to_vec())
+}
+
+/// Reads the tags from a file. Unreadable files just have no metadata.
+/// Cue sheet tracks get their title and performer from the sheet.
+pub fn read_metadata(path: &Path) -> TrackMetadata {
+    if let Some(track) = cue_track_for(path) {
+        let file = read_metadata(&track.file);
+        let duration = match track.end {
+            Some(end) => Some(end.saturating_sub(track.start)),
+            None => file.duration.map(|d| d.saturating_sub(track.start)),
+        };
+        return TrackMetadata {
+            title: track.title,
+            artist: track.performer.or(file.artist),
+            album: track.album.or(file.album),
+            duration,
+            ..file
+        };
+    }
+    
+    // Tags would mean downloading the stream, and a station has no length anyway
+    if stream_url(path).is_some() {
+        return TrackMetadata::default();
+    }
+    
+    let tagged_file = match lofty::read_from_path(path) {
+        Ok(file) => file,
+        Err(e) => {
+            log::debug!("No metadata for {}: {}", path.display(), e);
+            return TrackMetadata::default();
+        }
+    };
+
+    // Quality and length come from the audio stream, so untagged files still get them
+    let properties = tagged_file.properties();
+    let quality = Some(quality_for(&tagged_file.file_type(), properties.audio_bitrate()));
+    let duration = Some(properties.duration()).filter(|d| !d.is_zero());
+    let audio = AudioDetails {
+        sample_rate: properties.sample_rate(),
+        channels: properties.channels(),
+        bit_depth: properties.bit_depth(),
+        bitrate_kbps: properties.audio_bitrate().or_else(|| properties.overall_bitrate()),
+    };
+    let Some(tag) = tagged_file.primary_tag().or_else(|| tagged_file.first_tag()) else {
+        return TrackMetadata { duration, quality, audio, ..TrackMetadata::default() };
+    };
+
+    TrackMetadata {
+        title: tag.title().map(|s| s.to_string()),
+        artist: tag.artist().map(|s| s.to_string()),
+        album: tag.album().map(|s| s.to_string()),
+        genre: tag.genre().map(|s| s.to_string()),
+        year: tag.year(),
+        duration,
+        quality,
+        audio,
+    }
+}
+
+/// Metadata for playlist entries, read on a background thread so big playlists
+/// don't stall the UI. Entries show up a few frames after they're first asked for.
+pub struct MetadataCache {
+    entries: LruCache<PathBuf, TrackMetadata>,
+    pending: HashSet<PathBuf>,
+    requests: Sender<PathBuf>,
+    results: Receiver<(PathBuf, TrackMetadata)>,
+}
+
+impl MetadataCache {
+    /// Creates a cache holding metadata for at most `capacity` tracks
+    pub fn new(capacity: usize) -> Self {
+        let (request_tx, request_rx) = channel::<PathBuf>();
+        let (result_tx, result_rx) = channel();
+
+        thread::spawn(move || {
+            for path in request_rx {
+                let metadata = read_metadata(&path);
+                if result_tx.send((path, metadata)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            entries: LruCache::new(capacity),
+            pending: HashSet::new(),
+            requests: request_tx,
+            results: result_rx,
+        }
+    }
+
+    /// Returns the metadata if it has been read, otherwise queues the file to be read
+    pub fn get(&mut self, path: &Path) -> Option<&TrackMetadata> {
+        let key = path.to_path_buf();
+        if !self.entries.contains_key(&key) {
+            if self.pending.insert(key.clone()) {
+                let _ = self.requests.send(key);
+            }
+            return None;
+        }
+        self.entries.get(&key)
+    }
+
+    /// Collects metadata read since the last call. Returns true if anything new arrived.
+    pub fn poll(&mut self) -> bool {
+        let mut updated = false;
+        while let Ok((path, metadata)) = self.results.try_recv() {
+            self.pending.remove(&path);
+            self.entries.insert(path, metadata);
+            updated = true;
+        }
+        updated
+    }
+
+    /// Returns the metadata if it has already been read, without queueing anything
+    pub fn peek(&self, path: &Path) -> Option<&TrackMetadata> {
+        self.entries.peek(&path.to_path_buf())
+    }
+    
+    /// Forgets a file's metadata, e.g. after its tags were edited
+    #[allow(dead_code)]
+    pub fn invalidate(&mut self, path: &Path) {
+        self.entries.remove(&path.to_path_buf());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality_badges() {
+        assert_eq!(quality_for(&FileType::Flac, Some(900)), Quality { label: "FLAC".to_string(), lossless: true });
+        assert_eq!(quality_for(&FileType::Mpeg, Some(320)), Quality { label: "320".to_string(), lossless: false });
+        assert_eq!(quality_for(&FileType::Vorbis, None), Quality { label: "VORBIS".to_string(), lossless: false });
+    }
+
+    #[test]
+    fn test_audio_details_show_unknown_fields() {
+        let flac = AudioDetails { sample_rate: Some(44100), channels: Some(2), bit_depth: Some(16), bitrate_kbps: Some(912) };
+        assert_eq!(flac.describe(), "Sample rate: 44.1 kHz\nChannels: 2 (stereo)\nBit depth: 16-bit\nBitrate: 912 kbps");
+
+        let mp3 = AudioDetails { sample_rate: Some(48000), channels: Some(1), bitrate_kbps: Some(128), ..AudioDetails::default() };
+        assert_eq!(mp3.describe(), "Sample rate: 48 kHz\nChannels: 1 (mono)\nBit depth: unknown\nBitrate: 128 kbps");
+        assert!(AudioDetails::default().describe().lines().all(|line| line.ends_with("unknown")));
+    }
+
+    #[test]
+    fn test_matches_type_ahead() {
+        let path = Path::new("/music/Track 01.mp3");
+        let metadata = TrackMetadata {
+            title: Some("Blue Monday".to_string()),
+            artist: Some("New Order".to_string()),
+            ..TrackMetadata::default()
+        };
+
+        assert!(matches_type_ahead(path, None, "tr"));
+        assert!(matches_type_ahead(path, Some(&metadata), "new"));
+        assert!(matches_type_ahead(path, Some(&metadata), "blue m"));
+        assert!(!matches_type_ahead(path, Some(&metadata), "monday"));
+        assert!(!matches_type_ahead(path, None, "music"));
+    }
+
+    #[test]
+    fn test_matches_search() {
+        let path = Path::new("/music/Track 01.mp3");
+        let metadata = TrackMetadata {
+            title: Some("Blue Monday".to_string()),
+            artist: Some("New Order".to_string()),
+            ..TrackMetadata::default()
+        };
+
+        assert!(matches_search(path, None, "track 01"));
+        assert!(matches_search(path, Some(&metadata), "monday"));
+        assert!(matches_search(path, Some(&metadata), "new order"));
+        assert!(!matches_search(path, None, "monday"));
+        assert!(!matches_search(path, Some(&metadata), "music"));
+    }
+
+    #[test]
+    fn test_playlist_summary() {
+        let secs = |s| Some(Duration::from_secs(s));
+        assert_eq!(playlist_summary([secs(215)]), "1 track • 0:03:35 total");
+        assert_eq!(playlist_summary([secs(3000), secs(723)]), "2 tracks • 1:02:03 total");
+        assert_eq!(playlist_summary([secs(60), None]), "2 tracks • 0:01:00+ total");
+        assert_eq!(playlist_summary([None, None]), "2 tracks");
+        assert_eq!(playlist_summary([]), "0 tracks");
+    }
+
+    #[test]
+    fn test_display_name_falls_back_to_file_name() {
+        let path = Path::new("/music/01 track.mp3");
+        let tagged = TrackMetadata {
+            artist: Some("Artist".to_string()),
+            title: Some("Title".to_string()),
+            ..TrackMetadata::default()
+        };
+        let title_only = TrackMetadata { artist: None, ..tagged.clone() };
+        let artist_only = TrackMetadata { title: None, ..tagged.clone() };
+
+        assert_eq!(display_name(path, Some(&tagged)), "Artist - Title");
+        assert_eq!(display_name(path, Some(&title_only)), "Title");
+        assert_eq!(display_name(path, Some(&artist_only)), "01 track.mp3");
+        assert_eq!(display_name(path, None), "01 track.mp3");
+    }
+}
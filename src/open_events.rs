@@ -0,0 +1,273 @@
+// Files opened from the desktop while the player is running, or to launch it.
+//
+// On Windows and Linux these come in as command-line arguments. On macOS, Finder sends
+// them as an "open documents" Apple Event, which winit doesn't pass on, so `install`
+// registers a handler for it. The files are queued here for the GUI to pick up, which
+// adds them the same way as files dropped onto the window.
+
+use eframe::egui;
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref OPENED_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+    static ref REPAINT: Mutex<Option<egui::Context>> = Mutex::new(None);
+}
+
+/// Queue files to be opened by the GUI, waking it if it's idle
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn queue_opened_files(paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+    if let Ok(mut opened) = OPENED_FILES.lock() {
+        opened.extend(paths);
+    }
+    if let Ok(repaint) = REPAINT.lock() {
+        if let Some(ctx) = repaint.as_ref() {
+            ctx.request_repaint();
+        }
+    }
+}
+
+/// Take the files opened since the last call, in the order they arrived
+pub fn take_opened_files() -> Vec<PathBuf> {
+    match OPENED_FILES.lock() {
+        Ok(mut opened) => std::mem::take(&mut *opened),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Set the context to wake when files are opened
+pub fn set_repaint_context(ctx: egui::Context) {
+    if let Ok(mut repaint) = REPAINT.lock() {
+        *repaint = Some(ctx);
+    }
+}
+
+/// Where a newly added batch of files should start playing, if anywhere.
+/// Opened or dropped files only start by themselves when there was nothing to play before.
+pub fn first_to_play(was_empty: bool, playlist_len: usize) -> Option<usize> {
+    (was_empty && playlist_len > 0).then_some(0)
+}
+
+/// Turn a `file://` URL, as Finder sends it, into a path
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    let rest = url.strip_prefix("file://")?;
+    // The host is empty or "localhost" for local files
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut chars = path.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    let mut path = String::from_utf8(bytes).ok()?;
+    // Folders come with a trailing slash
+    if path.len() > 1 && path.ends_with('/') {
+        path.pop();
+    }
+    Some(PathBuf::from(path))
+}
+
+/// Handle Finder's "open documents" events from here on. Must be called before the
+/// event loop starts, so the handler is in place for the files the app was launched with.
+#[cfg(target_os = "macos")]
+pub fn install() {
+    macos::install();
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::{file_url_to_path, queue_opened_files};
+    use std::ffi::{c_char, c_void};
+
+    type Id = *mut c_void;
+    type Sel = *const c_void;
+    type Class = *mut c_void;
+
+    #[link(name = "objc")]
+    unsafe extern "C" {
+        fn objc_getClass(name: *const c_char) -> Class;
+        fn objc_allocateClassPair(superclass: Class, name: *const c_char, extra_bytes: usize) -> Class;
+        fn objc_registerClassPair(class: Class);
+        fn class_addMethod(class: Class, name: Sel, imp: *const c_void, types: *const c_char) -> bool;
+        fn sel_registerName(name: *const c_char) -> Sel;
+        fn objc_msgSend();
+    }
+
+    #[link(name = "AppKit", kind = "framework")]
+    unsafe extern "C" {
+        static NSApplicationWillFinishLaunchingNotification: Id;
+    }
+
+    const fn four_char_code(code: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*code)
+    }
+
+    const CORE_EVENT_CLASS: u32 = four_char_code(b"aevt");
+    const OPEN_DOCUMENTS: u32 = four_char_code(b"odoc");
+    const DIRECT_OBJECT: u32 = four_char_code(b"----");
+    const FILE_URL: u32 = four_char_code(b"furl");
+
+    fn sel(name: &'static [u8]) -> Sel {
+        unsafe { sel_registerName(name.as_ptr() as *const c_char) }
+    }
+
+    // objc_msgSend has to be called through a pointer of the method's real signature
+    unsafe fn send<F: Copy>() -> F {
+        let imp = objc_msgSend as unsafe extern "C" fn();
+        unsafe { std::mem::transmute_copy(&imp) }
+    }
+
+    pub fn install() {
+        unsafe {
+            let superclass = objc_getClass(c"NSObject".as_ptr());
+            let class = objc_allocateClassPair(superclass, c"MusicPlayerOpenHandler".as_ptr(), 0);
+            if class.is_null() {
+                // Already installed
+                return;
+            }
+            class_addMethod(
+                class,
+                sel(b"willFinishLaunching:\0"),
+                will_finish_launching as *const c_void,
+                c"v@:@".as_ptr(),
+            );
+            class_addMethod(
+                class,
+                sel(b"handleOpenDocuments:withReplyEvent:\0"),
+                handle_open_documents as *const c_void,
+                c"v@:@@".as_ptr(),
+            );
+            objc_registerClassPair(class);
+
+            // Lives for the rest of the run, as the notification centre and event manager
+            // don't keep it alive
+            let new: extern "C" fn(Id, Sel) -> Id = send();
+            let handler = new(class, sel(b"new\0"));
+
+            // NSApplication puts its own handler in place while launching, so ours goes in
+            // just before launching finishes, which is still ahead of the first event
+            let center_class = objc_getClass(c"NSNotificationCenter".as_ptr());
+            let default_center: extern "C" fn(Id, Sel) -> Id = send();
+            let center = default_center(center_class, sel(b"defaultCenter\0"));
+            let add_observer: extern "C" fn(Id, Sel, Id, Sel, Id, Id) = send();
+            add_observer(
+                center,
+                sel(b"addObserver:selector:name:object:\0"),
+                handler,
+                sel(b"willFinishLaunching:\0"),
+                NSApplicationWillFinishLaunchingNotification,
+                std::ptr::null_mut(),
+            );
+        }
+    }
+
+    extern "C" fn will_finish_launching(this: Id, _cmd: Sel, _notification: Id) {
+        unsafe {
+            let manager_class = objc_getClass(c"NSAppleEventManager".as_ptr());
+            let shared: extern "C" fn(Id, Sel) -> Id = send();
+            let manager = shared(manager_class, sel(b"sharedAppleEventManager\0"));
+            let set_handler: extern "C" fn(Id, Sel, Id, Sel, u32, u32) = send();
+            set_handler(
+                manager,
+                sel(b"setEventHandler:andSelector:forEventClass:andEventID:\0"),
+                this,
+                sel(b"handleOpenDocuments:withReplyEvent:\0"),
+                CORE_EVENT_CLASS,
+                OPEN_DOCUMENTS,
+            );
+        }
+    }
+
+    extern "C" fn handle_open_documents(_this: Id, _cmd: Sel, event: Id, _reply: Id) {
+        let mut paths = Vec::new();
+        unsafe {
+            let param: extern "C" fn(Id, Sel, u32) -> Id = send();
+            let list = param(event, sel(b"paramDescriptorForKeyword:\0"), DIRECT_OBJECT);
+            if list.is_null() {
+                return;
+            }
+            let count: extern "C" fn(Id, Sel) -> isize = send();
+            let items = count(list, sel(b"numberOfItems\0"));
+
+            // Apple Event lists count from 1
+            for index in 1..=items {
+                let at: extern "C" fn(Id, Sel, isize) -> Id = send();
+                let item = at(list, sel(b"descriptorAtIndex:\0"), index);
+                if item.is_null() {
+                    continue;
+                }
+                let coerce: extern "C" fn(Id, Sel, u32) -> Id = send();
+                let url = coerce(item, sel(b"coerceToDescriptorType:\0"), FILE_URL);
+                if url.is_null() {
+                    continue;
+                }
+                let data: extern "C" fn(Id, Sel) -> Id = send();
+                let data = data(url, sel(b"data\0"));
+                let bytes: extern "C" fn(Id, Sel) -> *const u8 = send();
+                let length: extern "C" fn(Id, Sel) -> usize = send();
+                let (bytes, length) = (bytes(data, sel(b"bytes\0")), length(data, sel(b"length\0")));
+                if bytes.is_null() {
+                    continue;
+                }
+                let url = String::from_utf8_lossy(std::slice::from_raw_parts(bytes, length));
+                match file_url_to_path(&url) {
+                    Some(path) => paths.push(path),
+                    None => log::warn!("Couldn't open {} from Finder", url),
+                }
+            }
+        }
+        log::info!("Finder opened {} file(s)", paths.len());
+        queue_opened_files(paths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_url_to_path() {
+        assert_eq!(file_url_to_path("file:///Users/me/Music/song.mp3"), Some(PathBuf::from("/Users/me/Music/song.mp3")));
+        assert_eq!(file_url_to_path("file://localhost/tmp/a.flac"), Some(PathBuf::from("/tmp/a.flac")));
+        assert_eq!(
+            file_url_to_path("file:///Users/me/My%20Music/Caf%C3%A9.m4a"),
+            Some(PathBuf::from("/Users/me/My Music/Café.m4a"))
+        );
+        assert_eq!(file_url_to_path("file:///Users/me/Album/"), Some(PathBuf::from("/Users/me/Album")));
+        assert_eq!(file_url_to_path("https://example.com/song.mp3"), None);
+        assert_eq!(file_url_to_path("file:///bad%2"), None);
+    }
+
+    #[test]
+    fn test_opened_files_routing() {
+        let first = PathBuf::from("/music/one.mp3");
+        let second = PathBuf::from("/music/two.mp3");
+        queue_opened_files(vec![first.clone()]);
+        queue_opened_files(Vec::new());
+        queue_opened_files(vec![second.clone()]);
+
+        // Handed over once, in the order they were opened
+        assert_eq!(take_opened_files(), vec![first, second]);
+        assert!(take_opened_files().is_empty());
+
+        // The first file starts only when nothing was loaded before
+        assert_eq!(first_to_play(true, 2), Some(0));
+        assert_eq!(first_to_play(false, 5), None);
+        assert_eq!(first_to_play(true, 0), None);
+    }
+}
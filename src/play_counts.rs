@@ -0,0 +1,160 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::config::get_config_dir;
+
+/// A track counts as played after this much of it, or half of it if that comes first
+pub const PLAY_COUNT_MIN: Duration = Duration::from_secs(30);
+
+/// How many times each track has been played. Stored next to the config file in `play_counts.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayCounts {
+    /// Track path to the number of times it has played
+    pub tracks: HashMap<PathBuf, u32>,
+}
+
+impl PlayCounts {
+    pub fn get(&self, path: &Path) -> u32 {
+        self.tracks.get(path).copied().unwrap_or(0)
+    }
+
+    /// Adds one play of `path`
+    pub fn record(&mut self, path: &Path) {
+        let count = self.tracks.entry(path.to_path_buf()).or_insert(0);
+        *count = count.saturating_add(1);
+    }
+
+    /// Plays of every track put together
+    pub fn total(&self) -> u64 {
+        self.tracks.values().map(|&count| count as u64).sum()
+    }
+
+    /// Forgets the plays of one track
+    #[allow(dead_code)]
+    pub fn reset(&mut self, path: &Path) {
+        self.tracks.remove(path);
+    }
+
+    /// Forgets every play
+    pub fn reset_all(&mut self) {
+        self.tracks.clear();
+    }
+}
+
+/// Whether `listened` is far enough into a track lasting `length` to count as a play,
+/// rather than the track just being skipped past
+pub fn counts_as_played(listened: Duration, length: Option<Duration>) -> bool {
+    listened >= PLAY_COUNT_MIN || length.is_some_and(|length| !length.is_zero() && listened * 2 >= length)
+}
+
+/// Lets the playback position run a little ahead of the clock between two readings
+const LISTEN_SLACK: Duration = Duration::from_millis(100);
+
+/// How much of a track has actually been heard. The position only counts as it moves in step
+/// with the clock, so starting part way in or seeking ahead adds nothing.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ListenTime {
+    listened: Duration,
+    last: Option<(Duration, Instant)>,
+}
+
+impl ListenTime {
+    /// Adds how far playback has moved on from `position` at the last update, up to the time
+    /// that has passed since then
+    pub fn update(&mut self, position: Duration, now: Instant) {
+        if let Some((last_position, at)) = self.last {
+            let moved = position.saturating_sub(last_position);
+            self.listened += moved.min(now.saturating_duration_since(at) + LISTEN_SLACK);
+        }
+        self.last = Some((position, now));
+    }
+
+    /// Stops counting until the next update, e.g. while paused or seeking
+    pub fn pause(&mut self) {
+        self.last = None;
+    }
+
+    pub fn listened(&self) -> Duration {
+        self.listened
+    }
+}
+
+fn get_play_counts_file_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("play_counts.toml"))
+}
+
+/// Loads the saved play counts, or none if nothing has been played yet
+pub fn load_play_counts() -> Result<PlayCounts> {
+    let path = get_play_counts_file_path()?;
+    if !path.exists() {
+        return Ok(PlayCounts::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+pub fn save_play_counts(counts: &PlayCounts) -> Result<()> {
+    let serialized = toml::to_string_pretty(counts)?;
+    fs::write(get_play_counts_file_path()?, serialized)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_as_played() {
+        assert!(!counts_as_played(Duration::from_secs(5), None));
+        assert!(counts_as_played(PLAY_COUNT_MIN, None));
+        // Short tracks count once half of them has played
+        assert!(counts_as_played(Duration::from_secs(10), Some(Duration::from_secs(20))));
+        assert!(!counts_as_played(Duration::from_secs(9), Some(Duration::from_secs(20))));
+        assert!(!counts_as_played(Duration::ZERO, Some(Duration::ZERO)));
+    }
+
+    #[test]
+    fn test_listen_time_ignores_jumps() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut time = ListenTime::default();
+        // Resumed ten minutes in
+        time.update(Duration::from_secs(600), at(0));
+        assert_eq!(time.listened(), Duration::ZERO);
+        time.update(Duration::from_secs(605), at(5));
+        assert_eq!(time.listened(), Duration::from_secs(5));
+        // Seeking a minute ahead in a second only counts the second
+        time.update(Duration::from_secs(665), at(6));
+        assert_eq!(time.listened(), Duration::from_secs(6) + LISTEN_SLACK);
+        // Nothing counts while paused, however long for
+        time.pause();
+        time.update(Duration::from_secs(665), at(100));
+        time.update(Duration::from_secs(667), at(102));
+        assert_eq!(time.listened(), Duration::from_secs(8) + LISTEN_SLACK);
+    }
+
+    #[test]
+    fn test_play_counts_round_trip_through_toml() {
+        let mut counts = PlayCounts::default();
+        let track = Path::new("/music/favourite.mp3");
+        counts.record(track);
+        counts.record(track);
+        counts.record(Path::new("/music/other.flac"));
+        assert_eq!(counts.get(track), 2);
+        assert_eq!(counts.total(), 3);
+
+        let serialized = toml::to_string_pretty(&counts).unwrap();
+        let loaded: PlayCounts = toml::from_str(&serialized).unwrap();
+        assert_eq!(loaded, counts);
+
+        counts.reset(track);
+        assert_eq!(counts.get(track), 0);
+        counts.reset_all();
+        assert_eq!(counts.total(), 0);
+    }
+}
@@ -1,21 +1,179 @@
-use anyhow::Result;
-use rodio::{Decoder, OutputStream, Sink, Source, source::SeekError};
-use std::fs::File;
-use std::io::BufReader;
+use anyhow::{Context, Result};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source, queue::SourcesQueueOutput, source::{SeekError, SineWave}};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
+use crate::timestretch::TimeStretch;
+use crate::balance::Balance;
+use crate::crossfeed::{Crossfeed, CrossfeedSettings};
+use crate::equalizer::{Equalizer, EQ_BANDS, normalize_gains};
+use crate::replaygain::{ReplayGainMode, read_replaygain};
+use crate::decode::open_decoder;
+use crate::stream::{open_stream, stream_url, StreamReader};
+use crate::metadata::track_duration;
+use crate::controller::{SeekTarget, relative_seek_target};
+
+/// Preview clips play at this fraction of the main volume
+const PREVIEW_VOLUME: f32 = 0.6;
+
+/// Whether the player is currently producing sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaybackState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// What happens when a track (or the whole playlist) finishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RepeatMode {
+    #[default]
+    None,
+    One,
+    All,
+}
+
+/// Slowest speed offered by `set_speed`
+pub const MIN_SPEED: f32 = 0.5;
+/// Fastest speed offered by `set_speed`
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Tags and headers can overstate a track's length a little, so a track has to stop at least
+/// this far short (or a twentieth of its length, if that's more) to count as ending early
+pub const EARLY_END_TOLERANCE: Duration = Duration::from_secs(5);
+
+/// Whether a track that stopped at `position` ran out well before its `length`, as happens
+/// when decoding fails partway through
+pub fn ended_early(position: Duration, length: Option<Duration>) -> bool {
+    length.is_some_and(|length| position + EARLY_END_TOLERANCE.max(length / 20) < length)
+}
+
+/// How closely a seek landed on the position asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekAccuracy {
+    /// The decoder jumped straight to the position
+    Exact,
+    /// The decoder can't seek, so the file was reopened and decoded up to roughly the
+    /// position instead. Compressed formats can be off by a frame or so.
+    Approximate,
+}
+
+/// Everything a remote front-end (MPRIS, IPC, HTTP...) needs to render the player
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSnapshot {
+    pub state: PlaybackState,
+    pub current_path: Option<PathBuf>,
+    pub position: Duration,
+    pub duration: Option<Duration>,
+    pub volume: f32,
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+}
+
+/// Every control action a front-end can ask for
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerCommand {
+    Play(PathBuf),
+    Pause,
+    Resume,
+    TogglePlayPause,
+    Stop,
+    Seek(Duration),
+    SetVolume(f32),
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    /// Playlist navigation, handled by whoever owns the playlist
+    Next,
+    /// Playlist navigation, handled by whoever owns the playlist
+    Previous,
+}
 
 pub struct MusicPlayer {
-    sink: Sink,
-    _stream: OutputStream,
-    _stream_handle: rodio::OutputStreamHandle,
+    sink: Arc<Sink>, // Shared with seek workers so they can seek without the player locked
+    _stream: Option<OutputStream>, // None while playing into a NullOutput
+    _stream_handle: Option<OutputStreamHandle>,
+    _null_output: Option<NullOutput>,
     current_song_index: Arc<Mutex<Option<usize>>>,
     is_song_finished: Arc<Mutex<bool>>,
     current_file_path: Arc<Mutex<Option<PathBuf>>>,
     song_duration: Arc<Mutex<Option<Duration>>>,
     play_position: Arc<Mutex<Duration>>,
-    last_position_update: Arc<Mutex<std::time::Instant>>,
+    last_sink_position: Arc<Mutex<Duration>>, // What the sink reported when play_position was last brought up to date
+    playback_rate: Arc<Mutex<f32>>, // Song seconds per wall-clock second
+    time_stretch: Arc<Mutex<f32>>, // Tempo ratio for the pitch-preserving adapter
+    shuffle: Arc<Mutex<bool>>,
+    repeat: Arc<Mutex<RepeatMode>>,
+    buffered_position: Arc<Mutex<Option<Duration>>>, // None when the whole track is available
+    seek_in_progress: Arc<Mutex<bool>>, // The sink may be briefly empty while this is set
+    queued: Arc<Mutex<VecDeque<QueuedTrack>>>, // Sources appended behind the current one, in order
+    killed_volume: Arc<Mutex<Option<f32>>>, // Volume to restore after a panic stop
+    crossfeed: Arc<Mutex<CrossfeedSettings>>,
+    balance: Arc<Mutex<f32>>, // -1.0 (left only) to 1.0 (right only)
+    eq_gains: Arc<Mutex<Vec<f32>>>, // dB for each band in EQ_BANDS
+    replaygain_mode: Arc<Mutex<ReplayGainMode>>,
+    preview: Arc<Mutex<Option<Sink>>>, // Short-lived sink for auditioning another track
+    paused_for_preview: Arc<Mutex<bool>>, // Main playback was paused by the preview and resumes after it
+    seek_generation: Arc<AtomicU64>, // Bumped by every seek and track change, so a slow seek can't undo a newer one or land on the next track
+    seeks_running: Arc<AtomicUsize>, // Seeks begun and not yet finished, seek_in_progress stays set until there are none
+    crossfade: Arc<Mutex<Duration>>, // Overlap between tracks, zero when off
+    fading_out: Arc<Mutex<Option<Sink>>>, // End of the previous track, fading out under the next
+    fade_in_next: Arc<Mutex<bool>>, // The next track loaded comes in under a fade-out
+    range_end: Arc<Mutex<Option<Duration>>>, // Where a track that's part of a larger file stops
+    connecting: Arc<Mutex<Option<Connecting>>>, // Stream being connected to, the sink is empty until it's ready
+}
+
+/// A stream being connected to on a worker, so a slow or dead station doesn't hold up the
+/// caller. `MusicPlayer::poll_stream` picks up the decoder once it's ready.
+struct Connecting {
+    path: PathBuf,
+    crossfade_in: bool,
+    decoder: Receiver<Result<Decoder<StreamReader>>>,
+}
+
+/// Stands in for an output device when none could be opened. The sink's audio is consumed
+/// at the speed it would be heard, so tracks still advance and seeks still complete, and
+/// the player can carry on once `MusicPlayer::reconnect_output` finds a device.
+struct NullOutput {
+    stop: Arc<AtomicBool>,
+}
+
+impl NullOutput {
+    fn start(mut queue: SourcesQueueOutput<f32>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                // Ten milliseconds of audio at a time
+                let samples = queue.sample_rate() as usize * queue.channels() as usize / 100;
+                if queue.by_ref().take(samples).count() < samples {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        Self { stop }
+    }
+}
+
+impl Drop for NullOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A track pre-loaded into the sink behind the one that's playing. The playlist index is
+/// kept alongside the path so the same file queued twice is still told apart.
+#[derive(Debug, Clone)]
+struct QueuedTrack {
+    index: usize,
+    path: PathBuf,
+    duration: Option<Duration>,
 }
 
 // Mark MusicPlayer as safe to send and share across threads
@@ -25,24 +183,187 @@ unsafe impl Sync for MusicPlayer {}
 
 impl MusicPlayer {
     pub fn new() -> Result<Self> {
-        let (_stream, stream_handle) = OutputStream::try_default()?;
+        let (stream, stream_handle) = OutputStream::try_default()?;
         let sink = Sink::try_new(&stream_handle)?;
-        
-        Ok(Self {
-            sink,
-            _stream,
+        Ok(Self::with_sink(sink, Some(stream), Some(stream_handle), None))
+    }
+    
+    /// A player with no output device, for when `new` fails. Nothing is heard, but
+    /// everything else works until `reconnect_output` gets a device.
+    pub fn without_output() -> Self {
+        let (sink, queue) = Sink::new_idle();
+        Self::with_sink(sink, None, None, Some(NullOutput::start(queue)))
+    }
+    
+    fn with_sink(sink: Sink, stream: Option<OutputStream>, stream_handle: Option<OutputStreamHandle>, null_output: Option<NullOutput>) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            _stream: stream,
             _stream_handle: stream_handle,
+            _null_output: null_output,
             current_song_index: Arc::new(Mutex::new(None)),
             is_song_finished: Arc::new(Mutex::new(false)),
             current_file_path: Arc::new(Mutex::new(None)),
             song_duration: Arc::new(Mutex::new(None)),
             play_position: Arc::new(Mutex::new(Duration::from_secs(0))),
-            last_position_update: Arc::new(Mutex::new(std::time::Instant::now())),
-        })
+            last_sink_position: Arc::new(Mutex::new(Duration::ZERO)),
+            playback_rate: Arc::new(Mutex::new(1.0)),
+            time_stretch: Arc::new(Mutex::new(1.0)),
+            shuffle: Arc::new(Mutex::new(false)),
+            repeat: Arc::new(Mutex::new(RepeatMode::None)),
+            buffered_position: Arc::new(Mutex::new(None)),
+            seek_in_progress: Arc::new(Mutex::new(false)),
+            queued: Arc::new(Mutex::new(VecDeque::new())),
+            killed_volume: Arc::new(Mutex::new(None)),
+            crossfeed: Arc::new(Mutex::new(CrossfeedSettings::default())),
+            balance: Arc::new(Mutex::new(0.0)),
+            eq_gains: Arc::new(Mutex::new(vec![0.0; EQ_BANDS.len()])),
+            replaygain_mode: Arc::new(Mutex::new(ReplayGainMode::Off)),
+            preview: Arc::new(Mutex::new(None)),
+            paused_for_preview: Arc::new(Mutex::new(false)),
+            seek_generation: Arc::new(AtomicU64::new(0)),
+            seeks_running: Arc::new(AtomicUsize::new(0)),
+            crossfade: Arc::new(Mutex::new(Duration::ZERO)),
+            fading_out: Arc::new(Mutex::new(None)),
+            fade_in_next: Arc::new(Mutex::new(false)),
+            range_end: Arc::new(Mutex::new(None)),
+            connecting: Arc::new(Mutex::new(None)),
+        }
+    }
+    
+    /// Reopens the audio output on the current default device, e.g. after the one we were
+    /// using was unplugged. A loaded track carries on from the same position, paused
+    /// unless `resume` is set.
+    pub fn reconnect_output(&mut self, resume: bool) -> Result<()> {
+        let (stream, stream_handle) = OutputStream::try_default()?;
+        let sink = Arc::new(Sink::try_new(&stream_handle)?);
+        
+        let state = self.get_state();
+        let position = self.get_current_position();
+        let volume = self.get_volume();
+        let speed = self.sink.speed();
+        let current = self.current_file_path.lock().ok().and_then(|path| path.clone());
+        let range_end = self.get_range_end();
+        
+        self.stop_preview();
+        self.stop_fade_out();
+        self.sink.stop();
+        sink.set_speed(speed);
+        self.sink = sink;
+        self._stream = Some(stream);
+        self._stream_handle = Some(stream_handle);
+        self._null_output = None;
+        if let Ok(mut killed) = self.killed_volume.lock() {
+            *killed = None;
+        }
+        
+        let Some(path) = current.filter(|_| state != PlaybackState::Stopped) else {
+            self.sink.set_volume(volume);
+            return Ok(());
+        };
+        
+        // Reload silently so nothing leaks out of the new device before we're back in place
+        self.sink.set_volume(0.0);
+        self.play_file(&path)?;
+        self.set_range_end(range_end);
+        self.pause();
+        // A station carries on from wherever it's got to
+        if stream_url(&path).is_none() {
+            self.seek_to(position)?;
+        }
+        self.sink.set_volume(volume);
+        if resume {
+            self.resume();
+        } else {
+            self.pause();
+        }
+        Ok(())
+    }
+    
+    // Extra sinks (previews, fades, tones) need a real device to play on
+    fn stream_handle(&self) -> Result<&OutputStreamHandle> {
+        self._stream_handle.as_ref().ok_or_else(|| anyhow::anyhow!("No audio output is open"))
+    }
+    
+    // Wrap a decoded source in the player's processing chain before it goes to the sink
+    fn with_effects<S>(&self, path: &Path, source: S) -> Box<dyn Source<Item = f32> + Send>
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        effect_chain(source, self.replaygain_factor(path), self.eq_gains.clone(), self.time_stretch.clone(), self.crossfeed.clone(), self.balance.clone())
+    }
+    
+    // Gain is worked out per file, so album mode still does the right thing in a mixed queue
+    fn replaygain_factor(&self, path: &Path) -> f32 {
+        let mode = if let Ok(mode) = self.replaygain_mode.lock() {
+            *mode
+        } else {
+            ReplayGainMode::Off
+        };
+        
+        if mode == ReplayGainMode::Off {
+            return 1.0;
+        }
+        read_replaygain(path).factor(mode)
+    }
+    
+    /// Chooses which ReplayGain value to apply. Takes effect from the next track loaded.
+    pub fn set_replaygain_mode(&self, mode: ReplayGainMode) {
+        if let Ok(mut current) = self.replaygain_mode.lock() {
+            *current = mode;
+        }
+    }
+    
+    /// Turns headphone crossfeed on or off. `strength` goes from 0.0 to 1.0.
+    /// Takes effect within a few milliseconds on the playing track.
+    pub fn set_crossfeed(&self, on: bool, strength: f32) {
+        if let Ok(mut settings) = self.crossfeed.lock() {
+            *settings = CrossfeedSettings {
+                enabled: on,
+                strength: strength.clamp(0.0, 1.0),
+            };
+        }
+    }
+    
+    /// Sets the left/right balance, from -1.0 (left only) through 0.0 (centred) to 1.0 (right
+    /// only). Takes effect within a few milliseconds. Mono tracks aren't affected.
+    pub fn set_balance(&self, balance: f32) {
+        if let Ok(mut current) = self.balance.lock() {
+            *current = if balance.is_nan() { 0.0 } else { balance.clamp(-1.0, 1.0) };
+        }
+    }
+    
+    /// Sets the equalizer, in dB for each band in `EQ_BANDS`. Missing bands are flat and
+    /// gains are limited to `MAX_GAIN_DB`. Takes effect within a few milliseconds.
+    pub fn set_eq_gains(&self, gains: &[f32]) {
+        if let Ok(mut current) = self.eq_gains.lock() {
+            *current = normalize_gains(gains);
+        }
+    }
+    
+    #[allow(dead_code)]
+    pub fn get_eq_gains(&self) -> Vec<f32> {
+        if let Ok(gains) = self.eq_gains.lock() {
+            gains.clone()
+        } else {
+            vec![0.0; EQ_BANDS.len()]
+        }
     }
 
     pub fn play_file(&self, path: &Path) -> Result<()> {
+        self.stop_preview();
+        self.cancel_connecting();
+        self.cancel_seek();
         self.sink.stop();
+        self.clear_queue();
+        
+        // Fade in under the end of the previous track if a crossfade handed it over,
+        // otherwise nothing else should still be playing
+        let crossfade_in = self.fade_in_next.lock().map(|mut next| std::mem::take(&mut *next)).unwrap_or(false)
+            && self.is_fading_out();
+        if !crossfade_in {
+            self.stop_fade_out();
+        }
         
         // Store the current file path
         if let Ok(mut file_path) = self.current_file_path.lock() {
@@ -50,30 +371,98 @@ impl MusicPlayer {
         }
         
         // Reset position tracking
-        if let Ok(mut position) = self.play_position.lock() {
-            *position = Duration::from_secs(0);
-        }
-        if let Ok(mut last_update) = self.last_position_update.lock() {
-            *last_update = std::time::Instant::now();
+        self.rebase_position(Duration::ZERO);
+        self.set_range_end(None);
+        
+        // Local files are fully available up front
+        self.set_buffered_position(None);
+        
+        // A stream has no duration: it plays until it's stopped or the connection drops.
+        // Connecting can take a while, so it's left to a worker and `poll_stream` starts it.
+        if let Some(url) = stream_url(path) {
+            if let Ok(mut song_duration) = self.song_duration.lock() {
+                *song_duration = None;
+            }
+            self.connect_stream(path, url, crossfade_in);
+            self.restore_killed_volume();
+            self.sink.play();
+            return Ok(());
         }
         
-        // Open the file and get its duration
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let source = Decoder::new(reader)?;
+        // Open the file and get its duration. The reason stays reachable with `downcast_ref`.
+        let source = open_decoder(path).with_context(|| format!("Couldn't play {}", path.display()))?;
+        let duration = track_duration(path, source.total_duration());
         
         // Store the song duration if available
-        let duration = source.total_duration();
         if let Ok(mut song_duration) = self.song_duration.lock() {
             *song_duration = duration;
         }
         
-        // Play the file
-        self.sink.append(source);
+        // Play the file (starting a new track also ends a panic stop)
+        self.append_source(path, Box::new(source.convert_samples()), crossfade_in);
+        self.restore_killed_volume();
         self.sink.play();
         
         Ok(())
     }
+    
+    // Puts a new track's source in the empty sink, through the effects
+    fn append_source(&self, path: &Path, source: Box<dyn Source<Item = f32> + Send>, crossfade_in: bool) {
+        let source: Box<dyn Source<Item = f32> + Send> = if crossfade_in {
+            Box::new(source.fade_in(self.get_crossfade()))
+        } else {
+            source
+        };
+        self.sink.append(self.with_effects(path, source));
+        self.rebase_position(Duration::ZERO);
+    }
+    
+    // Opens the stream on a worker and hands the decoder back through a channel
+    fn connect_stream(&self, path: &Path, url: &str, crossfade_in: bool) {
+        let (tx, rx) = channel();
+        let url = url.to_string();
+        thread::spawn(move || {
+            // Nothing's listening if another track was picked meanwhile, and the connection closes
+            let _ = tx.send(open_stream(&url));
+        });
+        if let Ok(mut connecting) = self.connecting.lock() {
+            *connecting = Some(Connecting { path: path.to_path_buf(), crossfade_in, decoder: rx });
+        }
+    }
+    
+    // Forgets a stream still connecting, e.g. when another track is picked
+    fn cancel_connecting(&self) {
+        if let Ok(mut connecting) = self.connecting.lock() {
+            *connecting = None;
+        }
+    }
+    
+    fn is_connecting(&self) -> bool {
+        self.connecting.lock().is_ok_and(|connecting| connecting.is_some())
+    }
+    
+    /// Starts a stream once its connection is made. Call this regularly. Returns the outcome
+    /// of a connection attempt that finished since the last call; after an error the sink is
+    /// left empty, so the track counts as finished like a file that couldn't be played.
+    pub fn poll_stream(&self) -> Option<Result<()>> {
+        let mut connecting = self.connecting.lock().ok()?;
+        let decoder = match connecting.as_ref()?.decoder.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("The connection attempt was abandoned")),
+            Ok(decoder) => decoder,
+        };
+        let Connecting { path, crossfade_in, .. } = connecting.take()?;
+        drop(connecting);
+        
+        match decoder {
+            // The sink keeps whatever state it was left in, so pausing while connecting holds
+            Ok(decoder) => {
+                self.append_source(&path, Box::new(decoder.convert_samples()), crossfade_in);
+                Some(Ok(()))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
 
     pub fn play_playlist_item(&self, path: &Path, index: usize) -> Result<()> {
         // Set the current index first to ensure it's set even if play_file fails
@@ -96,7 +485,137 @@ impl MusicPlayer {
         Ok(())
     }
     
+    /// Plays part of a file as a track of its own, such as one song from an album ripped to a
+    /// single file with a cue sheet. It starts at `start` and finishes at `end`, or the end of
+    /// the file. Positions are still counted from the start of the file.
+    pub fn play_playlist_range(&self, path: &Path, index: usize, start: Duration, end: Option<Duration>) -> Result<()> {
+        self.play_playlist_item(path, index)?;
+        self.set_range_end(end);
+        if !start.is_zero() {
+            self.seek_to(start)?;
+        }
+        Ok(())
+    }
+    
+    /// After a track finishes, whether it stopped well short of its end, most likely because
+    /// the rest of the file couldn't be decoded. Tracks ended on purpose (trims, crossfades,
+    /// seeking past the end) are marked as having played to the end, so they never count.
+    pub fn ended_early(&self) -> bool {
+        ended_early(self.get_current_position(), self.get_range_end().or(self.get_song_duration()))
+    }
+    
+    fn get_range_end(&self) -> Option<Duration> {
+        self.range_end.lock().ok().and_then(|end| *end)
+    }
+    
+    fn set_range_end(&self, end: Option<Duration>) {
+        if let Ok(mut range_end) = self.range_end.lock() {
+            *range_end = end;
+        }
+    }
+    
+    /// Appends a track to the sink so it starts the moment the current one ends, with no gap.
+    /// Call `reconcile_queue` regularly to find out when playback has rolled over to it.
+    pub fn queue_playlist_item(&self, path: &Path, index: usize) -> Result<()> {
+        let source = open_decoder(path)?;
+        let duration = track_duration(path, source.total_duration());
+        
+        // Record the entry before appending so a rollover is never seen without it
+        if let Ok(mut queued) = self.queued.lock() {
+            queued.push_back(QueuedTrack { index, path: path.to_path_buf(), duration });
+        } else {
+            return Err(anyhow::anyhow!("Failed to lock queue mutex"));
+        }
+        self.sink.append(self.with_effects(path, source.convert_samples()));
+        
+        Ok(())
+    }
+    
+    /// Number of tracks waiting behind the current one
+    #[allow(dead_code)]
+    pub fn queued_count(&self) -> usize {
+        if let Ok(queued) = self.queued.lock() {
+            queued.len()
+        } else {
+            0
+        }
+    }
+    
+    /// The track that starts when the current one ends, if one has been queued
+    pub fn next_queued(&self) -> Option<PathBuf> {
+        self.queued.lock().ok()?.front().map(|track| track.path.clone())
+    }
+    
+    fn clear_queue(&self) {
+        if let Ok(mut queued) = self.queued.lock() {
+            queued.clear();
+        }
+    }
+    
+    /// Checks whether the sink has moved on to a queued track. If it has, the current
+    /// index, path, duration and position are switched over to that entry and its
+    /// playlist index and path are returned.
+    pub fn reconcile_queue(&self) -> Option<(usize, PathBuf)> {
+        let mut queued = self.queued.lock().ok()?;
+        if queued.is_empty() {
+            return None;
+        }
+        
+        // While the current track plays the sink holds it plus everything queued.
+        // Each source missing from the sink is a track that has finished.
+        let in_sink = self.sink.len();
+        let finished = (queued.len() + 1).saturating_sub(in_sink);
+        if finished == 0 {
+            return None;
+        }
+        
+        let mut now_playing = None;
+        for _ in 0..finished {
+            now_playing = queued.pop_front();
+        }
+        drop(queued);
+        
+        let track = now_playing?;
+        if self.sink.empty() {
+            // Everything, including the last queued track, already played out
+            return None;
+        }
+        
+        if let Ok(mut index) = self.current_song_index.lock() {
+            *index = Some(track.index);
+        }
+        if let Ok(mut path) = self.current_file_path.lock() {
+            *path = Some(track.path.clone());
+        }
+        if let Ok(mut duration) = self.song_duration.lock() {
+            *duration = track.duration;
+        }
+        self.rebase_position(Duration::ZERO);
+        
+        Some((track.index, track.path))
+    }
+    
     pub fn check_if_song_finished(&self) -> bool {
+        // Part of a file ends at its end point. Checked first, as working out the position
+        // looks at the seek flag too.
+        if let Some(end) = self.get_range_end() {
+            let seeking = self.seek_in_progress.lock().map(|flag| *flag).unwrap_or(false);
+            if !seeking && !self.sink.empty() && self.get_current_position() >= end {
+                self.finish_track(end);
+            }
+        }
+        
+        // A seek may stop and refill the sink, which must not look like the song ending.
+        // Holding the lock keeps a seek from starting while we look at the sink.
+        let seeking = match self.seek_in_progress.lock() {
+            Ok(guard) => guard,
+            Err(_) => return false,
+        };
+        // Nor must waiting for a stream to connect
+        if *seeking || self.is_connecting() {
+            return false;
+        }
+        
         let empty = self.sink.empty();
         let paused = self.sink.is_paused();
         
@@ -130,27 +649,253 @@ impl MusicPlayer {
 
     pub fn pause(&self) {
         self.sink.pause();
+        if let Ok(fading_out) = self.fading_out.lock() {
+            if let Some(sink) = fading_out.as_ref() {
+                sink.pause();
+            }
+        }
     }
 
     pub fn resume(&self) {
+        self.restore_killed_volume();
         self.sink.play();
+        if let Ok(fading_out) = self.fading_out.lock() {
+            if let Some(sink) = fading_out.as_ref() {
+                sink.play();
+            }
+        }
+    }
+    
+    /// Overlaps the end of each track with the start of the next by `duration`, fading one
+    /// out as the other fades in. Zero turns crossfading off.
+    pub fn set_crossfade(&self, duration: Duration) {
+        if let Ok(mut crossfade) = self.crossfade.lock() {
+            *crossfade = duration;
+        }
+    }
+    
+    pub fn get_crossfade(&self) -> Duration {
+        if let Ok(crossfade) = self.crossfade.lock() {
+            *crossfade
+        } else {
+            Duration::ZERO
+        }
+    }
+    
+    /// Starts crossfading out of the current track, which stops at `end` (its length, or an
+    /// earlier trimmed end point). The rest of the track is handed to a sink of its own that
+    /// fades it out, and the track is finished so the usual finished handling starts the
+    /// next one, which then fades in. If nothing is played next the track just fades out.
+    pub fn begin_crossfade(&self, end: Duration) -> Result<()> {
+        let path = match self.current_file_path.lock() {
+            Ok(path) => path.clone().ok_or_else(|| anyhow::anyhow!("No file is currently playing"))?,
+            Err(_) => return Err(anyhow::anyhow!("Failed to lock file path mutex")),
+        };
+        let position = self.get_current_position();
+        
+        // The fade runs over whatever is left, so a late start still ends on silence
+        let mut tail = decode_from(&path, position)?.source.take_duration(end.saturating_sub(position));
+        tail.set_filter_fadeout();
+        
+        let tail_sink = Sink::try_new(self.stream_handle()?)?;
+        tail_sink.set_volume(self.get_volume());
+        tail_sink.set_speed(self.sink.speed());
+        tail_sink.append(self.with_effects(&path, tail));
+        
+        self.stop_fade_out();
+        if let Ok(mut fading_out) = self.fading_out.lock() {
+            *fading_out = Some(tail_sink);
+        }
+        if let Ok(mut next) = self.fade_in_next.lock() {
+            *next = true;
+        }
+        self.finish_track(end);
+        Ok(())
+    }
+    
+    /// True while the end of the previous track is still fading out
+    pub fn is_fading_out(&self) -> bool {
+        if let Ok(fading_out) = self.fading_out.lock() {
+            fading_out.as_ref().is_some_and(|sink| !sink.empty())
+        } else {
+            false
+        }
+    }
+    
+    // Cut off the end of the previous track, e.g. when skipping or seeking mid-crossfade
+    fn stop_fade_out(&self) {
+        if let Ok(mut fading_out) = self.fading_out.lock() {
+            if let Some(sink) = fading_out.take() {
+                sink.stop();
+            }
+        }
+    }
+    
+    // Bring the volume back if the audio was killed
+    fn restore_killed_volume(&self) {
+        if let Ok(mut killed) = self.killed_volume.lock() {
+            if let Some(volume) = killed.take() {
+                self.sink.set_volume(volume);
+            }
+        }
+    }
+    
+    /// Auditions `length` of audio from the middle of another track on its own sink, at a
+    /// lower volume and levelled with its ReplayGain track gain. The main track pauses
+    /// while the clip plays and carries on from the same spot afterwards.
+    pub fn preview(&self, path: &Path, length: Duration) -> Result<()> {
+        self.stop_preview();
+        self.stop_fade_out();
+        
+        let source = open_decoder(path)?;
+        let start = track_duration(path, source.total_duration())
+            .map(|total| total.saturating_sub(length) / 2)
+            .unwrap_or_default();
+        let gain = read_replaygain(path).factor(ReplayGainMode::Track);
+        let clip = source.skip_duration(start)
+            .take_duration(length)
+            .convert_samples::<f32>()
+            .amplify(gain);
+        
+        let preview_sink = Sink::try_new(self.stream_handle()?)?;
+        preview_sink.set_volume(self.get_volume() * PREVIEW_VOLUME);
+        preview_sink.append(clip);
+        
+        if !self.sink.is_paused() && !self.sink.empty() {
+            self.sink.pause();
+            if let Ok(mut paused) = self.paused_for_preview.lock() {
+                *paused = true;
+            }
+        }
+        
+        if let Ok(mut preview) = self.preview.lock() {
+            *preview = Some(preview_sink);
+        }
+        Ok(())
+    }
+    
+    pub fn is_previewing(&self) -> bool {
+        if let Ok(preview) = self.preview.lock() {
+            preview.as_ref().is_some_and(|sink| !sink.empty())
+        } else {
+            false
+        }
+    }
+    
+    /// Call regularly. Cleans up a finished preview and resumes the main track.
+    pub fn update_preview(&self) {
+        let finished = if let Ok(preview) = self.preview.lock() {
+            preview.as_ref().is_some_and(|sink| sink.empty())
+        } else {
+            false
+        };
+        
+        if finished {
+            self.stop_preview();
+        }
+    }
+    
+    /// Cuts a preview short and resumes the main track if the preview paused it
+    pub fn stop_preview(&self) {
+        if let Ok(mut preview) = self.preview.lock() {
+            if let Some(sink) = preview.take() {
+                sink.stop();
+            }
+        }
+        
+        let was_paused = if let Ok(mut paused) = self.paused_for_preview.lock() {
+            std::mem::take(&mut *paused)
+        } else {
+            false
+        };
+        
+        if was_paused {
+            self.sink.play();
+        }
+    }
+    
+    /// Plays a sine tone on the output device for `secs` seconds, on its own sink so the
+    /// current track is left alone. Handy for checking the device and volume work.
+    pub fn play_test_tone(&self, freq: f32, secs: f32) -> Result<()> {
+        let tone_sink = Sink::try_new(self.stream_handle()?)?;
+        tone_sink.set_volume(self.get_volume());
+        tone_sink.append(
+            SineWave::new(freq)
+                .take_duration(Duration::from_secs_f32(secs.max(0.0)))
+                .amplify(0.2)
+        );
+        
+        // Keeps playing until the tone ends (or the player is dropped)
+        tone_sink.detach();
+        Ok(())
+    }
+    
+    /// Panic stop: silences output instantly and pauses, keeping the position.
+    /// `resume` restores the previous volume and carries on.
+    pub fn kill_audio(&self) {
+        if let Ok(mut killed) = self.killed_volume.lock() {
+            // Don't overwrite the real volume if we were already killed
+            if killed.is_none() {
+                *killed = Some(self.sink.volume());
+            }
+        }
+        
+        // A preview is silenced too, without resuming the track it paused
+        if let Ok(mut paused) = self.paused_for_preview.lock() {
+            *paused = false;
+        }
+        self.stop_preview();
+        
+        self.sink.set_volume(0.0);
+        self.sink.pause();
+        self.stop_fade_out();
     }
 
+    /// Whether `kill_audio` has silenced playback and nothing has brought it back yet
+    pub fn is_killed(&self) -> bool {
+        self.killed_volume.lock().is_ok_and(|killed| killed.is_some())
+    }
+
+    /// Stops playback and rewinds to the start of the track. The current file is kept,
+    /// so playing it again restarts it from the beginning.
     pub fn stop(&self) {
+        self.cancel_connecting();
+        self.cancel_seek();
         self.sink.stop();
+        self.clear_queue();
+        self.stop_fade_out();
         
         // Set the finished flag to true when explicitly stopped
         if let Ok(mut flag) = self.is_song_finished.lock() {
             *flag = true;
         }
+        
+        // Rewind so the position display shows the start of the track
+        if let Ok(mut position) = self.play_position.lock() {
+            *position = Duration::from_secs(0);
+        }
+    }
+    
+    /// True when nothing is loaded in the sink (after stop or when a track ran out),
+    /// in which case the track has to be reloaded rather than resumed
+    pub fn is_stopped(&self) -> bool {
+        self.sink.empty() && !self.is_connecting()
     }
 
-    #[allow(dead_code)]
     pub fn is_playing(&self) -> bool {
         // A better implementation of is_playing that handles all cases:
         // - Not playing if sink is paused
         // - Not playing if sink is empty (stopped or finished)
         // - Not playing if we explicitly set the finished flag
+        // - Still playing while a seek briefly empties the sink
+        
+        let seeking = match self.seek_in_progress.lock() {
+            Ok(guard) => guard,
+            Err(_) => return false,
+        };
+        if *seeking || self.is_connecting() {
+            return !self.sink.is_paused();
+        }
         
         let paused = self.sink.is_paused();
         let empty = self.sink.empty();
@@ -170,14 +915,170 @@ impl MusicPlayer {
     pub fn set_volume(&self, volume: f32) {
         // Clamp volume between 0.0 and 1.0
         let volume = volume.clamp(0.0, 1.0);
+        
+        // While killed, remember the new volume for when playback resumes
+        if let Ok(mut killed) = self.killed_volume.lock() {
+            if killed.is_some() {
+                *killed = Some(volume);
+                return;
+            }
+        }
         self.sink.set_volume(volume);
+        if let Ok(fading_out) = self.fading_out.lock() {
+            if let Some(sink) = fading_out.as_ref() {
+                sink.set_volume(volume);
+            }
+        }
     }
     
-    #[allow(dead_code)]
     pub fn get_volume(&self) -> f32 {
+        if let Ok(killed) = self.killed_volume.lock() {
+            if let Some(volume) = *killed {
+                return volume;
+            }
+        }
         self.sink.volume()
     }
 
+    /// Plays faster or slower, `factor` being the speed from `MIN_SPEED` to `MAX_SPEED`.
+    /// This speeds up the sink like a tape, so the pitch goes up and down with the tempo.
+    /// Use `set_time_stretch` with `preserve_pitch` to keep music in tune.
+    pub fn set_speed(&self, factor: f32) {
+        self.set_time_stretch(factor.clamp(MIN_SPEED, MAX_SPEED), false);
+    }
+    
+    /// Changes the playback tempo. `ratio` is the speed factor (2.0 = twice as fast).
+    ///
+    /// With `preserve_pitch` the tempo is changed by the WSOLA time-stretch adapter,
+    /// which keeps music in tune but costs noticeably more CPU (see `TimeStretch`).
+    /// Without it the sink is simply sped up, which also raises the pitch.
+    pub fn set_time_stretch(&self, ratio: f32, preserve_pitch: bool) {
+        let ratio = ratio.clamp(0.25, 4.0);
+        
+        // Bank the time played at the old rate before switching
+        self.get_current_position();
+        
+        if preserve_pitch {
+            self.sink.set_speed(1.0);
+            if let Ok(mut stretch) = self.time_stretch.lock() {
+                *stretch = ratio;
+            }
+        } else {
+            if let Ok(mut stretch) = self.time_stretch.lock() {
+                *stretch = 1.0;
+            }
+            self.sink.set_speed(ratio);
+        }
+        
+        if let Ok(mut rate) = self.playback_rate.lock() {
+            *rate = ratio;
+        }
+    }
+    
+    /// Returns the current playback rate (1.0 = normal speed)
+    pub fn get_playback_rate(&self) -> f32 {
+        if let Ok(rate) = self.playback_rate.lock() {
+            *rate
+        } else {
+            1.0
+        }
+    }
+
+    // Shuffle and repeat are decided by the playlist owner, the player just records them
+    // so that snapshots are complete
+    pub fn set_shuffle(&self, shuffle: bool) {
+        if let Ok(mut flag) = self.shuffle.lock() {
+            *flag = shuffle;
+        }
+    }
+    
+    pub fn get_shuffle(&self) -> bool {
+        if let Ok(flag) = self.shuffle.lock() {
+            *flag
+        } else {
+            false
+        }
+    }
+    
+    pub fn set_repeat(&self, repeat: RepeatMode) {
+        if let Ok(mut mode) = self.repeat.lock() {
+            *mode = repeat;
+        }
+    }
+    
+    pub fn get_repeat(&self) -> RepeatMode {
+        if let Ok(mode) = self.repeat.lock() {
+            *mode
+        } else {
+            RepeatMode::None
+        }
+    }
+    
+    /// Returns whether the player is playing, paused or stopped
+    pub fn get_state(&self) -> PlaybackState {
+        if self.is_playing() {
+            PlaybackState::Playing
+        } else if self.sink.is_paused() && !self.is_stopped() {
+            PlaybackState::Paused
+        } else {
+            PlaybackState::Stopped
+        }
+    }
+    
+    /// Captures the complete player state in one call
+    pub fn snapshot(&self) -> PlayerSnapshot {
+        let current_path = if let Ok(path) = self.current_file_path.lock() {
+            path.clone()
+        } else {
+            None
+        };
+        
+        PlayerSnapshot {
+            state: self.get_state(),
+            current_path,
+            position: self.get_current_position(),
+            duration: self.get_song_duration(),
+            volume: self.get_volume(),
+            shuffle: self.get_shuffle(),
+            repeat: self.get_repeat(),
+        }
+    }
+    
+    /// Applies a single control action. `Next` and `Previous` need a playlist, so they
+    /// return an error here and must be handled by the playlist owner instead.
+    pub fn apply_command(&self, command: PlayerCommand) -> Result<()> {
+        match command {
+            PlayerCommand::Play(path) => {
+                // A new track isn't finished, whatever happened to the last one
+                if let Ok(mut flag) = self.is_song_finished.lock() {
+                    *flag = false;
+                }
+                self.play_file(&path)?;
+            },
+            PlayerCommand::Pause => self.pause(),
+            PlayerCommand::Resume => self.resume(),
+            PlayerCommand::TogglePlayPause => {
+                if self.sink.is_paused() {
+                    self.resume();
+                } else {
+                    self.pause();
+                }
+            },
+            PlayerCommand::Stop => self.stop(),
+            PlayerCommand::Seek(position) => {
+                self.seek_to(position)?;
+            },
+            PlayerCommand::SetVolume(volume) => self.set_volume(volume),
+            PlayerCommand::SetShuffle(shuffle) => self.set_shuffle(shuffle),
+            PlayerCommand::SetRepeat(repeat) => self.set_repeat(repeat),
+            PlayerCommand::Next | PlayerCommand::Previous => {
+                return Err(anyhow::anyhow!("{:?} must be handled by the playlist owner", command));
+            },
+        }
+        
+        Ok(())
+    }
+
     // Progress tracking methods
     pub fn get_song_duration(&self) -> Option<Duration> {
         if let Ok(duration) = self.song_duration.lock() {
@@ -187,127 +1088,309 @@ impl MusicPlayer {
         }
     }
     
-    pub fn get_current_position(&self) -> Duration {
-        // If paused, return the stored position
-        if self.sink.is_paused() {
-            if let Ok(position) = self.play_position.lock() {
-                return *position;
-            }
+    /// How far into the track audio has been downloaded. For local files this is the
+    /// whole track, streaming sources report how far ahead of playback they've buffered.
+    pub fn get_buffered_position(&self) -> Option<Duration> {
+        let buffered = if let Ok(buffered) = self.buffered_position.lock() {
+            *buffered
+        } else {
+            None
+        };
+        
+        buffered.or_else(|| self.get_song_duration())
+    }
+    
+    /// Called by streaming sources as data arrives. `None` means the whole track is available.
+    pub fn set_buffered_position(&self, position: Option<Duration>) {
+        if let Ok(mut buffered) = self.buffered_position.lock() {
+            *buffered = position;
         }
+    }
+    
+    /// Position in the current song. It's worked out from the samples the sink has actually
+    /// played, so it stands still while paused or stalled, and never runs past the song's end.
+    pub fn get_current_position(&self) -> Duration {
+        // The position was set by the seek, and the sink may be mid-refill
+        let seeking = self.seek_in_progress.lock().map(|flag| *flag).unwrap_or(false);
+        let duration = self.get_song_duration();
+        let rate = self.get_playback_rate();
         
-        // If playing, calculate the current position based on elapsed time
-        if let (Ok(mut position), Ok(mut last_update)) = (self.play_position.lock(), self.last_position_update.lock()) {
-            if !self.sink.is_paused() && !self.sink.empty() {
-                let now = std::time::Instant::now();
-                let elapsed = now.duration_since(*last_update);
-                *position += elapsed;
-                *last_update = now;
+        if let (Ok(mut position), Ok(mut last_sink_position)) = (self.play_position.lock(), self.last_sink_position.lock()) {
+            if !seeking {
+                let sink_position = self.sink.get_pos();
+                // The sink counts output time, which covers more (or less) of the song at
+                // other speeds. It starts again from zero with each new source.
+                if let Some(played) = sink_position.checked_sub(*last_sink_position) {
+                    *position += played.mul_f32(rate);
+                }
+                *last_sink_position = sink_position;
             }
-            return *position;
+            return duration.map_or(*position, |duration| (*position).min(duration));
         }
         
         Duration::from_secs(0)
     }
     
-    // Extract the file reload functionality to its own function
-    fn reload_current_file(&self, position: Option<Duration>) -> Result<()> {
-        // Get the current file path
-        let file_path = if let Ok(path) = self.current_file_path.lock() {
-            match &*path {
-                Some(p) => p.clone(),
-                None => return Err(anyhow::anyhow!("No file is currently playing")),
+    // Sets the position and counts on from what the sink reports now
+    fn rebase_position(&self, position: Duration) {
+        if let (Ok(mut play_position), Ok(mut last_sink_position)) = (self.play_position.lock(), self.last_sink_position.lock()) {
+            *play_position = position;
+            *last_sink_position = self.sink.get_pos();
+        }
+    }
+    
+    // Overtakes any seek still running, so it's dropped rather than landing on whatever plays next
+    fn cancel_seek(&self) {
+        self.seek_generation.fetch_add(1, Ordering::SeqCst);
+        self.set_seeking(false);
+    }
+    
+    fn set_seeking(&self, seeking: bool) {
+        if let Ok(mut flag) = self.seek_in_progress.lock() {
+            *flag = seeking;
+        }
+    }
+    
+    /// Seeks and waits for it to finish. Front-ends that can't afford to wait (the GUI)
+    /// use `begin_seek`, run the job elsewhere, and hand it back to `finish_seek`.
+    /// Returns whether the decoder could seek precisely or the position is approximate.
+    pub fn seek_to(&self, position: Duration) -> Result<SeekAccuracy> {
+        let job = self.begin_seek(position)?;
+        self.finish_seek(job.run())
+    }
+    
+    /// Seeks `delta_secs` from the current position (negative goes back). Going before the
+    /// start lands on the start. Going past the end finishes the track, so the usual
+    /// finished handling moves on to the next one.
+    pub fn seek_relative(&self, delta_secs: f64) -> Result<()> {
+        match self.begin_seek_relative(delta_secs)? {
+            Some(job) => self.finish_seek(job.run()).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+    
+    /// `begin_seek` for a relative seek. Returns `None` if the seek went past the end (of the
+    /// range being played, for part of a file) and finished the track instead.
+    pub fn begin_seek_relative(&self, delta_secs: f64) -> Result<Option<SeekJob>> {
+        let end = self.get_range_end().or(self.get_song_duration());
+        match relative_seek_target(self.get_current_position(), delta_secs, end) {
+            SeekTarget::To(position) => self.begin_seek(position).map(Some),
+            SeekTarget::End => {
+                self.finish_track(end.unwrap_or_default());
+                Ok(None)
             }
+        }
+    }
+    
+    /// First, quick part of a seek: moves the position display and suppresses finished
+    /// detection until `finish_seek`. The returned job does the slow part and doesn't need
+    /// the player, so it can run while the player's lock is released.
+    pub fn begin_seek(&self, position: Duration) -> Result<SeekJob> {
+        match self.current_song_index.lock() {
+            Ok(index) if index.is_some() => {}
+            Ok(_) => return Err(anyhow::anyhow!("No song index is set")),
+            Err(_) => return Err(anyhow::anyhow!("Failed to lock song index mutex")),
+        }
+        let path = match self.current_file_path.lock() {
+            Ok(path) => path.clone().ok_or_else(|| anyhow::anyhow!("No file is currently playing"))?,
+            Err(_) => return Err(anyhow::anyhow!("Failed to lock file path mutex")),
+        };
+        if stream_url(&path).is_some() {
+            return Err(anyhow::anyhow!("Streams can't be seeked"));
+        }
+        let replaygain_mode = if let Ok(mode) = self.replaygain_mode.lock() {
+            *mode
         } else {
-            return Err(anyhow::anyhow!("Failed to lock file path mutex"));
+            ReplayGainMode::Off
         };
-
-        // If a position is provided, load the file and skip to that position
-        if let Some(position) = position {
-            // Stop the current playback
-            self.sink.stop();
-            
-            // Store the current file path (mostly redundant here but consistent with play_file)
-            if let Ok(mut file_path_lock) = self.current_file_path.lock() {
-                *file_path_lock = Some(file_path.clone());
-            }
-            
-            // Set the position
-            if let Ok(mut play_pos) = self.play_position.lock() {
-                *play_pos = position;
-            }
-            if let Ok(mut last_update) = self.last_position_update.lock() {
-                *last_update = std::time::Instant::now();
-            }
-            
-            // Open the file and create a decoder
-            let file = File::open(&file_path)?;
-            let reader = BufReader::new(file);
-            let source = Decoder::new(reader)?;
-            
-            // Store the song duration if available
-            let duration = source.total_duration();
-            if let Ok(mut song_duration) = self.song_duration.lock() {
-                *song_duration = duration;
+        
+        // What's heard should match the seek bar, so the previous track stops fading out
+        self.stop_fade_out();
+        
+        // Suppress finished detection for the whole seek, including when it fails part way
+        self.set_seeking(true);
+        self.seeks_running.fetch_add(1, Ordering::SeqCst);
+        let generation = self.seek_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        
+        // Show the new position straight away
+        self.rebase_position(position);
+        
+        Ok(SeekJob {
+            position,
+            generation,
+            path,
+            sink: self.sink.clone(),
+            latest: self.seek_generation.clone(),
+            replaygain_mode,
+            time_stretch: self.time_stretch.clone(),
+            eq_gains: self.eq_gains.clone(),
+            crossfeed: self.crossfeed.clone(),
+            balance: self.balance.clone(),
+        })
+    }
+    
+    /// Last, quick part of a seek: swaps in the reloaded source if the job had to reopen
+    /// the file. Results of seeks that have since been overtaken by a newer one are dropped.
+    pub fn finish_seek(&self, outcome: SeekOutcome) -> Result<SeekAccuracy> {
+        let previously_running = self.seeks_running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| Some(running.saturating_sub(1)))
+            .unwrap_or_default();
+        if outcome.generation != self.seek_generation.load(Ordering::SeqCst) {
+            // Finished detection stays off while a newer seek is still to land
+            if previously_running <= 1 {
+                self.set_seeking(false);
             }
-            
-            // Skip to the desired position and append to sink
-            let skipped_source = source.skip_duration(position);
-            self.sink.append(skipped_source);
-            self.sink.play();
-            
-            return Ok(());
+            return outcome.result.map(|reloaded| reloaded.map_or(SeekAccuracy::Exact, |r| r.accuracy));
         }
+        
+        let still_current = self.current_file_path.lock()
+            .map(|path| path.as_deref() == Some(outcome.path.as_path()))
+            .unwrap_or(false);
+        
+        let result = match outcome.result {
+            Ok(Some(reloaded)) if still_current => {
+                // Stopping also drops anything queued behind the old source
+                self.sink.stop();
+                self.clear_queue();
+                if let Ok(mut song_duration) = self.song_duration.lock() {
+                    *song_duration = reloaded.duration;
+                }
+                // The sink stays paused if it was, so seeking while paused doesn't start playback
+                self.sink.append(reloaded.source);
+                Ok(reloaded.accuracy)
+            },
+            Ok(Some(reloaded)) => Ok(reloaded.accuracy),
+            Ok(None) => Ok(SeekAccuracy::Exact),
+            Err(e) => Err(e),
+        };
+        
+        // The sink's count moved with the seek (or restarted with the reloaded source),
+        // so carry on counting from the position the seek set
+        let position = self.play_position.lock().map(|p| *p).unwrap_or_default();
+        self.rebase_position(position);
+        self.set_seeking(false);
+        result
+    }
+    
+    /// Ends the current track right away, as if it had played to the end, so the usual
+    /// finished handling moves on (used for trimmed end points)
+    pub fn end_track(&self) {
+        let end = self.get_song_duration().unwrap_or_else(|| self.get_current_position());
+        self.finish_track(end);
+    }
+    
+    // End the current track as if it had played to the end
+    fn finish_track(&self, duration: Duration) {
+        self.sink.stop();
+        self.clear_queue();
+        
+        if let Ok(mut position) = self.play_position.lock() {
+            *position = duration;
+        }
+        if let Ok(mut flag) = self.is_song_finished.lock() {
+            *flag = true;
+        }
+    }
+}
 
-        // If no position provided, just reload the file normally
-        if let Ok(mut play_pos) = self.play_position.lock() {
-            *play_pos = Duration::from_secs(0);
+/// The slow part of a seek, made by `MusicPlayer::begin_seek`. Running it may wait for the
+/// audio thread, or reopen and decode the file when the source can't seek in place.
+pub struct SeekJob {
+    position: Duration,
+    generation: u64,
+    path: PathBuf,
+    sink: Arc<Sink>,
+    latest: Arc<AtomicU64>,
+    replaygain_mode: ReplayGainMode,
+    eq_gains: Arc<Mutex<Vec<f32>>>,
+    time_stretch: Arc<Mutex<f32>>,
+    crossfeed: Arc<Mutex<CrossfeedSettings>>,
+    balance: Arc<Mutex<f32>>,
+}
+
+/// What a `SeekJob` did, for `MusicPlayer::finish_seek`
+pub struct SeekOutcome {
+    generation: u64,
+    path: PathBuf,
+    /// `None` when the sink seeked in place, otherwise the file reopened at the new position
+    result: Result<Option<ReloadedSource>>,
+}
+
+struct ReloadedSource {
+    source: Box<dyn Source<Item = f32> + Send>,
+    duration: Option<Duration>,
+    accuracy: SeekAccuracy,
+}
+
+impl SeekJob {
+    pub fn run(self) -> SeekOutcome {
+        // A decoder that panics on a damaged file still has to hand the seek back, or
+        // finished detection would stay off for good
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.seek_or_reload()))
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Seeking in {} failed", self.path.display())));
+        SeekOutcome {
+            generation: self.generation,
+            path: self.path,
+            result,
         }
-        self.play_file(&file_path)
     }
     
-    pub fn seek_to(&self, position: Duration) -> Result<()> {
-        // Get the current song index
-        let _song_index = if let Ok(index) = self.current_song_index.lock() {
-            match *index {
-                Some(i) => i,
-                None => return Err(anyhow::anyhow!("No song index is set")),
-            }
-        } else {
-            return Err(anyhow::anyhow!("Failed to lock song index mutex"));
-        };
+    fn seek_or_reload(&self) -> Result<Option<ReloadedSource>> {
+        // Someone already asked for a newer position, leave the sink to that seek
+        if self.latest.load(Ordering::SeqCst) != self.generation {
+            return Ok(None);
+        }
         
-        // Store the seek position
-        if let Ok(mut play_pos) = self.play_position.lock() {
-            *play_pos = position;
-        } else {
-            return Err(anyhow::anyhow!("Failed to lock position mutex"));
-        }
-        
-        // Reset the last update time
-        if let Ok(mut last_update) = self.last_position_update.lock() {
-            *last_update = std::time::Instant::now();
-        }
-
-        // Try to seek to the new position
-        if let Err(e) = self.sink.try_seek(position) {
-            // If the error is `SeekError::NotSupported` just ignore the seek input
-            match e {
-                SeekError::NotSupported { underlying_source: _ } => { 
-                    log::info!("Seek not supported, reloading the file instead.");
-                    // We can't seek, so reload the file instead
-                    self.reload_current_file(Some(position))?;
-                },
-                _ => {
-                    log::error!("Failed to seek, reloading the file instead. Error: {e}");
-                    self.reload_current_file(Some(position))?;
-                },
-            }
+        match self.sink.try_seek(self.position) {
+            Ok(()) => return Ok(None),
+            Err(SeekError::NotSupported { underlying_source: _ }) => {
+                log::info!("Seek not supported, reloading the file instead.");
+            },
+            Err(e) => log::error!("Failed to seek, reloading the file instead. Error: {e}"),
         }
         
-        Ok(())
+        let reloaded = decode_from(&self.path, self.position)?;
+        let gain = match self.replaygain_mode {
+            ReplayGainMode::Off => 1.0,
+            mode => read_replaygain(&self.path).factor(mode),
+        };
+        let source = effect_chain(reloaded.source, gain, self.eq_gains.clone(), self.time_stretch.clone(), self.crossfeed.clone(), self.balance.clone());
+        Ok(Some(ReloadedSource { source, ..reloaded }))
     }
 }
 
+// Opens a file at `position`, without effects. The decoder gets to the position here,
+// rather than leaving the audio thread to decode its way there.
+fn decode_from(path: &Path, position: Duration) -> Result<ReloadedSource> {
+    let mut decoder = open_decoder(path)?;
+    let duration = track_duration(path, decoder.total_duration());
+    let (source, accuracy): (Box<dyn Source<Item = f32> + Send>, _) = if decoder.try_seek(position).is_ok() {
+        (Box::new(decoder.convert_samples()), SeekAccuracy::Exact)
+    } else {
+        (Box::new(decoder.skip_duration(position).convert_samples()), SeekAccuracy::Approximate)
+    };
+    Ok(ReloadedSource { source, duration, accuracy })
+}
+
+// The processing every track goes through on its way to the sink
+fn effect_chain<S>(
+    source: S,
+    gain: f32,
+    eq_gains: Arc<Mutex<Vec<f32>>>,
+    time_stretch: Arc<Mutex<f32>>,
+    crossfeed: Arc<Mutex<CrossfeedSettings>>,
+    balance: Arc<Mutex<f32>>,
+) -> Box<dyn Source<Item = f32> + Send>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    let leveled = source.amplify(gain);
+    let equalized = Equalizer::new(leveled, eq_gains);
+    let stretched = TimeStretch::new(equalized, time_stretch);
+    let crossfed = Crossfeed::new(stretched, crossfeed);
+    Box::new(Balance::new(crossfed, balance))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,6 +1399,32 @@ mod tests {
     use std::io::Write;
     use tempfile::tempdir;
     
+    // A silent 16-bit mono WAV file lasting `seconds`. Keep the directory alive while it's used.
+    fn create_wav_file(seconds: u32) -> Result<(PathBuf, tempfile::TempDir)> {
+        let dir = tempdir()?;
+        let file_path = dir.path().join("long.wav");
+        
+        let sample_rate: u32 = 44100;
+        let data_len = sample_rate * seconds * 2;
+        let mut bytes = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());     // Subchunk1Size
+        bytes.extend_from_slice(&1u16.to_le_bytes());      // AudioFormat (PCM)
+        bytes.extend_from_slice(&1u16.to_le_bytes());      // NumChannels
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // ByteRate
+        bytes.extend_from_slice(&2u16.to_le_bytes());      // BlockAlign
+        bytes.extend_from_slice(&16u16.to_le_bytes());     // BitsPerSample
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(44 + data_len as usize, 0);
+        
+        File::create(&file_path)?.write_all(&bytes)?;
+        Ok((file_path, dir))
+    }
+    
     // Helper function to create a temporary audio file for testing
     #[allow(dead_code)]
     fn create_test_file() -> Result<PathBuf> {
@@ -351,6 +1460,16 @@ mod tests {
         Ok(file_path)
     }
     
+    #[test]
+    fn test_ended_early() {
+        let length = Some(Duration::from_secs(200));
+        assert!(ended_early(Duration::from_secs(60), length));
+        // A few seconds short is just an overstated length
+        assert!(!ended_early(Duration::from_secs(196), length));
+        assert!(!ended_early(Duration::from_secs(200), length));
+        assert!(!ended_early(Duration::from_secs(1), None));
+    }
+    
     #[test]
     fn test_new_player() {
         let player = MusicPlayer::new();
@@ -380,6 +1499,63 @@ mod tests {
         assert!(!player.is_playing());
     }
     
+    #[test]
+    fn test_apply_commands_updates_snapshot() {
+        // No device needed, so this runs in CI as well
+        let player = MusicPlayer::without_output();
+        let (file, _dir) = create_wav_file(2).unwrap();
+        
+        let initial = player.snapshot();
+        assert_eq!(initial.state, PlaybackState::Stopped);
+        assert_eq!(initial.current_path, None);
+        assert!(!initial.shuffle);
+        assert_eq!(initial.repeat, RepeatMode::None);
+        
+        player.apply_command(PlayerCommand::SetVolume(0.25)).unwrap();
+        player.apply_command(PlayerCommand::SetShuffle(true)).unwrap();
+        player.apply_command(PlayerCommand::SetRepeat(RepeatMode::All)).unwrap();
+        let configured = player.snapshot();
+        assert_eq!(configured.volume, 0.25);
+        assert!(configured.shuffle);
+        assert_eq!(configured.repeat, RepeatMode::All);
+        assert_eq!(configured.state, PlaybackState::Stopped);
+        
+        // Volume is clamped like set_volume
+        player.apply_command(PlayerCommand::SetVolume(3.0)).unwrap();
+        assert_eq!(player.snapshot().volume, 1.0);
+        
+        player.apply_command(PlayerCommand::SetRepeat(RepeatMode::One)).unwrap();
+        player.apply_command(PlayerCommand::SetShuffle(false)).unwrap();
+        player.apply_command(PlayerCommand::Stop).unwrap();
+        let stopped = player.snapshot();
+        assert_eq!(stopped.repeat, RepeatMode::One);
+        assert!(!stopped.shuffle);
+        assert_eq!(stopped.state, PlaybackState::Stopped);
+        
+        // Playing after Stop starts again
+        player.apply_command(PlayerCommand::Play(file.clone())).unwrap();
+        let playing = player.snapshot();
+        assert_eq!(playing.state, PlaybackState::Playing);
+        assert_eq!(playing.current_path, Some(file.clone()));
+        assert_eq!(player.get_state(), PlaybackState::Playing);
+        
+        player.apply_command(PlayerCommand::Pause).unwrap();
+        assert_eq!(player.snapshot().state, PlaybackState::Paused);
+        player.apply_command(PlayerCommand::TogglePlayPause).unwrap();
+        assert_eq!(player.snapshot().state, PlaybackState::Playing);
+        
+        // A finished track doesn't stop the next one from counting as playing
+        player.end_track();
+        assert!(player.check_if_song_finished());
+        player.apply_command(PlayerCommand::Play(file.clone())).unwrap();
+        assert!(!player.check_if_song_finished());
+        assert_eq!(player.snapshot().state, PlaybackState::Playing);
+        
+        // Navigation belongs to the playlist owner
+        assert!(player.apply_command(PlayerCommand::Next).is_err());
+        assert!(player.apply_command(PlayerCommand::Previous).is_err());
+    }
+    
     #[test]
     fn test_current_song_index() {
         // Instead of creating an actual player and trying to play a file,
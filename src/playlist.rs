@@ -0,0 +1,656 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use crate::cache::LruCache;
+use crate::config::get_config_dir;
+use crate::metadata::{TrackMetadata, display_name};
+use crate::stream::is_stream;
+use crate::trims::{Trim, TrackTrims};
+use crate::utils::{DEFAULT_CACHE_CAPACITY, natural_cmp};
+
+lazy_static! {
+    /// Tracks of each cue sheet looked at, with the sheet's modification time when it was
+    /// read so edits are picked up. A sheet that couldn't be read has no tracks.
+    static ref CUE_CACHE: Mutex<LruCache<PathBuf, (Option<SystemTime>, Vec<CueTrack>)>> = Mutex::new(LruCache::new(DEFAULT_CACHE_CAPACITY));
+}
+
+/// Writes the tracks to an extended M3U file, with an `#EXTINF` line giving each one's length
+/// and title. Tracks in or below the playlist's folder are stored relative to it, so the
+/// folder can be moved around with its playlist. `metadata` looks up tags already read
+/// (files aren't opened here); a track without them gets its file name and a length of -1.
+/// A trimmed track gets an `#EXTTRIM:start,end` line too, which other players skip.
+pub fn save_m3u(paths: &[PathBuf], out: &Path, metadata: impl Fn(&Path) -> Option<TrackMetadata>, trims: &TrackTrims) -> Result<()> {
+    let base = out.parent().unwrap_or(Path::new(""));
+
+    let mut contents = String::from("#EXTM3U\n");
+    for path in paths {
+        let metadata = metadata(path);
+        let seconds = metadata.as_ref().and_then(|m| m.duration).map(|d| d.as_secs() as i64).unwrap_or(-1);
+        writeln!(contents, "#EXTINF:{},{}", seconds, display_name(path, metadata.as_ref()))?;
+        let trim = trims.get(path);
+        if !trim.is_empty() {
+            writeln!(contents, "#EXTTRIM:{}", trim_value(&trim))?;
+        }
+        writeln!(contents, "{}", playlist_entry(path, base).display())?;
+    }
+
+    fs::write(out, contents)?;
+    Ok(())
+}
+
+// How a track is written in a playlist saved in `base`: relative when it's in or below it
+fn playlist_entry<'a>(path: &'a Path, base: &Path) -> &'a Path {
+    path.strip_prefix(base).ok()
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .unwrap_or(path)
+}
+
+// How a trim is written in a playlist: `start,end` in seconds, either left empty for the
+// file's own start or end
+fn trim_value(trim: &Trim) -> String {
+    let seconds = |point: Option<f64>| point.map(|s| s.to_string()).unwrap_or_default();
+    format!("{},{}", seconds(trim.start), seconds(trim.end))
+}
+
+fn parse_trim(value: &str) -> Option<Trim> {
+    let (start, end) = value.split_once(',')?;
+    let seconds = |point: &str| -> Option<Option<f64>> {
+        match point.trim() {
+            "" => Some(None),
+            point => point.parse::<f64>().ok().filter(|s| s.is_finite() && *s >= 0.0).map(Some),
+        }
+    };
+    let trim = Trim { start: seconds(start)?, end: seconds(end)? };
+    match (trim.start, trim.end) {
+        (Some(start), Some(end)) if start >= end => None,
+        _ => Some(trim),
+    }
+}
+
+/// Reads the tracks from an M3U file, either extended (`#EXTM3U`) or a bare list of paths.
+/// Relative paths are resolved against the playlist's folder, and stream URLs are kept as
+/// they are. The tracks aren't checked, so missing files are still returned.
+// For library users; the player itself reads the trims as well
+#[allow(dead_code)]
+pub fn load_m3u(path: &Path) -> Result<Vec<PathBuf>> {
+    load_m3u_with_trims(path).map(|(tracks, _)| tracks)
+}
+
+/// Reads an M3U file like `load_m3u`, along with the trim points saved with its tracks
+pub fn load_m3u_with_trims(path: &Path) -> Result<(Vec<PathBuf>, TrackTrims)> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    Ok(parse_m3u(&contents, base))
+}
+
+fn parse_m3u(contents: &str, base: &Path) -> (Vec<PathBuf>, TrackTrims) {
+    let mut tracks = Vec::new();
+    let mut trims = TrackTrims::default();
+    // A trim line belongs to the track that follows it
+    let mut pending_trim = None;
+    for line in contents.trim_start_matches('\u{feff}').lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("#EXTTRIM:") {
+            pending_trim = parse_trim(value);
+            if pending_trim.is_none() {
+                log::warn!("Skipping trim points that can't be read in M3U playlist: {}", value);
+            }
+            continue;
+        }
+        // Blank lines, the #EXTM3U header and #EXTINF lines carry no paths
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let track = if is_stream(Path::new(line)) { PathBuf::from(line) } else { base.join(line) };
+        if let Some(trim) = pending_trim.take() {
+            trims.set(&track, trim);
+        }
+        tracks.push(track);
+    }
+    (tracks, trims)
+}
+
+fn get_startup_playlist_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("startup.m3u"))
+}
+
+/// Saves the tracks as the playlist loaded when the player starts without any files.
+/// Stored next to the config file in `startup.m3u`, trims included. Only the paths and
+/// trims are read back, so no tags are written.
+pub fn save_startup_playlist(paths: &[PathBuf], trims: &TrackTrims) -> Result<()> {
+    save_m3u(paths, &get_startup_playlist_path()?, |_| None, trims)
+}
+
+/// Loads the startup playlist and its trims, or nothing if one hasn't been set
+pub fn load_startup_playlist() -> Result<(Vec<PathBuf>, TrackTrims)> {
+    load_startup_playlist_from(&get_startup_playlist_path()?)
+}
+
+fn load_startup_playlist_from(path: &Path) -> Result<(Vec<PathBuf>, TrackTrims)> {
+    if !path.exists() {
+        return Ok((Vec::new(), TrackTrims::default()));
+    }
+    load_m3u_with_trims(path)
+}
+
+/// Writes the tracks to a PLS file, with each one's title and length in seconds (-1 when it
+/// isn't known). Paths are stored relative to the playlist's folder, and tags looked up with
+/// `metadata`, as with `save_m3u`. A trimmed track gets a `Trim` entry as well.
+pub fn save_pls(paths: &[PathBuf], out: &Path, metadata: impl Fn(&Path) -> Option<TrackMetadata>, trims: &TrackTrims) -> Result<()> {
+    let base = out.parent().unwrap_or(Path::new(""));
+
+    let mut contents = String::from("[playlist]\n");
+    for (number, path) in (1..).zip(paths) {
+        let metadata = metadata(path);
+        let seconds = metadata.as_ref().and_then(|m| m.duration).map(|d| d.as_secs() as i64).unwrap_or(-1);
+        writeln!(contents, "File{}={}", number, playlist_entry(path, base).display())?;
+        writeln!(contents, "Title{}={}", number, display_name(path, metadata.as_ref()))?;
+        writeln!(contents, "Length{}={}", number, seconds)?;
+        let trim = trims.get(path);
+        if !trim.is_empty() {
+            writeln!(contents, "Trim{}={}", number, trim_value(&trim))?;
+        }
+    }
+    writeln!(contents, "NumberOfEntries={}", paths.len())?;
+    writeln!(contents, "Version=2")?;
+
+    fs::write(out, contents)?;
+    Ok(())
+}
+
+/// Reads the tracks from a PLS file, in the order of their numbers. Relative paths are
+/// resolved against the playlist's folder and stream URLs are kept as they are. Lines that
+/// can't be read are skipped with a warning, and the tracks aren't checked.
+// For library users; the player itself reads the trims as well
+#[allow(dead_code)]
+pub fn load_pls(path: &Path) -> Result<Vec<PathBuf>> {
+    load_pls_with_trims(path).map(|(tracks, _)| tracks)
+}
+
+/// Reads a PLS file like `load_pls`, along with the trim points saved with its tracks
+pub fn load_pls_with_trims(path: &Path) -> Result<(Vec<PathBuf>, TrackTrims)> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    Ok(parse_pls(&contents, base))
+}
+
+fn parse_pls(contents: &str, base: &Path) -> (Vec<PathBuf>, TrackTrims) {
+    // By number, since nothing says the entries have to be in order
+    let mut files = BTreeMap::new();
+    let mut entry_trims = BTreeMap::new();
+    for (line_number, line) in contents.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        // Blank lines, the [playlist] header and comments
+        if line.is_empty() || line.starts_with('[') || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            log::warn!("Skipping line {} of PLS playlist, it isn't a setting: {}", line_number + 1, line);
+            continue;
+        };
+        // Titles and lengths are read from the tracks themselves, like everywhere else
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        if let Some(number) = key.strip_prefix("trim") {
+            match (number.parse::<u32>(), parse_trim(value)) {
+                (Ok(number), Some(trim)) => {
+                    entry_trims.insert(number, trim);
+                }
+                _ => log::warn!("Skipping line {} of PLS playlist, its trim points can't be read: {}", line_number + 1, line),
+            }
+            continue;
+        }
+        let Some(number) = key.strip_prefix("file") else {
+            continue;
+        };
+        let Ok(number) = number.parse::<u32>() else {
+            log::warn!("Skipping line {} of PLS playlist, it has no entry number: {}", line_number + 1, line);
+            continue;
+        };
+        if value.is_empty() {
+            log::warn!("Skipping entry {} of PLS playlist, it has no file", number);
+            continue;
+        }
+        if files.contains_key(&number) {
+            log::warn!("Skipping a second entry {} in PLS playlist: {}", number, value);
+            continue;
+        }
+
+        let file = if is_stream(Path::new(value)) { PathBuf::from(value) } else { base.join(value) };
+        files.insert(number, file);
+    }
+
+    let mut trims = TrackTrims::default();
+    for (number, trim) in entry_trims {
+        if let Some(file) = files.get(&number) {
+            trims.set(file, trim);
+        }
+    }
+    (files.into_values().collect(), trims)
+}
+
+/// CD frames per second, the unit of the last field in a cue sheet's `mm:ss:ff` times
+const CUE_FRAMES_PER_SECOND: f64 = 75.0;
+
+/// One track from a cue sheet: part of a larger audio file, usually a whole album
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    /// Playlist entry standing for this track, see `cue_track_path`
+    pub path: PathBuf,
+    pub number: u32,
+    /// The audio file the track is part of
+    pub file: PathBuf,
+    pub title: Option<String>,
+    /// The track's performer, or the whole album's when the track doesn't name one
+    pub performer: Option<String>,
+    pub album: Option<String>,
+    /// Where the track starts in `file`
+    pub start: Duration,
+    /// Where the next track starts, `None` when this one runs to the end of the file
+    pub end: Option<Duration>,
+}
+
+/// Reads the tracks from a cue sheet. Audio file names are resolved against the sheet's
+/// folder. The files aren't checked, so missing ones are still returned.
+pub fn load_cue(cue: &Path) -> Result<Vec<CueTrack>> {
+    let contents = fs::read_to_string(cue)
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {}", cue.display(), e))?;
+    Ok(parse_cue(&contents, cue))
+}
+
+/// The playlist entry for track `number` of a cue sheet, such as `Album.cue#03`. It isn't a
+/// real file: `cue_track_for` turns it back into the track.
+pub fn cue_track_path(cue: &Path, number: u32) -> PathBuf {
+    let mut path = cue.as_os_str().to_owned();
+    path.push(format!("#{:02}", number));
+    PathBuf::from(path)
+}
+
+/// The cue sheet track a playlist entry stands for, `None` for ordinary files (or when the
+/// sheet can no longer be read). This is called every frame, so sheets are only read again
+/// once they change.
+pub fn cue_track_for(path: &Path) -> Option<CueTrack> {
+    let (cue, number) = path.to_str()?.rsplit_once('#')?;
+    if !cue.to_lowercase().ends_with(".cue") {
+        return None;
+    }
+    let number: u32 = number.parse().ok()?;
+    let cue = PathBuf::from(cue);
+    let find = |tracks: &[CueTrack]| tracks.iter().find(|track| track.number == number).cloned();
+
+    let modified = fs::metadata(&cue).and_then(|metadata| metadata.modified()).ok();
+    if let Ok(mut cache) = CUE_CACHE.lock() {
+        if let Some((read_at, tracks)) = cache.get(&cue) {
+            if *read_at == modified {
+                return find(tracks.as_slice());
+            }
+        }
+    }
+
+    let tracks = load_cue(&cue).unwrap_or_default();
+    let track = find(tracks.as_slice());
+    if let Ok(mut cache) = CUE_CACHE.lock() {
+        cache.insert(cue, (modified, tracks));
+    }
+    track
+}
+
+fn parse_cue(contents: &str, cue: &Path) -> Vec<CueTrack> {
+    let base = cue.parent().unwrap_or(Path::new(""));
+    let mut album = None;
+    let mut album_performer = None;
+    let mut file = None;
+    let mut tracks: Vec<CueTrack> = Vec::new();
+
+    for line in contents.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        // Values after TRACK belong to it, before the first TRACK to the whole album
+        let track = tracks.last_mut().filter(|track| Some(&track.file) == file.as_ref());
+
+        match command.to_uppercase().as_str() {
+            "FILE" => file = Some(base.join(cue_value(rest))),
+            "TITLE" => match track {
+                Some(track) => track.title = Some(cue_value(rest)),
+                None => album = Some(cue_value(rest)),
+            },
+            "PERFORMER" => match track {
+                Some(track) => track.performer = Some(cue_value(rest)),
+                None => album_performer = Some(cue_value(rest)),
+            },
+            "TRACK" => {
+                let (Some(file), Some(Ok(number))) = (&file, rest.split_whitespace().next().map(str::parse)) else {
+                    continue;
+                };
+                tracks.push(CueTrack {
+                    path: cue_track_path(cue, number),
+                    number,
+                    file: file.clone(),
+                    title: None,
+                    performer: None,
+                    album: album.clone(),
+                    start: Duration::ZERO,
+                    end: None,
+                });
+            },
+            // INDEX 00 is the gap before the track, INDEX 01 where it starts
+            "INDEX" => {
+                let mut fields = rest.split_whitespace();
+                if let (Some(track), Some("01"), Some(time)) = (track, fields.next(), fields.next().and_then(parse_cue_time)) {
+                    track.start = time;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Each track runs until the next one in the same file starts
+    for i in 0..tracks.len() {
+        if tracks[i].performer.is_none() {
+            tracks[i].performer = album_performer.clone();
+        }
+        let next_start = tracks.get(i + 1)
+            .filter(|next| next.file == tracks[i].file)
+            .map(|next| next.start);
+        if next_start.is_some() {
+            tracks[i].end = next_start;
+        }
+    }
+    tracks
+}
+
+// A quoted value, or the first word of an unquoted one
+fn cue_value(rest: &str) -> String {
+    match rest.strip_prefix('"').and_then(|quoted| quoted.split_once('"')) {
+        Some((value, _)) => value.to_string(),
+        None => rest.split_whitespace().next().unwrap_or("").to_string(),
+    }
+}
+
+// "mm:ss:ff", where ff counts CD frames
+fn parse_cue_time(time: &str) -> Option<Duration> {
+    let mut fields = time.split(':').map(|field| field.parse::<u64>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    Some(Duration::from_secs(minutes * 60 + seconds) + Duration::from_secs_f64(frames as f64 / CUE_FRAMES_PER_SECOND))
+}
+
+/// What a playlist entry is sorted on
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortValue {
+    Text(String),
+    Number(u64),
+}
+
+/// Orders two entries by their sort values. Text compares naturally, ignoring case, so
+/// "Track 2" comes before "Track 10". Entries without a value go last either way round.
+pub fn compare_sort_values(a: Option<&SortValue>, b: Option<&SortValue>, ascending: bool) -> Ordering {
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (Some(_), None) => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        (None, None) => return Ordering::Equal,
+    };
+    let ordering = match (a, b) {
+        (SortValue::Text(a), SortValue::Text(b)) => natural_cmp(a, b),
+        (SortValue::Number(a), SortValue::Number(b)) => a.cmp(b),
+        // Never mixed in practice, numbers first to keep the order total
+        (SortValue::Number(_), SortValue::Text(_)) => Ordering::Less,
+        (SortValue::Text(_), SortValue::Number(_)) => Ordering::Greater,
+    };
+    if ascending { ordering } else { ordering.reverse() }
+}
+
+/// Where each entry goes when `values` (one per entry) are sorted. Stable, so entries with
+/// equal values keep their order.
+pub fn sorted_order(values: &[Option<SortValue>], ascending: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| compare_sort_values(values[a].as_ref(), values[b].as_ref(), ascending));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_natural_sort_of_file_names() {
+        let names = ["track10.mp3", "Track2.mp3", "track1.mp3", "intro.mp3", "track02b.mp3"];
+        let values: Vec<Option<SortValue>> = names.iter().map(|n| Some(SortValue::Text(n.to_string()))).collect();
+        let sorted: Vec<&str> = sorted_order(&values, true).into_iter().map(|i| names[i]).collect();
+        assert_eq!(sorted, ["intro.mp3", "track1.mp3", "Track2.mp3", "track02b.mp3", "track10.mp3"]);
+
+        let sorted: Vec<&str> = sorted_order(&values, false).into_iter().map(|i| names[i]).collect();
+        assert_eq!(sorted, ["track10.mp3", "track02b.mp3", "Track2.mp3", "track1.mp3", "intro.mp3"]);
+    }
+
+    #[test]
+    fn test_entries_without_a_value_sort_last() {
+        let values = [None, Some(SortValue::Number(180)), Some(SortValue::Number(95))];
+        assert_eq!(sorted_order(&values, true), vec![2, 1, 0]);
+        assert_eq!(sorted_order(&values, false), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_parse_extended_and_bare_lists() {
+        let base = Path::new("/music/lists");
+        let extended = "#EXTM3U\n#EXTINF:215,Artist - Title\nalbum/01.mp3\n\n#EXTINF:-1,Other\n/abs/02.flac\n";
+        assert_eq!(parse_m3u(extended, base).0, vec![
+            PathBuf::from("/music/lists/album/01.mp3"),
+            PathBuf::from("/abs/02.flac"),
+        ]);
+
+        let with_stream = "#EXTM3U\n#EXTINF:-1,http://radio.example/live\nhttp://radio.example/live\n";
+        assert_eq!(parse_m3u(with_stream, base).0, vec![PathBuf::from("http://radio.example/live")]);
+
+        let bare = "\u{feff}01.mp3\r\n02.mp3\r\n";
+        assert_eq!(parse_m3u(bare, base).0, vec![
+            PathBuf::from("/music/lists/01.mp3"),
+            PathBuf::from("/music/lists/02.mp3"),
+        ]);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let tracks = vec![
+            dir.path().join("sub").join("a.mp3"),
+            outside.path().join("b.mp3"),
+        ];
+        let playlist = dir.path().join("mix.m3u");
+
+        save_m3u(&tracks, &playlist, |_| None, &TrackTrims::default()).unwrap();
+        let contents = fs::read_to_string(&playlist).unwrap();
+        assert!(contents.starts_with("#EXTM3U\n#EXTINF:-1,a.mp3\n"));
+        // Tracks next to the playlist are written relative to it
+        assert!(contents.lines().any(|line| Path::new(line) == Path::new("sub").join("a.mp3")));
+
+        assert_eq!(load_m3u(&playlist).unwrap(), tracks);
+
+        // Tags already looked up give the title and length
+        let known = TrackMetadata {
+            title: Some("Song A".to_string()),
+            duration: Some(Duration::from_secs(215)),
+            ..TrackMetadata::default()
+        };
+        save_m3u(&tracks, &playlist, |path| (path == tracks[0]).then(|| known.clone()), &TrackTrims::default()).unwrap();
+        let contents = fs::read_to_string(&playlist).unwrap();
+        assert!(contents.starts_with("#EXTM3U\n#EXTINF:215,"));
+        assert!(contents.contains("#EXTINF:-1,b.mp3\n"));
+    }
+
+    #[test]
+    fn test_startup_playlist_round_trip() {
+        let dir = tempdir().unwrap();
+        let startup = dir.path().join("startup.m3u");
+        assert!(load_startup_playlist_from(&startup).unwrap().0.is_empty());
+
+        let music = tempdir().unwrap();
+        let tracks = vec![music.path().join("a.mp3"), PathBuf::from("https://radio.example/live"), music.path().join("b.flac")];
+        let mut trims = TrackTrims::default();
+        trims.set_end(&tracks[2], Duration::from_secs(90)).unwrap();
+        save_m3u(&tracks, &startup, |_| None, &trims).unwrap();
+        assert_eq!(load_startup_playlist_from(&startup).unwrap(), (tracks, trims));
+    }
+
+    #[test]
+    fn test_parse_pls_skips_malformed_entries() {
+        let base = Path::new("/music/lists");
+        let contents = "[playlist]\n\
+            File2=/abs/02.flac\n\
+            Title2=Second\n\
+            file1=album/01.mp3\n\
+            Length1=215\n\
+            garbage line\n\
+            FileX=nowhere.mp3\n\
+            File3=\n\
+            File4=http://radio.example:8000/live\n\
+            File2=/abs/duplicate.flac\n\
+            NumberOfEntries=4\n\
+            Version=2\n";
+        assert_eq!(parse_pls(contents, base).0, vec![
+            PathBuf::from("/music/lists/album/01.mp3"),
+            PathBuf::from("/abs/02.flac"),
+            PathBuf::from("http://radio.example:8000/live"),
+        ]);
+    }
+
+    #[test]
+    fn test_save_and_load_pls_round_trip() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let tracks = vec![
+            dir.path().join("sub").join("a.mp3"),
+            outside.path().join("b.mp3"),
+            PathBuf::from("https://radio.example/stream"),
+        ];
+        let playlist = dir.path().join("mix.pls");
+
+        let mut trims = TrackTrims::default();
+        trims.set_start(&tracks[1], Duration::from_millis(12_500)).unwrap();
+        save_pls(&tracks, &playlist, |_| None, &trims).unwrap();
+        let contents = fs::read_to_string(&playlist).unwrap();
+        assert!(contents.starts_with("[playlist]\n"));
+        assert!(contents.contains("Title1=a.mp3\nLength1=-1\n"));
+        assert!(contents.contains("NumberOfEntries=3\n"));
+        // Tracks next to the playlist are written relative to it
+        assert!(contents.lines().any(|line| line.strip_prefix("File1=").is_some_and(|entry| Path::new(entry) == Path::new("sub").join("a.mp3"))));
+
+        assert!(contents.contains("Trim2=12.5,\n"));
+
+        assert_eq!(load_pls(&playlist).unwrap(), tracks);
+        assert_eq!(load_pls_with_trims(&playlist).unwrap(), (tracks, trims));
+    }
+
+    #[test]
+    fn test_trims_are_saved_with_m3u_entries() {
+        let dir = tempdir().unwrap();
+        let tracks = vec![dir.path().join("intro.mp3"), dir.path().join("plain.mp3"), dir.path().join("outro.mp3")];
+        let mut trims = TrackTrims::default();
+        trims.set_start(&tracks[0], Duration::from_secs(30)).unwrap();
+        trims.set_end(&tracks[0], Duration::from_millis(200_250)).unwrap();
+        trims.set_end(&tracks[2], Duration::from_secs(95)).unwrap();
+        let playlist = dir.path().join("trimmed.m3u");
+
+        save_m3u(&tracks, &playlist, |_| None, &trims).unwrap();
+        let contents = fs::read_to_string(&playlist).unwrap();
+        assert!(contents.contains("#EXTINF:-1,intro.mp3\n#EXTTRIM:30,200.25\nintro.mp3\n"));
+        assert!(contents.contains("#EXTINF:-1,plain.mp3\nplain.mp3\n"));
+        assert!(contents.contains("#EXTTRIM:,95\n"));
+
+        // Other players see only the tracks
+        assert_eq!(load_m3u(&playlist).unwrap(), tracks);
+        assert_eq!(load_m3u_with_trims(&playlist).unwrap(), (tracks, trims));
+    }
+
+    #[test]
+    fn test_unreadable_trims_are_skipped() {
+        let base = Path::new("/music");
+        let contents = "#EXTM3U\n#EXTTRIM:50,20\na.mp3\n#EXTTRIM:abc\nb.mp3\n#EXTTRIM:-3,\nc.mp3\n#EXTTRIM:5,\nd.mp3\n";
+        let (tracks, trims) = parse_m3u(contents, base);
+        assert_eq!(tracks.len(), 4);
+        assert!(trims.get(&tracks[0]).is_empty());
+        assert!(trims.get(&tracks[1]).is_empty());
+        assert!(trims.get(&tracks[2]).is_empty());
+        assert_eq!(trims.get(&tracks[3]).start(), Some(Duration::from_secs(5)));
+
+        let pls = "[playlist]\nFile1=a.mp3\nTrim1=,oops\nFile2=b.mp3\nTrim2=1,2\nTrim9=1,2\n";
+        let (tracks, trims) = parse_pls(pls, base);
+        assert!(trims.get(&tracks[0]).is_empty());
+        assert_eq!(trims.get(&tracks[1]).end(), Some(Duration::from_secs(2)));
+        assert_eq!(trims.tracks.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_cue_sheet() {
+        let cue = Path::new("/music/Album.cue");
+        let contents = r#"REM GENRE Rock
+PERFORMER "The Band"
+TITLE "Live Album"
+FILE "Live Album.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Opener"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Second Song"
+    PERFORMER "Guest Singer"
+    INDEX 00 03:58:00
+    INDEX 01 04:00:37
+FILE bonus.wav WAVE
+  TRACK 03 AUDIO
+    TITLE "Bonus"
+    INDEX 01 00:00:00
+"#;
+        let tracks = parse_cue(contents, cue);
+        assert_eq!(tracks.len(), 3);
+
+        assert_eq!(tracks[0].path, PathBuf::from("/music/Album.cue#01"));
+        assert_eq!(tracks[0].file, PathBuf::from("/music/Live Album.flac"));
+        assert_eq!(tracks[0].title.as_deref(), Some("Opener"));
+        assert_eq!(tracks[0].performer.as_deref(), Some("The Band"));
+        assert_eq!(tracks[0].album.as_deref(), Some("Live Album"));
+        assert_eq!(tracks[0].start, Duration::ZERO);
+        // Ends where the next track starts, after its pregap
+        let second_start = Duration::from_secs(240) + Duration::from_secs_f64(37.0 / 75.0);
+        assert_eq!(tracks[0].end, Some(second_start));
+
+        assert_eq!(tracks[1].start, second_start);
+        assert_eq!(tracks[1].performer.as_deref(), Some("Guest Singer"));
+        // Last track in its file runs to the end
+        assert_eq!(tracks[1].end, None);
+
+        assert_eq!(tracks[2].file, PathBuf::from("/music/bonus.wav"));
+        assert_eq!((tracks[2].start, tracks[2].end), (Duration::ZERO, None));
+    }
+
+    #[test]
+    fn test_cue_track_paths_resolve() {
+        let dir = tempdir().unwrap();
+        let cue = dir.path().join("album.cue");
+        fs::write(&cue, "FILE \"album.flac\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nTITLE Two\nINDEX 01 01:00:00\n").unwrap();
+
+        let entry = cue_track_path(&cue, 2);
+        let track = cue_track_for(&entry).unwrap();
+        assert_eq!(track.path, entry);
+        assert_eq!(track.title.as_deref(), Some("Two"));
+        assert_eq!(track.start, Duration::from_secs(60));
+
+        assert!(cue_track_for(&cue_track_path(&cue, 3)).is_none());
+        assert!(cue_track_for(&dir.path().join("song#01.mp3")).is_none());
+    }
+}
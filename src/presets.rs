@@ -0,0 +1,118 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config::get_config_dir;
+
+/// Gain in dB for each EQ band, lowest band first
+pub type EqGains = Vec<f32>;
+
+/// Named EQ presets, and which tracks have their own preset. Tracks without one play with
+/// the EQ set in the window (`eq_gains` in the config file). Stored next to the config file
+/// in `eq_presets.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqPresets {
+    pub presets: BTreeMap<String, EqGains>,
+    /// Track path to preset name
+    pub bindings: BTreeMap<String, String>,
+}
+
+impl EqPresets {
+    /// Adds a preset, replacing any existing one with the same name
+    pub fn save_preset(&mut self, name: &str, gains: EqGains) {
+        self.presets.insert(name.to_string(), gains);
+    }
+
+    /// Removes a preset along with any track bindings that use it
+    pub fn remove_preset(&mut self, name: &str) {
+        self.presets.remove(name);
+        self.bindings.retain(|_, preset| preset != name);
+    }
+
+    /// Makes `path` always play with the named preset
+    pub fn bind_track(&mut self, path: &Path, name: &str) -> Result<()> {
+        if !self.presets.contains_key(name) {
+            return Err(anyhow::anyhow!("No EQ preset named '{}'", name));
+        }
+        self.bindings.insert(path.to_string_lossy().to_string(), name.to_string());
+        Ok(())
+    }
+
+    pub fn unbind_track(&mut self, path: &Path) {
+        self.bindings.remove(path.to_string_lossy().as_ref());
+    }
+
+    /// "Save EQ as default for this track": stores the gains as a preset named after
+    /// the file and binds the track to it
+    pub fn save_for_track(&mut self, path: &Path, gains: EqGains) -> String {
+        let name = path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+        self.save_preset(&name, gains);
+        self.bindings.insert(path.to_string_lossy().to_string(), name.clone());
+        name
+    }
+
+    /// The preset to apply when `path` starts, if it has one of its own
+    pub fn preset_for_track(&self, path: &Path) -> Option<(&str, &EqGains)> {
+        self.bindings.get(path.to_string_lossy().as_ref())
+            .and_then(|name| self.presets.get_key_value(name))
+            .map(|(name, gains)| (name.as_str(), gains))
+    }
+}
+
+fn get_presets_file_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("eq_presets.toml"))
+}
+
+/// Loads the presets, or an empty set if none have been saved yet
+pub fn load_presets() -> Result<EqPresets> {
+    let path = get_presets_file_path()?;
+    if !path.exists() {
+        return Ok(EqPresets::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+pub fn save_presets(presets: &EqPresets) -> Result<()> {
+    let serialized = toml::to_string_pretty(presets)?;
+    fs::write(get_presets_file_path()?, serialized)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_bound_tracks_have_a_preset() {
+        let mut presets = EqPresets::default();
+        presets.save_preset("Old Recording", vec![-3.0, 2.0, 4.0]);
+
+        let old = Path::new("/music/1931.flac");
+        presets.bind_track(old, "Old Recording").unwrap();
+        assert!(presets.bind_track(old, "Missing").is_err());
+
+        assert_eq!(presets.preset_for_track(old).map(|(name, _)| name), Some("Old Recording"));
+        // Unbound tracks go back to the EQ set in the window
+        assert_eq!(presets.preset_for_track(Path::new("/music/new.flac")), None);
+
+        presets.remove_preset("Old Recording");
+        assert_eq!(presets.preset_for_track(old), None);
+    }
+
+    #[test]
+    fn test_presets_round_trip_through_toml() {
+        let mut presets = EqPresets::default();
+        let name = presets.save_for_track(Path::new("/music/tape.mp3"), vec![1.5, -2.0]);
+        assert_eq!(name, "tape");
+
+        let serialized = toml::to_string_pretty(&presets).unwrap();
+        let loaded: EqPresets = toml::from_str(&serialized).unwrap();
+        assert_eq!(loaded, presets);
+    }
+}
@@ -0,0 +1,365 @@
+use anyhow::Result;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use crate::config::get_config_dir;
+use crate::player::{PlaybackState, PlayerCommand, PlayerSnapshot, RepeatMode};
+
+/// How long a client waits for the player to act on a command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a new connection has to send its token before it's dropped
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const GRAMMAR: &str = "\
+play [PATH]        resume, or play PATH (added to the playlist if needed)
+pause | resume | toggle | stop
+next | previous
+volume LEVEL       0.0 to 1.0
+seek SECONDS       jump to this position in the current track
+shuffle on|off
+repeat off|one|all
+status             just report the player state
+
+Put json in front of any command (json status) to get the reply as a JSON object.";
+
+/// One line sent by a remote client
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteRequest {
+    Command(PlayerCommand),
+    Status,
+}
+
+/// Parses a command line like `volume 0.3` or `seek 120`
+pub fn parse_request(line: &str) -> Result<RemoteRequest> {
+    let line = line.trim();
+    let (verb, argument) = match line.split_once(char::is_whitespace) {
+        Some((verb, argument)) => (verb, argument.trim()),
+        None => (line, ""),
+    };
+
+    let command = match (verb.to_ascii_lowercase().as_str(), argument) {
+        ("status", "") => return Ok(RemoteRequest::Status),
+        ("play", "") | ("resume", "") => PlayerCommand::Resume,
+        ("play", path) => PlayerCommand::Play(PathBuf::from(crate::utils::strip_quotes(path))),
+        ("pause", "") => PlayerCommand::Pause,
+        ("toggle", "") => PlayerCommand::TogglePlayPause,
+        ("stop", "") => PlayerCommand::Stop,
+        ("next", "") => PlayerCommand::Next,
+        ("previous", "") | ("prev", "") => PlayerCommand::Previous,
+        ("volume", level) => {
+            let level: f32 = level.parse().map_err(|_| anyhow::anyhow!("Volume must be a number from 0.0 to 1.0"))?;
+            PlayerCommand::SetVolume(level.clamp(0.0, 1.0))
+        }
+        ("seek", secs) => {
+            let secs: f64 = secs.parse().map_err(|_| anyhow::anyhow!("Seek position must be a number of seconds"))?;
+            PlayerCommand::Seek(Duration::from_secs_f64(secs.max(0.0)))
+        }
+        ("shuffle", "on") => PlayerCommand::SetShuffle(true),
+        ("shuffle", "off") => PlayerCommand::SetShuffle(false),
+        ("repeat", "off") => PlayerCommand::SetRepeat(RepeatMode::None),
+        ("repeat", "one") => PlayerCommand::SetRepeat(RepeatMode::One),
+        ("repeat", "all") => PlayerCommand::SetRepeat(RepeatMode::All),
+        _ => return Err(anyhow::anyhow!("Unknown command '{}'", line)),
+    };
+    Ok(RemoteRequest::Command(command))
+}
+
+/// The line sent back after every command, e.g. `playing 12.0/215.3 vol 0.50 /music/song.mp3`
+pub fn status_line(snapshot: &PlayerSnapshot) -> String {
+    let state = match snapshot.state {
+        PlaybackState::Stopped => "stopped",
+        PlaybackState::Playing => "playing",
+        PlaybackState::Paused => "paused",
+    };
+    let duration = snapshot.duration
+        .map(|d| format!("{:.1}", d.as_secs_f64()))
+        .unwrap_or_else(|| "-".to_string());
+    let path = snapshot.current_path
+        .as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "-".to_string());
+
+    format!("{} {:.1}/{} vol {:.2} {}", state, snapshot.position.as_secs_f64(), duration, snapshot.volume, path)
+}
+
+/// The same status as `status_line`, as a JSON object for scripts. Unknown values are `null`.
+pub fn status_json(snapshot: &PlayerSnapshot) -> String {
+    let state = match snapshot.state {
+        PlaybackState::Stopped => "stopped",
+        PlaybackState::Playing => "playing",
+        PlaybackState::Paused => "paused",
+    };
+    let repeat = match snapshot.repeat {
+        RepeatMode::None => "off",
+        RepeatMode::One => "one",
+        RepeatMode::All => "all",
+    };
+    let duration = snapshot.duration
+        .map(|d| format!("{:.1}", d.as_secs_f64()))
+        .unwrap_or_else(|| "null".to_string());
+    let path = snapshot.current_path
+        .as_ref()
+        .map(|p| json_string(&p.display().to_string()))
+        .unwrap_or_else(|| "null".to_string());
+
+    format!(
+        "{{\"ok\":true,\"state\":\"{}\",\"position\":{:.1},\"duration\":{},\"volume\":{:.2},\"shuffle\":{},\"repeat\":\"{}\",\"path\":{}}}",
+        state, snapshot.position.as_secs_f64(), duration, snapshot.volume, snapshot.shuffle, repeat, path
+    )
+}
+
+/// `s` as a quoted JSON string
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The line sent back for a failed command, in the format the client asked for
+fn error_reply(message: &str, json: bool) -> String {
+    if json {
+        format!("{{\"ok\":false,\"error\":{}}}", json_string(message))
+    } else {
+        format!("ERR {}", message)
+    }
+}
+
+/// Whether a reply line (plain or JSON) says the command failed
+pub fn is_error_reply(reply: &str) -> bool {
+    reply.starts_with("ERR") || reply.starts_with("{\"ok\":false")
+}
+
+/// A parsed request waiting for the player to act on it
+pub struct PendingRequest {
+    pub request: RemoteRequest,
+    /// The client put `json` in front of the command
+    json: bool,
+    reply: Sender<String>,
+}
+
+impl PendingRequest {
+    /// Sends the client `OK <status>`, or `ERR <reason>` if the command failed. JSON
+    /// requests get `status_json`, or `{"ok":false,"error":...}`.
+    pub fn reply(self, result: Result<()>, snapshot: Option<PlayerSnapshot>) {
+        let line = match (result, snapshot) {
+            (Err(e), _) => error_reply(&e.to_string(), self.json),
+            (Ok(()), Some(snapshot)) if self.json => status_json(&snapshot),
+            (Ok(()), Some(snapshot)) => format!("OK {}", status_line(&snapshot)),
+            (Ok(()), None) if self.json => "{\"ok\":true}".to_string(),
+            (Ok(()), None) => "OK".to_string(),
+        };
+        let _ = self.reply.send(line);
+    }
+}
+
+/// Where a running player keeps the token clients have to send first
+pub fn remote_token_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("remote_token"))
+}
+
+// Writes a new token that only the user can read, replacing the last run's
+fn write_token(path: &Path) -> Result<String> {
+    let token = format!("{:032x}", rand::random::<u128>());
+    // The mode only applies to new files, so an old one with looser permissions goes first
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// Accepts remote control connections on the loopback interface. Each connection starts
+/// with `auth TOKEN`, the token written to the token file when the server started, then
+/// sends one command per line and gets one status line back per command. Connections from
+/// other users (who can't read the file) or web pages (which can't send it) are turned away.
+pub struct RemoteServer {
+    requests: Receiver<PendingRequest>,
+    port: u16,
+    token_file: PathBuf,
+}
+
+impl RemoteServer {
+    /// Starts listening on `port`, or on any free port if it's 0 (see `port`), with a new
+    /// token in `token_file`
+    pub fn start(port: u16, token_file: &Path) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let port = listener.local_addr()?.port();
+        let token: Arc<str> = write_token(token_file)?.into();
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let tx = tx.clone();
+                        let token = token.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve_client(stream, tx, &token) {
+                                log::debug!("Remote control client error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => log::warn!("Remote control connection failed: {}", e),
+                }
+            }
+        });
+
+        Ok(Self { requests: rx, port, token_file: token_file.to_path_buf() })
+    }
+
+    /// The port clients connect to
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Requests that arrived since the last call. Call this regularly and reply to each.
+    pub fn poll(&self) -> Vec<PendingRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for RemoteServer {
+    // The token is no use once nothing is listening
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.token_file);
+    }
+}
+
+fn serve_client(stream: TcpStream, requests: Sender<PendingRequest>, token: &str) -> Result<()> {
+    // A client that connects and never says anything would hold its thread forever
+    stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut lines = BufReader::new(stream).lines();
+    let Some(first) = lines.next() else {
+        return Ok(());
+    };
+    if first?.trim().strip_prefix("auth ").map(str::trim) != Some(token) {
+        writeln!(writer, "ERR Not authorised")?;
+        return Ok(());
+    }
+    // Once in, a client may keep the connection open between commands
+    writer.set_read_timeout(None)?;
+
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line = line.trim();
+        let (json, command) = match line.split_once(char::is_whitespace) {
+            Some((prefix, command)) if prefix.eq_ignore_ascii_case("json") => (true, command),
+            _ if line.eq_ignore_ascii_case("json") => (true, "status"),
+            _ => (false, line),
+        };
+
+        let reply = match parse_request(command) {
+            Ok(request) => {
+                let (reply_tx, reply_rx) = channel();
+                requests.send(PendingRequest { request, json, reply: reply_tx })?;
+                reply_rx
+                    .recv_timeout(REPLY_TIMEOUT)
+                    .unwrap_or_else(|_| error_reply("The player did not respond", json))
+            }
+            Err(e) => error_reply(&e.to_string(), json),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+/// Sends one command to a running player, with the token from `token_file`, and returns
+/// its reply line
+pub fn send_command(port: u16, token_file: &Path, command: &str) -> Result<String> {
+    let token = fs::read_to_string(token_file)
+        .map_err(|e| anyhow::anyhow!("No running player with remote control turned on ({}: {})", token_file.display(), e))?;
+    let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+        .map_err(|e| anyhow::anyhow!("No running player found on port {}: {}", port, e))?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT * 2))?;
+
+    let mut writer = stream.try_clone()?;
+    writeln!(writer, "auth {}", token.trim())?;
+    writeln!(writer, "{}", command.trim())?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(parse_request("next").unwrap(), RemoteRequest::Command(PlayerCommand::Next));
+        assert_eq!(parse_request(" Volume 0.3 ").unwrap(), RemoteRequest::Command(PlayerCommand::SetVolume(0.3)));
+        assert_eq!(parse_request("seek 120").unwrap(), RemoteRequest::Command(PlayerCommand::Seek(Duration::from_secs(120))));
+        assert_eq!(parse_request("repeat all").unwrap(), RemoteRequest::Command(PlayerCommand::SetRepeat(RepeatMode::All)));
+        assert_eq!(
+            parse_request("play \"/music/my song.mp3\"").unwrap(),
+            RemoteRequest::Command(PlayerCommand::Play(PathBuf::from("/music/my song.mp3")))
+        );
+        assert_eq!(parse_request("status").unwrap(), RemoteRequest::Status);
+
+        assert!(parse_request("volume loud").is_err());
+        assert!(parse_request("dance").is_err());
+        assert!(parse_request("next please").is_err());
+    }
+
+    #[test]
+    fn test_status_line() {
+        let snapshot = PlayerSnapshot {
+            state: PlaybackState::Paused,
+            current_path: Some(PathBuf::from("song.mp3")),
+            position: Duration::from_secs(12),
+            duration: None,
+            volume: 0.5,
+            shuffle: false,
+            repeat: RepeatMode::None,
+        };
+        assert_eq!(status_line(&snapshot), "paused 12.0/- vol 0.50 song.mp3");
+    }
+
+    #[test]
+    fn test_status_json() {
+        let snapshot = PlayerSnapshot {
+            state: PlaybackState::Playing,
+            current_path: Some(PathBuf::from("/music/\"quoted\" song.mp3")),
+            position: Duration::from_secs(12),
+            duration: Some(Duration::from_millis(215_300)),
+            volume: 0.3,
+            shuffle: true,
+            repeat: RepeatMode::All,
+        };
+        assert_eq!(
+            status_json(&snapshot),
+            r#"{"ok":true,"state":"playing","position":12.0,"duration":215.3,"volume":0.30,"shuffle":true,"repeat":"all","path":"/music/\"quoted\" song.mp3"}"#
+        );
+
+        let stopped = PlayerSnapshot { state: PlaybackState::Stopped, current_path: None, duration: None, ..snapshot };
+        assert!(status_json(&stopped).ends_with(r#""duration":null,"volume":0.30,"shuffle":true,"repeat":"all","path":null}"#));
+        assert!(is_error_reply(&error_reply("Unknown command 'dance'", true)));
+        assert!(is_error_reply(&error_reply("Unknown command 'dance'", false)));
+    }
+}
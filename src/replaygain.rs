@@ -0,0 +1,122 @@
+use lofty::file::TaggedFileExt;
+use lofty::tag::ItemKey;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Which ReplayGain value to apply when playing a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    /// Level every track to the same loudness
+    Track,
+    /// Keep the loudness differences between tracks of the same album
+    Album,
+}
+
+/// ReplayGain values read from a file's tags. Gains are in dB, peaks are linear.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ReplayGainTags {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGainTags {
+    /// Linear volume factor for `mode`. Album mode falls back to the track values when
+    /// the file has no album gain, so a mixed queue still plays at sensible levels.
+    /// The factor is limited so the track's peak doesn't clip.
+    pub fn factor(&self, mode: ReplayGainMode) -> f32 {
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Album if self.album_gain.is_some() => (self.album_gain, self.album_peak),
+            _ => (self.track_gain, self.track_peak),
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let factor = 10f32.powf(gain / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+/// Parses tag values like "-6.48 dB" or "+1.2dB"
+pub fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value.strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value)
+        .trim();
+    number.trim_start_matches('+').parse().ok()
+}
+
+/// Reads the ReplayGain tags from a file. Files without tags (or that can't be read)
+/// just get no adjustment.
+pub fn read_replaygain(path: &Path) -> ReplayGainTags {
+    let tagged_file = match lofty::read_from_path(path) {
+        Ok(file) => file,
+        Err(e) => {
+            log::debug!("No ReplayGain for {}: {}", path.display(), e);
+            return ReplayGainTags::default();
+        }
+    };
+
+    let mut tags = ReplayGainTags::default();
+    for tag in tagged_file.tags() {
+        let read = |key: &ItemKey| tag.get_string(key).and_then(parse_gain);
+        tags.track_gain = tags.track_gain.or_else(|| read(&ItemKey::ReplayGainTrackGain));
+        tags.track_peak = tags.track_peak.or_else(|| read(&ItemKey::ReplayGainTrackPeak));
+        tags.album_gain = tags.album_gain.or_else(|| read(&ItemKey::ReplayGainAlbumGain));
+        tags.album_peak = tags.album_peak.or_else(|| read(&ItemKey::ReplayGainAlbumPeak));
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gain() {
+        assert_eq!(parse_gain("-6.48 dB"), Some(-6.48));
+        assert_eq!(parse_gain("+1.5dB"), Some(1.5));
+        assert_eq!(parse_gain("0.25"), Some(0.25));
+        assert_eq!(parse_gain("loud"), None);
+    }
+
+    #[test]
+    fn test_album_mode_falls_back_to_track_gain() {
+        let track_only = ReplayGainTags {
+            track_gain: Some(-20.0),
+            ..Default::default()
+        };
+        assert!((track_only.factor(ReplayGainMode::Album) - 0.1).abs() < 1e-4);
+
+        let both = ReplayGainTags {
+            track_gain: Some(-20.0),
+            album_gain: Some(0.0),
+            ..Default::default()
+        };
+        assert!((both.factor(ReplayGainMode::Album) - 1.0).abs() < 1e-4);
+        assert!((both.factor(ReplayGainMode::Track) - 0.1).abs() < 1e-4);
+        assert_eq!(both.factor(ReplayGainMode::Off), 1.0);
+    }
+
+    #[test]
+    fn test_peak_prevents_clipping() {
+        let tags = ReplayGainTags {
+            track_gain: Some(6.0),
+            track_peak: Some(0.8),
+            ..Default::default()
+        };
+        assert!((tags.factor(ReplayGainMode::Track) - 1.25).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::config::get_config_dir;
+
+/// Places this early in a track aren't worth keeping
+pub const RESUME_MIN: Duration = Duration::from_secs(10);
+/// A track stopped this close to its end counts as finished, and starts over next time
+pub const RESUME_END_MARGIN: Duration = Duration::from_secs(30);
+/// How often the playing track's place is saved, so a crash loses little
+pub const RESUME_SAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Where each track was left off, for picking long ones (podcasts, audiobooks) back up.
+/// Stored next to the config file in `resume_positions.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumePositions {
+    /// Track path to the position it was left at
+    pub tracks: HashMap<PathBuf, Duration>,
+}
+
+impl ResumePositions {
+    pub fn get(&self, path: &Path) -> Option<Duration> {
+        self.tracks.get(path).copied()
+    }
+
+    /// Keeps the place in `path`, or forgets it when the track is better started over (see
+    /// `position_to_remember`). Returns whether anything changed.
+    pub fn update(&mut self, path: &Path, position: Duration, length: Option<Duration>) -> bool {
+        match position_to_remember(position, length) {
+            Some(position) => self.tracks.insert(path.to_path_buf(), position) != Some(position),
+            None => self.tracks.remove(path).is_some(),
+        }
+    }
+
+    /// Forgets the place in one track, so it starts from the beginning
+    pub fn forget(&mut self, path: &Path) -> bool {
+        self.tracks.remove(path).is_some()
+    }
+}
+
+/// The place to keep for a track left at `position`, or `None` when it should start over:
+/// it had barely begun, or was close enough to the end to count as finished
+pub fn position_to_remember(position: Duration, length: Option<Duration>) -> Option<Duration> {
+    if position < RESUME_MIN {
+        return None;
+    }
+    if length.is_some_and(|length| position + RESUME_END_MARGIN >= length) {
+        return None;
+    }
+    Some(position)
+}
+
+/// Finds the track the last session ended on. If it's no longer where it was, a playlist
+/// entry with the same file name that does exist is taken to be it, moved elsewhere.
+pub fn find_last_track(last: &Path, playlist: &[PathBuf]) -> Option<PathBuf> {
+    if last.exists() {
+        return Some(last.to_path_buf());
+    }
+    let name = last.file_name()?;
+    playlist.iter().find(|path| path.file_name() == Some(name) && path.exists()).cloned()
+}
+
+fn get_resume_positions_file_path() -> Result<PathBuf> {
+    Ok(get_config_dir()?.join("resume_positions.toml"))
+}
+
+/// Loads the saved places, or none if no track has been left partway through
+pub fn load_resume_positions() -> Result<ResumePositions> {
+    let path = get_resume_positions_file_path()?;
+    if !path.exists() {
+        return Ok(ResumePositions::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+}
+
+pub fn save_resume_positions(positions: &ResumePositions) -> Result<()> {
+    let serialized = toml::to_string_pretty(positions)?;
+    fs::write(get_resume_positions_file_path()?, serialized)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_to_remember() {
+        let hour = Some(Duration::from_secs(3600));
+        assert_eq!(position_to_remember(Duration::from_secs(600), hour), Some(Duration::from_secs(600)));
+        // Barely started, or all but finished
+        assert_eq!(position_to_remember(Duration::from_secs(5), hour), None);
+        assert_eq!(position_to_remember(Duration::from_secs(3580), hour), None);
+        // Length not known yet
+        assert_eq!(position_to_remember(Duration::from_secs(600), None), Some(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn test_update_forgets_finished_tracks() {
+        let mut positions = ResumePositions::default();
+        let path = Path::new("/podcasts/episode.mp3");
+        let length = Some(Duration::from_secs(1800));
+
+        assert!(positions.update(path, Duration::from_secs(300), length));
+        assert!(!positions.update(path, Duration::from_secs(300), length));
+        assert_eq!(positions.get(path), Some(Duration::from_secs(300)));
+
+        assert!(positions.update(path, Duration::from_secs(1790), length));
+        assert_eq!(positions.get(path), None);
+        assert!(!positions.forget(path));
+    }
+
+    #[test]
+    fn test_find_last_track_follows_moved_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let moved = dir.path().join("song.mp3");
+        std::fs::write(&moved, b"").unwrap();
+        let playlist = vec![dir.path().join("other.mp3"), moved.clone()];
+
+        assert_eq!(find_last_track(&moved, &[]), Some(moved.clone()));
+        assert_eq!(find_last_track(Path::new("/old/place/song.mp3"), &playlist), Some(moved));
+        assert_eq!(find_last_track(Path::new("/old/place/gone.mp3"), &playlist), None);
+    }
+
+    #[test]
+    fn test_positions_survive_toml() {
+        let mut positions = ResumePositions::default();
+        positions.update(Path::new("/books/chapter 1.m4a"), Duration::from_millis(754_250), None);
+        let serialized = toml::to_string_pretty(&positions).unwrap();
+        assert_eq!(toml::from_str::<ResumePositions>(&serialized).unwrap(), positions);
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Volume fades out over this long before a timed sleep goes off
+pub const SLEEP_FADE: Duration = Duration::from_secs(30);
+
+/// What happens when the sleep timer goes off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SleepAction {
+    /// Pause, so Play picks up exactly where you drifted off
+    #[default]
+    Pause,
+    /// Stop playback
+    Stop,
+    /// Close the player
+    Quit,
+}
+
+/// When the sleep timer goes off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepTimer {
+    At(Instant),
+    /// When the current track finishes
+    EndOfTrack,
+}
+
+impl SleepTimer {
+    pub fn after(duration: Duration) -> Self {
+        SleepTimer::At(Instant::now() + duration)
+    }
+
+    /// Time left on a timed sleep, `None` for end of track
+    pub fn remaining(&self) -> Option<Duration> {
+        match self {
+            SleepTimer::At(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+            SleepTimer::EndOfTrack => None,
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Volume multiplier right now, easing down to silence as the timer runs out
+    pub fn fade(&self) -> f32 {
+        self.remaining().map(fade_factor).unwrap_or(1.0)
+    }
+}
+
+/// Volume multiplier with `remaining` left on the timer: full volume until the last
+/// `SLEEP_FADE`, then a linear fade to silence
+pub fn fade_factor(remaining: Duration) -> f32 {
+    if remaining >= SLEEP_FADE {
+        1.0
+    } else {
+        remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_factor() {
+        assert_eq!(fade_factor(Duration::from_secs(600)), 1.0);
+        assert_eq!(fade_factor(SLEEP_FADE), 1.0);
+        assert_eq!(fade_factor(SLEEP_FADE / 2), 0.5);
+        assert_eq!(fade_factor(Duration::ZERO), 0.0);
+        assert_eq!(SleepTimer::EndOfTrack.fade(), 1.0);
+    }
+}
@@ -0,0 +1,108 @@
+/// How bad an ongoing condition is. Later variants are worse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    #[allow(dead_code)]
+    Info,
+    Warning,
+    Error,
+}
+
+/// The kinds of lasting problems the status bar can report. Each kind is shown at most once,
+/// raising it again just updates the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConditionKind {
+    AudioOutput,
+    ConfigSave,
+    DecodeFailures,
+    WatchFolder,
+}
+
+impl ConditionKind {
+    /// Whether the front-end knows how to retry whatever caused this
+    pub fn retryable(self) -> bool {
+        matches!(self, ConditionKind::AudioOutput | ConditionKind::ConfigSave | ConditionKind::WatchFolder)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub kind: ConditionKind,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// The set of ongoing problems, as opposed to one-off notifications.
+/// Conditions stay until the code that raised them clears them or the user dismisses them.
+#[derive(Debug, Default)]
+pub struct StatusConditions {
+    conditions: Vec<Condition>,
+}
+
+impl StatusConditions {
+    /// Adds a condition, or replaces the existing one of the same kind
+    pub fn raise(&mut self, kind: ConditionKind, severity: Severity, message: impl Into<String>) {
+        // Re-raised conditions count as the newest
+        self.clear(kind);
+        self.conditions.push(Condition {
+            kind,
+            severity,
+            message: message.into(),
+        });
+    }
+
+    /// Removes a condition once the problem has gone away (or the user dismissed it)
+    pub fn clear(&mut self, kind: ConditionKind) {
+        self.conditions.retain(|c| c.kind != kind);
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, kind: ConditionKind) -> Option<&Condition> {
+        self.conditions.iter().find(|c| c.kind == kind)
+    }
+
+    /// The condition to show: the most severe one, and the most recent among equals
+    pub fn worst(&self) -> Option<&Condition> {
+        // max_by_key picks the last of equal elements, which is the newest
+        self.conditions.iter().max_by_key(|c| c.severity)
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.conditions.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worst_condition_is_shown() {
+        let mut status = StatusConditions::default();
+        assert!(status.worst().is_none());
+
+        status.raise(ConditionKind::DecodeFailures, Severity::Warning, "1 file failed to decode");
+        status.raise(ConditionKind::ConfigSave, Severity::Error, "Could not save settings");
+        status.raise(ConditionKind::WatchFolder, Severity::Warning, "Watch folder missing");
+        assert_eq!(status.worst().map(|c| c.kind), Some(ConditionKind::ConfigSave));
+
+        status.clear(ConditionKind::ConfigSave);
+        // Most recent of the remaining warnings
+        assert_eq!(status.worst().map(|c| c.kind), Some(ConditionKind::WatchFolder));
+    }
+
+    #[test]
+    fn test_raising_again_replaces_message() {
+        let mut status = StatusConditions::default();
+        status.raise(ConditionKind::DecodeFailures, Severity::Warning, "1 file failed to decode");
+        status.raise(ConditionKind::DecodeFailures, Severity::Warning, "2 files failed to decode");
+
+        assert_eq!(status.len(), 1);
+        assert_eq!(status.get(ConditionKind::DecodeFailures).map(|c| c.message.as_str()), Some("2 files failed to decode"));
+    }
+}
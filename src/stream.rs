@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use rodio::Decoder;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::decode::DecodeFailure;
+
+/// How long to wait for a server to answer
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// A station that sends nothing for this long has dropped the connection
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Downloaded data kept behind the read position. The decoders look back over the start of
+/// the stream while they work out its format, and never further than this.
+const KEEP_BEHIND: usize = 1 << 20;
+/// Most read from the connection at once
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// The URL a playlist entry stands for, if it's an HTTP or HTTPS stream rather than a file.
+/// Streams are kept in the playlist as paths holding the URL, like cue sheet tracks.
+pub fn stream_url(path: &Path) -> Option<&str> {
+    let url = path.to_str()?;
+    let scheme = url.split_once("://")?.0;
+    (scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")).then_some(url)
+}
+
+pub fn is_stream(path: &Path) -> bool {
+    stream_url(path).is_some()
+}
+
+/// Connects to `url` and opens a decoder on what it sends. The download carries on in the
+/// background; the decoder waits for data when it catches up. Connection problems come back
+/// as errors naming the URL, a format rodio can't play as a `DecodeFailure`.
+pub fn open_stream(url: &str) -> Result<Decoder<StreamReader>> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let response = agent.get(url).call().with_context(|| format!("Couldn't connect to {}", url))?;
+
+    let reader = StreamReader::start(response.into_reader());
+    Decoder::new(reader).map_err(|e| {
+        DecodeFailure::Unsupported(format!("{} doesn't send audio in a format this build can play ({})", url, e)).into()
+    })
+}
+
+// What the download thread has fetched so far, shared with the reader
+#[derive(Default)]
+struct Downloaded {
+    data: Vec<u8>,
+    /// Stream offset of `data[0]`, once earlier data has been let go
+    start: u64,
+    /// The connection ended or failed, so no more data is coming
+    finished: bool,
+    /// The reader was dropped, so the download should stop
+    closed: bool,
+}
+
+/// A stream being downloaded in the background, readable (and seekable a little way back)
+/// as if it were a file
+pub struct StreamReader {
+    shared: Arc<(Mutex<Downloaded>, Condvar)>,
+    position: u64,
+}
+
+impl StreamReader {
+    fn start(mut body: impl Read + Send + 'static) -> Self {
+        let shared = Arc::new((Mutex::new(Downloaded::default()), Condvar::new()));
+
+        let download = shared.clone();
+        thread::spawn(move || {
+            let (downloaded, arrived) = &*download;
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+            loop {
+                let read = body.read(&mut chunk);
+                let Ok(mut downloaded) = downloaded.lock() else {
+                    return;
+                };
+                match read {
+                    _ if downloaded.closed => return,
+                    Ok(0) => downloaded.finished = true,
+                    Ok(n) => downloaded.data.extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        log::warn!("Stream stopped: {}", e);
+                        downloaded.finished = true;
+                    }
+                }
+                let finished = downloaded.finished;
+                drop(downloaded);
+                arrived.notify_all();
+                if finished {
+                    return;
+                }
+            }
+        });
+
+        Self { shared, position: 0 }
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (downloaded, arrived) = &*self.shared;
+        let mut downloaded = downloaded.lock().map_err(|_| io::Error::other("Stream buffer poisoned"))?;
+
+        // Wait for the download to catch up
+        while self.position >= downloaded.start + downloaded.data.len() as u64 && !downloaded.finished {
+            downloaded = arrived.wait(downloaded).map_err(|_| io::Error::other("Stream buffer poisoned"))?;
+        }
+
+        // Past the end only once the download has finished, where there's nothing left to read
+        let offset = ((self.position - downloaded.start) as usize).min(downloaded.data.len());
+        let available = &downloaded.data[offset..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n as u64;
+
+        // Let go of what's well behind, so a station playing all day doesn't fill memory
+        let behind = (self.position - downloaded.start) as usize;
+        if behind > 2 * KEEP_BEHIND {
+            let excess = behind - KEEP_BEHIND;
+            downloaded.data.drain(..excess);
+            downloaded.start += excess as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            // A live stream has no end to count back from
+            SeekFrom::End(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "Streams have no end to seek from")),
+        };
+        let start = self.shared.0.lock().map(|downloaded| downloaded.start).unwrap_or(0);
+        match target {
+            Some(target) if target >= start => {
+                self.position = target;
+                Ok(target)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "That part of the stream has been let go")),
+        }
+    }
+}
+
+impl Drop for StreamReader {
+    fn drop(&mut self) {
+        if let Ok(mut downloaded) = self.shared.0.lock() {
+            downloaded.closed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_urls_are_recognised() {
+        assert_eq!(stream_url(Path::new("http://radio.example:8000/live")), Some("http://radio.example:8000/live"));
+        assert!(is_stream(Path::new("HTTPS://radio.example/stream.mp3")));
+        assert!(!is_stream(Path::new("/music/http_song.mp3")));
+        assert!(!is_stream(Path::new("ftp://files.example/song.mp3")));
+    }
+
+    #[test]
+    fn test_reader_reads_and_seeks_back_through_the_download() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut reader = StreamReader::start(io::Cursor::new(data.clone()));
+
+        let mut head = [0u8; 1000];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(&head[..], &data[..1000]);
+
+        // The decoders probe the start of the stream, then go back to it
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+        assert!(reader.seek(SeekFrom::End(0)).is_err());
+    }
+}
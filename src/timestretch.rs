@@ -0,0 +1,291 @@
+use rodio::{Source, source::SeekError};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Length of each analysis grain
+const GRAIN_MS: usize = 40;
+/// How far (either side) we search for the best-matching grain
+const TOLERANCE_MS: usize = 10;
+/// While passing audio through untouched, re-check the ratio every this many frames
+const PASSTHROUGH_CHECK_FRAMES: usize = 1024;
+
+/// Pitch-preserving tempo change using WSOLA (waveform-similarity overlap-add).
+///
+/// The source is cut into 40ms Hann-windowed grains that are overlap-added at a fixed
+/// hop, while the read position advances by `hop * ratio`. Each grain is nudged by up
+/// to ±10ms to line up with the previous one, which avoids the phasey sound of plain OLA.
+///
+/// Cost: while a ratio other than 1.0 is active this does a cross-correlation search
+/// for every grain (roughly 5M multiply-adds per second at 44.1kHz) and adds one grain
+/// (40ms) of latency. At a ratio of exactly 1.0 samples are passed straight through and
+/// the adapter costs nothing. Once stretching has started on a track it stays engaged
+/// until the track ends, even if the ratio goes back to 1.0.
+pub struct TimeStretch<S> {
+    inner: S,
+    settings: Arc<Mutex<f32>>,
+    channels: usize,
+    ratio: f32,
+    stretching: bool,
+    passthrough_countdown: usize,
+    window: Vec<f32>,
+    grain_frames: usize,
+    hop_frames: usize,
+    tolerance_frames: usize,
+    input: Vec<f32>,
+    analysis_pos: f64,
+    prev_natural: Option<usize>,
+    overlap: Vec<f32>,
+    output: VecDeque<f32>,
+    inner_done: bool,
+    finished: bool,
+}
+
+impl<S> TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    /// Wraps `inner`, reading the tempo ratio from `settings` (1.0 = unchanged, 2.0 = twice as fast)
+    pub fn new(inner: S, settings: Arc<Mutex<f32>>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate().max(1) as usize;
+
+        // Even grain length so the two halves line up exactly
+        let grain_frames = ((sample_rate * GRAIN_MS / 1000).max(64) / 2) * 2;
+        let hop_frames = grain_frames / 2;
+        let tolerance_frames = sample_rate * TOLERANCE_MS / 1000;
+
+        // Periodic Hann window: overlapping halves sum to exactly 1.0
+        let window = (0..grain_frames)
+            .map(|n| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / grain_frames as f32).cos())
+            .collect();
+
+        Self {
+            inner,
+            settings,
+            channels,
+            ratio: 1.0,
+            stretching: false,
+            passthrough_countdown: 0,
+            window,
+            grain_frames,
+            hop_frames,
+            tolerance_frames,
+            input: Vec::new(),
+            analysis_pos: 0.0,
+            prev_natural: None,
+            overlap: vec![0.0; hop_frames * channels],
+            output: VecDeque::new(),
+            inner_done: false,
+            finished: false,
+        }
+    }
+
+    fn read_ratio(&self) -> f32 {
+        if let Ok(ratio) = self.settings.lock() {
+            *ratio
+        } else {
+            1.0
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    // Pull from the inner source until `frames` whole frames are buffered or it runs dry
+    fn fill_to(&mut self, frames: usize) {
+        while !self.inner_done && self.frames() < frames {
+            for _ in 0..self.channels {
+                match self.inner.next() {
+                    Some(sample) => self.input.push(sample),
+                    None => {
+                        self.inner_done = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Drop a trailing partial frame so channels stay aligned
+        let whole = self.frames() * self.channels;
+        self.input.truncate(whole);
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        let start = frame * self.channels;
+        self.input[start..start + self.channels].iter().sum::<f32>() / self.channels as f32
+    }
+
+    // Emit one hop worth of output from the next grain
+    fn process_grain(&mut self) {
+        let nominal = self.analysis_pos as usize;
+        self.fill_to(nominal + self.tolerance_frames + self.grain_frames);
+        let available = self.frames();
+
+        if nominal >= available {
+            // Nothing left to read, flush the tail of the last grain
+            self.output.extend(self.overlap.drain(..));
+            self.finished = true;
+            return;
+        }
+
+        // Find the grain that best continues the previous one
+        let mut chosen = nominal;
+        if let Some(natural) = self.prev_natural {
+            let lo = nominal.saturating_sub(self.tolerance_frames);
+            let hi = (nominal + self.tolerance_frames).min(available.saturating_sub(self.grain_frames));
+            if natural + self.hop_frames <= available && lo <= hi {
+                let mut best = f32::MIN;
+                for candidate in (lo..=hi).step_by(2) {
+                    let mut correlation = 0.0;
+                    for k in (0..self.hop_frames).step_by(4) {
+                        correlation += self.mono(natural + k) * self.mono(candidate + k);
+                    }
+                    if correlation > best {
+                        best = correlation;
+                        chosen = candidate;
+                    }
+                }
+            }
+        }
+
+        // Window the grain: first half overlaps the previous grain, second half is kept for the next
+        for i in 0..self.grain_frames {
+            let frame = chosen + i;
+            for c in 0..self.channels {
+                let sample = if frame < available {
+                    self.input[frame * self.channels + c]
+                } else {
+                    0.0
+                };
+                let windowed = sample * self.window[i];
+
+                if i < self.hop_frames {
+                    self.output.push_back(self.overlap[i * self.channels + c] + windowed);
+                } else {
+                    self.overlap[(i - self.hop_frames) * self.channels + c] = windowed;
+                }
+            }
+        }
+
+        self.prev_natural = Some(chosen + self.hop_frames);
+        self.analysis_pos += self.hop_frames as f64 * self.ratio as f64;
+
+        // Forget input we can no longer reach so the buffer doesn't grow forever
+        let next_lowest = (self.analysis_pos as usize).saturating_sub(self.tolerance_frames);
+        let keep_from = next_lowest.min(chosen + self.hop_frames);
+        if keep_from > self.grain_frames * 4 {
+            self.input.drain(..keep_from * self.channels);
+            self.analysis_pos -= keep_from as f64;
+            self.prev_natural = Some(chosen + self.hop_frames - keep_from);
+        }
+    }
+}
+
+impl<S> Iterator for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(sample);
+            }
+            if self.finished {
+                return None;
+            }
+
+            if !self.stretching {
+                // Only look at the ratio on frame boundaries
+                if self.passthrough_countdown == 0 {
+                    self.ratio = self.read_ratio();
+                    self.passthrough_countdown = PASSTHROUGH_CHECK_FRAMES * self.channels;
+                    if (self.ratio - 1.0).abs() > f32::EPSILON {
+                        self.stretching = true;
+                        continue;
+                    }
+                }
+                self.passthrough_countdown -= 1;
+                return self.inner.next();
+            }
+
+            self.ratio = self.read_ratio();
+            self.process_grain();
+        }
+    }
+}
+
+impl<S> Source for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // Output length doesn't line up with the inner source's frames
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        // Reported in song time, like the rest of the player's position tracking
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+
+        // Throw away everything buffered from the old position
+        self.input.clear();
+        self.output.clear();
+        self.overlap = vec![0.0; self.hop_frames * self.channels];
+        self.analysis_pos = 0.0;
+        self.prev_natural = None;
+        self.passthrough_countdown = 0;
+        self.inner_done = false;
+        self.finished = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(frames: usize) -> SamplesBuffer<f32> {
+        let data: Vec<f32> = (0..frames)
+            .flat_map(|n| {
+                let s = (n as f32 * 440.0 * 2.0 * std::f32::consts::PI / 44100.0).sin();
+                [s, s]
+            })
+            .collect();
+        SamplesBuffer::new(2, 44100, data)
+    }
+
+    #[test]
+    fn test_unity_ratio_passes_samples_through() {
+        let expected: Vec<f32> = sine(10_000).collect();
+        let stretched: Vec<f32> = TimeStretch::new(sine(10_000), Arc::new(Mutex::new(1.0))).collect();
+        assert_eq!(stretched, expected);
+    }
+
+    #[test]
+    fn test_double_speed_halves_length() {
+        let frames = 44100;
+        let stretched: Vec<f32> = TimeStretch::new(sine(frames), Arc::new(Mutex::new(2.0))).collect();
+
+        // Channels stay interleaved and the output is about half as long (within a couple of grains)
+        assert_eq!(stretched.len() % 2, 0);
+        let out_frames = stretched.len() / 2;
+        assert!((out_frames as i64 - (frames / 2) as i64).abs() < 4000, "got {} frames", out_frames);
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use std::sync::mpsc::{channel, Receiver};
+use tray_icon::menu::{Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+
+/// Size of the generated tray icon, in pixels
+const ICON_SIZE: u32 = 32;
+
+/// Something picked from the tray icon or its menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    TogglePlayPause,
+    Next,
+    /// Bring the window back up and focus it
+    ShowWindow,
+    Quit,
+}
+
+/// An icon in the system tray with a small playback menu
+pub struct Tray {
+    // Keeps the icon up. `None` on Linux, where it lives on the GTK thread instead.
+    icon: Option<TrayIcon>,
+    actions: Receiver<TrayAction>,
+    tooltip: Option<String>,
+}
+
+struct MenuIds {
+    play_pause: MenuId,
+    next: MenuId,
+    show: MenuId,
+    quit: MenuId,
+}
+
+impl Tray {
+    /// Puts the icon in the tray. `ctx` is woken up whenever there's an action to pick up,
+    /// so the tray works while the window is minimized.
+    pub fn start(ctx: eframe::egui::Context) -> Result<Self> {
+        let (tx, rx) = channel();
+
+        let tray_tx = tx.clone();
+        let tray_ctx = ctx.clone();
+        TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                let _ = tray_tx.send(TrayAction::ShowWindow);
+                tray_ctx.request_repaint();
+            }
+        }));
+
+        let icon = spawn_icon(move |ids| {
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+                if let Some(action) = ids.action_for(&event.id) {
+                    let _ = tx.send(action);
+                    ctx.request_repaint();
+                }
+            }));
+        })?;
+
+        Ok(Self {
+            icon,
+            actions: rx,
+            tooltip: None,
+        })
+    }
+
+    /// Everything picked from the tray since the last call. Call this regularly.
+    pub fn poll(&self) -> Vec<TrayAction> {
+        self.actions.try_iter().collect()
+    }
+
+    /// Shows the current track when hovering the icon. Only touches the icon when the text
+    /// changes. Linux trays don't show tooltips.
+    pub fn set_tooltip(&mut self, text: &str) {
+        if self.tooltip.as_deref() == Some(text) {
+            return;
+        }
+        if let Some(icon) = &self.icon {
+            if let Err(e) = icon.set_tooltip(Some(text)) {
+                log::debug!("Could not update tray tooltip: {}", e);
+            }
+        }
+        self.tooltip = Some(text.to_string());
+    }
+}
+
+impl MenuIds {
+    fn action_for(&self, id: &MenuId) -> Option<TrayAction> {
+        if *id == self.play_pause {
+            Some(TrayAction::TogglePlayPause)
+        } else if *id == self.next {
+            Some(TrayAction::Next)
+        } else if *id == self.show {
+            Some(TrayAction::ShowWindow)
+        } else if *id == self.quit {
+            Some(TrayAction::Quit)
+        } else {
+            None
+        }
+    }
+}
+
+/// Builds the icon and its menu, handing the menu's ids to `on_menu` to listen for
+fn create_icon(on_menu: impl FnOnce(MenuIds)) -> Result<TrayIcon> {
+    let menu = Menu::new();
+    let play_pause = MenuItem::new("Play/Pause", true, None);
+    let next = MenuItem::new("Next", true, None);
+    let show = MenuItem::new("Show Window", true, None);
+    let quit = MenuItem::new("Quit", true, None);
+    menu.append_items(&[&play_pause, &next, &PredefinedMenuItem::separator(), &show, &quit])?;
+
+    on_menu(MenuIds {
+        play_pause: play_pause.id().clone(),
+        next: next.id().clone(),
+        show: show.id().clone(),
+        quit: quit.id().clone(),
+    });
+
+    Ok(TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("Music Player")
+        .with_icon(icon()?)
+        .build()?)
+}
+
+// Linux tray icons need a GTK main loop, which eframe doesn't run, so the icon and its
+// menu are made on a GTK thread of their own and stay there
+#[cfg(target_os = "linux")]
+fn spawn_icon(on_menu: impl FnOnce(MenuIds) + Send + 'static) -> Result<Option<TrayIcon>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        if let Err(e) = gtk::init() {
+            let _ = tx.send(Err(anyhow::anyhow!("Could not start GTK: {}", e)));
+            return;
+        }
+        match create_icon(on_menu) {
+            Ok(_icon) => {
+                let _ = tx.send(Ok(()));
+                gtk::main();
+            }
+            Err(e) => {
+                let _ = tx.send(Err(e));
+            }
+        }
+    });
+    rx.recv()??;
+    Ok(None)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn spawn_icon(on_menu: impl FnOnce(MenuIds) + Send + 'static) -> Result<Option<TrayIcon>> {
+    Ok(Some(create_icon(on_menu)?))
+}
+
+/// A play symbol on a round badge, drawn here so there's no image file to ship
+fn icon() -> Result<Icon> {
+    let size = ICON_SIZE as f32;
+    let centre = size / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let in_circle = (px - centre).hypot(py - centre) <= centre - 1.0;
+            // Triangle pointing right, between 37% and 72% across
+            let left = size * 0.37;
+            let half_height = (size * 0.72 - px).max(0.0) * 0.58;
+            let in_triangle = px >= left && (py - centre).abs() <= half_height;
+
+            let pixel = match (in_circle, in_triangle) {
+                (true, true) => [255, 255, 255, 255],
+                (true, false) => [52, 120, 200, 255],
+                (false, _) => [0, 0, 0, 0],
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+
+    Ok(Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE)?)
+}
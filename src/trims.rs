@@ -0,0 +1,101 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Where a track should start and stop playing, in seconds from the start of the file.
+/// `None` means the file's own start or end.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Trim {
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl Trim {
+    pub fn start(&self) -> Option<Duration> {
+        self.start.map(Duration::from_secs_f64)
+    }
+
+    pub fn end(&self) -> Option<Duration> {
+        self.end.map(Duration::from_secs_f64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+}
+
+/// Trim points for the playlist entries that have them. They belong to the playlist and are
+/// saved with it (see `save_m3u` and `save_pls`).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TrackTrims {
+    /// Track path to its trim
+    pub tracks: BTreeMap<String, Trim>,
+}
+
+impl TrackTrims {
+    pub fn get(&self, path: &Path) -> Trim {
+        self.tracks.get(path.to_string_lossy().as_ref()).copied().unwrap_or_default()
+    }
+
+    /// Starts the track at `start` from now on. Fails if that's at or after its end point.
+    pub fn set_start(&mut self, path: &Path, start: Duration) -> Result<()> {
+        let mut trim = self.get(path);
+        if trim.end().is_some_and(|end| start >= end) {
+            return Err(anyhow::anyhow!("The start has to come before the end"));
+        }
+        trim.start = Some(start.as_secs_f64());
+        self.set(path, trim);
+        Ok(())
+    }
+
+    /// Stops the track at `end` from now on. Fails if that's at or before its start point.
+    pub fn set_end(&mut self, path: &Path, end: Duration) -> Result<()> {
+        let mut trim = self.get(path);
+        if trim.start().is_some_and(|start| end <= start) {
+            return Err(anyhow::anyhow!("The end has to come after the start"));
+        }
+        trim.end = Some(end.as_secs_f64());
+        self.set(path, trim);
+        Ok(())
+    }
+
+    /// Plays the whole track again
+    pub fn clear(&mut self, path: &Path) {
+        self.tracks.remove(path.to_string_lossy().as_ref());
+    }
+
+    /// Replaces the track's trim, as read back from a playlist. An empty trim clears it.
+    pub fn set(&mut self, path: &Path, trim: Trim) {
+        let key = path.to_string_lossy().to_string();
+        if trim.is_empty() {
+            self.tracks.remove(&key);
+        } else {
+            self.tracks.insert(key, trim);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_points_must_stay_in_order() {
+        let mut trims = TrackTrims::default();
+        let track = Path::new("/music/live.mp3");
+
+        trims.set_start(track, Duration::from_secs(40)).unwrap();
+        assert!(trims.set_end(track, Duration::from_secs(30)).is_err());
+        trims.set_end(track, Duration::from_secs(200)).unwrap();
+        assert!(trims.set_start(track, Duration::from_secs(250)).is_err());
+
+        let trim = trims.get(track);
+        assert_eq!(trim.start(), Some(Duration::from_secs(40)));
+        assert_eq!(trim.end(), Some(Duration::from_secs(200)));
+
+        trims.clear(track);
+        assert!(trims.get(track).is_empty());
+        assert!(trims.tracks.is_empty());
+    }
+}
@@ -1,23 +1,35 @@
-use std::path::Path;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::path::{Path, PathBuf};
 use std::collections::HashSet;
+use std::sync::Mutex;
 use lazy_static::lazy_static;
+use glob::glob;
+use walkdir::WalkDir;
+use crate::cache::LruCache;
+
+/// Supported audio file extensions, sorted so file dialogs list them the same way every run.
+/// Only formats rodio has a decoder for with the features enabled in Cargo.toml: Opus and WMA
+/// would be listed and then fail to play.
+const AUDIO_EXTENSIONS: [&str; 6] = ["aac", "flac", "m4a", "mp3", "ogg", "wav"];
 
 lazy_static! {
-    /// A set of supported audio file extensions
-    static ref SUPPORTED_AUDIO_EXTENSIONS: HashSet<&'static str> = {
-        let mut extensions = HashSet::new();
-        extensions.insert("mp3");
-        extensions.insert("wav");
-        extensions.insert("ogg");
-        extensions.insert("flac");
-        extensions.insert("aac");
-        extensions.insert("m4a");
-        extensions.insert("opus");
-        extensions.insert("wma");
-        extensions
-    };
+    /// The supported extensions again, for quick lookups
+    static ref SUPPORTED_AUDIO_EXTENSIONS: HashSet<&'static str> = AUDIO_EXTENSIONS.into_iter().collect();
+    
+    /// Cover image found for each directory (None if the directory has no cover)
+    static ref FOLDER_COVER_CACHE: Mutex<LruCache<PathBuf, Option<PathBuf>>> = Mutex::new(LruCache::new(DEFAULT_CACHE_CAPACITY));
 }
 
+/// Default number of entries kept in each per-path cache
+pub const DEFAULT_CACHE_CAPACITY: usize = 2000;
+
+/// Sidecar cover image names, in order of preference
+const COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "albumart"];
+/// Image extensions we look for next to the audio files
+const COVER_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "bmp"];
+
 /// Check if a file is an audio file based on its extension
 pub fn is_audio_file<P: AsRef<Path>>(path: P) -> bool {
     let path = path.as_ref();
@@ -31,7 +43,251 @@ pub fn is_audio_file<P: AsRef<Path>>(path: P) -> bool {
     false
 }
 
-/// Get a slice of supported audio extensions for file dialogs
+/// Checks the first bytes of a file for an audio format's signature (ID3, RIFF/WAVE, OggS,
+/// fLaC...), to catch files with an audio extension that hold something else
+pub fn is_probably_audio(path: &Path) -> bool {
+    crate::decode::sniff(path).is_some()
+}
+
+/// Splits playlist entries into those still on disk and those that have gone missing,
+/// keeping their order. A cue sheet track counts as there while its sheet and the audio
+/// file it points into both are. Streams aren't on disk, so they're always kept.
+pub fn partition_existing(paths: &[PathBuf]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    paths.iter().cloned().partition(|path| {
+        path.exists()
+            || crate::stream::is_stream(path)
+            || crate::playlist::cue_track_for(path).is_some_and(|track| track.file.exists())
+    })
+}
+
+/// Opens the system's file manager on the folder holding `path`, with the file selected
+/// where the platform can do that (Windows and macOS)
+pub fn show_in_file_manager(path: &Path) -> std::io::Result<()> {
+    if cfg!(windows) {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(path);
+        spawn_detached(std::process::Command::new("explorer").arg(select))
+    } else if cfg!(target_os = "macos") {
+        spawn_detached(std::process::Command::new("open").arg("-R").arg(path))
+    } else {
+        open_folder(path.parent().filter(|folder| !folder.as_os_str().is_empty()).unwrap_or(Path::new(".")))
+    }
+}
+
+/// Opens `folder` in the system's file manager
+pub fn open_folder(folder: &Path) -> std::io::Result<()> {
+    let program = if cfg!(windows) {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    spawn_detached(std::process::Command::new(program).arg(folder))
+}
+
+// Starts a program without waiting for it, collecting its exit status in the background
+// so it doesn't linger as a zombie process
+fn spawn_detached(command: &mut std::process::Command) -> std::io::Result<()> {
+    let mut child = command.spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// Some launchers hand over a path with its quotes still attached
+pub(crate) fn strip_quotes(arg: &str) -> &str {
+    for quote in ['"', '\''] {
+        if arg.len() >= 2 && arg.starts_with(quote) && arg.ends_with(quote) {
+            return &arg[1..arg.len() - 1];
+        }
+    }
+    arg
+}
+
+/// Turns command-line arguments into audio file paths. An argument naming an existing
+/// file is always taken literally. Otherwise it's expanded as a glob pattern, unless
+/// `use_glob` is false, in which case every argument is a literal path (for names
+/// containing `[`, `?` or `*`).
+pub fn expand_paths(patterns: Vec<String>, use_glob: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    
+    for pattern in patterns {
+        // Check if it's a direct file path, with or without stray quotes
+        let literal = [pattern.as_str(), strip_quotes(&pattern)]
+            .into_iter()
+            .map(PathBuf::from)
+            .find(|path| path.is_file());
+        
+        if let Some(path) = literal {
+            if is_audio_file(&path) {
+                files.push(path);
+            } else {
+                eprintln!("Skipping non-audio file: {}", pattern);
+            }
+            continue;
+        }
+        
+        if !use_glob {
+            eprintln!("File not found: {}", pattern);
+            continue;
+        }
+        
+        // Try as a glob pattern
+        match glob(&pattern) {
+            Ok(entries) => {
+                let mut matched = false;
+                let mut audio_matched = false;
+                
+                for path in entries.flatten() {
+                    if path.is_file() {
+                        matched = true;
+                        if is_audio_file(&path) {
+                            audio_matched = true;
+                            files.push(path);
+                        } // Silently skip non-audio files from globs
+                    }
+                }
+                
+                if !matched {
+                    eprintln!("No files matched pattern: {}", pattern);
+                } else if !audio_matched {
+                    eprintln!("Pattern '{}' matched files, but none were audio files", pattern);
+                }
+            },
+            Err(_) => {
+                eprintln!("Invalid pattern: {}", pattern);
+            }
+        }
+    }
+    
+    if files.is_empty() {
+        eprintln!("No audio files found in the provided patterns");
+    } else {
+        println!("Found {} audio files", files.len());
+    }
+    
+    files
+}
+
+/// Every audio file in `dir`, and in its subfolders too when `recursive` is set, in natural
+/// order ("Track 2" before "Track 10"). Unreadable entries are skipped.
+pub fn scan_dir_for_audio(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let max_depth = if recursive { usize::MAX } else { 1 };
+    let mut files: Vec<PathBuf> = WalkDir::new(dir)
+        .max_depth(max_depth)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() && is_audio_file(entry.path()))
+        .map(|entry| entry.into_path())
+        .collect();
+
+    files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    files
+}
+
+/// Compares strings the way people expect file names to sort: runs of digits by their value,
+/// everything else ignoring case
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_digits(&mut a), take_digits(&mut b));
+                // Leading zeros don't change the value, and a longer number is a bigger one
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_value.len().cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Gain for a volume slider position, both 0.0 to 1.0. Loudness follows the gain's logarithm,
+/// so a straight line would do nearly all its work near the bottom of the slider; a cubic
+/// spreads it out, putting halfway at about -18 dB.
+pub fn perceptual_to_gain(position: f32) -> f32 {
+    position.clamp(0.0, 1.0).powi(3)
+}
+
+/// Slider position for a gain, the inverse of `perceptual_to_gain`
+pub fn gain_to_perceptual(gain: f32) -> f32 {
+    gain.clamp(0.0, 1.0).cbrt()
+}
+
+/// Get the supported audio extensions for file dialogs, in alphabetical order
 pub fn get_supported_extensions() -> Vec<&'static str> {
-    SUPPORTED_AUDIO_EXTENSIONS.iter().cloned().collect()
-} 
\ No newline at end of file
+    AUDIO_EXTENSIONS.to_vec()
+}
+
+/// Limit how many directories the folder cover cache remembers
+pub fn set_folder_cover_cache_capacity(capacity: usize) {
+    if let Ok(mut cache) = FOLDER_COVER_CACHE.lock() {
+        cache.set_capacity(capacity);
+    }
+}
+
+/// Find a cover image (cover.jpg, folder.png, ...) in the same directory as a track.
+/// Used when the file has no embedded art. Results are cached per directory.
+#[allow(dead_code)]
+pub fn find_folder_cover<P: AsRef<Path>>(track: P) -> Option<PathBuf> {
+    let dir = track.as_ref().parent()?.to_path_buf();
+    
+    if let Ok(mut cache) = FOLDER_COVER_CACHE.lock() {
+        if let Some(cached) = cache.get(&dir) {
+            return cached.clone();
+        }
+    }
+    
+    // Names are matched case-insensitively, so Folder.JPG counts too
+    let mut best: Option<(usize, PathBuf)> = None;
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) else {
+                continue;
+            };
+            let stem = stem.to_string_lossy().to_lowercase();
+            let ext = ext.to_string_lossy().to_lowercase();
+            
+            if !COVER_EXTENSIONS.contains(&ext.as_str()) || !path.is_file() {
+                continue;
+            }
+            if let Some(rank) = COVER_NAMES.iter().position(|name| *name == stem) {
+                if best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank) {
+                    best = Some((rank, path));
+                }
+            }
+        }
+    }
+    
+    let cover = best.map(|(_, path)| path);
+    if let Ok(mut cache) = FOLDER_COVER_CACHE.lock() {
+        cache.insert(dir, cover.clone());
+    }
+    cover
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use crate::utils::is_audio_file;
+
+/// How long a path must be quiet before it's reported. Files that are still
+/// being copied into the folder fire a burst of events, so we wait for them to settle.
+const DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// Watches a folder for new audio files and reports them once they've settled
+pub struct FolderWatcher {
+    // Keep the watcher alive for as long as we want events
+    _watcher: RecommendedWatcher,
+    events: Receiver<PathBuf>,
+    pending: HashMap<PathBuf, Instant>,
+    folder: PathBuf,
+}
+
+impl FolderWatcher {
+    pub fn new(folder: &Path) -> Result<Self> {
+        if !folder.is_dir() {
+            return Err(anyhow::anyhow!("Watched folder does not exist: {}", folder.display()));
+        }
+
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            match res {
+                Ok(event) => {
+                    // Only creations and renames/moves into the folder are interesting
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        for path in event.paths {
+                            let _ = tx.send(path);
+                        }
+                    }
+                },
+                Err(e) => log::error!("Folder watch error: {}", e),
+            }
+        })?;
+        watcher.watch(folder, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events: rx,
+            pending: HashMap::new(),
+            folder: folder.to_path_buf(),
+        })
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Drains filesystem events and returns audio files that have been quiet for
+    /// longer than the debounce window. Call this regularly (e.g. once per frame).
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        while let Ok(path) = self.events.try_recv() {
+            if is_audio_file(&path) {
+                self.pending.insert(path, now);
+            }
+        }
+
+        let mut ready: Vec<PathBuf> = self.pending.iter()
+            .filter(|(_, last_event)| now.duration_since(**last_event) >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in &ready {
+            self.pending.remove(path);
+        }
+
+        // The file may have been removed or renamed away again in the meantime
+        ready.retain(|path| path.is_file());
+        ready.sort();
+        ready
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use eframe::egui;
+use rodio::Source;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+use crate::decode::open_decoder;
+use crate::playlist::cue_track_for;
+
+/// Most bars drawn for a track, however long it is
+pub const MAX_BUCKETS: usize = 600;
+/// Peaks are first taken over blocks this many times a second, then merged into buckets
+const BLOCKS_PER_SECOND: u32 = 20;
+
+/// Loudest sample of each stretch of `path`, from 0.0 to 1.0, spread evenly over the track.
+/// Decodes the whole track, so run it off the UI thread. Gives up with `None` once `cancel`
+/// is set.
+pub fn compute_peaks(path: &Path, cancel: &AtomicBool) -> Result<Option<Vec<f32>>> {
+    // A cue sheet track is just its own part of the file, as on the progress bar
+    let cue = cue_track_for(path);
+    let (path, start, end) = match &cue {
+        Some(cue) => (cue.file.as_path(), cue.start, cue.end),
+        None => (path, Duration::ZERO, None),
+    };
+
+    let decoder = open_decoder(path)?;
+    let samples_per_second = decoder.sample_rate() as f64 * decoder.channels() as f64;
+    let block = (samples_per_second as u32 / BLOCKS_PER_SECOND).max(1) as usize;
+    let length = end.map_or(usize::MAX, |end| (end.saturating_sub(start).as_secs_f64() * samples_per_second) as usize);
+
+    let mut blocks = Vec::new();
+    let mut peak = 0.0f32;
+    let mut count = 0;
+    for sample in decoder.skip_duration(start).convert_samples::<f32>().take(length) {
+        peak = peak.max(sample.abs());
+        count += 1;
+        if count == block {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            blocks.push(peak.min(1.0));
+            peak = 0.0;
+            count = 0;
+        }
+    }
+    if count > 0 {
+        blocks.push(peak.min(1.0));
+    }
+    Ok(Some(downsample(&blocks, MAX_BUCKETS)))
+}
+
+/// Merges `peaks` into at most `buckets` values, keeping the loudest of each group
+pub fn downsample(peaks: &[f32], buckets: usize) -> Vec<f32> {
+    if buckets == 0 || peaks.len() <= buckets {
+        return peaks.to_vec();
+    }
+    (0..buckets)
+        .map(|bucket| {
+            let start = bucket * peaks.len() / buckets;
+            let end = ((bucket + 1) * peaks.len() / buckets).max(start + 1);
+            peaks[start..end].iter().copied().fold(0.0, f32::max)
+        })
+        .collect()
+}
+
+/// The current track's waveform, analysed in the background
+#[derive(Default)]
+pub struct Waveform {
+    track: Option<PathBuf>,
+    peaks: Option<Vec<f32>>,
+    loading: Option<Receiver<Option<Vec<f32>>>>,
+    /// Set to stop the analysis of a track that's no longer current
+    cancel: Arc<AtomicBool>,
+}
+
+impl Waveform {
+    /// Starts analysing `track` if it's a different one, and picks up peaks that have
+    /// finished. Call this every frame.
+    pub fn update(&mut self, ctx: &egui::Context, track: Option<&Path>) {
+        if self.track.as_deref() != track {
+            self.track = track.map(Path::to_path_buf);
+            self.peaks = None;
+            // Skipping through tracks shouldn't leave a decode running for each of them
+            self.cancel.store(true, Ordering::Relaxed);
+            self.cancel = Arc::new(AtomicBool::new(false));
+            self.loading = track.map(|track| {
+                let (tx, rx) = channel();
+                let track = track.to_path_buf();
+                let ctx = ctx.clone();
+                let cancel = self.cancel.clone();
+                thread::spawn(move || {
+                    let peaks = match compute_peaks(&track, &cancel) {
+                        Ok(Some(peaks)) => Some(peaks),
+                        Ok(None) => return,
+                        Err(e) => {
+                            log::warn!("Couldn't draw the waveform of {}: {}", track.display(), e);
+                            None
+                        }
+                    };
+                    let _ = tx.send(peaks);
+                    ctx.request_repaint();
+                });
+                rx
+            });
+        }
+
+        let loaded = self.loading.as_ref().and_then(|rx| rx.try_recv().ok());
+        if let Some(peaks) = loaded {
+            self.loading = None;
+            self.peaks = peaks;
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Bars for the current track, `None` while it's analysed or if it couldn't be
+    pub fn peaks(&self) -> Option<&[f32]> {
+        self.peaks.as_deref()
+    }
+
+    /// Draws the bars across `rect`, the part up to `played` (0.0 to 1.0) highlighted
+    pub fn paint(ui: &egui::Ui, rect: egui::Rect, peaks: &[f32], played: f32) {
+        if peaks.is_empty() {
+            return;
+        }
+        let painter = ui.painter_at(rect);
+        let played_color = ui.visuals().selection.bg_fill;
+        let rest_color = ui.visuals().widgets.inactive.bg_fill;
+        let width = rect.width() / peaks.len() as f32;
+        let played_x = rect.left() + rect.width() * played.clamp(0.0, 1.0);
+
+        for (i, peak) in peaks.iter().enumerate() {
+            let x = rect.left() + (i as f32 + 0.5) * width;
+            // Always a sliver, so silence still shows as part of the track
+            let half = (peak * rect.height() / 2.0).max(0.5);
+            let color = if x <= played_x { played_color } else { rest_color };
+            painter.line_segment(
+                [egui::pos2(x, rect.center().y - half), egui::pos2(x, rect.center().y + half)],
+                egui::Stroke::new(width.clamp(1.0, 3.0), color),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downsample_keeps_loudest_of_each_group() {
+        let peaks = [0.1, 0.5, 0.2, 0.9, 0.3, 0.4];
+        assert_eq!(downsample(&peaks, 3), vec![0.5, 0.9, 0.4]);
+        assert_eq!(downsample(&peaks, 10), peaks.to_vec());
+
+        let long: Vec<f32> = (0..10_000).map(|i| (i % 7) as f32 / 7.0).collect();
+        assert_eq!(downsample(&long, MAX_BUCKETS).len(), MAX_BUCKETS);
+    }
+}
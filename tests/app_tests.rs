@@ -6,7 +6,11 @@ use anyhow::Result;
 use std::thread;
 
 // Import from the main crate
-use musicplayer::player::MusicPlayer;
+use musicplayer::player::{MusicPlayer, PlayerCommand, SeekAccuracy};
+use musicplayer::controller::{FinishKind, InstantSkipGuard, PlayerController};
+use musicplayer::decode::{open_decoder, DecodeFailure};
+use musicplayer::metadata::track_duration;
+use musicplayer::trims::TrackTrims;
 
 // Helper function to create a temporary wav file for testing
 // Returns both the file path and the temp dir to keep it alive
@@ -150,4 +154,611 @@ fn test_song_finished_detection() -> Result<()> {
         "After stopping, check_if_song_finished() should return true");
     
     Ok(())
-} 
\ No newline at end of file
+} 
+
+// Helper to create a silent 16-bit mono wav file of the given length
+fn create_long_wav_file(filename: &str, seconds: u32) -> Result<(PathBuf, TempDir)> {
+    let dir = tempdir()?;
+    let file_path = dir.path().join(filename);
+    
+    let sample_rate: u32 = 44100;
+    let data_len = sample_rate * seconds * 2;
+    
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());     // Subchunk1Size
+    bytes.extend_from_slice(&1u16.to_le_bytes());      // AudioFormat (PCM)
+    bytes.extend_from_slice(&1u16.to_le_bytes());      // NumChannels
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // ByteRate
+    bytes.extend_from_slice(&2u16.to_le_bytes());      // BlockAlign
+    bytes.extend_from_slice(&16u16.to_le_bytes());     // BitsPerSample
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    bytes.resize(44 + data_len as usize, 0);
+    
+    let mut file = File::create(&file_path)?;
+    file.write_all(&bytes)?;
+    
+    Ok((file_path, dir))
+}
+
+#[test]
+fn test_seeking_never_reports_finished() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("seek_race.wav", 10)?;
+    let player = std::sync::Arc::new(MusicPlayer::new()?);
+    player.play_playlist_item(&file, 0)?;
+    
+    // Poll for a spurious "finished" from another thread while we seek
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let checker = {
+        let player = player.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut spurious = false;
+            while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                spurious |= player.check_if_song_finished();
+            }
+            spurious
+        })
+    };
+    
+    for i in 0..50 {
+        let position = std::time::Duration::from_millis((i * 137) % 8000);
+        player.seek_to(position)?;
+        assert!(player.is_playing(), "Seek {} reported the track as not playing", i);
+    }
+    
+    done.store(true, std::sync::atomic::Ordering::SeqCst);
+    let spurious = checker.join().unwrap();
+    assert!(!spurious, "Track was reported finished during a seek");
+    
+    Ok(())
+}
+
+#[test]
+fn test_seek_job_runs_without_holding_the_player() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("background_seek.wav", 10)?;
+    let player = std::sync::Arc::new(std::sync::Mutex::new(MusicPlayer::new()?));
+    player.lock().unwrap().play_playlist_item(&file, 0)?;
+    
+    let job = player.lock().unwrap().begin_seek(std::time::Duration::from_secs(5))?;
+    let worker = thread::spawn(move || job.run());
+    
+    // The player stays usable while the job runs, and already shows the new position
+    {
+        let player = player.lock().unwrap();
+        assert!(player.get_current_position() >= std::time::Duration::from_secs(5));
+        assert!(!player.check_if_song_finished());
+    }
+    
+    let outcome = worker.join().unwrap();
+    let player = player.lock().unwrap();
+    player.finish_seek(outcome)?;
+    assert!(player.is_playing());
+    assert!(player.get_current_position() >= std::time::Duration::from_secs(5));
+    
+    Ok(())
+}
+
+#[test]
+fn test_seek_begun_before_a_track_change_is_dropped() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (first, _first_dir) = create_long_wav_file("before_change.wav", 10)?;
+    let (second, _second_dir) = create_long_wav_file("after_change.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&first, 0)?;
+    
+    let job = player.begin_seek(std::time::Duration::from_secs(8))?;
+    player.play_playlist_item(&second, 1)?;
+    player.finish_seek(job.run())?;
+    
+    // The new track starts at the beginning, and its end is still noticed
+    assert!(player.get_current_position() < std::time::Duration::from_secs(8));
+    player.end_track();
+    assert!(player.check_if_song_finished(), "Finished detection was left off after the dropped seek");
+    
+    Ok(())
+}
+
+#[test]
+fn test_queued_duplicate_track_is_attributed_to_its_own_index() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    // The same file appears twice in a row in the playlist
+    let (file, _dir) = create_long_wav_file("twice.wav", 1)?;
+    let player = MusicPlayer::new()?;
+    
+    player.play_playlist_item(&file, 0)?;
+    player.queue_playlist_item(&file, 1)?;
+    assert_eq!(player.queued_count(), 1);
+    assert_eq!(player.next_queued(), Some(file.clone()));
+    
+    // Record every entry that gets to play
+    let mut played = vec![player.get_current_song_index().unwrap()];
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while std::time::Instant::now() < deadline && !player.check_if_song_finished() {
+        if let Some((index, path)) = player.reconcile_queue() {
+            assert_eq!(path, file);
+            played.push(index);
+        }
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    
+    // Both occurrences were counted, and the second one was highlighted as index 1
+    assert_eq!(played, vec![0, 1]);
+    assert_eq!(player.get_current_song_index(), Some(1));
+    assert_eq!(player.queued_count(), 0);
+    assert_eq!(player.next_queued(), None);
+    
+    Ok(())
+}
+
+#[test]
+fn test_duration_falls_back_to_file_headers() -> Result<()> {
+    let (file, _dir) = create_long_wav_file("headers.wav", 3)?;
+    
+    // As if the decoder couldn't say how long the stream is
+    let duration = track_duration(&file, None).expect("WAV headers give the length");
+    assert!(duration.abs_diff(std::time::Duration::from_secs(3)) < std::time::Duration::from_millis(50));
+    
+    // The decoder's own answer wins when it has one
+    let reported = std::time::Duration::from_secs(7);
+    assert_eq!(track_duration(&file, Some(reported)), Some(reported));
+    
+    Ok(())
+}
+
+#[test]
+fn test_position_follows_song_time_at_other_speeds() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("speed.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    player.set_speed(2.0);
+    
+    // Half a second of wall-clock time is a second of the song at double speed
+    thread::sleep(std::time::Duration::from_millis(500));
+    let position = player.get_current_position().as_secs_f64();
+    assert!((0.8..1.4).contains(&position), "position was {}", position);
+    
+    // Out of range speeds are clamped
+    player.set_speed(10.0);
+    assert_eq!(player.get_playback_rate(), 2.0);
+    
+    Ok(())
+}
+
+#[test]
+fn test_position_stands_still_while_paused() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("pause.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    thread::sleep(std::time::Duration::from_millis(300));
+    
+    player.pause();
+    let paused_at = player.get_current_position();
+    thread::sleep(std::time::Duration::from_millis(500));
+    assert_eq!(player.get_current_position(), paused_at);
+    
+    // Playing again carries on from the same spot, without counting the pause
+    player.resume();
+    thread::sleep(std::time::Duration::from_millis(300));
+    let resumed = player.get_current_position();
+    assert!(resumed > paused_at);
+    assert!(resumed < paused_at + std::time::Duration::from_millis(500), "position was {:?}", resumed);
+    
+    Ok(())
+}
+
+#[test]
+fn test_seek_is_precise_on_wav() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("precise.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    let tolerance = std::time::Duration::from_millis(250);
+    
+    // Forward, then back past where we started
+    for target in [6, 2] {
+        let target = std::time::Duration::from_secs(target);
+        assert_eq!(player.seek_to(target)?, SeekAccuracy::Exact);
+        assert!(player.get_current_position().abs_diff(target) < tolerance);
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_seek_relative_before_start_rewinds() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("seek_back.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    
+    player.seek_to(std::time::Duration::from_secs(2))?;
+    player.seek_relative(-30.0)?;
+    
+    assert!(player.get_current_position() < std::time::Duration::from_millis(500));
+    assert!(player.is_playing());
+    assert!(!player.check_if_song_finished());
+    
+    Ok(())
+}
+
+#[test]
+fn test_seek_relative_past_end_finishes_track() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("seek_forward.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    
+    player.seek_relative(60.0)?;
+    
+    assert!(player.check_if_song_finished());
+    assert!(!player.is_playing());
+    assert_eq!(player.get_current_position(), std::time::Duration::from_secs(10));
+    
+    Ok(())
+}
+
+#[test]
+fn test_seek_relative_past_range_end_finishes_track() -> Result<()> {
+    let (file, _dir) = create_long_wav_file("seek_range.wav", 10)?;
+    let player = MusicPlayer::without_output();
+    let end = std::time::Duration::from_secs(4);
+    player.play_playlist_range(&file, 0, std::time::Duration::from_secs(2), Some(end))?;
+    
+    // Within the range it's an ordinary seek
+    player.seek_relative(1.0)?;
+    assert!(!player.check_if_song_finished());
+    assert!(player.get_current_position() < end);
+    
+    // Past the range's end it finishes there, not at the end of the file
+    player.seek_relative(5.0)?;
+    assert!(player.check_if_song_finished());
+    assert_eq!(player.get_current_position(), end);
+    
+    Ok(())
+}
+
+// Helper to create a WAV whose header promises a second of audio but whose data is missing
+fn create_truncated_wav_file(filename: &str) -> Result<(PathBuf, TempDir)> {
+    let (file_path, dir) = create_long_wav_file(filename, 1)?;
+    
+    // Keep the 44 byte header and a couple of samples, drop the rest
+    let bytes = std::fs::read(&file_path)?;
+    std::fs::write(&file_path, &bytes[..48])?;
+    
+    Ok((file_path, dir))
+}
+
+#[test]
+fn test_truncated_file_triggers_instant_skip_guard() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_truncated_wav_file("truncated.wav")?;
+    let player = MusicPlayer::new()?;
+    let mut guard = InstantSkipGuard::default();
+    
+    // Decoding may fail outright or produce almost nothing, either way the track ends at once
+    let result = player.play_playlist_item(&file, 0);
+    guard.track_started(&result);
+    
+    let start = std::time::Instant::now();
+    while !player.check_if_song_finished() && start.elapsed() < std::time::Duration::from_millis(50) {
+        thread::sleep(std::time::Duration::from_millis(1));
+    }
+    
+    assert!(player.check_if_song_finished(), "Truncated file should finish immediately");
+    assert_eq!(guard.track_finished(player.get_current_position()), FinishKind::InstantSkip);
+    
+    Ok(())
+}
+
+#[test]
+fn test_undecodable_track_reports_error_and_finishes() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let dir = tempdir()?;
+    let text = dir.path().join("notes.mp3");
+    std::fs::write(&text, "These are my shopping notes, not a song.\n")?;
+    let player = MusicPlayer::new()?;
+    let mut guard = InstantSkipGuard::default();
+    
+    // The GUI shows the reason and counts the failure...
+    let result = player.play_playlist_item(&text, 0);
+    guard.track_started(&result);
+    let error = result.err().expect("Text file should fail to play");
+    assert!(matches!(error.downcast_ref::<DecodeFailure>(), Some(DecodeFailure::Unsupported(_))));
+    assert!(error.to_string().contains("notes.mp3"), "Error should name the file: {}", error);
+    
+    // ...and the track reads as finished straight away, so the skip guard moves past it
+    assert!(player.check_if_song_finished());
+    assert_eq!(player.get_current_song_index(), Some(0));
+    // However long it takes to notice
+    assert_eq!(guard.track_finished(std::time::Duration::from_secs(1)), FinishKind::InstantSkip);
+    
+    Ok(())
+}
+
+#[test]
+fn test_decode_failures_are_classified() -> Result<()> {
+    // No audio output needed, this only opens the decoder
+    let (wav, _wav_dir) = create_test_wav_file("cut.wav")?;
+    let bytes = std::fs::read(&wav)?;
+    // Cut off in the middle of the format chunk
+    std::fs::write(&wav, &bytes[..20])?;
+    
+    let dir = tempdir()?;
+    let text = dir.path().join("notes.mp3");
+    std::fs::write(&text, "These are my shopping notes, not a song.\n")?;
+    
+    let classify = |path: &PathBuf| {
+        let error = open_decoder(path).err().expect("File should fail to decode");
+        error.downcast_ref::<DecodeFailure>().cloned().expect("Decode errors should be classified")
+    };
+    
+    assert!(matches!(classify(&wav), DecodeFailure::Corrupt(_)));
+    assert!(matches!(classify(&text), DecodeFailure::Unsupported(_)));
+    assert!(classify(&text).to_string().contains(".mp3"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_crossfade_hands_over_to_next_track() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (first, _first_dir) = create_long_wav_file("first.wav", 6)?;
+    let (second, _second_dir) = create_long_wav_file("second.wav", 6)?;
+    let player = MusicPlayer::new()?;
+    player.set_crossfade(std::time::Duration::from_secs(2));
+    player.play_playlist_item(&first, 0)?;
+    player.seek_to(std::time::Duration::from_secs(4))?;
+    
+    // The first track finishes early and keeps fading out on its own
+    player.begin_crossfade(std::time::Duration::from_secs(6))?;
+    assert!(player.check_if_song_finished());
+    assert!(player.is_fading_out());
+    
+    // The next one starts from the top underneath it
+    player.play_playlist_item(&second, 1)?;
+    assert!(player.is_fading_out());
+    assert_eq!(player.get_current_song_index(), Some(1));
+    assert!(player.get_current_position() < std::time::Duration::from_secs(1));
+    
+    // Seeking the new track cuts the old one off
+    player.seek_to(std::time::Duration::from_secs(1))?;
+    assert!(!player.is_fading_out());
+    
+    Ok(())
+}
+
+#[test]
+fn test_range_plays_part_of_a_file() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("album.wav", 10)?;
+    let player = MusicPlayer::new()?;
+    let start = std::time::Duration::from_secs(2);
+    let end = std::time::Duration::from_millis(2500);
+    player.play_playlist_range(&file, 0, start, Some(end))?;
+    
+    // Starts part way in, counting from the start of the file
+    let position = player.get_current_position();
+    assert!(position >= start && position < end, "position was {:?}", position);
+    assert!(!player.check_if_song_finished());
+    
+    // And finishes at the end point, well before the file does
+    let started = std::time::Instant::now();
+    while !player.check_if_song_finished() && started.elapsed() < std::time::Duration::from_secs(3) {
+        thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert!(player.check_if_song_finished());
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    
+    Ok(())
+}
+
+#[test]
+fn test_track_cut_short_is_reported_as_ending_early() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    // The header promises 20 seconds, but only half a second of audio is there
+    let (file, _dir) = create_long_wav_file("cut_short.wav", 20)?;
+    let bytes = std::fs::read(&file)?;
+    std::fs::write(&file, &bytes[..44 + 44100])?;
+    
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    let start = std::time::Instant::now();
+    while !player.check_if_song_finished() && start.elapsed() < std::time::Duration::from_secs(5) {
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(player.check_if_song_finished(), "Playback should stop when the data runs out");
+    assert!(player.ended_early());
+    
+    // A track ended on purpose is treated as played to the end
+    player.play_playlist_item(&file, 0)?;
+    player.end_track();
+    assert!(player.check_if_song_finished());
+    assert!(!player.ended_early());
+    
+    Ok(())
+}
+
+#[test]
+fn test_player_without_output_still_plays_through() -> Result<()> {
+    // No device needed, so this runs in CI too
+    let (file, _dir) = create_long_wav_file("no_output.wav", 1)?;
+    
+    let player = MusicPlayer::without_output();
+    player.play_playlist_item(&file, 0)?;
+    
+    // Seeking mustn't wait on a device that isn't there
+    player.seek_to(std::time::Duration::from_millis(500))?;
+    let start = std::time::Instant::now();
+    while !player.check_if_song_finished() && start.elapsed() < std::time::Duration::from_secs(5) {
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(player.check_if_song_finished(), "The track should still play to its end");
+    assert!(!player.ended_early());
+    assert!(player.play_test_tone(440.0, 0.1).is_err(), "Extra sinks need a real device");
+    
+    Ok(())
+}
+
+#[test]
+fn test_killed_audio_stays_silent_until_resumed() -> Result<()> {
+    let (file, _dir) = create_long_wav_file("killed.wav", 2)?;
+    
+    let player = MusicPlayer::without_output();
+    player.set_volume(0.8);
+    player.play_playlist_item(&file, 0)?;
+    player.kill_audio();
+    assert!(player.is_killed());
+    
+    // A volume change while killed (a sleep timer fade, say) waits for the resume
+    player.set_volume(0.5);
+    assert!(player.is_killed());
+    assert!(!player.is_playing());
+    
+    player.resume();
+    assert!(!player.is_killed());
+    assert_eq!(player.get_volume(), 0.5);
+    
+    Ok(())
+}
+
+#[test]
+fn test_killing_audio_stops_a_preview() -> Result<()> {
+    // Skip if running in CI environment without audio
+    if std::env::var("CI").is_ok() {
+        return Ok(());
+    }
+    
+    let (file, _dir) = create_long_wav_file("main.wav", 2)?;
+    let (other, _other_dir) = create_long_wav_file("preview.wav", 2)?;
+    
+    let player = MusicPlayer::new()?;
+    player.play_playlist_item(&file, 0)?;
+    player.preview(&other, std::time::Duration::from_secs(2))?;
+    assert!(player.is_previewing());
+    
+    player.kill_audio();
+    assert!(!player.is_previewing(), "The preview should be silenced too");
+    
+    // Cleaning up after the preview mustn't bring the killed track back
+    player.stop_preview();
+    player.update_preview();
+    assert!(!player.is_playing());
+    assert!(player.is_killed());
+    
+    Ok(())
+}
+
+#[test]
+fn test_controller_plays_between_trim_points() -> Result<()> {
+    let (file, _dir) = create_long_wav_file("trimmed.wav", 3)?;
+    let mut trims = TrackTrims::default();
+    trims.set_start(&file, std::time::Duration::from_secs(1))?;
+    trims.set_end(&file, std::time::Duration::from_millis(1500))?;
+    
+    let player = std::sync::Arc::new(std::sync::Mutex::new(MusicPlayer::without_output()));
+    let mut controller = PlayerController::new(player);
+    controller.set_trims(trims);
+    controller.add(file);
+    controller.play_index(0)?;
+    
+    let position = controller.snapshot().map(|snapshot| snapshot.position).unwrap_or_default();
+    assert!(position >= std::time::Duration::from_secs(1), "Should start at the start point, not {:?}", position);
+    
+    // Half a second later the end point finishes the track, well before the file's end
+    let start = std::time::Instant::now();
+    while !controller.tick()? && start.elapsed() < std::time::Duration::from_secs(5) {
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(start.elapsed() < std::time::Duration::from_millis(1500), "Should stop at the end point");
+    
+    Ok(())
+}
+
+#[test]
+fn test_controller_previous_restarts_at_trim_start() -> Result<()> {
+    let (file, _dir) = create_long_wav_file("restart.wav", 8)?;
+    let mut trims = TrackTrims::default();
+    trims.set_start(&file, std::time::Duration::from_secs(2))?;
+    
+    let player = std::sync::Arc::new(std::sync::Mutex::new(MusicPlayer::without_output()));
+    let mut controller = PlayerController::new(player);
+    controller.set_trims(trims);
+    controller.add(file);
+    controller.play_index(0)?;
+    
+    // Well past the restart threshold, counted from the start point
+    controller.apply(PlayerCommand::Seek(std::time::Duration::from_secs(6)))?;
+    controller.previous()?;
+    
+    let position = controller.snapshot().map(|snapshot| snapshot.position).unwrap_or_default();
+    assert!(position >= std::time::Duration::from_secs(2), "Should restart at the start point, not {:?}", position);
+    assert!(position < std::time::Duration::from_secs(3), "Should restart at the start point, not {:?}", position);
+    
+    Ok(())
+}
@@ -1,8 +1,17 @@
+#[cfg(feature = "remote-control")]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(feature = "remote-control")]
+use std::net::TcpStream;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::thread;
 use anyhow::Result;
+use musicplayer::AudioBackend;
+#[cfg(feature = "remote-control")]
+use musicplayer::remote::{send_command, RemoteRequest, RemoteServer, AUTH_TIMEOUT};
+#[cfg(feature = "remote-control")]
+use musicplayer::player::{PlaybackState, PlayerCommand, PlayerSnapshot, RepeatMode};
 
 // A mock player implementation that simulates the real player without audio
 #[derive(Clone)]
@@ -10,6 +19,8 @@ struct MockPlayer {
     current_index: Arc<Mutex<Option<usize>>>,
     is_playing: Arc<Mutex<bool>>,
     is_finished: Arc<Mutex<bool>>,
+    position: Arc<Mutex<Duration>>,
+    volume: Arc<Mutex<f32>>,
 }
 
 impl MockPlayer {
@@ -18,9 +29,50 @@ impl MockPlayer {
             current_index: Arc::new(Mutex::new(None)),
             is_playing: Arc::new(Mutex::new(false)),
             is_finished: Arc::new(Mutex::new(false)),
+            position: Arc::new(Mutex::new(Duration::ZERO)),
+            volume: Arc::new(Mutex::new(1.0)),
         }
     }
     
+    fn is_playing(&self) -> bool {
+        // Match the real player's behavior:
+        // - Not playing if paused
+        // - Not playing if finished
+        if let (Ok(playing), Ok(finished)) = (self.is_playing.lock(), self.is_finished.lock()) {
+            return *playing && !*finished;
+        }
+        false
+    }
+    
+    fn is_finished(&self) -> bool {
+        if let Ok(finished) = self.is_finished.lock() {
+            *finished
+        } else {
+            false
+        }
+    }
+    
+    fn get_current_index(&self) -> Option<usize> {
+        if let Ok(current) = self.current_index.lock() {
+            *current
+        } else {
+            None
+        }
+    }
+    
+    // Simulate playing a song to completion
+    fn simulate_playback_complete(&self) {
+        // First stop the player
+        self.stop();
+        
+        // Then set finished flag
+        if let Ok(mut finished) = self.is_finished.lock() {
+            *finished = true;
+        }
+    }
+}
+
+impl AudioBackend for MockPlayer {
     fn play(&self, _path: &Path, index: usize) -> Result<()> {
         if let Ok(mut current) = self.current_index.lock() {
             *current = Some(index);
@@ -40,6 +92,10 @@ impl MockPlayer {
             return Err(anyhow::anyhow!("Failed to lock finished state"));
         }
         
+        if let Ok(mut position) = self.position.lock() {
+            *position = Duration::ZERO;
+        }
+        
         Ok(())
     }
     
@@ -65,41 +121,31 @@ impl MockPlayer {
         }
     }
     
-    fn is_playing(&self) -> bool {
-        // Match the real player's behavior:
-        // - Not playing if paused
-        // - Not playing if finished
-        if let (Ok(playing), Ok(finished)) = (self.is_playing.lock(), self.is_finished.lock()) {
-            return *playing && !*finished;
+    fn seek(&self, position: Duration) -> Result<()> {
+        if let Ok(mut current) = self.position.lock() {
+            *current = position;
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Failed to lock position"))
         }
-        false
     }
     
-    fn is_finished(&self) -> bool {
-        if let Ok(finished) = self.is_finished.lock() {
-            *finished
-        } else {
-            false
+    fn set_volume(&self, volume: f32) {
+        if let Ok(mut current) = self.volume.lock() {
+            *current = volume.clamp(0.0, 1.0);
         }
     }
     
-    fn get_current_index(&self) -> Option<usize> {
-        if let Ok(current) = self.current_index.lock() {
-            *current
-        } else {
-            None
-        }
+    fn position(&self) -> Duration {
+        self.position.lock().map(|position| *position).unwrap_or_default()
     }
     
-    // Simulate playing a song to completion
-    fn simulate_playback_complete(&self) {
-        // First stop the player
-        self.stop();
-        
-        // Then set finished flag
-        if let Ok(mut finished) = self.is_finished.lock() {
-            *finished = true;
-        }
+    fn duration(&self) -> Option<Duration> {
+        None
+    }
+    
+    fn finished(&self) -> bool {
+        self.is_finished()
     }
 }
 
@@ -192,4 +238,125 @@ fn test_mock_player_threading() -> Result<()> {
     assert!(player.is_finished());
     
     Ok(())
-} 
\ No newline at end of file
+} 
+
+// Code written against the trait runs the same on the mock as on the real player
+fn play_from(backend: &impl AudioBackend, path: &Path, start: Duration) -> Result<()> {
+    backend.play(path, 0)?;
+    backend.seek(start)?;
+    backend.set_volume(0.5);
+    Ok(())
+}
+
+#[test]
+fn test_mock_player_through_backend_trait() -> Result<()> {
+    let player = MockPlayer::new();
+    
+    play_from(&player, Path::new("/mock/track1.mp3"), Duration::from_secs(30))?;
+    assert_eq!(player.position(), Duration::from_secs(30));
+    assert_eq!(*player.volume.lock().unwrap(), 0.5);
+    assert_eq!(player.duration(), None);
+    assert!(!player.finished());
+    
+    player.stop();
+    assert!(player.finished());
+    
+    Ok(())
+}
+
+// What the remote control reports for the mock, playing its one track
+#[cfg(feature = "remote-control")]
+fn mock_snapshot(player: &MockPlayer) -> PlayerSnapshot {
+    PlayerSnapshot {
+        state: if player.is_playing() { PlaybackState::Playing } else { PlaybackState::Paused },
+        current_path: Some(PathBuf::from("/mock/track1.mp3")),
+        position: player.position(),
+        duration: player.duration(),
+        volume: *player.volume.lock().unwrap(),
+        shuffle: false,
+        repeat: RepeatMode::None,
+    }
+}
+
+#[test]
+#[cfg(feature = "remote-control")]
+fn test_silent_remote_clients_are_dropped() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let server = RemoteServer::start(0, &dir.path().join("remote_token"))?;
+    
+    // Connects and never sends its token: the player hangs up instead of waiting forever
+    let silent = TcpStream::connect(("127.0.0.1", server.port()))?;
+    silent.set_read_timeout(Some(AUTH_TIMEOUT * 3))?;
+    let started = std::time::Instant::now();
+    let mut line = String::new();
+    let read = BufReader::new(silent).read_line(&mut line)?;
+    assert_eq!(read, 0, "The connection should be closed, got {:?}", line);
+    assert!(started.elapsed() < AUTH_TIMEOUT * 2);
+    
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "remote-control")]
+fn test_remote_commands_over_socket() -> Result<()> {
+    let player = MockPlayer::new();
+    player.play(Path::new("/mock/track1.mp3"), 0)?;
+    
+    // Any free port and a token file of its own, so tests don't clash with a running player
+    let dir = tempfile::tempdir()?;
+    let token_file = dir.path().join("remote_token");
+    let server = RemoteServer::start(0, &token_file)?;
+    let port = server.port();
+    
+    // Clients that can't read the token get nothing done
+    let mut stranger = TcpStream::connect(("127.0.0.1", port))?;
+    writeln!(stranger, "next")?;
+    let mut refusal = String::new();
+    BufReader::new(stranger).read_line(&mut refusal)?;
+    assert_eq!(refusal.trim_end(), "ERR Not authorised");
+    
+    let client = thread::spawn(move || -> Result<Vec<String>> {
+        ["pause", "volume 0.25", "json seek 42", "json dance"]
+            .iter()
+            .map(|command| send_command(port, &token_file, command))
+            .collect()
+    });
+    
+    // Stand in for the GUI's update loop
+    while !client.is_finished() {
+        for pending in server.poll() {
+            let result = match pending.request.clone() {
+                RemoteRequest::Command(PlayerCommand::Pause) => {
+                    player.pause();
+                    Ok(())
+                }
+                RemoteRequest::Command(PlayerCommand::SetVolume(volume)) => {
+                    player.set_volume(volume);
+                    Ok(())
+                }
+                RemoteRequest::Command(PlayerCommand::Seek(position)) => player.seek(position),
+                RemoteRequest::Command(command) => Err(anyhow::anyhow!("Unexpected command {:?}", command)),
+                RemoteRequest::Status => Ok(()),
+            };
+            pending.reply(result, Some(mock_snapshot(&player)));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    
+    let replies = client.join().unwrap()?;
+    assert_eq!(replies, [
+        "OK paused 0.0/- vol 1.00 /mock/track1.mp3",
+        "OK paused 0.0/- vol 0.25 /mock/track1.mp3",
+        r#"{"ok":true,"state":"paused","position":42.0,"duration":null,"volume":0.25,"shuffle":false,"repeat":"off","path":"/mock/track1.mp3"}"#,
+        r#"{"ok":false,"error":"Unknown command 'dance'"}"#,
+    ]);
+    assert!(!player.is_playing());
+    assert_eq!(player.position(), Duration::from_secs(42));
+    assert!(server.poll().is_empty());
+    
+    // The token goes with the server
+    drop(server);
+    assert!(!dir.path().join("remote_token").exists());
+    
+    Ok(())
+}
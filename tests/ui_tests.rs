@@ -83,4 +83,87 @@ fn test_notification_expiration() {
     notification_system.update();
     assert!(!notification_system.has_active_notification());
     assert_eq!(notification_system.get_notification_text(), None);
-} 
\ No newline at end of file
+} 
+
+// Collect the accessible names egui reports to screen readers for one frame of UI
+fn accessible_labels(mut build: impl FnMut(&mut eframe::egui::Ui)) -> Vec<String> {
+    use eframe::egui;
+    
+    let ctx = egui::Context::default();
+    ctx.enable_accesskit();
+    
+    let output = ctx.run(egui::RawInput::default(), |ctx| {
+        egui::CentralPanel::default().show(ctx, &mut build);
+    });
+    
+    output.platform_output.accesskit_update
+        .map(|update| update.nodes.iter()
+            .filter_map(|(_, node)| node.label().map(|label| label.to_string()))
+            .collect())
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_icon_buttons_have_accessible_names() {
+    use musicplayer::a11y;
+    
+    let labels = accessible_labels(|ui| {
+        a11y::button(ui, "⚙", "Copy config file location");
+        a11y::button(ui, "⏭ Next", "Next track");
+    });
+    
+    assert!(labels.iter().any(|l| l == "Copy config file location"), "labels: {:?}", labels);
+    assert!(labels.iter().any(|l| l == "Next track"), "labels: {:?}", labels);
+    assert!(!labels.iter().any(|l| l == "⚙"), "icon should not be announced: {:?}", labels);
+}
+
+#[test]
+fn test_sliders_have_accessible_names() {
+    use musicplayer::a11y;
+    
+    let labels = accessible_labels(|ui| {
+        let mut volume = 0.5f32;
+        let response = ui.add(eframe::egui::Slider::new(&mut volume, 0.0..=1.0).show_value(false));
+        a11y::slider(ui, response, volume as f64, "Volume");
+    });
+    
+    assert!(labels.iter().any(|l| l == "Volume"), "labels: {:?}", labels);
+}
+
+#[test]
+fn test_panic_key_works_while_typing() {
+    use eframe::egui;
+    use musicplayer::gui::panic_key_pressed;
+    
+    let ctx = egui::Context::default();
+    let mut search = String::new();
+    
+    // Give the text field focus, as clicking into the search box would
+    let _ = ctx.run(egui::RawInput::default(), |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.text_edit_singleline(&mut search).request_focus();
+        });
+    });
+    
+    let escape = egui::RawInput {
+        events: vec![egui::Event::Key {
+            key: egui::Key::Escape,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: egui::Modifiers::NONE,
+        }],
+        ..Default::default()
+    };
+    let _ = ctx.run(escape, |ctx| {
+        assert!(ctx.wants_keyboard_input(), "The text field should still have focus");
+        assert!(panic_key_pressed(ctx, egui::Key::Escape, false));
+        // A dialog gets the key to close itself instead
+        assert!(!panic_key_pressed(ctx, egui::Key::Escape, true));
+        // Only the configured key counts
+        assert!(!panic_key_pressed(ctx, egui::Key::F12, false));
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.text_edit_singleline(&mut search);
+        });
+    });
+}
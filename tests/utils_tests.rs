@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+use musicplayer::cover_art::load_cover_image;
+use musicplayer::utils::{expand_paths, find_folder_cover, gain_to_perceptual, get_supported_extensions, is_audio_file, is_probably_audio, partition_existing, perceptual_to_gain, scan_dir_for_audio};
+
+#[test]
+fn test_find_folder_cover() {
+    let dir = tempdir().unwrap();
+    let track = dir.path().join("01 - song.mp3");
+    File::create(&track).unwrap();
+    File::create(dir.path().join("Front.PNG")).unwrap();
+    File::create(dir.path().join("folder.jpg")).unwrap();
+    File::create(dir.path().join("notes.txt")).unwrap();
+    
+    // "folder" is preferred over "front", and matching ignores case
+    assert_eq!(find_folder_cover(&track), Some(dir.path().join("folder.jpg")));
+}
+
+#[test]
+fn test_find_folder_cover_without_image() {
+    let dir = tempdir().unwrap();
+    let track = dir.path().join("song.flac");
+    File::create(&track).unwrap();
+    File::create(dir.path().join("cover.txt")).unwrap();
+    
+    assert_eq!(find_folder_cover(&track), None);
+}
+
+#[test]
+fn test_no_glob_finds_file_with_brackets() {
+    let dir = tempdir().unwrap();
+    let track = dir.path().join("Live [2019].mp3");
+    File::create(&track).unwrap();
+    
+    // The name is not a valid literal match as a glob, but no-glob takes it as-is
+    let pattern = track.to_string_lossy().to_string();
+    assert_eq!(expand_paths(vec![pattern.clone()], false), vec![track.clone()]);
+    
+    // An existing file is taken literally even with globbing on
+    assert_eq!(expand_paths(vec![pattern], true), vec![track]);
+}
+
+#[test]
+fn test_no_glob_strips_stray_quotes() {
+    let dir = tempdir().unwrap();
+    let track = dir.path().join("my song.flac");
+    File::create(&track).unwrap();
+    
+    let quoted = format!("\"{}\"", track.display());
+    assert_eq!(expand_paths(vec![quoted], false), vec![track]);
+    
+    // Patterns are not expanded with no-glob
+    let pattern = dir.path().join("*.flac").to_string_lossy().to_string();
+    assert!(expand_paths(vec![pattern], false).is_empty());
+}
+
+#[test]
+fn test_scan_dir_for_audio() {
+    let dir = tempdir().unwrap();
+    let album = dir.path().join("Album");
+    let disc = album.join("Disc 2");
+    std::fs::create_dir_all(&disc).unwrap();
+    for path in [
+        dir.path().join("Track 10.mp3"),
+        dir.path().join("track 2.FLAC"),
+        dir.path().join("cover.jpg"),
+        album.join("01.ogg"),
+        disc.join("01.wav"),
+    ] {
+        File::create(path).unwrap();
+    }
+    
+    // Only the top level, with numbers sorted by value and case ignored
+    assert_eq!(scan_dir_for_audio(dir.path(), false), vec![
+        dir.path().join("track 2.FLAC"),
+        dir.path().join("Track 10.mp3"),
+    ]);
+    
+    // Subfolders too, everything in natural order of the full path
+    assert_eq!(scan_dir_for_audio(dir.path(), true), vec![
+        album.join("01.ogg"),
+        disc.join("01.wav"),
+        dir.path().join("track 2.FLAC"),
+        dir.path().join("Track 10.mp3"),
+    ]);
+}
+
+#[test]
+fn test_load_cover_image_from_folder() {
+    let dir = tempdir().unwrap();
+    let track = dir.path().join("01 - song.mp3");
+    File::create(&track).unwrap();
+    
+    // No tags and no cover file: nothing to show
+    assert!(load_cover_image(&track).is_none());
+    
+    // An empty "track" has no embedded art, so the folder's cover is used
+    image::RgbaImage::new(4, 4).save(dir.path().join("cover.png")).unwrap();
+    assert_eq!(load_cover_image(&track).map(|image| image.size), Some([4, 4]));
+}
+
+#[test]
+fn test_supported_extensions_are_sorted() {
+    let extensions = get_supported_extensions();
+    assert_eq!(extensions, vec!["aac", "flac", "m4a", "mp3", "ogg", "wav"]);
+    
+    // Same answer every time, and every listed extension is recognised
+    assert_eq!(get_supported_extensions(), extensions);
+    for ext in extensions {
+        assert!(is_audio_file(format!("song.{}", ext.to_uppercase())));
+    }
+}
+
+#[test]
+fn test_is_probably_audio_checks_contents() {
+    let dir = tempdir().unwrap();
+    let fake = dir.path().join("notes.mp3");
+    std::fs::write(&fake, "just some text, not an mp3").unwrap();
+    let tagged = dir.path().join("tagged.mp3");
+    std::fs::write(&tagged, b"ID3\x04\x00\x00\x00\x00\x00\x00").unwrap();
+    let wav = dir.path().join("short.wav");
+    std::fs::write(&wav, b"RIFF\x24\x00\x00\x00WAVEfmt ").unwrap();
+    
+    assert!(is_audio_file(&fake));
+    assert!(!is_probably_audio(&fake));
+    assert!(is_probably_audio(&tagged));
+    assert!(is_probably_audio(&wav));
+    assert!(!is_probably_audio(&dir.path().join("missing.mp3")));
+}
+
+#[test]
+fn test_partition_existing_keeps_order() {
+    let dir = tempdir().unwrap();
+    let first = dir.path().join("first.mp3");
+    let second = dir.path().join("second.mp3");
+    File::create(&first).unwrap();
+    File::create(&second).unwrap();
+    let gone = dir.path().join("gone.mp3");
+    let cue = dir.path().join("Album.cue");
+    std::fs::write(&cue, "FILE \"Album.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n").unwrap();
+    let cue_track = dir.path().join("Album.cue#01");
+    
+    let paths = vec![gone.clone(), first.clone(), cue_track.clone(), second.clone()];
+    let (existing, missing) = partition_existing(&paths);
+    assert_eq!(existing, vec![first, second]);
+    // The sheet is there, but not the album file it plays from
+    assert_eq!(missing, vec![gone, cue_track.clone()]);
+    
+    File::create(dir.path().join("Album.flac")).unwrap();
+    let (existing, _) = partition_existing(&[cue_track.clone()]);
+    assert_eq!(existing, vec![cue_track]);
+    
+    // Streams aren't files, and are never counted as missing
+    let stream = PathBuf::from("http://radio.example:8000/live");
+    let (existing, _) = partition_existing(&[stream.clone()]);
+    assert_eq!(existing, vec![stream]);
+}
+
+#[test]
+fn test_perceptual_volume_curve() {
+    assert_eq!(perceptual_to_gain(0.0), 0.0);
+    assert_eq!(perceptual_to_gain(1.0), 1.0);
+    // Halfway up the slider is well under half the gain, about -18 dB
+    assert!((perceptual_to_gain(0.5) - 0.125).abs() < 1e-6);
+    assert_eq!(perceptual_to_gain(2.0), 1.0);
+    
+    // A restored gain puts the slider back where it was
+    for position in [0.0, 0.25, 0.5, 0.8, 1.0] {
+        assert!((gain_to_perceptual(perceptual_to_gain(position)) - position).abs() < 1e-5);
+    }
+}